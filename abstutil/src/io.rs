@@ -16,6 +16,11 @@ pub fn to_json<T: Serialize>(obj: &T) -> String {
     serde_json::to_string_pretty(obj).unwrap()
 }
 
+// All on one line, for streaming formats.
+pub fn to_json_terse<T: Serialize>(obj: &T) -> String {
+    serde_json::to_string(obj).unwrap()
+}
+
 // TODO Idea: Have a wrapper type DotJSON(...) and DotBin(...) to distinguish raw path strings
 fn maybe_write_json<T: Serialize>(path: &str, obj: &T) -> Result<(), Error> {
     if !path.ends_with(".json") {
@@ -106,6 +111,86 @@ pub fn read_binary<T: DeserializeOwned>(path: String, timer: &mut Timer) -> T {
     }
 }
 
+fn maybe_write_versioned_binary<T: Serialize>(
+    path: &str,
+    magic: &[u8; 8],
+    version: usize,
+    obj: &T,
+) -> Result<(), Error> {
+    if !path.ends_with(".bin") {
+        panic!("write_versioned_binary needs {} to end with .bin", path);
+    }
+
+    std::fs::create_dir_all(std::path::Path::new(path).parent().unwrap())
+        .expect("Creating parent dir failed");
+
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(magic)?;
+    bincode::serialize_into(&mut file, &version)
+        .map_err(|err| Error::new(ErrorKind::Other, err))?;
+    bincode::serialize_into(file, obj).map_err(|err| Error::new(ErrorKind::Other, err))
+}
+
+// Like write_binary, but starts with some bytes identifying the kind of file and the version of
+// its format, so readers can refuse stale files instead of misreading them.
+pub fn write_versioned_binary<T: Serialize>(
+    path: String,
+    magic: &[u8; 8],
+    version: usize,
+    obj: &T,
+) {
+    if let Err(err) = maybe_write_versioned_binary(&path, magic, version, obj) {
+        panic!("Can't write_versioned_binary({}): {}", path, err);
+    }
+    println!("Wrote {}", path);
+}
+
+// Only reads files written with exactly this version. Older files without the magic bytes are
+// refused too.
+pub fn maybe_read_versioned_binary<T: DeserializeOwned>(
+    path: String,
+    magic: &[u8; 8],
+    version: usize,
+    timer: &mut Timer,
+) -> Result<T, Error> {
+    if !path.ends_with(".bin") {
+        panic!("read_versioned_binary needs {} to end with .bin", path);
+    }
+
+    timer.start(format!("read {}", path));
+    let result = read_versioned(&path, magic, version);
+    timer.stop(format!("read {}", path));
+    result
+}
+
+fn read_versioned<T: DeserializeOwned>(
+    path: &str,
+    magic: &[u8; 8],
+    version: usize,
+) -> Result<T, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut actual_magic = [0; 8];
+    reader.read_exact(&mut actual_magic)?;
+    if &actual_magic != magic {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} is from before its format was versioned", path),
+        ));
+    }
+    let actual_version: usize =
+        bincode::deserialize_from(&mut reader).map_err(|err| Error::new(ErrorKind::Other, err))?;
+    if actual_version != version {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} is version {}, but version {} is needed",
+                path, actual_version, version
+            ),
+        ));
+    }
+    bincode::deserialize_from(reader).map_err(|err| Error::new(ErrorKind::Other, err))
+}
+
 // For BTreeMaps with struct keys. See https://github.com/serde-rs/json/issues/402.
 
 pub fn serialize_btreemap<S: Serializer, K: Serialize, V: Serialize>(
@@ -16,9 +16,10 @@ pub use crate::collections::{
 pub use crate::error::Error;
 pub use crate::io::{
     basename, deserialize_btreemap, deserialize_multimap, find_next_file, find_prev_file,
-    list_all_objects, load_all_objects, maybe_read_binary, maybe_read_json, read_binary, read_json,
-    serialize_btreemap, serialize_multimap, serialized_size_bytes, to_json, write_binary,
-    write_json, FileWithProgress,
+    list_all_objects, load_all_objects, maybe_read_binary, maybe_read_json,
+    maybe_read_versioned_binary, read_binary, read_json, serialize_btreemap, serialize_multimap,
+    serialized_size_bytes, to_json, to_json_terse, write_binary, write_json,
+    write_versioned_binary, FileWithProgress,
 };
 pub use crate::logs::Warn;
 pub use crate::random::{fork_rng, WeightedUsizeChoice};
@@ -67,6 +68,10 @@ pub fn path_prebaked_results(map_name: &str, scenario_name: &str) -> String {
     )
 }
 
+pub fn path_travel_times(map_name: &str, name: &str) -> String {
+    format!("../data/system/travel_times/{}/{}.bin", map_name, name)
+}
+
 pub fn path_scenario(map_name: &str, scenario_name: &str) -> String {
     format!(
         "../data/system/scenarios/{}/{}.bin",
@@ -154,6 +159,10 @@ pub fn path_pending_screenshots(map_name: &str) -> String {
     format!("../data/input/screenshots/pending_{}", map_name)
 }
 
+pub fn path_profile(name: &str) -> String {
+    format!("../data/input/profiles/{}.json", name)
+}
+
 pub fn path_polygon(polygon_name: &str) -> String {
     format!("../data/input/polygons/{}.poly", polygon_name)
 }
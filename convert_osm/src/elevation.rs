@@ -0,0 +1,285 @@
+use abstutil::Timer;
+use geom::{Distance, LonLat};
+use map_model::raw::RawMap;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+use tiff::ColorType;
+
+// Sets the elevation of every intersection. See Elevation::new for the inputs understood.
+pub fn add_elevation(map: &mut RawMap, path: &str, timer: &mut Timer) {
+    timer.start("add elevation data");
+    let mut elevation = Elevation::new(path, timer);
+    let mut missing = 0;
+    for i in map.intersections.values_mut() {
+        let gps = i.point.forcibly_to_gps(&map.gps_bounds);
+        if let Some(height) = elevation.height(gps, timer) {
+            i.elevation = height;
+        } else {
+            missing += 1;
+        }
+    }
+    if missing > 0 {
+        timer.warn(format!(
+            "No elevation data for {} intersections; leaving them at 0",
+            missing
+        ));
+    }
+    timer.stop("add elevation data");
+}
+
+pub struct Elevation {
+    source: Source,
+}
+
+enum Source {
+    // Each tile covers 1 degree of latitude and longitude, named by its southwest corner, like
+    // N47W123.hgt. They're loaded as needed.
+    Srtm {
+        dir: String,
+        tiles: BTreeMap<(i32, i32), Option<Grid>>,
+    },
+    GeoTiff(Option<Grid>),
+}
+
+impl Elevation {
+    // Either a single GeoTIFF (.tif or .tiff) in longitude and latitude, or a directory of SRTM
+    // .hgt tiles.
+    pub fn new(path: &str, timer: &mut Timer) -> Elevation {
+        let source = if path.ends_with(".tif") || path.ends_with(".tiff") {
+            Source::GeoTiff(load_geotiff(path, timer))
+        } else {
+            Source::Srtm {
+                dir: path.to_string(),
+                tiles: BTreeMap::new(),
+            }
+        };
+        Elevation { source }
+    }
+
+    pub fn height(&mut self, gps: LonLat, timer: &mut Timer) -> Option<Distance> {
+        match self.source {
+            Source::Srtm {
+                ref dir,
+                ref mut tiles,
+            } => {
+                let key = (gps.latitude.floor() as i32, gps.longitude.floor() as i32);
+                tiles
+                    .entry(key)
+                    .or_insert_with(|| load_srtm_tile(dir, key, timer))
+                    .as_ref()?
+                    .height(gps)
+            }
+            Source::GeoTiff(ref grid) => grid.as_ref()?.height(gps),
+        }
+    }
+}
+
+// Evenly spaced samples of height in meters, in rows from north to south.
+struct Grid {
+    // Where the first sample is
+    northwest: LonLat,
+    // Degrees between samples
+    lon_step: f64,
+    lat_step: f64,
+    width: usize,
+    height: usize,
+    samples: Vec<f64>,
+    // Marks missing data
+    void: Option<f64>,
+}
+
+impl Grid {
+    // Bilinear interpolation between the 4 surrounding samples
+    fn height(&self, gps: LonLat) -> Option<Distance> {
+        let row = (self.northwest.latitude - gps.latitude) / self.lat_step;
+        let col = (gps.longitude - self.northwest.longitude) / self.lon_step;
+        let (last_row, last_col) = ((self.height - 1) as f64, (self.width - 1) as f64);
+        if row < 0.0 || col < 0.0 || row > last_row || col > last_col {
+            return None;
+        }
+        let (r0, c0) = (row.floor() as usize, col.floor() as usize);
+        let (r1, c1) = ((r0 + 1).min(self.height - 1), (c0 + 1).min(self.width - 1));
+        let (dr, dc) = (row - r0 as f64, col - c0 as f64);
+
+        let mut total = 0.0;
+        for &(r, c, weight) in &[
+            (r0, c0, (1.0 - dr) * (1.0 - dc)),
+            (r0, c1, (1.0 - dr) * dc),
+            (r1, c0, dr * (1.0 - dc)),
+            (r1, c1, dr * dc),
+        ] {
+            if weight == 0.0 {
+                continue;
+            }
+            let sample = self.samples[r * self.width + c];
+            if Some(sample) == self.void || sample.is_nan() {
+                return None;
+            }
+            total += weight * sample;
+        }
+        Some(Distance::meters(total))
+    }
+}
+
+// Samples are big-endian signed 16-bit meters. The edges of neighboring tiles overlap.
+fn load_srtm_tile(dir: &str, (lat, lon): (i32, i32), timer: &mut Timer) -> Option<Grid> {
+    let path = format!(
+        "{}/{}{:02}{}{:03}.hgt",
+        dir,
+        if lat >= 0 { 'N' } else { 'S' },
+        lat.abs(),
+        if lon >= 0 { 'E' } else { 'W' },
+        lon.abs()
+    );
+    let mut bytes = Vec::new();
+    if let Err(err) = File::open(&path).and_then(|mut f| f.read_to_end(&mut bytes)) {
+        timer.warn(format!("Couldn't read {}: {}", path, err));
+        return None;
+    }
+    // 1 arc-second tiles have 3601 samples per side, 3 arc-second have 1201
+    let samples_per_side = ((bytes.len() / 2) as f64).sqrt() as usize;
+    if samples_per_side < 2 || samples_per_side * samples_per_side * 2 != bytes.len() {
+        timer.warn(format!("{} isn't a square grid of samples", path));
+        return None;
+    }
+    timer.note(format!(
+        "Loaded {} with {} samples per side",
+        path, samples_per_side
+    ));
+    let step = 1.0 / (samples_per_side - 1) as f64;
+    Some(Grid {
+        northwest: LonLat::new(f64::from(lon), f64::from(lat + 1)),
+        lon_step: step,
+        lat_step: step,
+        width: samples_per_side,
+        height: samples_per_side,
+        samples: bytes
+            .chunks(2)
+            .map(|pair| f64::from(i16::from_be_bytes([pair[0], pair[1]])))
+            .collect(),
+        void: Some(-32768.0),
+    })
+}
+
+fn load_geotiff(path: &str, timer: &mut Timer) -> Option<Grid> {
+    match read_geotiff(path) {
+        Ok(grid) => {
+            timer.note(format!(
+                "Loaded {} with {}x{} samples",
+                path, grid.width, grid.height
+            ));
+            Some(grid)
+        }
+        Err(err) => {
+            timer.warn(format!("Couldn't read {}: {}", path, err));
+            None
+        }
+    }
+}
+
+// GeoKeyDirectory keys and values
+const GT_MODEL_TYPE: u16 = 1024;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const GT_RASTER_TYPE: u16 = 1025;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+
+// Only single-band rasters in plain longitude and latitude are understood. Projected ones (like
+// UTM) need to be warped first, with something like gdalwarp -t_srs EPSG:4326.
+fn read_geotiff(path: &str) -> Result<Grid, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let mut decoder = Decoder::new(BufReader::new(file)).map_err(|err| err.to_string())?;
+
+    // The directory is a header of 4 shorts, then (key, location, count, value) per key. Only
+    // the short values stored inline matter here.
+    let mut model_type = None;
+    let mut raster_type = None;
+    if let Some(keys) = decoder
+        .find_tag_unsigned_vec::<u16>(Tag::GeoKeyDirectoryTag)
+        .map_err(|err| err.to_string())?
+    {
+        for key in keys.chunks(4).skip(1) {
+            if key.len() == 4 && key[1] == 0 {
+                if key[0] == GT_MODEL_TYPE {
+                    model_type = Some(key[3]);
+                } else if key[0] == GT_RASTER_TYPE {
+                    raster_type = Some(key[3]);
+                }
+            }
+        }
+    }
+    if model_type != Some(MODEL_TYPE_GEOGRAPHIC) {
+        return Err(format!(
+            "model type is {:?}; only longitude and latitude are supported",
+            model_type
+        ));
+    }
+
+    let scale = decoder
+        .get_tag_f64_vec(Tag::ModelPixelScaleTag)
+        .map_err(|err| err.to_string())?;
+    let tiepoint = decoder
+        .get_tag_f64_vec(Tag::ModelTiepointTag)
+        .map_err(|err| err.to_string())?;
+    if scale.len() < 2 || tiepoint.len() < 6 {
+        return Err("malformed pixel scale or tiepoint".to_string());
+    }
+    let (lon_step, lat_step) = (scale[0], scale[1]);
+    // The tiepoint pins a raster position to a longitude and latitude. By default, rasters are
+    // PixelIsArea, meaning (0, 0) is the corner of the first pixel, not its center.
+    let mut northwest = LonLat::new(
+        tiepoint[3] - tiepoint[0] * lon_step,
+        tiepoint[4] + tiepoint[1] * lat_step,
+    );
+    if raster_type != Some(RASTER_PIXEL_IS_POINT) {
+        northwest = LonLat::new(
+            northwest.longitude + lon_step / 2.0,
+            northwest.latitude - lat_step / 2.0,
+        );
+    }
+
+    // GDAL stores this as text
+    let void = match decoder.find_tag(Tag::GdalNodata) {
+        Ok(Some(value)) => value
+            .into_string()
+            .ok()
+            .and_then(|s| s.trim_matches(char::from(0)).trim().parse::<f64>().ok()),
+        _ => None,
+    };
+
+    match decoder.colortype().map_err(|err| err.to_string())? {
+        ColorType::Gray(_) => {}
+        x => {
+            return Err(format!("{:?} isn't a single band of heights", x));
+        }
+    }
+    let (width, height) = decoder.dimensions().map_err(|err| err.to_string())?;
+    let samples: Vec<f64> = match decoder.read_image().map_err(|err| err.to_string())? {
+        DecodingResult::U8(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::U16(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::U32(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::U64(v) => v.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::I8(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::I16(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::I32(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::I64(v) => v.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::F32(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::F64(v) => v,
+    };
+    let (width, height) = (width as usize, height as usize);
+    if width < 2 || height < 2 || samples.len() != width * height {
+        return Err(format!("{}x{} isn't a grid of samples", width, height));
+    }
+
+    Ok(Grid {
+        northwest,
+        lon_step,
+        lat_step,
+        width,
+        height,
+        samples,
+        void,
+    })
+}
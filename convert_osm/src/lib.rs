@@ -1,30 +1,53 @@
 mod clip;
+mod elevation;
 mod neighborhoods;
 mod osm_reader;
+mod profile;
+mod reader;
 mod split_ways;
 
 use abstutil::Timer;
-use geom::{Distance, FindClosest, Line, PolyLine, Pt2D};
+use geom::{Distance, Duration, FindClosest, Line, PolyLine, Pt2D};
 use kml::ExtraShapes;
 use map_model::raw::{OriginalBuilding, OriginalRoad, RawMap};
-use map_model::{osm, LaneID, OffstreetParking, Position, LANE_THICKNESS};
+use map_model::{osm, LaneID, OffstreetParking, ParkingPolicy, Position, LANE_THICKNESS};
+
+pub use self::elevation::Elevation;
+pub use self::profile::ImportProfile;
 
 pub struct Flags {
+    // .osm XML or .osm.pbf
     pub osm: String,
     pub parking_shapes: Option<String>,
     pub offstreet_parking: Option<String>,
+    // KML with prices, time limits, and permit zones for blockfaces and parking garages
+    pub parking_policies: Option<String>,
     pub sidewalks: Option<String>,
     pub gtfs: Option<String>,
+    // Like "monday". Only GTFS trips running that day are kept.
+    pub gtfs_service_day: Option<String>,
     pub neighborhoods: Option<String>,
     pub clip: Option<String>,
+    // JSON ImportProfile with regional assumptions. Seattle's are used by default.
+    pub profile: Option<String>,
+    // A directory of SRTM .hgt tiles, or a GeoTIFF in longitude and latitude
+    pub elevation: Option<String>,
     pub output: String,
 }
 
 pub fn convert(flags: &Flags, timer: &mut abstutil::Timer) -> RawMap {
+    let profile: ImportProfile = abstutil::read_json(
+        flags
+            .profile
+            .clone()
+            .unwrap_or_else(|| abstutil::path_profile("seattle")),
+        timer,
+    );
     let mut map = split_ways::split_up_roads(
-        osm_reader::extract_osm(&flags.osm, &flags.clip, timer),
+        osm_reader::extract_osm(&flags.osm, &flags.clip, &profile, timer),
         timer,
     );
+    map.driving_side = profile.driving_side;
     clip::clip_map(&mut map, timer);
 
     // Need to do a first pass of removing cul-de-sacs here, or we wind up with loop PolyLines when doing the parking hint matching.
@@ -36,15 +59,22 @@ pub fn convert(flags: &Flags, timer: &mut abstutil::Timer) -> RawMap {
     if let Some(ref path) = flags.offstreet_parking {
         use_offstreet_parking(&mut map, path, timer);
     }
+    if let Some(ref path) = flags.parking_policies {
+        use_parking_policies(&mut map, path, timer);
+    }
     if let Some(ref path) = flags.sidewalks {
         use_sidewalk_hints(&mut map, path.clone(), timer);
     }
     if let Some(ref path) = flags.gtfs {
         timer.start("load GTFS");
-        map.bus_routes = gtfs::load(path);
+        map.bus_routes = gtfs::load(path, flags.gtfs_service_day.as_ref().map(|s| s.as_str()));
         timer.stop("load GTFS");
     }
 
+    if let Some(ref path) = flags.elevation {
+        elevation::add_elevation(&mut map, path, timer);
+    }
+
     if let Some(ref path) = flags.neighborhoods {
         timer.start("convert neighborhood polygons");
         neighborhoods::convert(path.clone(), map.name.clone(), &map.gps_bounds);
@@ -57,21 +87,7 @@ pub fn convert(flags: &Flags, timer: &mut abstutil::Timer) -> RawMap {
 fn use_parking_hints(map: &mut RawMap, path: String, timer: &mut Timer) {
     timer.start("apply parking hints");
     let shapes: ExtraShapes = abstutil::read_binary(path, timer);
-
-    // Match shapes with the nearest road + direction (true for forwards)
-    let mut closest: FindClosest<(OriginalRoad, bool)> =
-        FindClosest::new(&map.gps_bounds.to_bounds());
-    for (id, r) in &map.roads {
-        let center = PolyLine::new(r.center_points.clone());
-        closest.add(
-            (*id, true),
-            center.shift_right(LANE_THICKNESS).get(timer).points(),
-        );
-        closest.add(
-            (*id, false),
-            center.shift_left(LANE_THICKNESS).get(timer).points(),
-        );
-    }
+    let closest = closest_road_sides(map, timer);
 
     for s in shapes.shapes.into_iter() {
         let pts = if let Some(pts) = map.gps_bounds.try_convert(&s.points) {
@@ -147,6 +163,23 @@ fn use_parking_hints(map: &mut RawMap, path: String, timer: &mut Timer) {
     timer.stop("apply parking hints");
 }
 
+// Match shapes with the nearest road + direction (true for forwards)
+fn closest_road_sides(map: &RawMap, timer: &mut Timer) -> FindClosest<(OriginalRoad, bool)> {
+    let mut closest = FindClosest::new(&map.gps_bounds.to_bounds());
+    for (id, r) in &map.roads {
+        let center = PolyLine::new(r.center_points.clone());
+        closest.add(
+            (*id, true),
+            center.shift_right(LANE_THICKNESS).get(timer).points(),
+        );
+        closest.add(
+            (*id, false),
+            center.shift_left(LANE_THICKNESS).get(timer).points(),
+        );
+    }
+    closest
+}
+
 fn use_offstreet_parking(map: &mut RawMap, path: &str, timer: &mut Timer) {
     timer.start("match offstreet parking points");
     let shapes = kml::load(path, &map.gps_bounds, timer).expect("loading offstreet_parking failed");
@@ -185,6 +218,7 @@ fn use_offstreet_parking(map: &mut RawMap, path: &str, timer: &mut Timer) {
             // Temporary values, populate later
             driveway_line: Line::new(Pt2D::new(0.0, 0.0), Pt2D::new(1.0, 1.0)),
             driving_pos: Position::new(LaneID(0), Distance::ZERO),
+            policy: ParkingPolicy::free(),
         });
         None
     });
@@ -195,24 +229,96 @@ fn use_offstreet_parking(map: &mut RawMap, path: &str, timer: &mut Timer) {
     timer.stop("match offstreet parking points");
 }
 
+// Lines are blockfaces, and become parking:lane tags on the matching side of the road. Points
+// update the offstreet parking of the building they're in. Every attribute is optional:
+// HOURLY_PRICE in dollars, MAX_STAY_MINUTES, and PERMIT_ZONE.
+fn use_parking_policies(map: &mut RawMap, path: &str, timer: &mut Timer) {
+    timer.start("apply parking policies");
+    let shapes = kml::load(path, &map.gps_bounds, timer).expect("loading parking_policies failed");
+    let closest_roads = closest_road_sides(map, timer);
+    let mut closest_bldgs: FindClosest<OriginalBuilding> =
+        FindClosest::new(&map.gps_bounds.to_bounds());
+    for (id, b) in &map.buildings {
+        closest_bldgs.add(*id, b.polygon.points());
+    }
+
+    let mut unmatched = 0;
+    for s in shapes.shapes.into_iter() {
+        let pts = if let Some(pts) = map.gps_bounds.try_convert(&s.points) {
+            pts
+        } else {
+            unmatched += 1;
+            continue;
+        };
+        let price = s.attributes.get("HOURLY_PRICE");
+        let max_stay = s.attributes.get("MAX_STAY_MINUTES");
+        let zone = s.attributes.get("PERMIT_ZONE");
+
+        if pts.len() == 1 {
+            let bldg = closest_bldgs
+                .closest_pt(pts[0], Distance::meters(50.0))
+                .map(|(id, _)| id)
+                .filter(|id| map.buildings[id].polygon.contains_pt(pts[0]));
+            let parking = match bldg {
+                Some(id) => map.buildings.get_mut(&id).unwrap().parking.as_mut(),
+                None => None,
+            };
+            if let Some(p) = parking {
+                if let Some(dollars) = price.and_then(|x| x.parse::<f64>().ok()) {
+                    p.policy.hourly_price_cents = (dollars * 100.0).round() as usize;
+                }
+                if let Some(mins) = max_stay.and_then(|x| x.parse::<usize>().ok()) {
+                    p.policy.max_stay = Some(Duration::minutes(mins));
+                }
+                if let Some(z) = zone {
+                    p.policy.permit_zone = Some(z.to_string());
+                }
+            } else {
+                unmatched += 1;
+            }
+            continue;
+        }
+
+        // Like the blockfaces, match based on the middle.
+        let middle = if let Some(pl) = PolyLine::maybe_new(pts) {
+            pl.middle()
+        } else {
+            unmatched += 1;
+            continue;
+        };
+        if let Some(((r, fwds), _)) = closest_roads.closest_pt(middle, LANE_THICKNESS * 5.0) {
+            let side = if fwds {
+                osm::PARKING_RIGHT
+            } else {
+                osm::PARKING_LEFT
+            };
+            let tags = &mut map.roads.get_mut(&r).unwrap().osm_tags;
+            if let Some(x) = price {
+                tags.insert(format!("{}:fee", side), x.to_string());
+            }
+            if let Some(x) = max_stay {
+                tags.insert(format!("{}:maxstay", side), format!("{} minutes", x));
+            }
+            if let Some(x) = zone {
+                tags.insert(format!("{}:residents", side), x.to_string());
+            }
+        } else {
+            unmatched += 1;
+        }
+    }
+    if unmatched > 0 {
+        timer.warn(format!(
+            "{} parking policies didn't match any road or parking garage",
+            unmatched
+        ));
+    }
+    timer.stop("apply parking policies");
+}
+
 fn use_sidewalk_hints(map: &mut RawMap, path: String, timer: &mut Timer) {
     timer.start("apply sidewalk hints");
     let shapes: ExtraShapes = abstutil::read_binary(path, timer);
-
-    // Match shapes with the nearest road + direction (true for forwards)
-    let mut closest: FindClosest<(OriginalRoad, bool)> =
-        FindClosest::new(&map.gps_bounds.to_bounds());
-    for (id, r) in &map.roads {
-        let center = PolyLine::new(r.center_points.clone());
-        closest.add(
-            (*id, true),
-            center.shift_right(LANE_THICKNESS).get(timer).points(),
-        );
-        closest.add(
-            (*id, false),
-            center.shift_left(LANE_THICKNESS).get(timer).points(),
-        );
-    }
+    let closest = closest_road_sides(map, timer);
 
     for s in shapes.shapes.into_iter() {
         let pts = if let Some(pts) = map.gps_bounds.try_convert(&s.points) {
@@ -7,10 +7,14 @@ fn main() {
         osm: args.required("--osm"),
         parking_shapes: args.optional("--parking_shapes"),
         offstreet_parking: args.optional("--offstreet_parking"),
+        parking_policies: args.optional("--parking_policies"),
         sidewalks: args.optional("--sidewalks"),
         gtfs: args.optional("--gtfs"),
+        gtfs_service_day: args.optional("--gtfs_service_day"),
         neighborhoods: args.optional("--neighborhoods"),
         clip: args.optional("--clip"),
+        profile: args.optional("--profile"),
+        elevation: args.optional("--elevation"),
         output: args.required("--output"),
     };
     args.done();
@@ -1,8 +1,9 @@
-use abstutil::{FileWithProgress, Timer};
+use crate::profile::ImportProfile;
+use crate::reader;
+use abstutil::Timer;
 use geom::{GPSBounds, HashablePt2D, LonLat, PolyLine, Polygon, Pt2D, Ring};
 use map_model::raw::{OriginalBuilding, RawArea, RawBuilding, RawMap, RawRoad, RestrictionType};
-use map_model::{osm, AreaType};
-use osm_xml;
+use map_model::{osm, AreaType, CrossingType};
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
@@ -10,6 +11,7 @@ use std::io::{BufRead, BufReader};
 pub fn extract_osm(
     osm_path: &str,
     maybe_clip_path: &Option<String>,
+    profile: &ImportProfile,
     timer: &mut Timer,
 ) -> (
     RawMap,
@@ -17,27 +19,24 @@ pub fn extract_osm(
     Vec<(i64, RawRoad)>,
     // Traffic signals
     HashSet<HashablePt2D>,
+    // Pedestrian crossings
+    HashMap<HashablePt2D, CrossingType>,
     // OSM Node IDs
     HashMap<HashablePt2D, i64>,
     // Turn restrictions: (restriction type, from way ID, via node ID, to way ID)
     Vec<(RestrictionType, i64, i64, i64)>,
 ) {
-    let (reader, done) = FileWithProgress::new(osm_path).unwrap();
-    let doc = osm_xml::OSM::parse(reader).expect("OSM parsing failed");
-    println!(
-        "OSM doc has {} nodes, {} ways, {} relations",
-        doc.nodes.len(),
-        doc.ways.len(),
-        doc.relations.len()
-    );
-    done(timer);
-
-    let mut map = if let Some(ref path) = maybe_clip_path {
-        read_osmosis_polygon(path)
+    let clip = maybe_clip_path
+        .as_ref()
+        .map(|path| read_osmosis_polygon(path));
+    let doc = reader::read(osm_path, clip.as_ref(), timer);
+
+    let mut map = if let Some(m) = clip {
+        m
     } else {
         let mut m = RawMap::blank(abstutil::basename(osm_path));
         for node in doc.nodes.values() {
-            m.gps_bounds.update(LonLat::new(node.lon, node.lat));
+            m.gps_bounds.update(node.pt);
         }
         m.boundary_polygon = m.gps_bounds.to_bounds().get_rectangle();
         m
@@ -46,81 +45,47 @@ pub fn extract_osm(
     let mut id_to_way: HashMap<i64, Vec<Pt2D>> = HashMap::new();
     let mut roads: Vec<(i64, RawRoad)> = Vec::new();
     let mut traffic_signals: HashSet<HashablePt2D> = HashSet::new();
+    let mut crossings: HashMap<HashablePt2D, CrossingType> = HashMap::new();
     let mut osm_node_ids = HashMap::new();
 
     timer.start_iter("processing OSM nodes", doc.nodes.len());
-    for node in doc.nodes.values() {
+    for (id, node) in &doc.nodes {
         timer.next();
-        let pt =
-            Pt2D::forcibly_from_gps(LonLat::new(node.lon, node.lat), &map.gps_bounds).to_hashable();
-        osm_node_ids.insert(pt, node.id);
+        let pt = Pt2D::forcibly_from_gps(node.pt, &map.gps_bounds).to_hashable();
+        osm_node_ids.insert(pt, *id);
 
-        let tags = tags_to_map(&node.tags);
-        if tags.get(osm::HIGHWAY) == Some(&"traffic_signals".to_string()) {
+        if node.tags.get(osm::HIGHWAY) == Some(&"traffic_signals".to_string()) {
             traffic_signals.insert(pt);
         }
+        if let Some(ct) = get_crossing_type(&node.tags) {
+            crossings.insert(pt, ct);
+        }
     }
 
     let mut coastline_groups: Vec<Vec<Pt2D>> = Vec::new();
     timer.start_iter("processing OSM ways", doc.ways.len());
-    for way in doc.ways.values() {
+    for (id, way) in &doc.ways {
         timer.next();
 
-        let mut valid = true;
-        let mut gps_pts = Vec::new();
-        for node_ref in &way.nodes {
-            match doc.resolve_reference(node_ref) {
-                osm_xml::Reference::Node(node) => {
-                    gps_pts.push(LonLat::new(node.lon, node.lat));
-                }
-                // Don't handle nested ways/relations yet
-                _ => {
-                    valid = false;
-                }
-            }
-        }
-        if !valid {
+        // Nodes can be missing from extracts that were already clipped
+        let gps_pts: Vec<LonLat> = way
+            .nodes
+            .iter()
+            .filter_map(|n| doc.nodes.get(n).map(|node| node.pt))
+            .collect();
+        if gps_pts.len() != way.nodes.len() {
             continue;
         }
         let pts = map.gps_bounds.forcibly_convert(&gps_pts);
-        let mut tags = tags_to_map(&way.tags);
-        tags.insert(osm::OSM_WAY_ID.to_string(), way.id.to_string());
+        let mut tags = way.tags.clone();
+        tags.insert(osm::OSM_WAY_ID.to_string(), id.to_string());
 
         if is_road(&tags) {
-            // If there's no parking data in OSM already, then assume no parking and mark that it's
-            // inferred.
-            if !tags.contains_key(osm::PARKING_LEFT)
-                && !tags.contains_key(osm::PARKING_RIGHT)
-                && !tags.contains_key(osm::PARKING_BOTH)
-                && tags.get(osm::HIGHWAY) != Some(&"motorway".to_string())
-                && tags.get(osm::HIGHWAY) != Some(&"motorway_link".to_string())
-                && tags.get("junction") != Some(&"roundabout".to_string())
-            {
-                tags.insert(osm::PARKING_BOTH.to_string(), "no_parking".to_string());
-                tags.insert(osm::INFERRED_PARKING.to_string(), "true".to_string());
-            }
-
-            // If there's no sidewalk data in OSM already, then make an assumption and mark that
-            // it's inferred.
-            if !tags.contains_key(osm::SIDEWALK) {
-                tags.insert(osm::INFERRED_SIDEWALKS.to_string(), "true".to_string());
-                if tags.get(osm::HIGHWAY) == Some(&"motorway".to_string())
-                    || tags.get(osm::HIGHWAY) == Some(&"motorway_link".to_string())
-                    || tags.get("junction") == Some(&"roundabout".to_string())
-                {
-                    tags.insert(osm::SIDEWALK.to_string(), "none".to_string());
-                } else if tags.get("oneway") == Some(&"yes".to_string()) {
-                    tags.insert(osm::SIDEWALK.to_string(), "right".to_string());
-                    if tags.get(osm::HIGHWAY) == Some(&"residential".to_string()) {
-                        tags.insert(osm::SIDEWALK.to_string(), "both".to_string());
-                    }
-                } else {
-                    tags.insert(osm::SIDEWALK.to_string(), "both".to_string());
-                }
-            }
+            let trail = is_trail(&tags);
+            profile.apply(&mut tags, trail);
 
             roads.push((
-                way.id,
+                *id,
                 RawRoad {
                     center_points: pts,
                     osm_tags: tags,
@@ -134,7 +99,7 @@ pub fn extract_osm(
                 continue;
             }
             map.buildings.insert(
-                OriginalBuilding { osm_way_id: way.id },
+                OriginalBuilding { osm_way_id: *id },
                 RawBuilding {
                     polygon: Polygon::new(&deduped),
                     osm_tags: tags,
@@ -147,7 +112,7 @@ pub fn extract_osm(
             }
             map.areas.push(RawArea {
                 area_type: at,
-                osm_id: way.id,
+                osm_id: *id,
                 polygon: Polygon::new(&pts),
                 osm_tags: tags,
             });
@@ -155,7 +120,7 @@ pub fn extract_osm(
             coastline_groups.push(pts);
         } else {
             // The way might be part of a relation later.
-            id_to_way.insert(way.id, pts);
+            id_to_way.insert(*id, pts);
         }
     }
 
@@ -163,17 +128,18 @@ pub fn extract_osm(
 
     let mut turn_restrictions = Vec::new();
     timer.start_iter("processing OSM relations", doc.relations.len());
-    for rel in doc.relations.values() {
+    for (rel_id, rel) in &doc.relations {
+        let rel_id = *rel_id;
         timer.next();
-        let mut tags = tags_to_map(&rel.tags);
-        tags.insert(osm::OSM_REL_ID.to_string(), rel.id.to_string());
+        let mut tags = rel.tags.clone();
+        tags.insert(osm::OSM_REL_ID.to_string(), rel_id.to_string());
         if let Some(at) = get_area_type(&tags) {
             if tags.get("type") == Some(&"multipolygon".to_string()) {
                 let mut ok = true;
                 let mut pts_per_way: Vec<Vec<Pt2D>> = Vec::new();
-                for member in &rel.members {
+                for (member, role) in &rel.members {
                     match member {
-                        osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(id), ref role) => {
+                        reader::Member::Way(id) => {
                             // If the way is clipped out, that's fine
                             if let Some(pts) = id_to_way.get(id) {
                                 if role == "outer" {
@@ -181,22 +147,22 @@ pub fn extract_osm(
                                 } else {
                                     println!(
                                         "Relation {} has unhandled member role {}, ignoring it",
-                                        rel.id, role
+                                        rel_id, role
                                     );
                                 }
                             }
                         }
                         _ => {
-                            println!("Relation {} refers to {:?}", rel.id, member);
+                            println!("Relation {} refers to {:?}", rel_id, member);
                             ok = false;
                         }
                     }
                 }
                 if ok {
-                    for polygon in glue_multipolygon(rel.id, pts_per_way, &boundary) {
+                    for polygon in glue_multipolygon(rel_id, pts_per_way, &boundary) {
                         map.areas.push(RawArea {
                             area_type: at,
-                            osm_id: rel.id,
+                            osm_id: rel_id,
                             polygon,
                             osm_tags: tags.clone(),
                         });
@@ -207,9 +173,9 @@ pub fn extract_osm(
             let mut from_way_id: Option<i64> = None;
             let mut via_node_id: Option<i64> = None;
             let mut to_way_id: Option<i64> = None;
-            for member in &rel.members {
+            for (member, role) in &rel.members {
                 match member {
-                    osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(id), ref role) => {
+                    reader::Member::Way(id) => {
                         if role == "from" {
                             from_way_id = Some(*id);
                         } else if role == "to" {
@@ -217,7 +183,7 @@ pub fn extract_osm(
                         }
                         // TODO Handle 'via' ways
                     }
-                    osm_xml::Member::Node(osm_xml::UnresolvedReference::Node(id), ref role) => {
+                    reader::Member::Node(id) => {
                         if role == "via" {
                             via_node_id = Some(*id);
                         }
@@ -253,21 +219,39 @@ pub fn extract_osm(
         });
     }
 
-    (map, roads, traffic_signals, osm_node_ids, turn_restrictions)
+    // Crossings on footways and other trails don't need intersections
+    let road_pts: HashSet<HashablePt2D> = roads
+        .iter()
+        .filter(|(_, r)| !is_trail(&r.osm_tags))
+        .flat_map(|(_, r)| r.center_points.iter().map(|pt| pt.to_hashable()))
+        .collect();
+    crossings.retain(|pt, _| road_pts.contains(pt));
+
+    (
+        map,
+        roads,
+        traffic_signals,
+        crossings,
+        osm_node_ids,
+        turn_restrictions,
+    )
 }
 
-fn tags_to_map(raw_tags: &[osm_xml::Tag]) -> BTreeMap<String, String> {
-    raw_tags
-        .iter()
-        .filter_map(|tag| {
-            // Toss out really useless metadata.
-            if tag.key.starts_with("tiger:") || tag.key.starts_with("old_name:") {
-                None
-            } else {
-                Some((tag.key.clone(), tag.val.clone()))
-            }
-        })
-        .collect()
+// Only zebras give pedestrians priority. Other crossings are left alone; pedestrians can already
+// cross at intersections.
+fn get_crossing_type(tags: &BTreeMap<String, String>) -> Option<CrossingType> {
+    // highway=traffic_signals nodes are for vehicles, even with crossing=traffic_signals.
+    if tags.get(osm::HIGHWAY) != Some(&"crossing".to_string()) {
+        return None;
+    }
+    let crossing = tags.get("crossing").map(|x| x.as_str()).unwrap_or("");
+    if crossing == "traffic_signals" {
+        Some(CrossingType::Signalized)
+    } else if crossing == "zebra" || tags.get("crossing_ref") == Some(&"zebra".to_string()) {
+        Some(CrossingType::Zebra)
+    } else {
+        None
+    }
 }
 
 fn is_road(tags: &BTreeMap<String, String>) -> bool {
@@ -279,22 +263,14 @@ fn is_road(tags: &BTreeMap<String, String>) -> bool {
     // potential reference
     for &value in &[
         // List of non-car types from https://wiki.openstreetmap.org/wiki/Key:highway
-        // TODO Footways are very useful, but they need more work to associate with main roads
-        "footway",
         "living_street",
-        "pedestrian",
         "track",
         "bus_guideway",
         "escape",
         "raceway",
         "bridleway",
-        "steps",
-        "path",
-        "cycleway",
         "proposed",
         "construction",
-        // This one's debatable. Includes alleys.
-        "service",
         // more discovered manually
         "abandoned",
         "elevator",
@@ -306,9 +282,44 @@ fn is_road(tags: &BTreeMap<String, String>) -> bool {
         }
     }
 
+    // Alleys and other service roads are kept, but driveways and the aisles of parking lots would
+    // just add dead-ends. Buildings connect to the closest road anyway.
+    if tags.get(osm::HIGHWAY) == Some(&"service".to_string()) {
+        match tags.get("service").map(|s| s.as_str()) {
+            Some("driveway") | Some("parking_aisle") | Some("drive-through") => {
+                return false;
+            }
+            _ => {}
+        }
+    }
+
+    if is_trail(tags) {
+        // Sidewalks and crossings mapped separately from the road are already covered by the
+        // road's own sidewalks and the crosswalks at intersections. Pedestrian plazas are areas.
+        if tags.get("footway") == Some(&"sidewalk".to_string())
+            || tags.get("footway") == Some(&"crossing".to_string())
+            || tags.get("area") == Some(&"yes".to_string())
+            || tags.get("access") == Some(&"private".to_string())
+            || tags.get("access") == Some(&"no".to_string())
+        {
+            return false;
+        }
+    }
+
     true
 }
 
+// Footways, cycleways, and other paths that cars can't use. These become roads with only
+// sidewalks and bike lanes.
+fn is_trail(tags: &BTreeMap<String, String>) -> bool {
+    match tags.get(osm::HIGHWAY).map(|hwy| hwy.as_str()) {
+        Some("footway") | Some("pedestrian") | Some("steps") | Some("path") | Some("cycleway") => {
+            true
+        }
+        _ => false,
+    }
+}
+
 fn is_bldg(tags: &BTreeMap<String, String>) -> bool {
     tags.contains_key("building")
 }
@@ -0,0 +1,137 @@
+use map_model::{osm, DrivingSide};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Regional assumptions for filling in tags that OSM is usually missing. Whatever gets inferred is
+// written as plain OSM tags, so the rest of the pipeline doesn't need to know about profiles.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImportProfile {
+    pub name: String,
+    #[serde(default)]
+    pub driving_side: DrivingSide,
+    // highway class -> maxspeed value, like "25 mph" or "50" (km/h, as in OSM)
+    pub default_speeds: BTreeMap<String, String>,
+    pub lanes: LaneRules,
+    pub sidewalks: SidewalkRules,
+    pub parking: ParkingRules,
+    pub bikes: BikeRules,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LaneRules {
+    // highway class -> driving lanes in each direction. Anything else gets 1.
+    pub per_direction: BTreeMap<String, usize>,
+    // Roundabouts are always one-way
+    pub roundabout: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SidewalkRules {
+    // Values of the sidewalk tag: "both", "right", "left", or "none"
+    pub two_way: String,
+    pub one_way: String,
+    // Highway classes with sidewalks on both sides, even when one-way
+    pub both_sides_on: Vec<String>,
+    pub none_on: Vec<String>,
+    // Only on the outside of the ring
+    pub on_roundabouts: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ParkingRules {
+    // Value of parking:lane:both, like "no_parking" or "parallel"
+    pub default: String,
+    // Highway classes left alone
+    pub skip: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BikeRules {
+    // Without a bicycle tag, can bikes use footways, paths, and pedestrian streets?
+    pub allowed_on_footways: bool,
+    // Without a foot tag, can pedestrians use cycleways?
+    pub peds_on_cycleways: bool,
+}
+
+impl ImportProfile {
+    // Fills in missing tags for a road or trail, marking anything inferred.
+    pub fn apply(&self, tags: &mut BTreeMap<String, String>, trail: bool) {
+        let highway = tags.get(osm::HIGHWAY).cloned().unwrap_or_else(String::new);
+        let roundabout = osm::is_roundabout(tags);
+
+        if !tags.contains_key(osm::MAXSPEED) {
+            if let Some(speed) = self.default_speeds.get(&highway) {
+                tags.insert(osm::MAXSPEED.to_string(), speed.clone());
+                tags.insert(osm::INFERRED_MAXSPEED.to_string(), "true".to_string());
+            }
+        }
+
+        if trail {
+            if (highway == "footway" || highway == "path" || highway == "pedestrian")
+                && !tags.contains_key("bicycle")
+                && self.bikes.allowed_on_footways
+            {
+                tags.insert("bicycle".to_string(), "yes".to_string());
+            }
+            if highway == "cycleway" && !tags.contains_key("foot") && !self.bikes.peds_on_cycleways
+            {
+                tags.insert("foot".to_string(), "no".to_string());
+            }
+            return;
+        }
+
+        if !tags.contains_key("lanes")
+            && !tags.contains_key("lanes:forward")
+            && !tags.contains_key("lanes:backward")
+        {
+            let oneway = roundabout
+                || tags.get("oneway") == Some(&"yes".to_string())
+                || tags.get("oneway") == Some(&"reversible".to_string());
+            let n = if roundabout {
+                self.lanes.roundabout
+            } else {
+                self.lanes.per_direction.get(&highway).cloned().unwrap_or(1)
+            };
+            let total = if oneway { n } else { 2 * n };
+            tags.insert("lanes".to_string(), total.to_string());
+            tags.insert(osm::INFERRED_LANES.to_string(), "true".to_string());
+        }
+
+        // If there's no parking data in OSM already, then make an assumption and mark that it's
+        // inferred.
+        if !tags.contains_key(osm::PARKING_LEFT)
+            && !tags.contains_key(osm::PARKING_RIGHT)
+            && !tags.contains_key(osm::PARKING_BOTH)
+            && !self.parking.skip.contains(&highway)
+            && !roundabout
+        {
+            tags.insert(osm::PARKING_BOTH.to_string(), self.parking.default.clone());
+            tags.insert(osm::INFERRED_PARKING.to_string(), "true".to_string());
+        }
+
+        // Same for sidewalks
+        if !tags.contains_key(osm::SIDEWALK) {
+            tags.insert(osm::INFERRED_SIDEWALKS.to_string(), "true".to_string());
+            let value = if roundabout {
+                // Every lane on the ring points forwards, and a sidewalk on the curb side goes
+                // with them.
+                if self.sidewalks.on_roundabouts {
+                    self.driving_side.osm_sides().0
+                } else {
+                    "none"
+                }
+            } else if self.sidewalks.none_on.contains(&highway) {
+                "none"
+            } else if tags.get("oneway") == Some(&"yes".to_string()) {
+                if self.sidewalks.both_sides_on.contains(&highway) {
+                    "both"
+                } else {
+                    self.sidewalks.one_way.as_str()
+                }
+            } else {
+                self.sidewalks.two_way.as_str()
+            };
+            tags.insert(osm::SIDEWALK.to_string(), value.to_string());
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use abstutil::{FileWithProgress, Timer};
+use geom::{LonLat, Pt2D};
+use map_model::raw::RawMap;
+use osm_xml;
+use osmpbfreader::{OsmObj, OsmPbfReader};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+
+// Just the parts of OSM that extract_osm needs, independent of the input format.
+pub struct Document {
+    pub nodes: HashMap<i64, Node>,
+    pub ways: BTreeMap<i64, Way>,
+    pub relations: BTreeMap<i64, Relation>,
+}
+
+pub struct Node {
+    pub pt: LonLat,
+    pub tags: BTreeMap<String, String>,
+}
+
+pub struct Way {
+    pub nodes: Vec<i64>,
+    pub tags: BTreeMap<String, String>,
+}
+
+pub struct Relation {
+    pub tags: BTreeMap<String, String>,
+    // (member, role)
+    pub members: Vec<(Member, String)>,
+}
+
+#[derive(Debug)]
+pub enum Member {
+    Node(i64),
+    Way(i64),
+    Relation(i64),
+}
+
+// .osm.pbf files are streamed; anything else is parsed as XML. If there's a clipping polygon, only
+// ways with at least one node inside it are kept (with all of their nodes), along with relations
+// referring to something kept. This matches what osmosis does with completeWays=yes. XML input is
+// clipped the same way. Later clipping would drop the same roads and buildings anyway, but an area
+// with no nodes inside the polygon is lost now, even if it surrounds the polygon.
+pub fn read(osm_path: &str, clip: Option<&RawMap>, timer: &mut Timer) -> Document {
+    let doc = if osm_path.ends_with(".pbf") {
+        read_pbf(osm_path, clip, timer)
+    } else {
+        read_xml(osm_path, clip, timer)
+    };
+    println!(
+        "OSM doc has {} nodes, {} ways, {} relations",
+        doc.nodes.len(),
+        doc.ways.len(),
+        doc.relations.len()
+    );
+    doc
+}
+
+fn inside_clip(pt: LonLat, clip: Option<&RawMap>) -> bool {
+    if let Some(map) = clip {
+        if let Some(pt) = Pt2D::from_gps(pt, &map.gps_bounds) {
+            map.boundary_polygon.contains_pt(pt)
+        } else {
+            false
+        }
+    } else {
+        true
+    }
+}
+
+fn read_xml(osm_path: &str, clip: Option<&RawMap>, timer: &mut Timer) -> Document {
+    let (reader, done) = FileWithProgress::new(osm_path).unwrap();
+    let xml = osm_xml::OSM::parse(reader).expect("OSM parsing failed");
+    done(timer);
+
+    let mut doc = Document {
+        nodes: HashMap::new(),
+        ways: BTreeMap::new(),
+        relations: BTreeMap::new(),
+    };
+    let mut inside_nodes: HashSet<i64> = HashSet::new();
+    for node in xml.nodes.values() {
+        let pt = LonLat::new(node.lon, node.lat);
+        if inside_clip(pt, clip) {
+            inside_nodes.insert(node.id);
+        }
+        doc.nodes.insert(
+            node.id,
+            Node {
+                pt,
+                tags: tags_to_map(node.tags.iter().map(|tag| (&tag.key, &tag.val))),
+            },
+        );
+    }
+
+    for way in xml.ways.values() {
+        let mut nodes = Vec::new();
+        for node_ref in &way.nodes {
+            match node_ref {
+                osm_xml::UnresolvedReference::Node(id) => {
+                    nodes.push(*id);
+                }
+                // Don't handle nested ways/relations yet
+                _ => {
+                    nodes.clear();
+                    break;
+                }
+            }
+        }
+        if nodes.is_empty() || !nodes.iter().any(|id| inside_nodes.contains(id)) {
+            continue;
+        }
+        doc.ways.insert(
+            way.id,
+            Way {
+                nodes,
+                tags: tags_to_map(way.tags.iter().map(|tag| (&tag.key, &tag.val))),
+            },
+        );
+    }
+
+    for rel in xml.relations.values() {
+        let members: Vec<(Member, String)> = rel
+            .members
+            .iter()
+            .map(|member| match member {
+                osm_xml::Member::Node(osm_xml::UnresolvedReference::Node(id), ref role) => {
+                    (Member::Node(*id), role.clone())
+                }
+                osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(id), ref role) => {
+                    (Member::Way(*id), role.clone())
+                }
+                osm_xml::Member::Relation(osm_xml::UnresolvedReference::Relation(id), ref role) => {
+                    (Member::Relation(*id), role.clone())
+                }
+                _ => panic!("Relation {} has a malformed member {:?}", rel.id, member),
+            })
+            .collect();
+        if !keep_relation(&members, &inside_nodes, &doc.ways) {
+            continue;
+        }
+        doc.relations.insert(
+            rel.id,
+            Relation {
+                tags: tags_to_map(rel.tags.iter().map(|tag| (&tag.key, &tag.val))),
+                members,
+            },
+        );
+    }
+
+    // Don't hang onto nodes that nothing uses.
+    if clip.is_some() {
+        let mut used_nodes: HashSet<i64> = inside_nodes;
+        for way in doc.ways.values() {
+            used_nodes.extend(way.nodes.iter().cloned());
+        }
+        doc.nodes.retain(|id, _| used_nodes.contains(id));
+    }
+
+    doc
+}
+
+// Nodes only have coordinates, so the file is read 3 times to avoid holding onto everything:
+// first to find nodes inside the clipping polygon, then to find ways and relations using those,
+// and finally to get the coordinates of all nodes those ways need.
+fn read_pbf(osm_path: &str, clip: Option<&RawMap>, timer: &mut Timer) -> Document {
+    let mut pbf = OsmPbfReader::new(File::open(osm_path).unwrap());
+    let mut doc = Document {
+        nodes: HashMap::new(),
+        ways: BTreeMap::new(),
+        relations: BTreeMap::new(),
+    };
+
+    // Without a clipping polygon, every node counts as inside, so don't bother tracking them.
+    let mut inside_nodes: HashSet<i64> = HashSet::new();
+    if clip.is_some() {
+        timer.start(format!(
+            "find nodes inside clipping polygon in {}",
+            osm_path
+        ));
+        for obj in pbf.iter() {
+            if let OsmObj::Node(node) = obj.expect("OSM PBF parsing failed") {
+                if inside_clip(LonLat::new(node.lon(), node.lat()), clip) {
+                    inside_nodes.insert(node.id.0);
+                }
+            }
+        }
+        timer.stop(format!(
+            "find nodes inside clipping polygon in {}",
+            osm_path
+        ));
+        pbf.rewind().unwrap();
+    }
+
+    // Ways come before relations in PBF files, so relations can check which ways were kept.
+    timer.start(format!("read ways and relations from {}", osm_path));
+    for obj in pbf.iter() {
+        match obj.expect("OSM PBF parsing failed") {
+            OsmObj::Node(_) => {}
+            OsmObj::Way(way) => {
+                let nodes: Vec<i64> = way.nodes.iter().map(|id| id.0).collect();
+                if clip.is_some() && !nodes.iter().any(|id| inside_nodes.contains(id)) {
+                    continue;
+                }
+                doc.ways.insert(
+                    way.id.0,
+                    Way {
+                        nodes,
+                        tags: tags_to_map(way.tags.iter()),
+                    },
+                );
+            }
+            OsmObj::Relation(rel) => {
+                let members: Vec<(Member, String)> = rel
+                    .refs
+                    .iter()
+                    .map(|r| {
+                        let member = match r.member {
+                            osmpbfreader::OsmId::Node(id) => Member::Node(id.0),
+                            osmpbfreader::OsmId::Way(id) => Member::Way(id.0),
+                            osmpbfreader::OsmId::Relation(id) => Member::Relation(id.0),
+                        };
+                        (member, r.role.to_string())
+                    })
+                    .collect();
+                if clip.is_some() && !keep_relation(&members, &inside_nodes, &doc.ways) {
+                    continue;
+                }
+                doc.relations.insert(
+                    rel.id.0,
+                    Relation {
+                        tags: tags_to_map(rel.tags.iter()),
+                        members,
+                    },
+                );
+            }
+        }
+    }
+    timer.stop(format!("read ways and relations from {}", osm_path));
+    pbf.rewind().unwrap();
+
+    let mut used_nodes: HashSet<i64> = inside_nodes;
+    for way in doc.ways.values() {
+        used_nodes.extend(way.nodes.iter().cloned());
+    }
+    timer.start(format!("read nodes from {}", osm_path));
+    for obj in pbf.iter() {
+        if let OsmObj::Node(node) = obj.expect("OSM PBF parsing failed") {
+            if clip.is_some() && !used_nodes.contains(&node.id.0) {
+                continue;
+            }
+            doc.nodes.insert(
+                node.id.0,
+                Node {
+                    pt: LonLat::new(node.lon(), node.lat()),
+                    tags: tags_to_map(node.tags.iter()),
+                },
+            );
+        }
+    }
+    timer.stop(format!("read nodes from {}", osm_path));
+
+    doc
+}
+
+fn keep_relation(
+    members: &[(Member, String)],
+    inside_nodes: &HashSet<i64>,
+    ways: &BTreeMap<i64, Way>,
+) -> bool {
+    members.iter().any(|(member, _)| match member {
+        Member::Node(id) => inside_nodes.contains(id),
+        Member::Way(id) => ways.contains_key(id),
+        // Nested relations aren't handled yet
+        Member::Relation(_) => false,
+    })
+}
+
+fn tags_to_map<'a, I: Iterator<Item = (&'a String, &'a String)>>(
+    raw_tags: I,
+) -> BTreeMap<String, String> {
+    raw_tags
+        .filter_map(|(key, val)| {
+            // Toss out really useless metadata.
+            if key.starts_with("tiger:") || key.starts_with("old_name:") {
+                None
+            } else {
+                Some((key.clone(), val.clone()))
+            }
+        })
+        .collect()
+}
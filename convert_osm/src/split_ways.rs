@@ -1,16 +1,17 @@
 use abstutil::{Counter, Timer};
-use geom::{HashablePt2D, Pt2D};
+use geom::{Distance, HashablePt2D, Pt2D};
 use map_model::raw::{
     OriginalIntersection, OriginalRoad, RawIntersection, RawMap, RawRoad, RestrictionType,
 };
-use map_model::{osm, IntersectionType};
+use map_model::{osm, CrossingType, IntersectionType};
 use std::collections::{HashMap, HashSet};
 
 pub fn split_up_roads(
-    (mut map, roads, traffic_signals, osm_node_ids, turn_restrictions): (
+    (mut map, roads, traffic_signals, crossings, osm_node_ids, turn_restrictions): (
         RawMap,
         Vec<(i64, RawRoad)>,
         HashSet<HashablePt2D>,
+        HashMap<HashablePt2D, CrossingType>,
         HashMap<HashablePt2D, i64>,
         Vec<(RestrictionType, i64, i64, i64)>,
     ),
@@ -25,8 +26,13 @@ pub fn split_up_roads(
             let pt = raw_pt.to_hashable();
             let count = counts_per_pt.inc(pt);
 
-            // All start and endpoints of ways are also intersections.
-            if count == 2 || idx == 0 || idx == r.center_points.len() - 1 {
+            // All start and endpoints of ways are also intersections. So are crossings, even
+            // in the middle of a road.
+            if count == 2
+                || idx == 0
+                || idx == r.center_points.len() - 1
+                || crossings.contains_key(&pt)
+            {
                 if !pt_to_intersection.contains_key(&pt) {
                     let id = OriginalIntersection {
                         osm_node_id: osm_node_ids[&pt],
@@ -42,11 +48,15 @@ pub fn split_up_roads(
             *id,
             RawIntersection {
                 point: pt.to_pt2d(),
-                intersection_type: if traffic_signals.contains(pt) {
+                intersection_type: if traffic_signals.contains(pt)
+                    || crossings.get(pt) == Some(&CrossingType::Signalized)
+                {
                     IntersectionType::TrafficSignal
                 } else {
                     IntersectionType::StopSign
                 },
+                elevation: Distance::ZERO,
+                crossing: crossings.get(pt).cloned(),
             },
         );
     }
@@ -11,7 +11,7 @@ use ezgui::{hotkey, lctrl, Color, EventCtx, GeomBatch, GfxCtx, Key, Line, ModalM
 use geom::{Circle, Distance, Line, PolyLine};
 use map_model::{Map, LANE_THICKNESS};
 use serde_derive::{Deserialize, Serialize};
-use sim::{Sim, SimOptions, TripID, TripMode};
+use sim::{Sim, SimOptions, TripID, TripMode, SAVESTATE_VERSION};
 
 // TODO I took out speed controls
 pub struct ABTestMode {
@@ -255,12 +255,14 @@ impl ABTestMode {
             ),
         };
 
-        abstutil::write_binary(
+        abstutil::write_versioned_binary(
             abstutil::path_ab_test_save(
                 ss.primary_map.get_name(),
                 &self.test_name,
                 ss.primary_sim.time().as_filename(),
             ),
+            AB_TEST_SAVESTATE_MAGIC,
+            SAVESTATE_VERSION,
             &ss,
         );
 
@@ -389,6 +391,9 @@ impl DiffAllTrips {
     }
 }
 
+// These hold whole Sims, so they're versioned along with regular savestates.
+const AB_TEST_SAVESTATE_MAGIC: &[u8; 8] = b"abstabts";
+
 #[derive(Serialize, Deserialize)]
 pub struct ABTestSavestate {
     primary_map: Map,
@@ -396,3 +401,15 @@ pub struct ABTestSavestate {
     secondary_map: Map,
     secondary_sim: Sim,
 }
+
+impl ABTestSavestate {
+    pub fn load(path: String, timer: &mut Timer) -> Result<ABTestSavestate, String> {
+        abstutil::maybe_read_versioned_binary(
+            path,
+            AB_TEST_SAVESTATE_MAGIC,
+            SAVESTATE_VERSION,
+            timer,
+        )
+        .map_err(|err| err.to_string())
+    }
+}
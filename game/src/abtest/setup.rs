@@ -1,6 +1,6 @@
 use crate::abtest::{ABTestMode, ABTestSavestate};
 use crate::edit::apply_map_edits;
-use crate::game::{State, Transition, WizardState};
+use crate::game::{msg, State, Transition, WizardState};
 use crate::render::DrawMap;
 use crate::ui::{Flags, PerMapUI, UI};
 use ezgui::{hotkey, Choice, EventCtx, GfxCtx, Key, Line, ModalMenu, Text, Wizard, WrappedWizard};
@@ -110,9 +110,10 @@ fn make_load_savestate(ab_test: ABTest) -> Box<dyn State> {
                 &ab_test.test_name,
             ))
         })?;
-        Some(Transition::Replace(Box::new(launch_savestate(
-            &ab_test, ss, ui, ctx,
-        ))))
+        match launch_savestate(&ab_test, ss, ui, ctx) {
+            Ok(mode) => Some(Transition::Replace(Box::new(mode))),
+            Err(err) => Some(Transition::Replace(msg("Can't load savestate", vec![err]))),
+        }
     }))
 }
 
@@ -134,12 +135,11 @@ fn launch_test(test: &ABTest, ui: &mut UI, ctx: &mut EventCtx) -> ABTestMode {
                     format!("{} with {}", test.test_name, test.edits1_name);
                 ui.primary.current_flags.sim_flags.opts.savestate_every = None;
 
-                apply_map_edits(
-                    &mut ui.primary,
-                    &ui.cs,
-                    ctx,
-                    MapEdits::load(&test.map_name, &test.edits1_name, &mut timer),
-                );
+                let edits = match MapEdits::load(&ui.primary.map, &test.edits1_name, &mut timer) {
+                    Ok(edits) => edits,
+                    Err(err) => panic!("Can't run {}: {}", test.test_name, err),
+                };
+                apply_map_edits(&mut ui.primary, &ui.cs, ctx, edits);
                 ui.primary.map.mark_edits_fresh();
                 ui.primary
                     .map
@@ -163,6 +163,7 @@ fn launch_test(test: &ABTest, ui: &mut UI, ctx: &mut EventCtx) -> ABTestMode {
                             load: abstutil::path_map(&test.map_name),
                             use_map_fixes: current_flags.sim_flags.use_map_fixes,
                             rng_seed: current_flags.sim_flags.rng_seed,
+                            travel_times: current_flags.sim_flags.travel_times.clone(),
                             opts: SimOptions {
                                 run_name: format!("{} with {}", test.test_name, test.edits2_name),
                                 savestate_every: None,
@@ -178,6 +179,15 @@ fn launch_test(test: &ABTest, ui: &mut UI, ctx: &mut EventCtx) -> ABTestMode {
                                     .sim_flags
                                     .opts
                                     .recalc_lanechanging,
+                                use_acceleration: current_flags.sim_flags.opts.use_acceleration,
+                                mid_block_lanechanging: current_flags
+                                    .sim_flags
+                                    .opts
+                                    .mid_block_lanechanging,
+                                reroute_after_blocked: current_flags
+                                    .sim_flags
+                                    .opts
+                                    .reroute_after_blocked,
                             },
                         },
                         ..current_flags.clone()
@@ -186,12 +196,11 @@ fn launch_test(test: &ABTest, ui: &mut UI, ctx: &mut EventCtx) -> ABTestMode {
                     ctx,
                     &mut timer,
                 );
-                apply_map_edits(
-                    &mut secondary,
-                    &ui.cs,
-                    ctx,
-                    MapEdits::load(&test.map_name, &test.edits2_name, &mut timer),
-                );
+                let edits = match MapEdits::load(&secondary.map, &test.edits2_name, &mut timer) {
+                    Ok(edits) => edits,
+                    Err(err) => panic!("Can't run {}: {}", test.test_name, err),
+                };
+                apply_map_edits(&mut secondary, &ui.cs, ctx, edits);
                 secondary.map.mark_edits_fresh();
                 secondary
                     .map
@@ -211,11 +220,16 @@ fn launch_test(test: &ABTest, ui: &mut UI, ctx: &mut EventCtx) -> ABTestMode {
     ABTestMode::new(ctx, ui, &test.test_name)
 }
 
-fn launch_savestate(test: &ABTest, ss_path: String, ui: &mut UI, ctx: &mut EventCtx) -> ABTestMode {
+fn launch_savestate(
+    test: &ABTest,
+    ss_path: String,
+    ui: &mut UI,
+    ctx: &mut EventCtx,
+) -> Result<ABTestMode, String> {
     ctx.loading_screen(
         format!("Launch A/B test from savestate {}", ss_path),
         |ctx, mut timer| {
-            let ss: ABTestSavestate = abstutil::read_binary(ss_path, &mut timer);
+            let ss = ABTestSavestate::load(ss_path, &mut timer)?;
 
             timer.start("setup primary");
             ui.primary.map = ss.primary_map;
@@ -248,7 +262,7 @@ fn launch_savestate(test: &ABTest, ss_path: String, ui: &mut UI, ctx: &mut Event
             ui.secondary = Some(secondary);
             timer.stop("setup secondary");
 
-            ABTestMode::new(ctx, ui, &test.test_name)
+            Ok(ABTestMode::new(ctx, ui, &test.test_name))
         },
     )
 }
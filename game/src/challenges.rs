@@ -1,5 +1,5 @@
 use crate::edit::apply_map_edits;
-use crate::game::{State, Transition, WizardState};
+use crate::game::{msg, State, Transition, WizardState};
 use crate::managed::{Composite, ManagedGUIState};
 use crate::sandbox::{GameplayMode, SandboxMode};
 use crate::ui::UI;
@@ -9,6 +9,7 @@ use ezgui::{
     ModalMenu, Text, VerticalAlignment,
 };
 use geom::{Duration, Time};
+use map_model::MapEdits;
 use sim::{Sim, SimFlags, SimOptions, TripMode};
 use std::collections::{BTreeMap, HashSet};
 
@@ -213,19 +214,32 @@ impl State for ChallengeSplash {
             let gameplay = self.challenge.gameplay.clone();
             return Transition::Push(WizardState::new(Box::new(move |wiz, ctx, ui| {
                 let mut wizard = wiz.wrap(ctx);
-                let (_, new_edits) = wizard.choose("Load which map edits?", || {
-                    Choice::from(
-                        abstutil::load_all_objects(abstutil::path_all_edits(&abstutil::basename(
-                            &map_path,
-                        )))
-                        .into_iter()
-                        .filter(|(_, edits)| gameplay.allows(edits))
-                        .collect(),
-                    )
+                let name = wizard.choose_string("Load which map edits?", || {
+                    abstutil::list_all_objects(abstutil::path_all_edits(&abstutil::basename(
+                        &map_path,
+                    )))
                 })?;
+                // Edits can only be matched up with the map they're for
                 if &abstutil::basename(&map_path) != ui.primary.map.get_name() {
                     ui.switch_map(ctx, map_path.clone());
                 }
+                let new_edits =
+                    match MapEdits::load(&ui.primary.map, &name, &mut Timer::new("load map edits"))
+                    {
+                        Ok(edits) => edits,
+                        Err(err) => {
+                            return Some(Transition::Replace(msg(
+                                "Can't load proposal",
+                                vec![err],
+                            )));
+                        }
+                    };
+                if !gameplay.allows(&new_edits) {
+                    return Some(Transition::Replace(msg(
+                        "Can't load proposal",
+                        vec![format!("{} has edits that aren't allowed here", name)],
+                    )));
+                }
                 apply_map_edits(&mut ui.primary, &ui.cs, ctx, new_edits);
                 ui.primary.map.mark_edits_fresh();
                 ui.primary
@@ -297,10 +311,8 @@ pub fn prebake() {
                 scenario.instantiate(&mut sim, &map, &mut rng, &mut timer);
                 sim.timed_step(&map, Time::END_OF_DAY - Time::START_OF_DAY, &mut timer);
 
-                abstutil::write_binary(
-                    abstutil::path_prebaked_results(&scenario.map_name, &scenario.scenario_name),
-                    sim.get_analytics(),
-                );
+                sim.get_analytics()
+                    .save_prebaked(&scenario.map_name, &scenario.scenario_name);
                 timer.stop(format!(
                     "prebake for {} / {}",
                     scenario.map_name, scenario.scenario_name
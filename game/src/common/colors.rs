@@ -111,7 +111,7 @@ impl ColorerBuilder {
             );
         }
         for (r, color) in self.roads {
-            unzoomed.push(color, map.get_r(r).get_thick_polygon().unwrap());
+            unzoomed.push(color, map.get_r(r).get_thick_polygon(map).unwrap());
         }
 
         for (i, color) in self.intersections {
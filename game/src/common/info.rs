@@ -133,6 +133,7 @@ fn info_for(id: ID, ui: &UI) -> Text {
                 txt.add(Line(format!("Parent is {}", r.id)));
             }
             txt.add(Line(format!("Lane is {} long", l.length())));
+            txt.add(Line(format!("{:.1}% grade", l.grade(map) * 100.0)));
 
             txt.add(Line(""));
             styled_kv(&mut txt, &r.osm_tags);
@@ -292,6 +293,13 @@ fn info_for(id: ID, ui: &UI) -> Text {
                 {
                     txt.add(Line(format!("  Waiting: {}", hgram.describe())));
                 }
+                let denied = sim
+                    .get_analytics()
+                    .bus_boardings_denied(sim.time(), r.id)
+                    .get(id);
+                if denied > 0 {
+                    txt.add(Line(format!("  {} left behind by full buses", denied)));
+                }
             }
         }
         ID::Area(id) => {
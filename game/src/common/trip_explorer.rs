@@ -53,6 +53,15 @@ impl TripExplorer {
                 unzoomed.push(start_color, i.polygon.clone());
                 zoomed.push(start_color, i.polygon.clone());
             }
+            TripStart::ServeBusRoute(br) => {
+                rows.insert(
+                    0,
+                    (
+                        format!("start serving route {}", map.get_br(br).name),
+                        start_color,
+                    ),
+                );
+            }
         };
 
         // Is the trip ongoing?
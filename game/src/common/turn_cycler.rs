@@ -35,14 +35,16 @@ impl TurnCyclerState {
                 }
             }
             Some(ID::Intersection(i)) => {
-                if let Some(ref signal) = ui.primary.map.maybe_get_traffic_signal(i) {
+                if ui.primary.map.maybe_get_traffic_signal(i).is_some() {
                     if ui
                         .per_obj
                         .action(ctx, Key::F, "show full traffic signal diagram")
                     {
                         ui.primary.current_selection = None;
-                        let (idx, _, _) =
-                            signal.current_phase_and_remaining_time(ui.primary.sim.time());
+                        let (idx, _) = ui
+                            .primary
+                            .sim
+                            .current_phase_and_remaining_time(i, &ui.primary.map);
                         return Some(Transition::Push(Box::new(ShowTrafficSignal {
                             menu: ModalMenu::new(
                                 "Traffic Signal Diagram",
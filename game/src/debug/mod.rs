@@ -99,7 +99,7 @@ impl State for DebugMode {
         if self.menu.action("save sim state") {
             ctx.loading_screen("savestate", |_, timer| {
                 timer.start("save sim state");
-                ui.primary.sim.save();
+                ui.primary.sim.save(&ui.primary.map);
                 timer.stop("save sim state");
             });
         }
@@ -354,7 +354,7 @@ fn search_osm(wiz: &mut Wizard, ctx: &mut EventCtx, ui: &mut UI) -> Option<Trans
             for l in r.all_lanes() {
                 ids.insert(ID::Lane(l));
             }
-            batch.push(color, r.get_thick_polygon().unwrap());
+            batch.push(color, r.get_thick_polygon(map).unwrap());
         }
     }
     for b in map.all_buildings() {
@@ -400,10 +400,15 @@ fn load_savestate(wiz: &mut Wizard, ctx: &mut EventCtx, ui: &mut UI) -> Option<T
     // TODO Oh no, we have to do path construction here :(
     let ss_path = format!("{}/{}.bin", ui.primary.sim.save_dir(), ss);
 
-    ctx.loading_screen("load savestate", |ctx, mut timer| {
-        ui.primary.sim = Sim::load_savestate(ss_path, &ui.primary.map, &mut timer)
-            .expect("Can't load savestate");
-        ui.recalculate_current_selection(ctx);
-    });
-    Some(Transition::Pop)
+    ctx.loading_screen(
+        "load savestate",
+        |ctx, mut timer| match Sim::load_savestate(ss_path, &ui.primary.map, &mut timer) {
+            Ok(sim) => {
+                ui.primary.sim = sim;
+                ui.recalculate_current_selection(ctx);
+                Some(Transition::Pop)
+            }
+            Err(err) => Some(Transition::Replace(msg("Can't load savestate", vec![err]))),
+        },
+    )
 }
@@ -1,4 +1,5 @@
 mod lanes;
+mod parking;
 mod stop_signs;
 mod traffic_signals;
 
@@ -117,6 +118,8 @@ impl State for EditMode {
                             EditCmd::ChangeTrafficSignal(ss) => ID::Intersection(ss.id),
                             EditCmd::CloseIntersection { id, .. } => ID::Intersection(*id),
                             EditCmd::UncloseIntersection(id, _) => ID::Intersection(*id),
+                            EditCmd::ChangeParkingPolicy { id, .. } => ID::Lane(*id),
+                            EditCmd::ChangeOffstreetParkingPolicy { b, .. } => ID::Building(*b),
                         };
                         return Transition::PushWithMode(
                             Warping::new(
@@ -235,6 +238,16 @@ impl State for EditMode {
                 apply_map_edits(&mut ui.primary, &ui.cs, ctx, edits);
             }
         }
+        if let Some(id) = ui.primary.current_selection.clone() {
+            let has_parking = match id {
+                ID::Lane(l) => ui.primary.map.get_l(l).is_parking(),
+                ID::Building(b) => ui.primary.map.get_b(b).parking.is_some(),
+                _ => false,
+            };
+            if has_parking && ui.per_obj.action(ctx, Key::M, "edit parking rules") {
+                return Transition::Push(parking::edit_parking_policy(id));
+            }
+        }
 
         if !ui.primary.map.get_edits().commands.is_empty() && self.menu.action("undo") {
             let mut edits = ui.primary.map.get_edits().clone();
@@ -316,7 +329,10 @@ impl State for EditMode {
         } else {
             let color = ui.cs.get_def("unzoomed map diffs", Color::RED);
             for l in edits.original_lts.keys().chain(&edits.reversed_lanes) {
-                g.draw_polygon(color, &ctx.map.get_parent(*l).get_thick_polygon().unwrap());
+                g.draw_polygon(
+                    color,
+                    &ctx.map.get_parent(*l).get_thick_polygon(&ctx.map).unwrap(),
+                );
             }
 
             for i in &edits.changed_intersections {
@@ -390,11 +406,20 @@ fn make_load_edits(mode: GameplayMode) -> Box<dyn State> {
         }
 
         // TODO Exclude current
-        let map_name = ui.primary.map.get_name().to_string();
+        let map = &ui.primary.map;
+        let map_name = map.get_name().to_string();
         let (_, new_edits) = wizard.choose("Load which map edits?", || {
+            let mut timer = Timer::new("load all map edits");
             let mut list = Choice::from(
-                abstutil::load_all_objects(abstutil::path_all_edits(&map_name))
+                abstutil::list_all_objects(abstutil::path_all_edits(&map_name))
                     .into_iter()
+                    .filter_map(|name| match MapEdits::load(map, &name, &mut timer) {
+                        Ok(edits) => Some((name, edits)),
+                        Err(err) => {
+                            timer.warn(format!("Skipping {}: {}", name, err));
+                            None
+                        }
+                    })
                     .filter(|(_, edits)| mode.allows(edits))
                     .collect(),
             );
@@ -0,0 +1,74 @@
+use crate::edit::apply_map_edits;
+use crate::game::{State, Transition, WizardState};
+use crate::helpers::ID;
+use geom::Duration;
+use map_model::{EditCmd, ParkingPolicy};
+
+// Works on parking lanes and buildings with offstreet parking.
+pub fn edit_parking_policy(id: ID) -> Box<dyn State> {
+    WizardState::new(Box::new(move |wiz, ctx, ui| {
+        let mut wizard = wiz.wrap(ctx);
+        let orig_policy = match id {
+            ID::Lane(l) => ui.primary.map.get_l(l).parking_policy.clone(),
+            ID::Building(b) => ui
+                .primary
+                .map
+                .get_b(b)
+                .parking
+                .as_ref()
+                .unwrap()
+                .policy
+                .clone(),
+            _ => unreachable!(),
+        };
+
+        let hourly_price_cents = wizard.input_usize_prefilled(
+            "Hourly price, in cents",
+            orig_policy.hourly_price_cents.to_string(),
+        )?;
+        let max_stay_mins = wizard.input_usize_prefilled(
+            "Maximum stay in minutes (0 for unlimited)",
+            orig_policy
+                .max_stay
+                .map(|d| (d.inner_seconds() / 60.0) as usize)
+                .unwrap_or(0)
+                .to_string(),
+        )?;
+        let permit_zone = wizard.input_something(
+            "Residential permit zone (blank for none)",
+            orig_policy.permit_zone.clone(),
+            Box::new(|line| {
+                let zone = line.trim().to_string();
+                Some(if zone.is_empty() { None } else { Some(zone) })
+            }),
+        )?;
+
+        let policy = ParkingPolicy {
+            hourly_price_cents,
+            max_stay: if max_stay_mins == 0 {
+                None
+            } else {
+                Some(Duration::minutes(max_stay_mins))
+            },
+            permit_zone,
+        };
+        if policy != orig_policy {
+            let mut edits = ui.primary.map.get_edits().clone();
+            edits.commands.push(match id {
+                ID::Lane(l) => EditCmd::ChangeParkingPolicy {
+                    id: l,
+                    policy,
+                    orig_policy,
+                },
+                ID::Building(b) => EditCmd::ChangeOffstreetParkingPolicy {
+                    b,
+                    policy,
+                    orig_policy,
+                },
+                _ => unreachable!(),
+            });
+            apply_map_edits(&mut ui.primary, &ui.cs, ctx, edits);
+        }
+        Some(Transition::Pop)
+    }))
+}
@@ -21,15 +21,13 @@ pub struct StopSignEditor {
 impl StopSignEditor {
     pub fn new(id: IntersectionID, ctx: &EventCtx, ui: &mut UI) -> StopSignEditor {
         ui.primary.current_selection = None;
-        let geom = ui
-            .primary
-            .map
-            .get_stop_sign(id)
+        let sign = ui.primary.map.get_stop_sign(id);
+        let geom = sign
             .roads
             .iter()
             .map(|(r, ss)| {
                 let (octagon, pole) =
-                    DrawIntersection::stop_sign_geom(ss, &ui.primary.map).unwrap();
+                    DrawIntersection::stop_sign_geom(ss, sign.roundabout, &ui.primary.map).unwrap();
                 (*r, (octagon, pole))
             })
             .collect();
@@ -14,7 +14,8 @@ use ezgui::{
 };
 use geom::Duration;
 use map_model::{
-    ControlTrafficSignal, EditCmd, IntersectionID, Phase, TurnGroupID, TurnPriority, TurnType,
+    ActuatedPhase, ControlTrafficSignal, EditCmd, IntersectionID, Phase, TurnGroupID, TurnPriority,
+    TurnType,
 };
 use sim::Sim;
 use std::collections::BTreeSet;
@@ -45,6 +46,7 @@ impl TrafficSignalEditor {
                 (hotkey(Key::UpArrow), "select previous phase"),
                 (hotkey(Key::DownArrow), "select next phase"),
                 (hotkey(Key::D), "change phase duration"),
+                (hotkey(Key::A), "change phase actuation"),
                 (hotkey(Key::K), "move current phase up"),
                 (hotkey(Key::J), "move current phase down"),
                 (hotkey(Key::Backspace), "delete current phase"),
@@ -164,6 +166,10 @@ impl State for TrafficSignalEditor {
             return Transition::Push(change_phase_duration(
                 orig_signal.phases[self.diagram.current_phase()].duration,
             ));
+        } else if self.menu.action("change phase actuation") {
+            return Transition::Push(change_phase_actuation(
+                orig_signal.phases[self.diagram.current_phase()].clone(),
+            ));
         } else if self.menu.action("change signal offset") {
             return Transition::Push(change_offset(orig_signal.offset));
         } else if self.menu.action("choose a preset signal") {
@@ -384,6 +390,63 @@ fn change_phase_duration(current_duration: Duration) -> Box<dyn State> {
     }))
 }
 
+fn change_phase_actuation(current: Phase) -> Box<dyn State> {
+    WizardState::new(Box::new(move |wiz, ctx, _| {
+        let mut wizard = wiz.wrap(ctx);
+        let (_, actuated) = wizard.choose("How should this phase be timed?", || {
+            vec![
+                Choice::new("fixed time", false),
+                Choice::new("actuated by demand", true),
+            ]
+        })?;
+        let new_actuation = if actuated {
+            let prev = current.actuated.clone().unwrap_or_else(|| ActuatedPhase {
+                min_green: Duration::seconds(10.0),
+                max_green: current.duration,
+                extension: Duration::seconds(3.0),
+                skip_if_no_demand: false,
+            });
+            let min_green = wizard.input_usize_prefilled(
+                "Minimum green time (seconds)?",
+                format!("{}", prev.min_green.inner_seconds() as usize),
+            )?;
+            let max_green = wizard.input_usize_prefilled(
+                "Maximum green time (seconds)?",
+                format!("{}", prev.max_green.inner_seconds() as usize),
+            )?;
+            let extension = wizard.input_usize_prefilled(
+                "How long to extend the phase for each arriving vehicle (seconds)?",
+                format!("{}", prev.extension.inner_seconds() as usize),
+            )?;
+            let (_, skip_if_no_demand) = wizard
+                .choose("Skip this phase when nobody is waiting for it?", || {
+                    vec![Choice::new("no", false), Choice::new("yes", true)]
+                })?;
+            // Zero-length phases would make the signal spin forever.
+            let min_green = min_green.max(1);
+            Some(ActuatedPhase {
+                min_green: Duration::seconds(min_green as f64),
+                max_green: Duration::seconds(max_green.max(min_green) as f64),
+                extension: Duration::seconds(extension.max(1) as f64),
+                skip_if_no_demand,
+            })
+        } else {
+            None
+        };
+        Some(Transition::PopWithData(Box::new(move |state, ui, ctx| {
+            let mut editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
+            let mut signal = ui.primary.map.get_traffic_signal(editor.diagram.i).clone();
+            editor
+                .command_stack
+                .push(("change phase actuation".to_string(), signal.clone()));
+            let idx = editor.diagram.current_phase();
+            signal.phases[idx].actuated = new_actuation;
+            change_traffic_signal(signal, ui, ctx);
+            editor.diagram = TrafficSignalDiagram::new(editor.diagram.i, idx, ui, ctx);
+        })))
+    }))
+}
+
 fn change_offset(current_duration: Duration) -> Box<dyn State> {
     WizardState::new(Box::new(move |wiz, ctx, _| {
         let new_duration = wiz.wrap(ctx).input_usize_prefilled(
@@ -500,6 +563,7 @@ fn make_previewer(i: IntersectionID, phase: usize, suspended_sim: Sim) -> Box<dy
             }
             x if x == right_now => {
                 ui.primary.sim = suspended_sim.clone();
+                ui.primary.sim.handle_live_edits(&ui.primary.map);
             }
             _ => unreachable!(),
         };
@@ -47,12 +47,16 @@ impl ScenarioManager {
             match trip {
                 // TODO CarAppearing might be from a border
                 SpawnTrip::CarAppearing { .. } => {}
-                SpawnTrip::MaybeUsingParkedCar(_, b, _) => {
+                SpawnTrip::ParkAndRideFromBorder(_, start, _, _, _) => {
+                    trips_from_border.insert(ui.primary.map.get_l(start.lane()).src_i, idx);
+                }
+                SpawnTrip::MaybeUsingParkedCar(_, b, _) | SpawnTrip::ParkAndRide(_, b, _, _, _) => {
                     trips_from_bldg.insert(*b, idx);
                 }
                 SpawnTrip::UsingBike(_, ref spot, _)
                 | SpawnTrip::JustWalking(_, ref spot, _)
-                | SpawnTrip::UsingTransit(_, ref spot, _, _, _, _) => match spot.connection {
+                | SpawnTrip::UsingTransit(_, ref spot, _, _)
+                | SpawnTrip::BikeAndRide(_, ref spot, _, _, _) => match spot.connection {
                     SidewalkPOI::Building(b) => {
                         trips_from_bldg.insert(b, idx);
                     }
@@ -76,7 +80,10 @@ impl ScenarioManager {
                     }
                 },
                 SpawnTrip::JustWalking(_, _, ref spot)
-                | SpawnTrip::UsingTransit(_, _, ref spot, _, _, _) => match spot.connection {
+                | SpawnTrip::UsingTransit(_, _, ref spot, _)
+                | SpawnTrip::ParkAndRide(_, _, _, ref spot, _)
+                | SpawnTrip::ParkAndRideFromBorder(_, _, _, ref spot, _)
+                | SpawnTrip::BikeAndRide(_, _, _, ref spot, _) => match spot.connection {
                     SidewalkPOI::Building(b) => {
                         trips_to_bldg.insert(b, idx);
                     }
@@ -557,12 +564,56 @@ fn describe(trip: &SpawnTrip, home: OD) -> String {
             sidewalk_spot(start),
             sidewalk_spot(goal)
         ),
-        SpawnTrip::UsingTransit(depart, start, goal, route, _, _) => format!(
+        SpawnTrip::UsingTransit(depart, start, goal, rides) => format!(
             "{}: bus from {} to {} using {}",
             depart,
             sidewalk_spot(start),
             sidewalk_spot(goal),
-            route
+            rides
+                .iter()
+                .map(|(_, _, route)| route.to_string())
+                .collect::<Vec<_>>()
+                .join(", then ")
+        ),
+        SpawnTrip::ParkAndRide(depart, start_bldg, park_near, goal, rides) => format!(
+            "{}: drive from {} to park near {}, then bus to {} using {}",
+            depart,
+            if OD::Bldg(*start_bldg) == home {
+                "HERE".to_string()
+            } else {
+                start_bldg.to_string()
+            },
+            park_near,
+            sidewalk_spot(goal),
+            rides
+                .iter()
+                .map(|(_, _, route)| route.to_string())
+                .collect::<Vec<_>>()
+                .join(", then ")
+        ),
+        SpawnTrip::ParkAndRideFromBorder(depart, start, park_near, goal, rides) => format!(
+            "{}: car appears at {}, parks near {}, then bus to {} using {}",
+            depart,
+            start.lane(),
+            park_near,
+            sidewalk_spot(goal),
+            rides
+                .iter()
+                .map(|(_, _, route)| route.to_string())
+                .collect::<Vec<_>>()
+                .join(", then ")
+        ),
+        SpawnTrip::BikeAndRide(depart, start, park_near, goal, rides) => format!(
+            "{}: bike from {} to {}, then bus to {} using {}",
+            depart,
+            sidewalk_spot(start),
+            park_near,
+            sidewalk_spot(goal),
+            rides
+                .iter()
+                .map(|(_, _, route)| route.to_string())
+                .collect::<Vec<_>>()
+                .join(", then ")
         ),
     }
 }
@@ -588,9 +639,15 @@ fn other_endpt(trip: &SpawnTrip, home: OD, map: &Map) -> ID {
         }
         SpawnTrip::UsingBike(_, start, goal) => (sidewalk_spot(start), driving_goal(goal)),
         SpawnTrip::JustWalking(_, start, goal) => (sidewalk_spot(start), sidewalk_spot(goal)),
-        SpawnTrip::UsingTransit(_, start, goal, _, _, _) => {
-            (sidewalk_spot(start), sidewalk_spot(goal))
+        SpawnTrip::UsingTransit(_, start, goal, _) => (sidewalk_spot(start), sidewalk_spot(goal)),
+        SpawnTrip::ParkAndRide(_, start_bldg, _, goal, _) => {
+            (ID::Building(*start_bldg), sidewalk_spot(goal))
         }
+        SpawnTrip::ParkAndRideFromBorder(_, start, _, goal, _) => (
+            ID::Intersection(map.get_l(start.lane()).src_i),
+            sidewalk_spot(goal),
+        ),
+        SpawnTrip::BikeAndRide(_, start, _, goal, _) => (sidewalk_spot(start), sidewalk_spot(goal)),
     };
     let home_id = match home {
         OD::Bldg(b) => ID::Building(b),
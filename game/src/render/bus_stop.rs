@@ -2,7 +2,7 @@ use crate::helpers::{ColorScheme, ID};
 use crate::render::{DrawCtx, DrawOptions, Renderable, OUTLINE_THICKNESS};
 use ezgui::{Color, Drawable, GfxCtx, Prerender};
 use geom::{Distance, PolyLine, Polygon, Pt2D};
-use map_model::{BusStop, BusStopID, Map, LANE_THICKNESS};
+use map_model::{BusStop, BusStopID, DrivingSide, Map, LANE_THICKNESS};
 
 pub struct DrawBusStop {
     pub id: BusStopID,
@@ -19,14 +19,15 @@ impl DrawBusStop {
         // Kinda sad that bus stops might be very close to the start of the lane, but it's
         // happening.
         let lane = map.get_l(stop.id.sidewalk);
-        let main_pl = lane
-            .lane_center_pts
-            .exact_slice(
-                Distance::ZERO.max(stop.sidewalk_pos.dist_along() - radius),
-                lane.length().min(stop.sidewalk_pos.dist_along() + radius),
-            )
-            .shift_right(LANE_THICKNESS * 0.3)
-            .unwrap();
+        let slice = lane.lane_center_pts.exact_slice(
+            Distance::ZERO.max(stop.sidewalk_pos.dist_along() - radius),
+            lane.length().min(stop.sidewalk_pos.dist_along() + radius),
+        );
+        let main_pl = match map.get_driving_side() {
+            DrivingSide::Right => slice.shift_right(LANE_THICKNESS * 0.3),
+            DrivingSide::Left => slice.shift_left(LANE_THICKNESS * 0.3),
+        }
+        .unwrap();
         let polyline = PolyLine::new(vec![
             main_pl.first_pt().project_away(
                 LANE_THICKNESS * 0.5,
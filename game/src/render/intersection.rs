@@ -7,8 +7,8 @@ use abstutil::Timer;
 use ezgui::{Color, Drawable, GeomBatch, GfxCtx, Line, Prerender, Text};
 use geom::{Angle, Distance, Line, PolyLine, Polygon, Pt2D, Time, EPSILON_DIST};
 use map_model::{
-    Intersection, IntersectionID, IntersectionType, Map, Road, RoadWithStopSign, Turn, TurnType,
-    LANE_THICKNESS,
+    DrivingSide, Intersection, IntersectionID, IntersectionType, Map, Road, RoadWithStopSign, Turn,
+    TurnType, LANE_THICKNESS,
 };
 use std::cell::RefCell;
 
@@ -57,15 +57,27 @@ impl DrawIntersection {
                 let r = map.get_r(*i.roads.iter().next().unwrap());
                 default_geom.extend(
                     cs.get_def("incoming border node arrow", Color::PURPLE),
-                    calculate_border_arrows(i, r, timer),
+                    calculate_border_arrows(i, r, map.get_driving_side(), timer),
                 );
             }
             IntersectionType::StopSign => {
-                for ss in map.get_stop_sign(i.id).roads.values() {
+                let sign = map.get_stop_sign(i.id);
+                for ss in sign.roads.values() {
                     if ss.must_stop {
-                        if let Some((octagon, pole)) = DrawIntersection::stop_sign_geom(ss, map) {
-                            default_geom
-                                .push(cs.get_def("stop sign on side of road", Color::RED), octagon);
+                        if let Some((octagon, pole)) =
+                            DrawIntersection::stop_sign_geom(ss, sign.roundabout, map)
+                        {
+                            if sign.roundabout {
+                                default_geom.push(
+                                    cs.get_def("yield sign on side of road", Color::WHITE),
+                                    octagon,
+                                );
+                            } else {
+                                default_geom.push(
+                                    cs.get_def("stop sign on side of road", Color::RED),
+                                    octagon,
+                                );
+                            }
                             default_geom.push(cs.get_def("stop sign pole", Color::grey(0.5)), pole);
                         }
                     }
@@ -86,8 +98,13 @@ impl DrawIntersection {
         }
     }
 
-    // Returns the (octagon, pole) if there's room to draw it.
-    pub fn stop_sign_geom(ss: &RoadWithStopSign, map: &Map) -> Option<(Polygon, Polygon)> {
+    // Returns the (octagon, pole) if there's room to draw it. Roundabout entrances get a yield
+    // triangle instead of the octagon.
+    pub fn stop_sign_geom(
+        ss: &RoadWithStopSign,
+        yield_sign: bool,
+        map: &Map,
+    ) -> Option<(Polygon, Polygon)> {
         let trim_back = Distance::meters(0.1);
         let rightmost = &map.get_l(ss.rightmost_lane).lane_center_pts;
         // TODO The dream of trimming f64's was to isolate epsilon checks like this...
@@ -97,10 +114,18 @@ impl DrawIntersection {
         }
         let last_line = rightmost
             .exact_slice(Distance::ZERO, rightmost.length() - trim_back)
-            .last_line()
-            .shift_right(1.0 * LANE_THICKNESS);
+            .last_line();
+        // On the curb
+        let last_line = match map.get_driving_side() {
+            DrivingSide::Right => last_line.shift_right(1.0 * LANE_THICKNESS),
+            DrivingSide::Left => last_line.shift_left(1.0 * LANE_THICKNESS),
+        };
 
-        let octagon = make_octagon(last_line.pt2(), Distance::meters(1.0), last_line.angle());
+        let octagon = if yield_sign {
+            make_triangle(last_line.pt2(), Distance::meters(1.0), last_line.angle())
+        } else {
+            make_octagon(last_line.pt2(), Distance::meters(1.0), last_line.angle())
+        };
         let pole = Line::new(
             last_line
                 .pt2()
@@ -137,7 +162,8 @@ impl Renderable for DrawIntersection {
                     .map(|(t, _, _, _)| *t != ctx.sim.time())
                     .unwrap_or(true);
                 if recalc {
-                    let (idx, phase, t) = signal.current_phase_and_remaining_time(ctx.sim.time());
+                    let (idx, t) = ctx.sim.current_phase_and_remaining_time(self.id, ctx.map);
+                    let phase = &signal.phases[idx];
                     let mut batch = GeomBatch::new();
                     draw_signal_phase(phase, self.id, Some(t), &mut batch, ctx);
                     *maybe_redraw = Some((
@@ -174,8 +200,10 @@ pub fn calculate_corners(i: &Intersection, map: &Map, timer: &mut Timer) -> Vec<
 
     for turn in &map.get_turns_in_intersection(i.id) {
         if turn.turn_type == TurnType::SharedSidewalkCorner {
-            // Avoid double-rendering
-            if map.get_l(turn.id.src).dst_i != i.id {
+            // Avoid double-rendering. Driving on the left, the corner curves the other way, so use
+            // the turn going the other direction.
+            let right = map.get_driving_side() == DrivingSide::Right;
+            if (map.get_l(turn.id.src).dst_i == i.id) != right {
                 continue;
             }
 
@@ -185,16 +213,26 @@ pub fn calculate_corners(i: &Intersection, map: &Map, timer: &mut Timer) -> Vec<
                 continue;
             }
 
-            let l1 = map.get_l(turn.id.src);
-            let l2 = map.get_l(turn.id.dst);
+            // Orient both sidewalks so the corner is on the right
+            let (pl1, pl2) = if right {
+                (
+                    map.get_l(turn.id.src).lane_center_pts.clone(),
+                    map.get_l(turn.id.dst).lane_center_pts.clone(),
+                )
+            } else {
+                (
+                    map.get_l(turn.id.src).lane_center_pts.reversed(),
+                    map.get_l(turn.id.dst).lane_center_pts.reversed(),
+                )
+            };
 
-            let src_line = l1.last_line().shift_left(LANE_THICKNESS / 2.0);
-            let dst_line = l2.first_line().shift_left(LANE_THICKNESS / 2.0);
+            let src_line = pl1.last_line().shift_left(LANE_THICKNESS / 2.0);
+            let dst_line = pl2.first_line().shift_left(LANE_THICKNESS / 2.0);
 
             let pt_maybe_in_intersection = src_line.infinite().intersection(&dst_line.infinite());
             // Now find all of the points on the intersection polygon between the two sidewalks.
-            let corner1 = l1.last_line().shift_right(LANE_THICKNESS / 2.0).pt2();
-            let corner2 = l2.first_line().shift_right(LANE_THICKNESS / 2.0).pt1();
+            let corner1 = pl1.last_line().shift_right(LANE_THICKNESS / 2.0).pt2();
+            let corner2 = pl2.first_line().shift_right(LANE_THICKNESS / 2.0).pt1();
             // Intersection polygons are constructed in clockwise order, so do corner2 to corner1.
             // TODO This threshold is higher than the 0.1 intersection polygons use to dedupe
             // because of jagged lane teeth from bad polyline shifting. Seemingly.
@@ -227,8 +265,22 @@ pub fn calculate_corners(i: &Intersection, map: &Map, timer: &mut Timer) -> Vec<
     corners
 }
 
-fn calculate_border_arrows(i: &Intersection, r: &Road, timer: &mut Timer) -> Vec<Polygon> {
+fn calculate_border_arrows(
+    i: &Intersection,
+    r: &Road,
+    driving_side: DrivingSide,
+    timer: &mut Timer,
+) -> Vec<Polygon> {
     let mut result = Vec::new();
+    // The sides below are for driving on the right
+    let shift_left = |line: Line, width: Distance| match driving_side {
+        DrivingSide::Right => line.shift_left(width),
+        DrivingSide::Left => line.shift_right(width),
+    };
+    let shift_right = |line: Line, width: Distance| match driving_side {
+        DrivingSide::Right => line.shift_right(width),
+        DrivingSide::Left => line.shift_left(width),
+    };
 
     // These arrows should point from the void to the road
     if !i.outgoing_lanes.is_empty() {
@@ -236,12 +288,12 @@ fn calculate_border_arrows(i: &Intersection, r: &Road, timer: &mut Timer) -> Vec
         let (line, width) = if r.dst_i == i.id {
             let width = (r.children_forwards.len() as f64) * LANE_THICKNESS;
             (
-                r.center_pts.last_line().shift_left(width / 2.0).reverse(),
+                shift_left(r.center_pts.last_line(), width / 2.0).reverse(),
                 width,
             )
         } else {
             let width = (r.children_forwards.len() as f64) * LANE_THICKNESS;
-            (r.center_pts.first_line().shift_right(width / 2.0), width)
+            (shift_right(r.center_pts.first_line(), width / 2.0), width)
         };
         result.push(
             // DEGENERATE_INTERSECTION_HALF_LENGTH is 5m...
@@ -260,12 +312,12 @@ fn calculate_border_arrows(i: &Intersection, r: &Road, timer: &mut Timer) -> Vec
         let (line, width) = if r.dst_i == i.id {
             let width = (r.children_forwards.len() as f64) * LANE_THICKNESS;
             (
-                r.center_pts.last_line().shift_right(width / 2.0).reverse(),
+                shift_right(r.center_pts.last_line(), width / 2.0).reverse(),
                 width,
             )
         } else {
             let width = (r.children_backwards.len() as f64) * LANE_THICKNESS;
-            (r.center_pts.first_line().shift_left(width / 2.0), width)
+            (shift_left(r.center_pts.first_line(), width / 2.0), width)
         };
         result.push(
             PolyLine::new(vec![
@@ -288,6 +340,15 @@ fn make_octagon(center: Pt2D, radius: Distance, facing: Angle) -> Polygon {
     )
 }
 
+// One corner points into the intersection
+fn make_triangle(center: Pt2D, radius: Distance, facing: Angle) -> Polygon {
+    Polygon::new(
+        &(0..3)
+            .map(|i| center.project_away(radius, facing.rotate_degs(f64::from(i * 120))))
+            .collect(),
+    )
+}
+
 fn make_crosswalk(batch: &mut GeomBatch, turn: &Turn, cs: &ColorScheme) {
     // Start at least LANE_THICKNESS out to not hit sidewalk corners. Also account for the
     // thickness of the crosswalk line itself. Center the lines inside these two boundaries.
@@ -3,7 +3,9 @@ use crate::render::{dashed_lines, DrawCtx, DrawOptions, Renderable, OUTLINE_THIC
 use abstutil::Timer;
 use ezgui::{Color, Drawable, GeomBatch, GfxCtx, Prerender};
 use geom::{Distance, Line, PolyLine, Polygon, Pt2D};
-use map_model::{Lane, LaneID, LaneType, Map, Road, TurnType, LANE_THICKNESS, PARKING_SPOT_LENGTH};
+use map_model::{
+    DrivingSide, Lane, LaneID, LaneType, Map, Road, TurnType, LANE_THICKNESS, PARKING_SPOT_LENGTH,
+};
 
 // Split into two phases like this, because AlmostDrawLane can be created in parallel, but GPU
 // upload has to be serial.
@@ -76,7 +78,7 @@ impl DrawLane {
                 LaneType::Driving | LaneType::Bus => {
                     draw.extend(
                         cs.get("general road marking"),
-                        calculate_driving_lines(lane, road, timer),
+                        calculate_driving_lines(lane, road, map.get_driving_side(), timer),
                     );
                     draw.extend(
                         cs.get("general road marking"),
@@ -203,16 +205,22 @@ fn calculate_parking_lines(lane: &Lane) -> Vec<Polygon> {
     result
 }
 
-fn calculate_driving_lines(lane: &Lane, parent: &Road, timer: &mut Timer) -> Vec<Polygon> {
-    // The leftmost lanes don't have dashed lines.
+fn calculate_driving_lines(
+    lane: &Lane,
+    parent: &Road,
+    driving_side: DrivingSide,
+    timer: &mut Timer,
+) -> Vec<Polygon> {
+    // The innermost lanes don't have dashed lines.
     let (dir, idx) = parent.dir_and_offset(lane.id);
     if idx == 0 || (dir && parent.children_forwards[idx - 1].1 == LaneType::SharedLeftTurn) {
         return Vec::new();
     }
-    let lane_edge_pts = lane
-        .lane_center_pts
-        .shift_left(LANE_THICKNESS / 2.0)
-        .get(timer);
+    let lane_edge_pts = match driving_side {
+        DrivingSide::Right => lane.lane_center_pts.shift_left(LANE_THICKNESS / 2.0),
+        DrivingSide::Left => lane.lane_center_pts.shift_right(LANE_THICKNESS / 2.0),
+    }
+    .get(timer);
     dashed_lines(
         &lane_edge_pts,
         Distance::meters(0.25),
@@ -64,7 +64,7 @@ impl DrawMap {
         for r in road_refs {
             all_roads.push(
                 osm_rank_to_color(cs, r.get_rank()),
-                r.get_thick_polygon().get(timer),
+                r.get_thick_polygon(map).get(timer),
             );
             if false {
                 all_roads.push(
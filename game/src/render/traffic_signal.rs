@@ -123,7 +123,7 @@ pub fn draw_signal_phase(
 
     let radius = Distance::meters(2.0);
     let center = ctx.map.get_i(i).polygon.center();
-    let percent = time_left.unwrap() / phase.duration;
+    let percent = time_left.unwrap() / phase.max_duration();
     // TODO Tune colors.
     batch.push(
         ctx.cs.get_def("traffic signal box", Color::grey(0.5)),
@@ -250,7 +250,13 @@ fn make_diagram(i: IntersectionID, selected: usize, ui: &UI, ctx: &mut EventCtx)
         col.push(
             ManagedWidget::row(vec![
                 ManagedWidget::draw_text(ctx, Text::from(Line(format!("#{}", idx + 1)))),
-                ManagedWidget::draw_text(ctx, Text::from(Line(phase.duration.to_string()))),
+                ManagedWidget::draw_text(
+                    ctx,
+                    Text::from(Line(match phase.actuated {
+                        Some(ref a) => format!("actuated, {} to {}", a.min_green, a.max_green),
+                        None => phase.duration.to_string(),
+                    })),
+                ),
             ])
             .margin(5)
             .evenly_spaced(),
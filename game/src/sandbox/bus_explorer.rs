@@ -27,20 +27,14 @@ impl ShowBusRoute {
         }
 
         let mut txt = Text::from(Line(&route.name));
+        if !route.long_name.is_empty() {
+            txt.add(Line(&route.long_name));
+        }
         txt.add(Line(format!("{} buses", bus_locations.len())));
         let mut colorer = ColorerBuilder::new(txt, vec![("route", Color::RED)]);
-        for (stop1, stop2) in
-            route
-                .stops
-                .iter()
-                .zip(route.stops.iter().skip(1))
-                .chain(std::iter::once((
-                    route.stops.last().unwrap(),
-                    &route.stops[0],
-                )))
-        {
-            let bs1 = map.get_bs(*stop1);
-            let bs2 = map.get_bs(*stop2);
+        for (stop1, stop2) in route.all_segments() {
+            let bs1 = map.get_bs(stop1);
+            let bs2 = map.get_bs(stop2);
             for step in map
                 .pathfind(PathRequest {
                     start: bs1.driving_pos,
@@ -119,7 +119,9 @@ impl GameplayMode {
                 }
                 EditCmd::ChangeTrafficSignal(_)
                 | EditCmd::CloseIntersection { .. }
-                | EditCmd::UncloseIntersection(_, _) => {}
+                | EditCmd::UncloseIntersection(_, _)
+                | EditCmd::ChangeParkingPolicy { .. }
+                | EditCmd::ChangeOffstreetParkingPolicy { .. } => {}
             }
         }
         true
@@ -163,17 +165,14 @@ impl GameplayRunner {
                     timer,
                 );
                 ui.primary.sim.step(&ui.primary.map, Duration::seconds(0.1));
-                abstutil::maybe_read_binary::<Analytics>(
-                    abstutil::path_prebaked_results(&scenario.map_name, &scenario.scenario_name),
-                    timer,
-                )
-                .unwrap_or_else(|_| {
-                    println!(
-                        "WARNING! No prebaked sim analytics for {} on {}",
-                        scenario.scenario_name, scenario.map_name
-                    );
-                    Analytics::new()
-                })
+                Analytics::load_prebaked(&scenario.map_name, &scenario.scenario_name, timer)
+                    .unwrap_or_else(|err| {
+                        println!(
+                            "WARNING! No prebaked sim analytics for {} on {}: {}",
+                            scenario.scenario_name, scenario.map_name, err
+                        );
+                        Analytics::new()
+                    })
             } else {
                 Analytics::new()
             }
@@ -26,7 +26,8 @@ impl OptimizeBus {
         ctx: &mut EventCtx,
         ui: &UI,
     ) -> (ModalMenu, crate::managed::Composite, Box<dyn GameplayState>) {
-        let route = ui.primary.map.get_bus_route(&route_name).unwrap();
+        // TODO Only optimizes one direction of the route.
+        let route = ui.primary.map.get_bus_routes(&route_name)[0];
         (
             ModalMenu::new(
                 format!("Optimize {}", route_name),
@@ -164,12 +165,8 @@ fn bus_route_panel(id: BusRouteID, stat: Statistic, ui: &UI) -> Text {
     let route = ui.primary.map.get_br(id);
     let mut txt = Text::new();
     txt.add(Line(format!("{} delay between stops", stat)));
-    for idx1 in 0..route.stops.len() {
-        let idx2 = if idx1 == route.stops.len() - 1 {
-            0
-        } else {
-            idx1 + 1
-        };
+    for idx1 in 0..route.all_segments().len() {
+        let idx2 = (idx1 + 1) % route.stops.len();
         // TODO Also display number of arrivals...
         txt.add(Line(format!("Stop {}->{}: ", idx1 + 1, idx2 + 1)));
         if let Some(ref stats1) = now.get(&route.stops[idx2]) {
@@ -291,12 +288,8 @@ fn bus_delays(id: BusRouteID, ui: &UI, ctx: &mut EventCtx) -> Composite {
         .bus_arrivals_over_time(ui.primary.sim.time(), id);
 
     let mut series = Vec::new();
-    for idx1 in 0..route.stops.len() {
-        let idx2 = if idx1 == route.stops.len() - 1 {
-            0
-        } else {
-            idx1 + 1
-        };
+    for idx1 in 0..route.all_segments().len() {
+        let idx2 = (idx1 + 1) % route.stops.len();
         series.push(Series {
             label: format!("Stop {}->{}", idx1 + 1, idx2 + 1),
             color: rotating_color_total(idx1, route.stops.len()),
@@ -449,7 +449,10 @@ pub struct PerMapUI {
 impl PerMapUI {
     pub fn new(flags: Flags, cs: &ColorScheme, ctx: &mut EventCtx, timer: &mut Timer) -> PerMapUI {
         let mut mem = MeasureMemory::new();
-        let (map, sim, _) = flags.sim_flags.load(timer);
+        let (map, sim, _) = match flags.sim_flags.load(timer) {
+            Ok(loaded) => loaded,
+            Err(err) => panic!("{}", err),
+        };
         mem.reset("Map and Sim", timer);
 
         timer.start("draw_map");
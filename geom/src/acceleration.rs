@@ -0,0 +1,66 @@
+use crate::{trim_f64, Duration, Speed};
+use serde_derive::{Deserialize, Serialize};
+use std::{f64, fmt, ops};
+
+// In meters per second squared. Can be negative.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Acceleration(f64);
+
+impl Acceleration {
+    pub const ZERO: Acceleration = Acceleration::const_meters_per_second_squared(0.0);
+
+    pub fn meters_per_second_squared(value: f64) -> Acceleration {
+        if !value.is_finite() {
+            panic!("Bad Acceleration {}", value);
+        }
+
+        Acceleration(trim_f64(value))
+    }
+
+    pub const fn const_meters_per_second_squared(value: f64) -> Acceleration {
+        Acceleration(value)
+    }
+
+    // TODO Remove if possible.
+    pub fn inner_meters_per_second_squared(self) -> f64 {
+        self.0
+    }
+
+    pub fn max(self, other: Acceleration) -> Acceleration {
+        if self >= other {
+            self
+        } else {
+            other
+        }
+    }
+
+    pub fn min(self, other: Acceleration) -> Acceleration {
+        if self <= other {
+            self
+        } else {
+            other
+        }
+    }
+}
+
+impl ops::Mul<f64> for Acceleration {
+    type Output = Acceleration;
+
+    fn mul(self, scalar: f64) -> Acceleration {
+        Acceleration::meters_per_second_squared(self.0 * scalar)
+    }
+}
+
+impl ops::Mul<Duration> for Acceleration {
+    type Output = Speed;
+
+    fn mul(self, other: Duration) -> Speed {
+        Speed::meters_per_second(self.0 * other.inner_seconds())
+    }
+}
+
+impl fmt::Display for Acceleration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}m/s^2", self.0)
+    }
+}
@@ -3,7 +3,7 @@ use serde_derive::{Deserialize, Serialize};
 use std::{cmp, f64, fmt, ops};
 
 // In meters. Can be negative.
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct Distance(f64);
 
 // By construction, Distance is a finite f64 with trimmed precision.
@@ -0,0 +1,64 @@
+use abstutil::{CmdArgs, Timer};
+use geom::{Duration, Speed};
+use map_model::{EditCmd, IntersectionID, Map, MapEdits};
+
+fn main() {
+    let mut args = CmdArgs::new();
+    let load = args.required_free();
+    // Comma-separated intersection IDs along the corridor, in the direction of travel
+    let corridor: Vec<IntersectionID> = args
+        .required("--intersections")
+        .split(',')
+        .map(|i| IntersectionID(i.parse::<usize>().unwrap()))
+        .collect();
+    let speed = Speed::miles_per_hour(
+        args.optional_parse("--speed_mph", |s| s.parse::<f64>())
+            .unwrap_or(25.0),
+    );
+    let cycle_length = args.optional_parse("--cycle_length", Duration::parse);
+    // Build on top of these existing edits
+    let base_edits = args.optional("--base_edits");
+    let edits_name = args.required("--edits_name");
+    args.done();
+
+    let mut timer = Timer::new(format!("green wave for {}", load));
+    let mut map = Map::new(load, false, &mut timer);
+    if let Some(name) = base_edits {
+        let edits = match MapEdits::load(&map, &name, &mut timer) {
+            Ok(edits) => edits,
+            Err(err) => panic!("Can't start from {}: {}", name, err),
+        };
+        map.apply_edits(edits, &mut timer);
+    }
+
+    let signals = match map_model::coordination::green_wave(&map, &corridor, speed, cycle_length) {
+        Ok(signals) => signals,
+        Err(err) => panic!("Can't coordinate {:?}: {}", corridor, err),
+    };
+    let mut edits = map.get_edits().clone();
+    for signal in signals {
+        println!(
+            "{}: offset {}, cycle length {}",
+            signal.id,
+            signal.offset,
+            signal.cycle_length()
+        );
+        edits.commands.push(EditCmd::ChangeTrafficSignal(signal));
+    }
+    edits.edits_name = edits_name.clone();
+    edits.dirty = true;
+    map.apply_edits(edits, &mut timer);
+    map.save_edits();
+    timer.done();
+
+    println!(
+        "Wrote {}. Compare intersection delays with headless --edits={} --report_delays={}",
+        abstutil::path_edits(map.get_name(), &edits_name),
+        edits_name,
+        corridor
+            .iter()
+            .map(|i| i.0.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+}
@@ -1,24 +1,40 @@
-use geom::LonLat;
+use geom::{Duration, LonLat, Time};
 use itertools::Itertools;
 use serde_derive::{Deserialize, Serialize};
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
 use transitfeed::GTFSIterator;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct Route {
+    // One Route per GTFS route and direction, so the name is shared by both directions.
     pub name: String,
+    pub long_name: String,
+    // Like "Bus" or "LightRail"
+    pub route_type: String,
+    // Hex, like "FF0000"
+    pub color: Option<String>,
+    pub agency: Option<String>,
     pub stops: Vec<LonLat>,
+    // Every trip along this route running on the service day chosen at import, sorted by the
+    // first departure. Each trip is a list of (index into stops, departure time), in order. A trip
+    // might skip some stops.
+    pub trips: Vec<Vec<(usize, Time)>>,
 }
 
-pub fn load(dir_path: &str) -> Vec<Route> {
+// service_day is something like "monday". If it's None or the feed doesn't have a calendar, every
+// trip is kept, regardless of what days it runs.
+pub fn load(dir_path: &str, service_day: Option<&str>) -> Vec<Route> {
     println!("Loading GTFS from {}", dir_path);
 
-    let mut route_id_to_name: HashMap<String, String> = HashMap::new();
+    let agencies = load_agencies(dir_path);
+
+    let mut route_metadata: HashMap<String, transitfeed::Route> = HashMap::new();
     for rec in GTFSIterator::<_, transitfeed::Route>::from_path(&format!("{}/routes.txt", dir_path))
         .unwrap()
     {
         let rec = rec.unwrap();
-        route_id_to_name.insert(rec.route_id.clone(), rec.route_short_name.clone());
+        route_metadata.insert(rec.route_id.clone(), rec);
     }
 
     let mut stop_id_to_pt: HashMap<String, LonLat> = HashMap::new();
@@ -29,7 +45,10 @@ pub fn load(dir_path: &str) -> Vec<Route> {
         stop_id_to_pt.insert(rec.stop_id.clone(), LonLat::new(rec.stop_lon, rec.stop_lat));
     }
 
+    let active_services = service_day.and_then(|day| load_active_services(dir_path, day));
+
     let mut trip_id_to_route_id_and_direction: HashMap<String, (String, bool)> = HashMap::new();
+    let mut inactive_trips: HashSet<String> = HashSet::new();
     for rec in
         GTFSIterator::<_, transitfeed::Trip>::from_path(&format!("{}/trips.txt", dir_path)).unwrap()
     {
@@ -41,12 +60,21 @@ pub fn load(dir_path: &str) -> Vec<Route> {
                 rec.direction_id.map(|d| d == "0").unwrap_or(true),
             ),
         );
+        if let Some(ref services) = active_services {
+            if !services.contains(&rec.service_id) {
+                inactive_trips.insert(rec.trip_id.clone());
+            }
+        }
     }
 
-    // Each (directed) route has many trips. Ignore all but the first and assume the list of stops
-    // is the same. Also assume that records with the same trip are contiguous and that
-    // stop_sequence is monotonic.
-    let mut directed_routes: HashMap<(String, bool), Vec<LonLat>> = HashMap::new();
+    let frequencies = load_frequencies(dir_path);
+
+    // Each (directed) route has many trips. Use the first one running on the service day to define
+    // the list of stops, falling back to the first inactive trip if none run. Also assume that
+    // records with the same trip are contiguous and that stop_sequence is monotonic.
+    // (directed route => (stop IDs, from an active trip))
+    let mut directed_routes: HashMap<(String, bool), (Vec<String>, bool)> = HashMap::new();
+    let mut directed_trips: HashMap<(String, bool), Vec<Vec<(usize, Time)>>> = HashMap::new();
     for (key, group) in
         GTFSIterator::<_, transitfeed::StopTime>::from_path(&format!("{}/stop_times.txt", dir_path))
             .unwrap()
@@ -55,35 +83,164 @@ pub fn load(dir_path: &str) -> Vec<Route> {
             .into_iter()
     {
         let (route_id, forwards) = trip_id_to_route_id_and_direction[&key].clone();
-        if directed_routes.contains_key(&(route_id.clone(), forwards)) {
+        let stop_times: Vec<(String, Time)> = group
+            .map(|rec| (rec.stop_id.clone(), to_time(&rec.departure_time)))
+            .collect();
+        let active = !inactive_trips.contains(&key);
+        let (pattern, from_active) = directed_routes
+            .entry((route_id.clone(), forwards))
+            .or_insert_with(|| (Vec::new(), false));
+        // Nothing has been matched against an inactive pattern, so it's fine to replace it.
+        if pattern.is_empty() || (active && !*from_active) {
+            *pattern = stop_times.iter().map(|(id, _)| id.clone()).collect();
+            *from_active = active;
+        }
+        if !active {
             continue;
         }
-        directed_routes.insert(
-            (route_id, forwards),
-            group.map(|rec| stop_id_to_pt[&rec.stop_id]).collect(),
-        );
+
+        // Match up the stops with the first active trip's. Stops that trip doesn't visit are
+        // dropped.
+        let mut trip = Vec::new();
+        let mut next_idx = 0;
+        for (stop_id, time) in stop_times {
+            if let Some(offset) = pattern[next_idx..].iter().position(|id| *id == stop_id) {
+                trip.push((next_idx + offset, time));
+                next_idx += offset + 1;
+            }
+        }
+        if trip.len() < 2 {
+            continue;
+        }
+
+        let trips = directed_trips
+            .entry((route_id, forwards))
+            .or_insert_with(Vec::new);
+        if let Some(list) = frequencies.get(&key) {
+            // The stop_times are just a template; shift them to every departure.
+            let first_departure = trip[0].1;
+            for (start, end, headway) in list {
+                let mut departure = *start;
+                while departure < *end {
+                    trips.push(
+                        trip.iter()
+                            .map(|(idx, t)| (*idx, departure + (*t - first_departure)))
+                            .collect(),
+                    );
+                    departure = departure + *headway;
+                }
+            }
+        } else {
+            trips.push(trip);
+        }
     }
 
-    // Group together the pairs of directed routes
-    let route_ids: BTreeSet<String> = directed_routes
-        .keys()
-        .map(|(id, _)| id.to_string())
-        .collect();
+    // Deterministic order, with both directions of a route next to each other
+    let keys: BTreeSet<(String, bool)> = directed_routes.keys().cloned().collect();
     let mut results = Vec::new();
-    for route_id in route_ids {
-        let mut stops = directed_routes
-            .remove(&(route_id.clone(), true))
-            .unwrap_or_else(Vec::new);
-        if let Some(more_stops) = directed_routes.remove(&(route_id.clone(), false)) {
-            stops.extend(more_stops);
-        }
+    for key in keys {
+        let stops: Vec<LonLat> = directed_routes
+            .remove(&key)
+            .unwrap()
+            .0
+            .into_iter()
+            .map(|id| stop_id_to_pt[&id])
+            .collect();
         assert!(!stops.is_empty());
+        let mut trips = directed_trips.remove(&key).unwrap_or_else(Vec::new);
+        trips.sort_by_key(|trip| trip[0].1);
+
+        let rec = &route_metadata[&key.0];
+        let agency = match rec.agency_id {
+            Some(ref id) => agencies.get(id).cloned(),
+            // The agency is only optional when the feed has just one.
+            None if agencies.len() == 1 => agencies.values().next().cloned(),
+            None => None,
+        };
         results.push(Route {
-            name: route_id_to_name[&route_id].to_string(),
+            name: rec.route_short_name.clone(),
+            long_name: rec.route_long_name.clone(),
+            route_type: format!("{:?}", rec.route_type),
+            color: rec.route_color.clone().filter(|c| !c.is_empty()),
+            agency,
             stops,
+            trips,
         });
     }
-    assert!(directed_routes.is_empty());
 
     results
 }
+
+// agency ID => name. Feeds with a single agency might not fill out the ID; that one is keyed by
+// the empty string.
+fn load_agencies(dir_path: &str) -> HashMap<String, String> {
+    let mut results = HashMap::new();
+    let path = format!("{}/agency.txt", dir_path);
+    if !Path::new(&path).exists() {
+        return results;
+    }
+    for rec in GTFSIterator::<_, transitfeed::Agency>::from_path(&path).unwrap() {
+        let rec = rec.unwrap();
+        results.insert(
+            rec.agency_id.clone().unwrap_or_else(String::new),
+            rec.agency_name.clone(),
+        );
+    }
+    results
+}
+
+// None if the feed has no calendar.
+fn load_active_services(dir_path: &str, day: &str) -> Option<HashSet<String>> {
+    let path = format!("{}/calendar.txt", dir_path);
+    if !Path::new(&path).exists() {
+        println!("No {}, so using trips from every day", path);
+        return None;
+    }
+    let mut services = HashSet::new();
+    for rec in GTFSIterator::<_, transitfeed::Calendar>::from_path(&path).unwrap() {
+        let rec = rec.unwrap();
+        let active = match day {
+            "monday" => rec.monday,
+            "tuesday" => rec.tuesday,
+            "wednesday" => rec.wednesday,
+            "thursday" => rec.thursday,
+            "friday" => rec.friday,
+            "saturday" => rec.saturday,
+            "sunday" => rec.sunday,
+            _ => panic!("Unknown GTFS service day {}", day),
+        };
+        if active {
+            services.insert(rec.service_id.clone());
+        }
+    }
+    Some(services)
+}
+
+// trip ID => (start, end, headway)
+fn load_frequencies(dir_path: &str) -> HashMap<String, Vec<(Time, Time, Duration)>> {
+    let mut results: HashMap<String, Vec<(Time, Time, Duration)>> = HashMap::new();
+    let path = format!("{}/frequencies.txt", dir_path);
+    if !Path::new(&path).exists() {
+        return results;
+    }
+    for rec in GTFSIterator::<_, transitfeed::Frequency>::from_path(&path).unwrap() {
+        let rec = rec.unwrap();
+        if rec.headway_secs == 0 {
+            continue;
+        }
+        results
+            .entry(rec.trip_id.clone())
+            .or_insert_with(Vec::new)
+            .push((
+                to_time(&rec.start_time),
+                to_time(&rec.end_time),
+                Duration::seconds(rec.headway_secs as f64),
+            ));
+    }
+    results
+}
+
+// GTFS times can go past midnight, for trips that started the previous service day.
+fn to_time(t: &transitfeed::TimeOffset) -> Time {
+    Time::START_OF_DAY + Duration::seconds((3600 * t.hours + 60 * t.minutes + t.seconds) as f64)
+}
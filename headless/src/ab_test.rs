@@ -0,0 +1,199 @@
+use crate::run_until_done_or_limit;
+use abstutil::{Counter, Timer};
+use geom::{Duration, DurationHistogram, Time};
+use map_model::{Map, MapEdits};
+use sim::{ABTest, Analytics, Scenario, Sim, SimFlags, TripMode};
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::io::Error;
+
+// Runs both sides of an A/B test against the same scenario and seed, then writes a report
+// comparing the second set of edits against the first. Each side stops after time_limit.
+pub fn run(
+    test_path: String,
+    flags: SimFlags,
+    time_limit: Duration,
+    report_path: String,
+) -> Result<(), Error> {
+    let mut timer = Timer::new(format!("run A/B test {}", test_path));
+    let test: ABTest = abstutil::read_json(test_path.clone(), &mut timer);
+    // The test says which map to use, so don't silently ignore a different one.
+    if flags.load != abstutil::path_map(&test.map_name) {
+        panic!(
+            "{} runs on {}, so load {}, not {}",
+            test_path,
+            test.map_name,
+            abstutil::path_map(&test.map_name),
+            flags.load
+        );
+    }
+    let scenario: Scenario = abstutil::read_binary(
+        abstutil::path_scenario(&test.map_name, &test.scenario_name),
+        &mut timer,
+    );
+    let mut flags = flags;
+    if flags.rng_seed.is_none() {
+        flags.rng_seed = Some(42);
+    }
+    flags.opts.savestate_every = None;
+
+    let test = &test;
+    let scenario = &scenario;
+    let flags = &flags;
+    let mut results = timer.parallelize(
+        "run both sides",
+        vec![test.edits1_name.clone(), test.edits2_name.clone()],
+        |edits_name| {
+            let mut timer = Timer::throwaway();
+            let mut map = Map::new(abstutil::path_map(&test.map_name), false, &mut timer);
+            let edits = match MapEdits::load(&map, &edits_name, &mut timer) {
+                Ok(edits) => edits,
+                Err(err) => panic!("Can't run with {}: {}", edits_name, err),
+            };
+            map.apply_edits(edits, &mut timer);
+            map.recalculate_pathfinding_after_edits(&mut timer);
+            flags.use_travel_times(&mut map, &mut timer);
+
+            let mut opts = flags.opts.clone();
+            opts.run_name = format!("{} with {}", test.test_name, edits_name);
+            let mut sim = Sim::new(&map, opts, &mut timer);
+            let mut rng = flags.make_rng();
+            scenario.instantiate(&mut sim, &map, &mut rng, &mut timer);
+            let finished = run_until_done_or_limit(&mut sim, &map, time_limit);
+            (sim.time(), finished, sim.get_analytics().clone())
+        },
+    );
+    let (time2, finished2, after) = results.pop().unwrap();
+    let (time1, finished1, before) = results.pop().unwrap();
+    timer.done();
+
+    let mut report = make_report(test, time1.max(time2), &before, &after);
+    for (edits_name, finished) in &[
+        (&test.edits1_name, finished1),
+        (&test.edits2_name, finished2),
+    ] {
+        if !finished {
+            report.push_str(&format!(
+                "\nThe run with {} didn't finish within {}. Only trips finished by then count.\n",
+                edits_name, time_limit
+            ));
+        }
+    }
+    std::fs::write(&report_path, report)?;
+    println!("Wrote {}", report_path);
+    Ok(())
+}
+
+pub fn make_report(test: &ABTest, now: Time, before: &Analytics, after: &Analytics) -> String {
+    let mut lines = vec![
+        format!(
+            "A/B test {} on {} with scenario {}",
+            test.test_name, test.map_name, test.scenario_name
+        ),
+        format!("{} vs {}", test.edits1_name, test.edits2_name),
+        String::new(),
+    ];
+
+    // Positive means faster with the second set of edits
+    let mut faster = DurationHistogram::new();
+    let mut slower = DurationHistogram::new();
+    let mut same = 0;
+    let mut total = Duration::ZERO;
+    for dt in after.finished_trip_deltas(now, before) {
+        total += dt;
+        if dt > Duration::ZERO {
+            faster.add(dt);
+        } else if dt < Duration::ZERO {
+            slower.add(Duration::ZERO - dt);
+        } else {
+            same += 1;
+        }
+    }
+    lines.push("Trips finished in both:".to_string());
+    lines.push(format!(
+        "  {} faster: {}",
+        faster.count(),
+        faster.describe()
+    ));
+    lines.push(format!(
+        "  {} slower: {}",
+        slower.count(),
+        slower.describe()
+    ));
+    lines.push(format!("  {} unchanged", same));
+    lines.push(format!("  Total time saved: {}", total));
+    lines.push(String::new());
+
+    let (_, aborted1, per_mode1) = before.all_finished_trips(now);
+    let (_, aborted2, per_mode2) = after.all_finished_trips(now);
+    lines.push("Per mode:".to_string());
+    for mode in TripMode::all() {
+        lines.push(format!("  {}", mode));
+        lines.push(format!("    before: {}", per_mode1[&mode].describe()));
+        lines.push(format!("    after: {}", per_mode2[&mode].describe()));
+    }
+    lines.push(format!(
+        "  aborted: {} before, {} after",
+        aborted1, aborted2
+    ));
+    lines.push(String::new());
+
+    lines.push("Roads:".to_string());
+    lines.extend(throughput_changes(
+        &before.thruput_stats.count_per_road,
+        &after.thruput_stats.count_per_road,
+    ));
+    lines.push(String::new());
+    lines.push("Intersections:".to_string());
+    lines.extend(throughput_changes(
+        &before.thruput_stats.count_per_intersection,
+        &after.thruput_stats.count_per_intersection,
+    ));
+    lines.push(String::new());
+
+    let mut trips = after.finished_trip_pairs(now, before);
+    // Biggest losses first
+    trips.sort_by_key(|(_, _, dt1, dt2)| *dt1 - *dt2);
+    lines.push("Trips:".to_string());
+    for (id, mode, dt1, dt2) in trips {
+        lines.push(format!(
+            "  {} ({}): {} -> {} ({})",
+            id,
+            mode,
+            dt1,
+            dt2,
+            dt2 - dt1
+        ));
+    }
+
+    let mut report = lines.join("\n");
+    report.push('\n');
+    report
+}
+
+// Only lists the ones that changed, biggest gains first
+fn throughput_changes<T: Ord + Copy + Display>(
+    before: &Counter<T>,
+    after: &Counter<T>,
+) -> Vec<String> {
+    let mut keys: BTreeSet<T> = BTreeSet::new();
+    keys.extend(before.sorted_asc().into_iter().cloned());
+    keys.extend(after.sorted_asc().into_iter().cloned());
+
+    let mut changes: Vec<(isize, T, usize, usize)> = keys
+        .into_iter()
+        .filter_map(|key| {
+            let (cnt1, cnt2) = (before.get(key), after.get(key));
+            if cnt1 == cnt2 {
+                None
+            } else {
+                Some((cnt2 as isize - cnt1 as isize, key, cnt1, cnt2))
+            }
+        })
+        .collect();
+    changes.sort_by_key(|(diff, _, _, _)| -*diff);
+    changes
+        .into_iter()
+        .map(|(diff, key, cnt1, cnt2)| format!("  {}: {} -> {} ({:+})", key, cnt1, cnt2, diff))
+        .collect()
+}
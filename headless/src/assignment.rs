@@ -0,0 +1,61 @@
+use abstutil::Timer;
+use map_model::{Map, TravelTimes};
+use sim::{Scenario, Sim, SimFlags, TripMode};
+
+// Iterative traffic assignment using the method of successive averages. Each iteration runs the
+// whole scenario, routing cars by the travel times averaged so far, then moves those times towards
+// what was observed. Stops once the times barely change, and saves them, so that later runs can
+// use them with --travel_times.
+pub fn run(flags: SimFlags, name: String, max_iterations: usize, convergence_gap: f64) {
+    let mut timer = Timer::new(format!("traffic assignment {}", name));
+    let (mut map, scenario) = if flags.load.starts_with("../data/system/scenarios/") {
+        let scenario: Scenario = abstutil::read_binary(flags.load.clone(), &mut timer);
+        let map = Map::new(abstutil::path_map(&scenario.map_name), false, &mut timer);
+        (map, scenario)
+    } else {
+        let map = Map::new(flags.load.clone(), flags.use_map_fixes, &mut timer);
+        let scenario = Scenario::small_run(&map);
+        (map, scenario)
+    };
+    // Every iteration has to see the same demand.
+    let mut flags = flags;
+    if flags.rng_seed.is_none() {
+        flags.rng_seed = Some(42);
+    }
+    flags.opts.savestate_every = None;
+
+    let mut times = TravelTimes::new(map.get_name().clone(), name);
+    for iteration in 1..=max_iterations {
+        timer.start(format!("iteration {}", iteration));
+        let mut sim = Sim::new(&map, flags.opts.clone(), &mut timer);
+        let mut rng = flags.make_rng();
+        scenario.instantiate(&mut sim, &map, &mut rng, &mut timer);
+        sim.just_run_until_done(&map, None);
+
+        let (lanes, turns) = sim.get_analytics().observed_travel_times();
+        let mut observed = TravelTimes::new(times.map_name.clone(), times.name.clone());
+        observed.lanes = lanes;
+        observed.turns = turns;
+        let gap = times.average_with(&observed, 1.0 / (iteration as f64), &map);
+        println!(
+            "Iteration {}: car trips {}. Relative gap {:.4}",
+            iteration,
+            sim.get_analytics()
+                .finished_trips(sim.time(), TripMode::Drive)
+                .describe(),
+            gap
+        );
+
+        map.use_travel_times(Some(times.clone()));
+        map.recalculate_pathfinding_after_edits(&mut timer);
+        timer.stop(format!("iteration {}", iteration));
+
+        // The first iteration only compares against free-flow times.
+        if iteration > 1 && gap < convergence_gap {
+            println!("Converged after {} iterations", iteration);
+            break;
+        }
+    }
+    timer.done();
+    times.save();
+}
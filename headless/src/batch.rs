@@ -0,0 +1,241 @@
+use crate::run_until_done_or_limit;
+use abstutil::Timer;
+use geom::{Duration, DurationHistogram};
+use map_model::{Map, MapEdits};
+use serde_derive::Deserialize;
+use sim::{Scenario, Sim, SimFlags, SimOptions, TripMode};
+use std::fs::File;
+use std::io::{Error, Write};
+
+// Every combination of map, edits, scenario, seed, and options is run.
+#[derive(Deserialize)]
+pub struct Manifest {
+    // Like montlake
+    pub maps: Vec<String>,
+    // Names of scenarios for each map. small_run is generated, instead of loaded from a file.
+    pub scenarios: Vec<String>,
+    #[serde(default = "no_edits")]
+    pub edits: Vec<String>,
+    pub seeds: Vec<u8>,
+    #[serde(default = "default_options")]
+    pub options: Vec<OptionsVariant>,
+    // Each run stops after this long, even if trips are unfinished.
+    #[serde(default = "default_time_limit")]
+    pub time_limit_secs: f64,
+}
+
+// Toggles for SimOptions, named so they can be told apart in the results
+#[derive(Deserialize)]
+pub struct OptionsVariant {
+    pub name: String,
+    #[serde(default)]
+    pub use_freeform_policy_everywhere: bool,
+    #[serde(default)]
+    pub disable_block_the_box: bool,
+    #[serde(default)]
+    pub dont_recalc_lanechanging: bool,
+    #[serde(default)]
+    pub use_acceleration: bool,
+    #[serde(default)]
+    pub mid_block_lanechanging: bool,
+    #[serde(default)]
+    pub reroute_after_blocked_secs: Option<f64>,
+}
+
+fn no_edits() -> Vec<String> {
+    vec!["no_edits".to_string()]
+}
+
+fn default_time_limit() -> f64 {
+    Duration::hours(24).inner_seconds()
+}
+
+fn default_options() -> Vec<OptionsVariant> {
+    vec![OptionsVariant {
+        name: "default".to_string(),
+        use_freeform_policy_everywhere: false,
+        disable_block_the_box: false,
+        dont_recalc_lanechanging: false,
+        use_acceleration: false,
+        mid_block_lanechanging: false,
+        reroute_after_blocked_secs: None,
+    }]
+}
+
+impl OptionsVariant {
+    fn to_sim_options(&self, run_name: String) -> SimOptions {
+        SimOptions {
+            run_name,
+            savestate_every: None,
+            use_freeform_policy_everywhere: self.use_freeform_policy_everywhere,
+            disable_block_the_box: self.disable_block_the_box,
+            recalc_lanechanging: !self.dont_recalc_lanechanging,
+            use_acceleration: self.use_acceleration,
+            mid_block_lanechanging: self.mid_block_lanechanging,
+            reroute_after_blocked: self.reroute_after_blocked_secs.map(Duration::seconds),
+        }
+    }
+}
+
+struct RunSummary {
+    map: String,
+    edits: String,
+    scenario: String,
+    seed: u8,
+    options: String,
+    end_time: String,
+    finished: bool,
+    per_mode: Vec<DurationHistogram>,
+    aborted_trips: usize,
+    bus_headways: DurationHistogram,
+}
+
+impl RunSummary {
+    fn header() -> String {
+        let mut cols = vec![
+            "map".to_string(),
+            "edits".to_string(),
+            "scenario".to_string(),
+            "seed".to_string(),
+            "options".to_string(),
+            "end_time".to_string(),
+            "finished".to_string(),
+        ];
+        for mode in TripMode::all() {
+            for col in &["count", "p50", "p90", "p99"] {
+                cols.push(format!("{}_{}", mode, col));
+            }
+        }
+        cols.push("aborted_trips".to_string());
+        for col in &["count", "p50", "p90", "p99"] {
+            cols.push(format!("bus_headway_{}", col));
+        }
+        cols.join(",")
+    }
+
+    // Durations are in seconds, and blank if there's no data.
+    fn to_row(&self) -> String {
+        let mut cols = vec![
+            self.map.clone(),
+            self.edits.clone(),
+            self.scenario.clone(),
+            self.seed.to_string(),
+            self.options.clone(),
+            self.end_time.clone(),
+            self.finished.to_string(),
+        ];
+        for distrib in &self.per_mode {
+            cols.extend(percentiles(distrib));
+        }
+        cols.push(self.aborted_trips.to_string());
+        cols.extend(percentiles(&self.bus_headways));
+        cols.join(",")
+    }
+}
+
+fn percentiles(distrib: &DurationHistogram) -> Vec<String> {
+    let mut cols = vec![distrib.count().to_string()];
+    for p in &[50.0, 90.0, 99.0] {
+        cols.push(
+            distrib
+                .percentile(*p)
+                .map(|d: Duration| d.inner_seconds().to_string())
+                .unwrap_or_else(String::new),
+        );
+    }
+    cols
+}
+
+pub fn run(manifest_path: String, output_path: String) -> Result<(), Error> {
+    let mut timer = Timer::new(format!("batch run of {}", manifest_path));
+    let manifest: Manifest = abstutil::read_json(manifest_path, &mut timer);
+
+    let mut requests = Vec::new();
+    for scenario_idx in 0..manifest.scenarios.len() {
+        for seed in &manifest.seeds {
+            for options_idx in 0..manifest.options.len() {
+                requests.push((scenario_idx, *seed, options_idx));
+            }
+        }
+    }
+
+    let mut f = File::create(&output_path)?;
+    writeln!(f, "{}", RunSummary::header())?;
+
+    for map_name in &manifest.maps {
+        let mut map = Map::new(abstutil::path_map(map_name), false, &mut timer);
+        let scenarios: Vec<Scenario> = manifest
+            .scenarios
+            .iter()
+            .map(|name| {
+                if name == "small_run" {
+                    Scenario::small_run(&map)
+                } else {
+                    abstutil::read_binary(abstutil::path_scenario(map_name, name), &mut timer)
+                }
+            })
+            .collect();
+
+        for edits_name in &manifest.edits {
+            let edits = match MapEdits::load(&map, edits_name, &mut timer) {
+                Ok(edits) => edits,
+                Err(err) => panic!("Can't run with {}: {}", edits_name, err),
+            };
+            map.apply_edits(edits, &mut timer);
+            map.recalculate_pathfinding_after_edits(&mut timer);
+
+            let map = &map;
+            let scenarios = &scenarios;
+            let manifest = &manifest;
+            let results = timer.parallelize(
+                &format!("run everything on {} with {}", map_name, edits_name),
+                requests.clone(),
+                |(scenario_idx, seed, options_idx)| {
+                    let scenario = &scenarios[scenario_idx];
+                    let options = &manifest.options[options_idx];
+                    let flags = SimFlags {
+                        load: abstutil::path_map(map_name),
+                        use_map_fixes: false,
+                        rng_seed: Some(seed),
+                        travel_times: None,
+                        opts: options.to_sim_options(format!(
+                            "{}_{}_{}_{}",
+                            scenario.scenario_name, edits_name, seed, options.name
+                        )),
+                    };
+                    let mut rng = flags.make_rng();
+                    let mut timer = Timer::throwaway();
+                    let mut sim = Sim::new(map, flags.opts, &mut timer);
+                    scenario.instantiate(&mut sim, map, &mut rng, &mut timer);
+                    let finished = run_until_done_or_limit(
+                        &mut sim,
+                        map,
+                        Duration::seconds(manifest.time_limit_secs),
+                    );
+
+                    let now = sim.time();
+                    let analytics = sim.get_analytics();
+                    let (_, aborted_trips, per_mode) = analytics.all_finished_trips(now);
+                    RunSummary {
+                        map: map_name.clone(),
+                        edits: edits_name.clone(),
+                        scenario: scenario.scenario_name.clone(),
+                        seed,
+                        options: options.name.clone(),
+                        end_time: now.to_string(),
+                        finished,
+                        per_mode: per_mode.into_iter().map(|(_, distrib)| distrib).collect(),
+                        aborted_trips,
+                        bus_headways: analytics.bus_headways(now),
+                    }
+                },
+            );
+            for summary in results {
+                writeln!(f, "{}", summary.to_row())?;
+            }
+        }
+    }
+    timer.done();
+    println!("Wrote {}", output_path);
+    Ok(())
+}
@@ -0,0 +1,22 @@
+pub mod ab_test;
+pub mod assignment;
+pub mod batch;
+
+use geom::Duration;
+use map_model::Map;
+use sim::Sim;
+
+// Like Sim::just_run_until_done, but stops at the time limit instead of panicking, so a gridlocked
+// run still has results. True if everything finished.
+pub fn run_until_done_or_limit(sim: &mut Sim, map: &Map, time_limit: Duration) -> bool {
+    let end = sim.time() + time_limit;
+    while !sim.is_done() {
+        let remaining = end - sim.time();
+        if remaining <= Duration::ZERO {
+            return false;
+        }
+        let dt = Duration::seconds(30.0);
+        sim.step(map, if remaining < dt { remaining } else { dt });
+    }
+    true
+}
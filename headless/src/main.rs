@@ -1,6 +1,8 @@
 use abstutil::{CmdArgs, Timer};
-use geom::Time;
-use sim::{GetDrawAgents, Scenario, SimFlags};
+use geom::{Duration, Time};
+use headless::{ab_test, assignment, batch};
+use map_model::{IntersectionID, MapEdits};
+use sim::{EventFilter, EventLog, EventLogFormat, GetDrawAgents, Scenario, Sim, SimFlags};
 
 fn main() {
     let mut args = CmdArgs::new();
@@ -10,15 +12,94 @@ fn main() {
     let enable_profiler = args.enabled("--enable_profiler");
     // Every 0.1s, pretend to draw everything to make sure there are no bugs.
     let paranoia = args.enabled("--paranoia");
+    // Stream events to a file as the sim runs. CSV if the path ends in .csv, otherwise
+    // newline-delimited JSON.
+    let event_log = args.optional("--event_log");
+    let event_filter = EventFilter {
+        // Comma-separated, like TripFinished,BusArrivedAtStop
+        types: args
+            .optional("--event_types")
+            .map(|s| match EventFilter::parse_types(&s) {
+                Ok(types) => types,
+                Err(err) => panic!("Bad --event_types: {}", err),
+            }),
+        start: args.optional_parse("--event_log_start", Time::parse),
+        end: args.optional_parse("--event_log_end", Time::parse),
+    };
+    // Apply these map edits before running. Only works when starting from a map.
+    let edits_name = args.optional("--edits");
+    // Comma-separated intersection IDs. At the end, summarize the delays at each.
+    let report_delays: Vec<IntersectionID> = args
+        .optional("--report_delays")
+        .map(|s| {
+            s.split(',')
+                .map(|i| IntersectionID(i.parse::<usize>().unwrap()))
+                .collect()
+        })
+        .unwrap_or_else(Vec::new);
+    // Instead of one run, run everything described by a JSON manifest in parallel, writing one
+    // summary row per run as CSV.
+    let batch = args.optional("--batch");
+    let batch_output = args
+        .optional("--batch_output")
+        .unwrap_or_else(|| "batch_results.csv".to_string());
+    // Run both sides of an A/B test (the path to its JSON file) and write a report comparing them.
+    // The map to load must be the test's. Uses --rng_seed, --travel_times, and the sim options.
+    let ab_test = args.optional("--ab_test");
+    let ab_report = args
+        .optional("--ab_report")
+        .unwrap_or_else(|| "ab_test_report.txt".to_string());
+    // Stop each side of the A/B test after this long, even if trips are unfinished
+    let ab_time_limit = args
+        .optional_parse("--ab_time_limit", Duration::parse)
+        .unwrap_or_else(|| Duration::hours(24));
+    // Iteratively assign traffic, then save the travel times under this name
+    let traffic_assignment = args.optional("--traffic_assignment");
+    let max_iterations = args
+        .optional_parse("--max_iterations", |s| s.parse::<usize>())
+        .unwrap_or(10);
+    let convergence_gap = args
+        .optional_parse("--convergence_gap", |s| s.parse::<f64>())
+        .unwrap_or(0.01);
     args.done();
 
+    if let Some(manifest) = batch {
+        if let Err(err) = batch::run(manifest, batch_output.clone()) {
+            panic!("Couldn't write {}: {}", batch_output, err);
+        }
+        return;
+    }
+    if let Some(name) = traffic_assignment {
+        assignment::run(sim_flags, name, max_iterations, convergence_gap);
+        return;
+    }
+    if let Some(test) = ab_test {
+        if let Err(err) = ab_test::run(test, sim_flags, ab_time_limit, ab_report.clone()) {
+            panic!("Couldn't write {}: {}", ab_report, err);
+        }
+        return;
+    }
+
     let mut timer = Timer::new("setup headless");
-    let (map, mut sim, mut rng) = sim_flags.load(&mut timer);
+    let (mut map, mut sim, mut rng) = match sim_flags.load(&mut timer) {
+        Ok(loaded) => loaded,
+        Err(err) => panic!("{}", err),
+    };
 
     // TODO not the ideal way to distinguish what thing we loaded
     if sim_flags.load.starts_with(&abstutil::path_all_raw_maps())
         || sim_flags.load.starts_with(&abstutil::path_all_maps())
     {
+        if let Some(ref name) = edits_name {
+            let edits = match MapEdits::load(&map, name, &mut timer) {
+                Ok(edits) => edits,
+                Err(err) => panic!("Can't run with {}: {}", name, err),
+            };
+            map.apply_edits(edits, &mut timer);
+            map.recalculate_pathfinding_after_edits(&mut timer);
+            // The sim has to be created after the map edits.
+            sim = Sim::new(&map, sim_flags.opts.clone(), &mut timer);
+        }
         let s = if let Some(n) = num_agents {
             Scenario::scaled_run(&map, n)
         } else {
@@ -28,6 +109,22 @@ fn main() {
     }
     timer.done();
 
+    if let Some(ref path) = event_log {
+        let format = if path.ends_with(".csv") {
+            EventLogFormat::Csv
+        } else {
+            EventLogFormat::Json
+        };
+        match EventLog::new(path, format, event_filter) {
+            Ok(log) => {
+                sim.add_event_sink(Box::new(log));
+            }
+            Err(err) => {
+                panic!("Couldn't create event log {}: {}", path, err);
+            }
+        }
+    }
+
     if enable_profiler {
         #[cfg(feature = "profiler")]
         {
@@ -44,7 +141,7 @@ fn main() {
         move |sim, map| {
             // TODO We want to savestate at the end of this time; this'll happen at the beginning.
             if Some(sim.time()) == save_at {
-                sim.save();
+                sim.save(map);
                 // Some simulations run for a really long time, just do this.
                 if enable_profiler {
                     #[cfg(feature = "profiler")]
@@ -60,7 +157,20 @@ fn main() {
         None,
     );
     timer.done();
+    sim.flush_event_sinks();
+    if let Some(path) = event_log {
+        println!("Wrote events to {}", path);
+    }
     println!("Done at {}", sim.time());
+    for i in report_delays {
+        println!(
+            "Delays at {}: {}",
+            i,
+            sim.get_analytics()
+                .intersection_delays(i, Time::START_OF_DAY, sim.time())
+                .describe()
+        );
+    }
     if enable_profiler && save_at.is_none() {
         #[cfg(feature = "profiler")]
         {
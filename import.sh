@@ -93,6 +93,12 @@ if [ ! -f data/input/offstreet_parking.kml ]; then
 	get_if_needed http://data-seattlecitygis.opendata.arcgis.com/datasets/8e52dfde6d5d45948f7a90654c8d50cd_0.kml data/input/offstreet_parking.kml;
 fi
 
+# Optional SRTM .hgt tiles, like N47W123.hgt
+elevation_flag="";
+if [ -d data/input/srtm ]; then
+	elevation_flag="--elevation=../data/input/srtm";
+fi
+
 cd convert_osm
 for poly in `ls ../data/input/polygons/`; do
 	name=`basename -s .poly $poly`;
@@ -106,8 +112,11 @@ for poly in `ls ../data/input/polygons/`; do
 		--parking_shapes=../data/input/blockface.bin \
 		--offstreet_parking=../data/input/offstreet_parking.kml \
 		--gtfs=../data/input/google_transit_2018_18_08 \
+		--gtfs_service_day=monday \
 		--neighborhoods=../data/input/neighborhoods.geojson \
 		--clip=../data/input/polygons/$name.poly \
+		--profile=../data/input/profiles/seattle.json \
+		$elevation_flag \
 		--output=../data/input/raw_maps/$name.bin
 		#--sidewalks=../data/input/sidewalks.bin \
 done
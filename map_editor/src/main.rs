@@ -192,7 +192,8 @@ impl GUI for UI {
                     }
                     Some(ID::Road(r)) => {
                         let could_swap = {
-                            let lanes = self.model.map.roads[&r].get_spec();
+                            let lanes =
+                                self.model.map.roads[&r].get_spec(self.model.map.driving_side);
                             lanes.fwd != lanes.back
                         };
 
@@ -227,7 +228,7 @@ impl GUI for UI {
                         {
                             let road = &self.model.map.roads[&r];
                             self.state = State::StampingRoads(
-                                road.get_spec().to_string(),
+                                road.get_spec(self.model.map.driving_side).to_string(),
                                 road.osm_tags
                                     .get(osm::NAME)
                                     .cloned()
@@ -373,7 +374,9 @@ impl GUI for UI {
             State::EditingLanes(id, ref mut wizard) => {
                 if let Some(s) = wizard.wrap(ctx).input_string_prefilled(
                     "Specify the lanes",
-                    self.model.map.roads[&id].get_spec().to_string(),
+                    self.model.map.roads[&id]
+                        .get_spec(self.model.map.driving_side)
+                        .to_string(),
                 ) {
                     self.model.edit_lanes(id, s, ctx.prerender);
                     self.state = State::viewing();
@@ -245,6 +245,8 @@ impl Model {
             RawIntersection {
                 point,
                 intersection_type: IntersectionType::StopSign,
+                elevation: Distance::ZERO,
+                crossing: None,
             },
         );
         self.intersection_added(id, prerender);
@@ -379,9 +381,10 @@ impl Model {
     pub fn swap_lanes(&mut self, id: OriginalRoad, prerender: &Prerender) {
         self.road_deleted(id);
 
+        let driving_side = self.map.driving_side;
         let (mut lanes, osm_tags) = {
             let r = self.map.roads.get_mut(&id).unwrap();
-            (r.get_spec(), &mut r.osm_tags)
+            (r.get_spec(driving_side), &mut r.osm_tags)
         };
         mem::swap(&mut lanes.fwd, &mut lanes.back);
         osm_tags.insert(osm::SYNTHETIC_LANES.to_string(), lanes.to_string());
@@ -544,7 +547,7 @@ impl Model {
             r.synthetic() && r.osm_tags.get(osm::NAME) == Some(&"Streety McStreetFace".to_string());
         let lanes_unknown = r.osm_tags.contains_key(osm::INFERRED_PARKING)
             || r.osm_tags.contains_key(osm::INFERRED_SIDEWALKS);
-        let spec = r.get_spec();
+        let spec = r.get_spec(self.map.driving_side);
         let center_pts = PolyLine::new(r.center_points.clone());
 
         let mut obj = Object::blank(ID::Road(id));
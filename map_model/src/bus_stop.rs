@@ -1,4 +1,5 @@
 use crate::{LaneID, Position};
+use geom::{Duration, Time};
 use serde_derive::{Deserialize, Serialize};
 use std::fmt;
 
@@ -38,5 +39,75 @@ pub struct BusStop {
 pub struct BusRoute {
     pub id: BusRouteID,
     pub name: String,
+    pub long_name: String,
+    pub route_type: String,
+    pub color: Option<String>,
+    pub agency: Option<String>,
     pub stops: Vec<BusStopID>,
+    // Routes from GTFS only go in one direction; buses reaching the last stop lay over and turn
+    // around onto the opposite_route or leave the map. Otherwise, the last stop connects back to
+    // the first.
+    pub is_loop: bool,
+    pub opposite_route: Option<BusRouteID>,
+    // From GTFS. Each trip is a list of (index into stops, departure time), sorted by the first
+    // departure. Empty if there's no timetable; buses just loop.
+    pub schedule: Vec<Vec<(usize, Time)>>,
+}
+
+impl BusRoute {
+    // Every pair of consecutive stops along the route, including wrapping around for loops.
+    pub fn all_segments(&self) -> Vec<(BusStopID, BusStopID)> {
+        let mut segments: Vec<(BusStopID, BusStopID)> = self
+            .stops
+            .windows(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect();
+        if self.is_loop && self.stops.len() >= 2 {
+            segments.push((*self.stops.last().unwrap(), self.stops[0]));
+        }
+        segments
+    }
+
+    // Can somebody board at stop1 and ride to stop2 without transferring?
+    pub fn goes_between(&self, stop1: BusStopID, stop2: BusStopID) -> bool {
+        match (
+            self.stops.iter().position(|s| *s == stop1),
+            self.stops.iter().position(|s| *s == stop2),
+        ) {
+            (Some(idx1), Some(idx2)) => self.is_loop || idx1 < idx2,
+            _ => false,
+        }
+    }
+
+    // Average time between trips leaving the first stop. None without a timetable.
+    pub fn average_headway(&self) -> Option<Duration> {
+        if self.schedule.len() < 2 {
+            return None;
+        }
+        let first = self.schedule[0][0].1;
+        let last = self.schedule.last().unwrap()[0].1;
+        Some((last - first) / ((self.schedule.len() - 1) as f64))
+    }
+
+    // Board the first trip in the timetable leaving some stop at or after a time. Returns every
+    // later stop on that trip and when the bus gets there.
+    pub fn scheduled_rides_from(&self, stop: BusStopID, after: Time) -> Vec<(BusStopID, Time)> {
+        let mut best: Option<(Time, &[(usize, Time)])> = None;
+        for trip in &self.schedule {
+            if let Some(pos) = trip
+                .iter()
+                .position(|(idx, depart)| self.stops[*idx] == stop && *depart >= after)
+            {
+                if best.map(|(time, _)| trip[pos].1 < time).unwrap_or(true) {
+                    best = Some((trip[pos].1, &trip[pos + 1..]));
+                }
+            }
+        }
+        best.map(|(_, rest)| {
+            rest.iter()
+                .map(|(idx, arrive)| (self.stops[*idx], *arrive))
+                .collect()
+        })
+        .unwrap_or_else(Vec::new)
+    }
 }
@@ -0,0 +1,134 @@
+use crate::{ControlTrafficSignal, IntersectionID, IntersectionType, Map, RoadID, TurnGroupID};
+use geom::{Duration, Speed};
+
+// Coordinates the signals along a corridor, so that a vehicle travelling at the progression speed
+// and starting at the beginning of the corridor's phase at the first signal reaches each
+// subsequent signal just as its corridor phase begins. If cycle_length is specified, every signal
+// is rescaled to it; otherwise they must already share a cycle length. Returns the modified
+// signals, in corridor order.
+pub fn green_wave(
+    map: &Map,
+    corridor: &[IntersectionID],
+    speed: Speed,
+    cycle_length: Option<Duration>,
+) -> Result<Vec<ControlTrafficSignal>, String> {
+    if corridor.len() < 2 {
+        return Err("Need at least 2 intersections to coordinate".to_string());
+    }
+    if speed <= Speed::ZERO {
+        return Err(format!("Progression speed {} must be positive", speed));
+    }
+    if let Some(c) = cycle_length {
+        if c <= Duration::ZERO {
+            return Err(format!("Cycle length {} must be positive", c));
+        }
+    }
+    for i in corridor {
+        if map.get_i(*i).intersection_type != IntersectionType::TrafficSignal {
+            return Err(format!("{} isn't a traffic signal", i));
+        }
+        if map.get_traffic_signal(*i).is_actuated() {
+            return Err(format!(
+                "{} is actuated; offsets only apply to fixed-time signals",
+                i
+            ));
+        }
+    }
+
+    let mut roads = Vec::new();
+    for pair in corridor.windows(2) {
+        roads.push(road_between(map, pair[0], pair[1])?);
+    }
+
+    let mut results: Vec<ControlTrafficSignal> = Vec::new();
+    let mut travel_time = Duration::ZERO;
+    for (idx, i) in corridor.iter().enumerate() {
+        let mut signal = map.get_traffic_signal(*i).clone();
+        if let Some(c) = cycle_length {
+            rescale(&mut signal, c)?;
+        }
+        let cycle = signal.cycle_length();
+        if let Some(first) = results.first() {
+            let expected = first.cycle_length();
+            if cycle != expected {
+                return Err(format!(
+                    "{} has a cycle length of {}, but {} has {}. Specify a common cycle length.",
+                    i, cycle, corridor[0], expected
+                ));
+            }
+        }
+
+        if idx > 0 {
+            travel_time += map.get_r(roads[idx - 1]).center_pts.length() / speed;
+        }
+        let from = if idx > 0 { Some(roads[idx - 1]) } else { None };
+        let to = roads.get(idx).cloned();
+        let phase_start = corridor_phase_start(&signal, from, to)?;
+
+        // The signal uses (now + offset) % cycle to pick the phase.
+        let mut offset = (phase_start - travel_time) % cycle;
+        if offset < Duration::ZERO {
+            offset += cycle;
+        }
+        signal.offset = offset;
+        results.push(signal);
+    }
+    Ok(results)
+}
+
+fn road_between(map: &Map, i1: IntersectionID, i2: IntersectionID) -> Result<RoadID, String> {
+    for r in &map.get_i(i1).roads {
+        let road = map.get_r(*r);
+        if (road.src_i == i1 && road.dst_i == i2) || (road.src_i == i2 && road.dst_i == i1) {
+            return Ok(*r);
+        }
+    }
+    Err(format!("{} and {} aren't connected by a road", i1, i2))
+}
+
+// When does the first phase protecting movement along the corridor start, relative to the
+// beginning of the cycle? The first and last signals only have one corridor road.
+fn corridor_phase_start(
+    signal: &ControlTrafficSignal,
+    from: Option<RoadID>,
+    to: Option<RoadID>,
+) -> Result<Duration, String> {
+    let matches = |g: &TurnGroupID| {
+        g.crosswalk.is_none()
+            && from.map(|r| g.from == r).unwrap_or(true)
+            && to.map(|r| g.to == r).unwrap_or(true)
+    };
+    let mut start = Duration::ZERO;
+    for phase in &signal.phases {
+        if phase.protected_groups.iter().any(&matches) {
+            return Ok(start);
+        }
+        start += phase.duration;
+    }
+    Err(format!(
+        "No phase at {} protects movement from {:?} to {:?}",
+        signal.id, from, to
+    ))
+}
+
+fn rescale(signal: &mut ControlTrafficSignal, cycle_length: Duration) -> Result<(), String> {
+    let ratio = cycle_length / signal.cycle_length();
+    let mut total = Duration::ZERO;
+    let num_phases = signal.phases.len();
+    for (idx, phase) in signal.phases.iter_mut().enumerate() {
+        // Round to whole seconds, letting the last phase absorb the difference.
+        if idx == num_phases - 1 {
+            phase.duration = cycle_length - total;
+        } else {
+            phase.duration = Duration::seconds((phase.duration * ratio).inner_seconds().round());
+        }
+        if phase.duration <= Duration::ZERO {
+            return Err(format!(
+                "Cycle length {} is too short for {}",
+                cycle_length, signal.id
+            ));
+        }
+        total += phase.duration;
+    }
+    Ok(())
+}
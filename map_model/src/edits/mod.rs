@@ -1,6 +1,9 @@
+mod perma;
+
+pub use self::perma::{find_legacy_edits, upgrade_legacy_edits, PermanentMapEdits};
 use crate::{
-    ControlStopSign, ControlTrafficSignal, IntersectionID, IntersectionType, LaneID, LaneType, Map,
-    RoadID, TurnID,
+    BuildingID, ControlStopSign, ControlTrafficSignal, IntersectionID, IntersectionType, LaneID,
+    LaneType, Map, ParkingPolicy, RoadID, TurnID,
 };
 use abstutil::{retain_btreemap, retain_btreeset, Timer};
 use serde_derive::{Deserialize, Serialize};
@@ -16,6 +19,10 @@ pub struct MapEdits {
     pub original_lts: BTreeMap<LaneID, LaneType>,
     pub reversed_lanes: BTreeSet<LaneID>,
     pub changed_intersections: BTreeSet<IntersectionID>,
+    #[serde(default)]
+    pub original_parking_policies: BTreeMap<LaneID, ParkingPolicy>,
+    #[serde(default)]
+    pub original_offstreet_policies: BTreeMap<BuildingID, ParkingPolicy>,
 
     #[serde(skip_serializing, skip_deserializing)]
     pub dirty: bool,
@@ -40,6 +47,16 @@ pub enum EditCmd {
         orig_it: IntersectionType,
     },
     UncloseIntersection(IntersectionID, IntersectionType),
+    ChangeParkingPolicy {
+        id: LaneID,
+        policy: ParkingPolicy,
+        orig_policy: ParkingPolicy,
+    },
+    ChangeOffstreetParkingPolicy {
+        b: BuildingID,
+        policy: ParkingPolicy,
+        orig_policy: ParkingPolicy,
+    },
 }
 
 pub struct EditEffects {
@@ -61,6 +78,8 @@ impl MapEdits {
             original_lts: BTreeMap::new(),
             reversed_lanes: BTreeSet::new(),
             changed_intersections: BTreeSet::new(),
+            original_parking_policies: BTreeMap::new(),
+            original_offstreet_policies: BTreeMap::new(),
             dirty: false,
         }
     }
@@ -69,20 +88,40 @@ impl MapEdits {
         self.edits_name == "no_edits" && self.commands.is_empty()
     }
 
-    pub fn load(map_name: &str, edits_name: &str, timer: &mut Timer) -> MapEdits {
+    // Anything that can't be matched to this map anymore is skipped, with a warning.
+    pub fn load(map: &Map, edits_name: &str, timer: &mut Timer) -> Result<MapEdits, String> {
         if edits_name == "no_edits" {
-            return MapEdits::new(map_name.to_string());
+            return Ok(MapEdits::new(map.get_name().to_string()));
+        }
+        let path = abstutil::path_edits(map.get_name(), edits_name);
+        let perma = PermanentMapEdits::read(&path, map)
+            .map_err(|err| format!("Couldn't load {}: {}", path, err))?;
+        let num_cmds = perma.commands.len();
+        let (edits, unmatched) = perma.into_edits(map);
+        if !unmatched.is_empty() {
+            timer.warn(format!(
+                "{} of {} edits in {} don't match {} anymore:",
+                unmatched.len(),
+                num_cmds,
+                edits_name,
+                map.get_name()
+            ));
+            for err in unmatched {
+                timer.warn(format!("  {}", err));
+            }
         }
-        abstutil::read_json(abstutil::path_edits(map_name, edits_name), timer)
+        Ok(edits)
     }
 
-    // TODO Version these
     pub(crate) fn save(&mut self, map: &Map) {
         self.compress(map);
 
         assert!(self.dirty);
         assert_ne!(self.edits_name, "no_edits");
-        abstutil::write_json(abstutil::path_edits(&self.map_name, &self.edits_name), self);
+        abstutil::write_json(
+            abstutil::path_edits(&self.map_name, &self.edits_name),
+            &PermanentMapEdits::from_edits(self, map),
+        );
         self.dirty = false;
     }
 
@@ -97,9 +136,12 @@ impl MapEdits {
         panic!("{} isn't closed", i);
     }
 
-    // Original lane types, reversed lanes, and all changed intersections
+    // Original lane types, reversed lanes, all changed intersections, and original parking
+    // policies
     pub(crate) fn update_derived(&mut self, map: &Map, timer: &mut Timer) {
         let mut orig_lts = BTreeMap::new();
+        let mut orig_policies = BTreeMap::new();
+        let mut orig_offstreet_policies = BTreeMap::new();
         let mut reversed_lanes = BTreeSet::new();
         let mut changed_stop_signs = BTreeSet::new();
         let mut changed_traffic_signals = BTreeSet::new();
@@ -131,10 +173,28 @@ impl MapEdits {
                 EditCmd::UncloseIntersection(id, _) => {
                     closed_intersections.remove(id);
                 }
+                EditCmd::ChangeParkingPolicy {
+                    id, orig_policy, ..
+                } => {
+                    if !orig_policies.contains_key(id) {
+                        orig_policies.insert(*id, orig_policy.clone());
+                    }
+                }
+                EditCmd::ChangeOffstreetParkingPolicy { b, orig_policy, .. } => {
+                    if !orig_offstreet_policies.contains_key(b) {
+                        orig_offstreet_policies.insert(*b, orig_policy.clone());
+                    }
+                }
             }
         }
 
         retain_btreemap(&mut orig_lts, |l, lt| map.get_l(*l).lane_type != *lt);
+        retain_btreemap(&mut orig_policies, |l, policy| {
+            &map.get_l(*l).parking_policy != policy
+        });
+        retain_btreemap(&mut orig_offstreet_policies, |b, policy| {
+            map.get_b(*b).parking.as_ref().map(|p| &p.policy) != Some(policy)
+        });
         for i in &closed_intersections {
             changed_stop_signs.remove(i);
             changed_traffic_signals.remove(i);
@@ -147,6 +207,8 @@ impl MapEdits {
         });
 
         self.original_lts = orig_lts;
+        self.original_parking_policies = orig_policies;
+        self.original_offstreet_policies = orig_offstreet_policies;
         self.reversed_lanes = reversed_lanes;
         self.changed_intersections = closed_intersections;
         self.changed_intersections.extend(changed_stop_signs);
@@ -170,6 +232,20 @@ impl MapEdits {
                 dst_i: map.get_l(*l).dst_i,
             });
         }
+        for (l, orig_policy) in &self.original_parking_policies {
+            self.commands.push(EditCmd::ChangeParkingPolicy {
+                id: *l,
+                policy: map.get_l(*l).parking_policy.clone(),
+                orig_policy: orig_policy.clone(),
+            });
+        }
+        for (b, orig_policy) in &self.original_offstreet_policies {
+            self.commands.push(EditCmd::ChangeOffstreetParkingPolicy {
+                b: *b,
+                policy: map.get_b(*b).parking.as_ref().unwrap().policy.clone(),
+                orig_policy: orig_policy.clone(),
+            });
+        }
         for i in &self.changed_intersections {
             match map.get_i(*i).intersection_type {
                 IntersectionType::StopSign => {
@@ -222,6 +298,12 @@ impl EditCmd {
             EditCmd::ChangeTrafficSignal(ts) => format!("Edit traffic signal {}", ts.id),
             EditCmd::CloseIntersection { id, .. } => format!("Close {}", id),
             EditCmd::UncloseIntersection(id, _) => format!("Restore {}", id),
+            EditCmd::ChangeParkingPolicy { id, policy, .. } => {
+                format!("Parking on {}: {}", id, policy.describe())
+            }
+            EditCmd::ChangeOffstreetParkingPolicy { b, policy, .. } => {
+                format!("Parking at {}: {}", b, policy.describe())
+            }
         }
     }
 }
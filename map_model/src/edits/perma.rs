@@ -0,0 +1,541 @@
+use crate::raw::{OriginalBuilding, OriginalIntersection, OriginalRoad};
+use crate::{
+    ActuatedPhase, BuildingID, ControlStopSign, ControlTrafficSignal, EditCmd, IntersectionID,
+    IntersectionType, LaneID, LaneType, Map, MapEdits, ParkingPolicy, Phase, RoadID, TurnGroup,
+    TurnGroupID, TurnID,
+};
+use geom::Duration;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Bump this whenever PermanentMapEdits changes, and add a step to upgrade().
+pub const EDITS_VERSION: usize = 1;
+
+// LaneIDs, IntersectionIDs, and friends are just indices that change every time a map is
+// regenerated. On disk, edits refer to things by their OSM IDs instead.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PermanentMapEdits {
+    pub map_name: String,
+    pub edits_name: String,
+    pub version: usize,
+    pub commands: Vec<PermanentEditCmd>,
+}
+
+// Lanes are identified by their position across the road, counting from the left side when facing
+// from i1 to i2. Reversing a lane doesn't change this. If OSM changes the number of lanes, then
+// edits to that road can't be matched.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct OriginalLane {
+    pub parent: OriginalRoad,
+    pub idx: usize,
+    pub num_lanes: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum PermanentEditCmd {
+    ChangeLaneType {
+        id: OriginalLane,
+        lt: LaneType,
+        orig_lt: LaneType,
+    },
+    ReverseLane {
+        l: OriginalLane,
+        dst_i: OriginalIntersection,
+    },
+    ChangeStopSign {
+        id: OriginalIntersection,
+        must_stop: Vec<(OriginalRoad, bool)>,
+    },
+    ChangeTrafficSignal(PermanentTrafficSignal),
+    CloseIntersection {
+        id: OriginalIntersection,
+        orig_it: IntersectionType,
+    },
+    UncloseIntersection(OriginalIntersection, IntersectionType),
+    ChangeParkingPolicy {
+        id: OriginalLane,
+        policy: ParkingPolicy,
+        orig_policy: ParkingPolicy,
+    },
+    ChangeOffstreetParkingPolicy {
+        b: OriginalBuilding,
+        policy: ParkingPolicy,
+        orig_policy: ParkingPolicy,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PermanentTrafficSignal {
+    pub id: OriginalIntersection,
+    pub phases: Vec<PermanentPhase>,
+    pub offset: Duration,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PermanentPhase {
+    pub protected_groups: Vec<PermanentTurnGroup>,
+    pub yield_groups: Vec<PermanentTurnGroup>,
+    pub duration: Duration,
+    pub actuated: Option<ActuatedPhase>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PermanentTurnGroup {
+    pub from: OriginalRoad,
+    pub to: OriginalRoad,
+    // The (src, dst) sidewalks
+    pub crosswalk: Option<(OriginalLane, OriginalLane)>,
+}
+
+impl PermanentMapEdits {
+    pub fn from_edits(edits: &MapEdits, map: &Map) -> PermanentMapEdits {
+        PermanentMapEdits {
+            map_name: edits.map_name.clone(),
+            edits_name: edits.edits_name.clone(),
+            version: EDITS_VERSION,
+            commands: edits
+                .commands
+                .iter()
+                .map(|cmd| match cmd {
+                    EditCmd::ChangeLaneType { id, lt, orig_lt } => {
+                        PermanentEditCmd::ChangeLaneType {
+                            id: OriginalLane::new(*id, map),
+                            lt: *lt,
+                            orig_lt: *orig_lt,
+                        }
+                    }
+                    EditCmd::ReverseLane { l, dst_i } => PermanentEditCmd::ReverseLane {
+                        l: OriginalLane::new(*l, map),
+                        dst_i: map.get_i(*dst_i).orig_id,
+                    },
+                    EditCmd::ChangeStopSign(ss) => PermanentEditCmd::ChangeStopSign {
+                        id: map.get_i(ss.id).orig_id,
+                        must_stop: ss
+                            .roads
+                            .iter()
+                            .map(|(r, cfg)| (map.get_r(*r).orig_id, cfg.must_stop))
+                            .collect(),
+                    },
+                    EditCmd::ChangeTrafficSignal(ts) => {
+                        PermanentEditCmd::ChangeTrafficSignal(PermanentTrafficSignal {
+                            id: map.get_i(ts.id).orig_id,
+                            phases: ts
+                                .phases
+                                .iter()
+                                .map(|p| PermanentPhase {
+                                    protected_groups: p
+                                        .protected_groups
+                                        .iter()
+                                        .map(|g| PermanentTurnGroup::new(*g, map))
+                                        .collect(),
+                                    yield_groups: p
+                                        .yield_groups
+                                        .iter()
+                                        .map(|g| PermanentTurnGroup::new(*g, map))
+                                        .collect(),
+                                    duration: p.duration,
+                                    actuated: p.actuated.clone(),
+                                })
+                                .collect(),
+                            offset: ts.offset,
+                        })
+                    }
+                    EditCmd::CloseIntersection { id, orig_it } => {
+                        PermanentEditCmd::CloseIntersection {
+                            id: map.get_i(*id).orig_id,
+                            orig_it: *orig_it,
+                        }
+                    }
+                    EditCmd::UncloseIntersection(id, orig_it) => {
+                        PermanentEditCmd::UncloseIntersection(map.get_i(*id).orig_id, *orig_it)
+                    }
+                    EditCmd::ChangeParkingPolicy {
+                        id,
+                        policy,
+                        orig_policy,
+                    } => PermanentEditCmd::ChangeParkingPolicy {
+                        id: OriginalLane::new(*id, map),
+                        policy: policy.clone(),
+                        orig_policy: orig_policy.clone(),
+                    },
+                    EditCmd::ChangeOffstreetParkingPolicy {
+                        b,
+                        policy,
+                        orig_policy,
+                    } => PermanentEditCmd::ChangeOffstreetParkingPolicy {
+                        b: OriginalBuilding {
+                            osm_way_id: map.get_b(*b).osm_way_id,
+                        },
+                        policy: policy.clone(),
+                        orig_policy: orig_policy.clone(),
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    // Also returns a description of every command that couldn't be matched to this map. Those are
+    // left out.
+    pub fn into_edits(self, map: &Map) -> (MapEdits, Vec<String>) {
+        let lookup = Lookup::new(map);
+        let mut edits = MapEdits::new(self.map_name);
+        edits.edits_name = self.edits_name;
+        let mut unmatched = Vec::new();
+        for cmd in self.commands {
+            match cmd.resolve(map, &lookup) {
+                Ok(cmd) => edits.commands.push(cmd),
+                Err(err) => unmatched.push(err),
+            }
+        }
+        (edits, unmatched)
+    }
+
+    // Reads any version of the format. Edits saved before stable IDs existed have no version,
+    // which counts as 0. They only make sense for the exact map they were made on; map is assumed
+    // to be that one.
+    pub fn read(path: &str, map: &Map) -> Result<PermanentMapEdits, String> {
+        let contents = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let value: serde_json::Value =
+            serde_json::from_str(&contents).map_err(|err| err.to_string())?;
+        let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+        let value = upgrade(value, version as usize, map)?;
+        serde_json::from_value(value).map_err(|err| err.to_string())
+    }
+
+    pub fn is_legacy(path: &str) -> bool {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+            .map(|value| value.get("version").is_none())
+            .unwrap_or(false)
+    }
+}
+
+// Each step transforms the JSON from one version to the next.
+fn upgrade(
+    value: serde_json::Value,
+    version: usize,
+    map: &Map,
+) -> Result<serde_json::Value, String> {
+    if version > EDITS_VERSION {
+        return Err(format!(
+            "Edits are version {}, but only up to {} is supported",
+            version, EDITS_VERSION
+        ));
+    }
+    if version == 0 {
+        // This goes through MapEdits, so it skips straight to the current version.
+        return upgrade_legacy(value, map);
+    }
+    Ok(value)
+}
+
+// Version 0 is just MapEdits, using the dense IDs of the map it was made on.
+fn upgrade_legacy(value: serde_json::Value, map: &Map) -> Result<serde_json::Value, String> {
+    let legacy: MapEdits = serde_json::from_value(value).map_err(|err| err.to_string())?;
+    legacy.check_legacy_ids(map)?;
+    serde_json::to_value(PermanentMapEdits::from_edits(&legacy, map)).map_err(|err| err.to_string())
+}
+
+impl MapEdits {
+    // The IDs in legacy edits can't be verified for real, but at least catch edits from a
+    // completely different map.
+    fn check_legacy_ids(&self, map: &Map) -> Result<(), String> {
+        let lane = |l: &LaneID| l.0 < map.all_lanes().len();
+        let intersection = |i: &IntersectionID| i.0 < map.all_intersections().len();
+        let road = |r: &RoadID| r.0 < map.all_roads().len();
+        for cmd in &self.commands {
+            let ok = match cmd {
+                EditCmd::ChangeLaneType { id, orig_lt, .. } => {
+                    lane(id) && map.get_l(*id).lane_type == *orig_lt
+                }
+                EditCmd::ReverseLane { l, dst_i } => lane(l) && intersection(dst_i),
+                EditCmd::ChangeStopSign(ss) => intersection(&ss.id) && ss.roads.keys().all(road),
+                EditCmd::ChangeTrafficSignal(ts) => {
+                    intersection(&ts.id)
+                        && ts.turn_groups.keys().all(|g| {
+                            road(&g.from)
+                                && road(&g.to)
+                                && g.crosswalk
+                                    .map(|t| lane(&t.src) && lane(&t.dst))
+                                    .unwrap_or(true)
+                        })
+                }
+                EditCmd::CloseIntersection { id, .. } | EditCmd::UncloseIntersection(id, _) => {
+                    intersection(id)
+                }
+                EditCmd::ChangeParkingPolicy { id, .. } => lane(id),
+                EditCmd::ChangeOffstreetParkingPolicy { b, .. } => b.0 < map.all_buildings().len(),
+            };
+            if !ok {
+                return Err(format!(
+                    "Legacy edits {} don't match map {}: {:?}",
+                    self.edits_name,
+                    map.get_name(),
+                    cmd
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginalLane {
+    fn new(l: LaneID, map: &Map) -> OriginalLane {
+        let r = map.get_parent(l);
+        let lanes = lanes_across(r.id, map);
+        OriginalLane {
+            parent: r.orig_id,
+            idx: lanes.iter().position(|x| *x == l).unwrap(),
+            num_lanes: lanes.len(),
+        }
+    }
+
+    fn resolve(self, map: &Map, lookup: &Lookup) -> Result<LaneID, String> {
+        let r = lookup.road(self.parent)?;
+        let lanes = lanes_across(r, map);
+        if lanes.len() != self.num_lanes {
+            return Err(format!(
+                "{} now has {} lanes, not {}",
+                self.parent,
+                lanes.len(),
+                self.num_lanes
+            ));
+        }
+        Ok(lanes[self.idx])
+    }
+}
+
+impl PermanentTurnGroup {
+    fn new(id: TurnGroupID, map: &Map) -> PermanentTurnGroup {
+        PermanentTurnGroup {
+            from: map.get_r(id.from).orig_id,
+            to: map.get_r(id.to).orig_id,
+            crosswalk: id
+                .crosswalk
+                .map(|t| (OriginalLane::new(t.src, map), OriginalLane::new(t.dst, map))),
+        }
+    }
+
+    fn resolve(
+        &self,
+        i: IntersectionID,
+        map: &Map,
+        lookup: &Lookup,
+    ) -> Result<TurnGroupID, String> {
+        let crosswalk = match self.crosswalk {
+            Some((src, dst)) => Some(TurnID {
+                parent: i,
+                src: src.resolve(map, lookup)?,
+                dst: dst.resolve(map, lookup)?,
+            }),
+            None => None,
+        };
+        Ok(TurnGroupID {
+            from: lookup.road(self.from)?,
+            to: lookup.road(self.to)?,
+            crosswalk,
+        })
+    }
+}
+
+impl PermanentEditCmd {
+    fn resolve(self, map: &Map, lookup: &Lookup) -> Result<EditCmd, String> {
+        match self {
+            PermanentEditCmd::ChangeLaneType { id, lt, orig_lt } => {
+                let l = id.resolve(map, lookup)?;
+                // The map might already have other edits applied
+                let base_lt = map
+                    .get_edits()
+                    .original_lts
+                    .get(&l)
+                    .cloned()
+                    .unwrap_or_else(|| map.get_l(l).lane_type);
+                if base_lt != orig_lt {
+                    return Err(format!(
+                        "Lane {} of {} is now {:?}, not {:?}",
+                        id.idx, id.parent, base_lt, orig_lt
+                    ));
+                }
+                Ok(EditCmd::ChangeLaneType { id: l, lt, orig_lt })
+            }
+            PermanentEditCmd::ReverseLane { l, dst_i } => {
+                let id = l.resolve(map, lookup)?;
+                let dst_i = lookup.intersection(dst_i)?;
+                let r = map.get_parent(id);
+                if r.src_i != dst_i && r.dst_i != dst_i {
+                    return Err(format!("{} doesn't end at {}", l.parent, dst_i));
+                }
+                Ok(EditCmd::ReverseLane { l: id, dst_i })
+            }
+            PermanentEditCmd::ChangeStopSign { id, must_stop } => {
+                let i = lookup.intersection(id)?;
+                if map.get_i(i).intersection_type != IntersectionType::StopSign {
+                    return Err(format!("{} isn't a stop sign anymore", id));
+                }
+                let mut ss = ControlStopSign::new(map, i);
+                for (r, stop) in must_stop {
+                    let road = lookup.road(r)?;
+                    match ss.roads.get_mut(&road) {
+                        Some(cfg) => {
+                            cfg.must_stop = stop;
+                        }
+                        None => {
+                            return Err(format!("{} doesn't have a stop sign for {}", id, r));
+                        }
+                    }
+                }
+                Ok(EditCmd::ChangeStopSign(ss))
+            }
+            PermanentEditCmd::ChangeTrafficSignal(ts) => {
+                let orig_id = ts.id;
+                let i = lookup.intersection(orig_id)?;
+                if map.get_i(i).intersection_type != IntersectionType::TrafficSignal {
+                    return Err(format!("{} isn't a traffic signal anymore", orig_id));
+                }
+                let mut phases = Vec::new();
+                for p in ts.phases {
+                    let mut phase = Phase::new();
+                    for g in p.protected_groups {
+                        phase.protected_groups.insert(g.resolve(i, map, lookup)?);
+                    }
+                    for g in p.yield_groups {
+                        phase.yield_groups.insert(g.resolve(i, map, lookup)?);
+                    }
+                    phase.duration = p.duration;
+                    phase.actuated = p.actuated;
+                    phases.push(phase);
+                }
+                // TODO If other edits in the same list change the turns here, these groups are
+                // stale and validation fails.
+                ControlTrafficSignal {
+                    id: i,
+                    phases,
+                    offset: ts.offset,
+                    turn_groups: TurnGroup::for_i(i, map),
+                }
+                .validate()
+                .map(EditCmd::ChangeTrafficSignal)
+                .map_err(|err| format!("Traffic signal for {} no longer fits: {}", orig_id, err))
+            }
+            PermanentEditCmd::CloseIntersection { id, orig_it } => Ok(EditCmd::CloseIntersection {
+                id: lookup.intersection(id)?,
+                orig_it,
+            }),
+            PermanentEditCmd::UncloseIntersection(id, orig_it) => Ok(EditCmd::UncloseIntersection(
+                lookup.intersection(id)?,
+                orig_it,
+            )),
+            PermanentEditCmd::ChangeParkingPolicy {
+                id,
+                policy,
+                orig_policy,
+            } => Ok(EditCmd::ChangeParkingPolicy {
+                id: id.resolve(map, lookup)?,
+                policy,
+                orig_policy,
+            }),
+            PermanentEditCmd::ChangeOffstreetParkingPolicy {
+                b,
+                policy,
+                orig_policy,
+            } => {
+                let id = lookup.building(b)?;
+                if map.get_b(id).parking.is_none() {
+                    return Err(format!("{} doesn't have parking anymore", b));
+                }
+                Ok(EditCmd::ChangeOffstreetParkingPolicy {
+                    b: id,
+                    policy,
+                    orig_policy,
+                })
+            }
+        }
+    }
+}
+
+// Left to right across the road, facing from src_i to dst_i
+fn lanes_across(r: RoadID, map: &Map) -> Vec<LaneID> {
+    let road = map.get_r(r);
+    road.children_backwards
+        .iter()
+        .rev()
+        .chain(road.children_forwards.iter())
+        .map(|(l, _)| *l)
+        .collect()
+}
+
+struct Lookup {
+    roads: BTreeMap<OriginalRoad, RoadID>,
+    intersections: BTreeMap<OriginalIntersection, IntersectionID>,
+    buildings: BTreeMap<OriginalBuilding, BuildingID>,
+}
+
+impl Lookup {
+    fn new(map: &Map) -> Lookup {
+        Lookup {
+            roads: map.all_roads().iter().map(|r| (r.orig_id, r.id)).collect(),
+            intersections: map
+                .all_intersections()
+                .iter()
+                .map(|i| (i.orig_id, i.id))
+                .collect(),
+            buildings: map
+                .all_buildings()
+                .iter()
+                .map(|b| {
+                    (
+                        OriginalBuilding {
+                            osm_way_id: b.osm_way_id,
+                        },
+                        b.id,
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn road(&self, id: OriginalRoad) -> Result<RoadID, String> {
+        self.roads
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| format!("Can't find {}", id))
+    }
+
+    fn intersection(&self, id: OriginalIntersection) -> Result<IntersectionID, String> {
+        self.intersections
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| format!("Can't find {}", id))
+    }
+
+    fn building(&self, id: OriginalBuilding) -> Result<BuildingID, String> {
+        self.buildings
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| format!("Can't find {}", id))
+    }
+}
+
+// Edits that still use the dense IDs of an old map
+pub fn find_legacy_edits(map_name: &str) -> Vec<String> {
+    abstutil::list_all_objects(abstutil::path_all_edits(map_name))
+        .into_iter()
+        .filter(|name| PermanentMapEdits::is_legacy(&abstutil::path_edits(map_name, name)))
+        .collect()
+}
+
+// Rewrite legacy edits in the current format, using the map they were made for.
+pub fn upgrade_legacy_edits(old_map: &Map, names: Vec<String>, timer: &mut abstutil::Timer) {
+    for name in names {
+        let path = abstutil::path_edits(old_map.get_name(), &name);
+        match PermanentMapEdits::read(&path, old_map) {
+            Ok(perma) => {
+                abstutil::write_json(path, &perma);
+            }
+            Err(err) => {
+                timer.warn(format!("Couldn't upgrade {}: {}", path, err));
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::raw::OriginalIntersection;
 use crate::{DirectedRoadID, LaneID, Map, PathConstraints, Road, RoadID, TurnID};
-use geom::Polygon;
+use geom::{Distance, Polygon};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::BTreeSet;
 use std::fmt;
@@ -23,6 +23,15 @@ pub enum IntersectionType {
     Construction,
 }
 
+// From OSM highway=crossing nodes. These split roads even when nothing else meets there.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum CrossingType {
+    // Marked crossings without a signal. Vehicles yield to pedestrians.
+    Zebra,
+    // Pedestrians push a button and wait for their phase.
+    Signalized,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Intersection {
     pub id: IntersectionID,
@@ -32,6 +41,8 @@ pub struct Intersection {
 
     pub intersection_type: IntersectionType,
     pub orig_id: OriginalIntersection,
+    pub elevation: Distance,
+    pub crossing: Option<CrossingType>,
 
     // Note that a lane may belong to both incoming_lanes and outgoing_lanes.
     // TODO narrow down when and why. is it just sidewalks in weird cases?
@@ -1,11 +1,11 @@
 use crate::pathfind;
 use crate::{
-    osm, BuildingID, BusStopID, DirectedRoadID, IntersectionID, Map, PathConstraints, Road, RoadID,
-    TurnType,
+    osm, BuildingID, BusStopID, DirectedRoadID, DrivingSide, IntersectionID, Map, PathConstraints,
+    Road, RoadID, TurnType,
 };
-use geom::{Angle, Distance, Line, PolyLine, Pt2D};
+use geom::{Angle, Distance, Duration, Line, PolyLine, Pt2D};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 
 // Bit longer than the longest car.
@@ -70,6 +70,121 @@ impl LaneType {
     }
 }
 
+// Rules for parking along a lane or in a building's lot. Everything is free and unlimited by
+// default.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParkingPolicy {
+    pub hourly_price_cents: usize,
+    pub max_stay: Option<Duration>,
+    // Only residents of this zone may park here.
+    pub permit_zone: Option<String>,
+}
+
+impl ParkingPolicy {
+    pub fn free() -> ParkingPolicy {
+        ParkingPolicy {
+            hourly_price_cents: 0,
+            max_stay: None,
+            permit_zone: None,
+        }
+    }
+
+    // Reads parking:lane:{right,left,both}:{fee,maxstay,residents}. Forwards lanes are on the
+    // right, unless driving on the left.
+    pub fn from_osm(
+        osm_tags: &BTreeMap<String, String>,
+        fwds: bool,
+        driving_side: DrivingSide,
+    ) -> ParkingPolicy {
+        let (fwd_side, back_side) = driving_side.osm_sides();
+        let side = format!("parking:lane:{}", if fwds { fwd_side } else { back_side });
+        let get = |key: &str| {
+            osm_tags
+                .get(&format!("{}:{}", side, key))
+                .or_else(|| osm_tags.get(&format!("{}:{}", osm::PARKING_BOTH, key)))
+        };
+
+        let mut policy = ParkingPolicy::free();
+        if let Some(fee) = get("fee") {
+            policy.hourly_price_cents = parse_fee(fee);
+        }
+        policy.max_stay = get("maxstay").and_then(|x| parse_maxstay(x));
+        policy.permit_zone = get("residents").cloned();
+        policy
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.hourly_price_cents == 0
+    }
+
+    // Residents of a permit zone live along a road with parking in that zone.
+    pub fn allows(&self, owner: Option<BuildingID>, map: &Map) -> bool {
+        let zone = match self.permit_zone {
+            Some(ref z) => z,
+            None => {
+                return true;
+            }
+        };
+        match owner {
+            Some(b) => map
+                .get_parent(map.get_b(b).sidewalk())
+                .all_lanes()
+                .into_iter()
+                .any(|l| map.get_l(l).parking_policy.permit_zone.as_ref() == Some(zone)),
+            None => false,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.is_free() {
+            parts.push("free".to_string());
+        } else {
+            parts.push(format!(
+                "${}.{:02}/hour",
+                self.hourly_price_cents / 100,
+                self.hourly_price_cents % 100
+            ));
+        }
+        if let Some(d) = self.max_stay {
+            parts.push(format!("{} max", d));
+        }
+        if let Some(ref zone) = self.permit_zone {
+            parts.push(format!("zone {} permit only", zone));
+        }
+        parts.join(", ")
+    }
+}
+
+// Either yes/no or a price in dollars per hour, like "2.50" or "2.50 USD/hour". A fee of unknown
+// amount gets a typical price.
+fn parse_fee(value: &str) -> usize {
+    if value == "no" {
+        return 0;
+    }
+    let amount: String = value
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    match amount.parse::<f64>() {
+        Ok(dollars) => (dollars * 100.0).round() as usize,
+        Err(_) => 200,
+    }
+}
+
+// Like "2 hours" or "90 min". A bare number is in minutes.
+fn parse_maxstay(value: &str) -> Option<Duration> {
+    let mut parts = value.split_whitespace();
+    let amount = parts.next()?.parse::<f64>().ok()?;
+    match parts.next() {
+        Some(unit) if unit.starts_with('h') => Some(Duration::seconds(amount * 3600.0)),
+        Some(unit) if unit.starts_with("min") => Some(Duration::seconds(amount * 60.0)),
+        None => Some(Duration::seconds(amount * 60.0)),
+        _ => None,
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Lane {
     pub id: LaneID,
@@ -87,6 +202,8 @@ pub struct Lane {
     // If set, cars trying to park near here should actually start their search at this other lane.
     // Only populated for driving lanes inevitably leading to borders.
     pub parking_blackhole: Option<LaneID>,
+    // Only meaningful for parking lanes
+    pub parking_policy: ParkingPolicy,
 }
 
 impl Lane {
@@ -143,6 +260,12 @@ impl Lane {
         self.lane_center_pts.length()
     }
 
+    // Rise over run in the direction of travel, so positive is uphill
+    pub fn grade(&self, map: &Map) -> f64 {
+        let rise = map.get_i(self.dst_i).elevation - map.get_i(self.src_i).elevation;
+        rise / self.length()
+    }
+
     pub fn intersections(&self) -> Vec<IntersectionID> {
         // TODO I think we're assuming there are no loop lanes
         vec![self.src_i, self.dst_i]
@@ -2,6 +2,7 @@ mod area;
 mod building;
 mod bus_stop;
 pub mod connectivity;
+pub mod coordination;
 mod edits;
 mod intersection;
 mod lane;
@@ -14,22 +15,29 @@ pub mod raw;
 mod road;
 mod stop_signs;
 mod traffic_signals;
+mod travel_times;
 mod traversable;
 mod turn;
 
 pub use crate::area::{Area, AreaID, AreaType};
 pub use crate::building::{Building, BuildingID, FrontPath, OffstreetParking};
 pub use crate::bus_stop::{BusRoute, BusRouteID, BusStop, BusStopID};
-pub use crate::edits::{EditCmd, EditEffects, MapEdits};
-pub use crate::intersection::{Intersection, IntersectionID, IntersectionType};
-pub use crate::lane::{Lane, LaneID, LaneType, PARKING_SPOT_LENGTH};
+pub use crate::edits::{
+    find_legacy_edits, upgrade_legacy_edits, EditCmd, EditEffects, MapEdits, PermanentMapEdits,
+};
+pub use crate::intersection::{CrossingType, Intersection, IntersectionID, IntersectionType};
+pub use crate::lane::{Lane, LaneID, LaneType, ParkingPolicy, PARKING_SPOT_LENGTH};
 pub use crate::make::RoadSpec;
 pub use crate::map::Map;
 pub use crate::neighborhood::{FullNeighborhoodInfo, Neighborhood, NeighborhoodBuilder};
-pub use crate::pathfind::{Path, PathConstraints, PathRequest, PathStep};
+pub use crate::pathfind::{
+    find_transit_rides, Path, PathConstraints, PathRequest, PathStep, WALKING_SPEED,
+};
+pub use crate::raw::DrivingSide;
 pub use crate::road::{DirectedRoadID, Road, RoadID};
 pub use crate::stop_signs::{ControlStopSign, RoadWithStopSign};
-pub use crate::traffic_signals::{ControlTrafficSignal, Phase};
+pub use crate::traffic_signals::{ActuatedPhase, ControlTrafficSignal, Phase};
+pub use crate::travel_times::TravelTimes;
 pub use crate::traversable::{Position, Traversable};
 pub use crate::turn::{Turn, TurnGroup, TurnGroupID, TurnID, TurnPriority, TurnType};
 use abstutil::Cloneable;
@@ -43,7 +43,15 @@ pub fn make_all_buildings(
     }
 
     // Skip buildings that're too far away from their sidewalk
-    let sidewalk_pts = find_sidewalk_points(bounds, query, lanes, Distance::meters(100.0), timer);
+    let sidewalk_pts = find_sidewalk_points(
+        bounds,
+        query,
+        lanes,
+        roads,
+        true,
+        Distance::meters(100.0),
+        timer,
+    );
 
     timer.start_iter("create building front paths", center_per_bldg.len());
     for (orig_id, bldg_center) in center_per_bldg {
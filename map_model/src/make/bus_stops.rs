@@ -4,7 +4,7 @@ use crate::{
     Position,
 };
 use abstutil::{MultiMap, Timer};
-use geom::{Bounds, Distance, GPSBounds, HashablePt2D, Pt2D};
+use geom::{Bounds, Distance, GPSBounds, HashablePt2D, Pt2D, Time};
 use gtfs;
 use std::collections::{BTreeMap, HashMap, HashSet};
 
@@ -17,16 +17,10 @@ pub fn make_bus_stops(
 ) -> (BTreeMap<BusStopID, BusStop>, Vec<BusRoute>) {
     timer.start("make bus stops");
     let mut bus_stop_pts: HashSet<HashablePt2D> = HashSet::new();
-    let mut route_lookups: HashMap<String, Vec<HashablePt2D>> = HashMap::new();
     for route in bus_routes {
         for gps in &route.stops {
             if let Some(pt) = Pt2D::from_gps(*gps, gps_bounds) {
-                let hash_pt = pt.to_hashable();
-                bus_stop_pts.insert(hash_pt);
-                route_lookups
-                    .entry(route.name.clone())
-                    .or_insert_with(Vec::new)
-                    .push(hash_pt);
+                bus_stop_pts.insert(pt.to_hashable());
             }
         }
     }
@@ -36,6 +30,8 @@ pub fn make_bus_stops(
         bounds,
         bus_stop_pts,
         map.all_lanes(),
+        map.all_roads(),
+        false,
         Distance::meters(10.0),
         timer,
     )
@@ -80,19 +76,30 @@ pub fn make_bus_stops(
 
     let mut routes: Vec<BusRoute> = Vec::new();
     for route in bus_routes {
-        let route_name = route.name.to_string();
-        let stops: Vec<BusStopID> = route_lookups
-            .remove(&route_name)
-            .unwrap_or_else(Vec::new)
-            .into_iter()
-            .filter_map(|pt| point_to_stop_id.get(&pt))
-            .cloned()
-            .collect();
+        let mut stops: Vec<BusStopID> = Vec::new();
+        let mut old_to_new_idx: Vec<Option<usize>> = Vec::new();
+        for gps in &route.stops {
+            if let Some(stop) = Pt2D::from_gps(*gps, gps_bounds)
+                .and_then(|pt| point_to_stop_id.get(&pt.to_hashable()))
+            {
+                old_to_new_idx.push(Some(stops.len()));
+                stops.push(*stop);
+            } else {
+                old_to_new_idx.push(None);
+            }
+        }
         let id = BusRouteID(routes.len());
         routes.push(BusRoute {
             id,
-            name: route_name.to_string(),
+            name: route.name.to_string(),
+            long_name: route.long_name.to_string(),
+            route_type: route.route_type.to_string(),
+            color: route.color.clone(),
+            agency: route.agency.clone(),
             stops,
+            is_loop: false,
+            opposite_route: None,
+            schedule: remap_schedule(&route.trips, &old_to_new_idx),
         });
     }
     timer.stop("make bus stops");
@@ -102,27 +109,56 @@ pub fn make_bus_stops(
 pub fn fix_bus_route(map: &Map, r: &mut BusRoute) -> bool {
     // Trim out stops if needed; map borders sometimes mean some paths don't work.
     let mut stops = Vec::new();
+    let mut old_to_new_idx: Vec<Option<usize>> = Vec::new();
     for stop in r.stops.drain(..) {
-        if stops.is_empty() {
+        if stops.is_empty() || check_stops(*stops.last().unwrap(), stop, map) {
+            old_to_new_idx.push(Some(stops.len()));
             stops.push(stop);
         } else {
-            if check_stops(*stops.last().unwrap(), stop, map) {
-                stops.push(stop);
-            }
+            old_to_new_idx.push(None);
         }
     }
     // Don't forget the last and first
-    while stops.len() >= 2 {
+    while r.is_loop && stops.len() >= 2 {
         if check_stops(*stops.last().unwrap(), stops[0], map) {
             break;
         }
         // TODO Or the front one
         stops.pop();
+        let removed = stops.len();
+        for idx in old_to_new_idx.iter_mut() {
+            if *idx == Some(removed) {
+                *idx = None;
+            }
+        }
     }
     r.stops = stops;
+    r.schedule = remap_schedule(&r.schedule, &old_to_new_idx);
     r.stops.len() >= 2
 }
 
+// Some stops get dropped; fix up the indices in a timetable. Trips that don't visit at least two
+// of the remaining stops are dropped.
+fn remap_schedule(
+    schedule: &Vec<Vec<(usize, Time)>>,
+    old_to_new_idx: &Vec<Option<usize>>,
+) -> Vec<Vec<(usize, Time)>> {
+    schedule
+        .iter()
+        .filter_map(|trip| {
+            let new_trip: Vec<(usize, Time)> = trip
+                .iter()
+                .filter_map(|(idx, t)| old_to_new_idx[*idx].map(|new_idx| (new_idx, *t)))
+                .collect();
+            if new_trip.len() >= 2 {
+                Some(new_trip)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
 fn check_stops(stop1: BusStopID, stop2: BusStopID, map: &Map) -> bool {
     let bs1 = map.get_bs(stop1);
     let bs2 = map.get_bs(stop2);
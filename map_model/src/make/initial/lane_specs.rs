@@ -1,10 +1,14 @@
+use crate::raw::DrivingSide;
 use crate::{osm, LaneType};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::{fmt, iter};
 
 // (original direction, reversed direction)
-pub fn get_lane_types(osm_tags: &BTreeMap<String, String>) -> (Vec<LaneType>, Vec<LaneType>) {
+pub fn get_lane_types(
+    osm_tags: &BTreeMap<String, String>,
+    driving_side: DrivingSide,
+) -> (Vec<LaneType>, Vec<LaneType>) {
     if let Some(s) = osm_tags.get(osm::SYNTHETIC_LANES) {
         if let Some(spec) = RoadSpec::parse(s.to_string()) {
             return (spec.fwd, spec.back);
@@ -14,17 +18,21 @@ pub fn get_lane_types(osm_tags: &BTreeMap<String, String>) -> (Vec<LaneType>, Ve
     }
 
     // Easy special cases first.
-    if osm_tags.get("junction") == Some(&"roundabout".to_string()) {
-        return (vec![LaneType::Driving, LaneType::Sidewalk], Vec::new());
-    }
-    if osm_tags.get(osm::HIGHWAY) == Some(&"footway".to_string()) {
-        return (vec![LaneType::Sidewalk], Vec::new());
+    if let Some(lts) = get_trail_lane_types(osm_tags) {
+        return lts;
     }
 
+    // Which of OSM's left and right is next to each direction of lanes
+    let (fwd, back) = driving_side.osm_sides();
+
     // TODO Reversible roads should be handled differently?
-    let oneway = osm_tags.get("oneway") == Some(&"yes".to_string())
+    let oneway = osm::is_roundabout(osm_tags)
+        || osm_tags.get("oneway") == Some(&"yes".to_string())
         || osm_tags.get("oneway") == Some(&"reversible".to_string());
 
+    // When OSM doesn't say how many lanes there are, convert_osm fills it in from the import
+    // profile. Only raw maps from before that fall back to one lane each way.
+
     // How many driving lanes in each direction?
     let num_driving_fwd = if let Some(n) = osm_tags
         .get("lanes:forward")
@@ -44,7 +52,6 @@ pub fn get_lane_types(osm_tags: &BTreeMap<String, String>) -> (Vec<LaneType>, Ve
             (n / 2).max(1)
         }
     } else {
-        // TODO Grrr.
         1
     };
     let num_driving_back = if let Some(n) = osm_tags
@@ -64,13 +71,10 @@ pub fn get_lane_types(osm_tags: &BTreeMap<String, String>) -> (Vec<LaneType>, Ve
             // TODO Really, this is ambiguous, but...
             (n / 2).max(1)
         }
+    } else if oneway {
+        0
     } else {
-        // TODO Grrr.
-        if oneway {
-            0
-        } else {
-            1
-        }
+        1
     };
 
     let mut fwd_side: Vec<LaneType> = iter::repeat(LaneType::Driving)
@@ -103,10 +107,10 @@ pub fn get_lane_types(osm_tags: &BTreeMap<String, String>) -> (Vec<LaneType>, Ve
             back_side.push(LaneType::Biking);
         }
     } else {
-        if osm_tags.get("cycleway:right") == Some(&"lane".to_string()) {
+        if osm_tags.get(&format!("cycleway:{}", fwd)) == Some(&"lane".to_string()) {
             fwd_side.push(LaneType::Biking);
         }
-        if osm_tags.get("cycleway:left") == Some(&"lane".to_string()) {
+        if osm_tags.get(&format!("cycleway:{}", back)) == Some(&"lane".to_string()) {
             back_side.push(LaneType::Biking);
         }
     }
@@ -116,9 +120,9 @@ pub fn get_lane_types(osm_tags: &BTreeMap<String, String>) -> (Vec<LaneType>, Ve
             || value == Some(&"diagonal".to_string())
             || value == Some(&"perpendicular".to_string())
     }
-    let parking_lane_fwd = has_parking(osm_tags.get(osm::PARKING_RIGHT))
+    let parking_lane_fwd = has_parking(osm_tags.get(&format!("parking:lane:{}", fwd)))
         || has_parking(osm_tags.get(osm::PARKING_BOTH));
-    let parking_lane_back = has_parking(osm_tags.get(osm::PARKING_LEFT))
+    let parking_lane_back = has_parking(osm_tags.get(&format!("parking:lane:{}", back)))
         || has_parking(osm_tags.get(osm::PARKING_BOTH));
     if parking_lane_fwd {
         fwd_side.push(LaneType::Parking);
@@ -133,15 +137,49 @@ pub fn get_lane_types(osm_tags: &BTreeMap<String, String>) -> (Vec<LaneType>, Ve
     {
         fwd_side.push(LaneType::Sidewalk);
         back_side.push(LaneType::Sidewalk);
-    } else if osm_tags.get(osm::SIDEWALK) == Some(&"right".to_string()) {
+    } else if osm_tags.get(osm::SIDEWALK) == Some(&fwd.to_string()) {
         fwd_side.push(LaneType::Sidewalk);
-    } else if osm_tags.get(osm::SIDEWALK) == Some(&"left".to_string()) {
+    } else if osm_tags.get(osm::SIDEWALK) == Some(&back.to_string()) {
         back_side.push(LaneType::Sidewalk);
     }
 
     (fwd_side, back_side)
 }
 
+// Footways, cycleways, and other paths that cars can't use. Bikes get a lane in each direction, and
+// pedestrians can walk either way along a single sidewalk.
+fn get_trail_lane_types(
+    osm_tags: &BTreeMap<String, String>,
+) -> Option<(Vec<LaneType>, Vec<LaneType>)> {
+    let tag_is = |key: &str, values: &[&str]| {
+        osm_tags
+            .get(key)
+            .map(|v| values.contains(&v.as_str()))
+            .unwrap_or(false)
+    };
+    let (bikes, peds) = match osm_tags.get(osm::HIGHWAY)?.as_str() {
+        "cycleway" => (true, !tag_is("foot", &["no"])),
+        "footway" | "path" | "pedestrian" => (tag_is("bicycle", &["yes", "designated"]), true),
+        "steps" => (false, true),
+        _ => {
+            return None;
+        }
+    };
+
+    let mut fwd_side = Vec::new();
+    let mut back_side = Vec::new();
+    if bikes {
+        fwd_side.push(LaneType::Biking);
+        if !tag_is("oneway", &["yes"]) {
+            back_side.push(LaneType::Biking);
+        }
+    }
+    if peds {
+        fwd_side.push(LaneType::Sidewalk);
+    }
+    Some((fwd_side, back_side))
+}
+
 // This is a convenient way for map_editor to plumb instructions here.
 #[derive(Serialize, Deserialize)]
 pub struct RoadSpec {
@@ -2,8 +2,8 @@ mod geometry;
 pub mod lane_specs;
 
 pub use self::geometry::intersection_polygon;
-use crate::raw::{OriginalIntersection, OriginalRoad, RawMap, RawRoad};
-use crate::{IntersectionType, LaneType, LANE_THICKNESS};
+use crate::raw::{DrivingSide, OriginalIntersection, OriginalRoad, RawMap, RawRoad};
+use crate::{CrossingType, IntersectionType, LaneType, LANE_THICKNESS};
 use abstutil::Timer;
 use geom::{Bounds, Distance, PolyLine, Pt2D};
 use std::collections::{BTreeMap, BTreeSet};
@@ -23,14 +23,15 @@ pub struct Road {
     pub dst_i: OriginalIntersection,
     pub original_center_pts: PolyLine,
     pub trimmed_center_pts: PolyLine,
-    pub fwd_width: Distance,
-    pub back_width: Distance,
+    // Total width of the lanes on each side of the center line
+    pub right_width: Distance,
+    pub left_width: Distance,
     pub lane_specs: Vec<LaneSpec>,
 }
 
 impl Road {
-    pub fn new(id: OriginalRoad, r: &RawRoad) -> Road {
-        let lane_specs = get_lane_specs(&r.osm_tags);
+    pub fn new(id: OriginalRoad, r: &RawRoad, driving_side: DrivingSide) -> Road {
+        let lane_specs = get_lane_specs(&r.osm_tags, driving_side);
         let mut fwd_width = Distance::ZERO;
         let mut back_width = Distance::ZERO;
         for l in &lane_specs {
@@ -40,6 +41,10 @@ impl Road {
                 fwd_width += LANE_THICKNESS;
             }
         }
+        let (right_width, left_width) = match driving_side {
+            DrivingSide::Right => (fwd_width, back_width),
+            DrivingSide::Left => (back_width, fwd_width),
+        };
 
         let center_pts = PolyLine::new(r.center_points.clone());
         Road {
@@ -48,8 +53,8 @@ impl Road {
             dst_i: id.i2,
             original_center_pts: center_pts.clone(),
             trimmed_center_pts: center_pts,
-            fwd_width,
-            back_width,
+            right_width,
+            left_width,
             lane_specs,
         }
     }
@@ -61,6 +66,8 @@ pub struct Intersection {
     pub polygon: Vec<Pt2D>,
     pub roads: BTreeSet<OriginalRoad>,
     pub intersection_type: IntersectionType,
+    pub elevation: Distance,
+    pub crossing: Option<CrossingType>,
 }
 
 impl InitialMap {
@@ -80,6 +87,8 @@ impl InitialMap {
                     polygon: Vec::new(),
                     roads: BTreeSet::new(),
                     intersection_type: i.intersection_type,
+                    elevation: i.elevation,
+                    crossing: i.crossing,
                 },
             );
         }
@@ -92,7 +101,7 @@ impl InitialMap {
             m.intersections.get_mut(&id.i1).unwrap().roads.insert(*id);
             m.intersections.get_mut(&id.i2).unwrap().roads.insert(*id);
 
-            m.roads.insert(*id, Road::new(*id, r));
+            m.roads.insert(*id, Road::new(*id, r, raw.driving_side));
         }
 
         timer.start_iter("find each intersection polygon", m.intersections.len());
@@ -111,8 +120,11 @@ pub struct LaneSpec {
     pub reverse_pts: bool,
 }
 
-pub fn get_lane_specs(osm_tags: &BTreeMap<String, String>) -> Vec<LaneSpec> {
-    let (side1_types, side2_types) = lane_specs::get_lane_types(osm_tags);
+pub fn get_lane_specs(
+    osm_tags: &BTreeMap<String, String>,
+    driving_side: DrivingSide,
+) -> Vec<LaneSpec> {
+    let (side1_types, side2_types) = lane_specs::get_lane_types(osm_tags, driving_side);
 
     let mut specs: Vec<LaneSpec> = Vec::new();
     for lane_type in side1_types {
@@ -1,14 +1,17 @@
-use crate::{Lane, LaneID, Position};
+use crate::{Lane, LaneID, Position, Road};
 use abstutil::Timer;
 use geom::{Bounds, Distance, FindClosest, HashablePt2D};
 use std::collections::{HashMap, HashSet};
 
 // If the result doesn't contain a requested point, then there was no matching sidewalk close
-// enough.
+// enough. Points near both a street and a trail will prefer the street's sidewalk. Trails can be
+// excluded entirely.
 pub fn find_sidewalk_points(
     bounds: &Bounds,
     pts: HashSet<HashablePt2D>,
     lanes: &Vec<Lane>,
+    roads: &Vec<Road>,
+    use_trails: bool,
     max_dist_away: Distance,
     timer: &mut Timer,
 ) -> HashMap<HashablePt2D, Position> {
@@ -16,12 +19,17 @@ pub fn find_sidewalk_points(
         return HashMap::new();
     }
 
-    let mut closest: FindClosest<LaneID> = FindClosest::new(bounds);
+    let mut closest_streets: FindClosest<LaneID> = FindClosest::new(bounds);
+    let mut closest_trails: FindClosest<LaneID> = FindClosest::new(bounds);
     timer.start_iter("index lanes", lanes.len());
     for l in lanes {
         timer.next();
         if l.is_sidewalk() {
-            closest.add(l.id, l.lane_center_pts.points());
+            if !roads[l.parent.0].is_trail() {
+                closest_streets.add(l.id, l.lane_center_pts.points());
+            } else if use_trails {
+                closest_trails.add(l.id, l.lane_center_pts.points());
+            }
         }
     }
 
@@ -31,7 +39,9 @@ pub fn find_sidewalk_points(
     timer.start_iter("find closest sidewalk point", pts.len());
     for query_pt in pts {
         timer.next();
-        if let Some((sidewalk, sidewalk_pt)) = closest.closest_pt(query_pt.to_pt2d(), max_dist_away)
+        if let Some((sidewalk, sidewalk_pt)) = closest_streets
+            .closest_pt(query_pt.to_pt2d(), max_dist_away)
+            .or_else(|| closest_trails.closest_pt(query_pt.to_pt2d(), max_dist_away))
         {
             if let Some(dist_along) = lanes[sidewalk.0].dist_along_of_point(sidewalk_pt) {
                 results.insert(query_pt, Position::new(sidewalk, dist_along));
@@ -1,4 +1,4 @@
-use crate::raw::RestrictionType;
+use crate::raw::{DrivingSide, RestrictionType};
 use crate::{
     Intersection, IntersectionID, Lane, LaneID, LaneType, Road, RoadID, Turn, TurnID, TurnType,
     LANE_THICKNESS,
@@ -14,13 +14,20 @@ pub fn make_all_turns(
     i: &Intersection,
     roads: &Vec<Road>,
     lanes: &Vec<Lane>,
+    driving_side: DrivingSide,
     timer: &mut Timer,
 ) -> Vec<Turn> {
     assert!(!i.is_border());
 
     let mut raw_turns: Vec<Turn> = Vec::new();
-    raw_turns.extend(make_vehicle_turns(i, roads, lanes, timer));
-    raw_turns.extend(make_walking_turns(i, roads, lanes, timer));
+    raw_turns.extend(make_vehicle_turns(i, roads, lanes, driving_side, timer));
+    // A trail's sidewalk can be found from both sides, so it might get the same corner twice.
+    let mut walking_ids = HashSet::new();
+    raw_turns.extend(
+        make_walking_turns(i, roads, lanes, driving_side, timer)
+            .into_iter()
+            .filter(|t| walking_ids.insert(t.id)),
+    );
     let unique_turns = ensure_unique(raw_turns);
 
     let mut final_turns: Vec<Turn> = Vec::new();
@@ -101,8 +108,11 @@ fn make_vehicle_turns(
     i: &Intersection,
     all_roads: &Vec<Road>,
     lanes: &Vec<Lane>,
+    driving_side: DrivingSide,
     timer: &mut Timer,
 ) -> Vec<Turn> {
+    // Lanes are ordered from the center line out to the curb, on either side of the road.
+    let curbside_turn = driving_side.curbside_turn();
     let sorted_roads: Vec<&Road> = i
         .get_roads_sorted_by_incoming_angle(all_roads)
         .iter()
@@ -162,13 +172,13 @@ fn make_vehicle_turns(
                 let angle2 = lanes[outgoing[0].0].first_line().angle();
 
                 let type_from_angle = TurnType::from_angles(angle1, angle2);
-                let tt = if type_from_angle == TurnType::Right {
+                let tt = if type_from_angle == curbside_turn {
                     // This one's fragile, based on angles. Really we care that there aren't roads
                     // between the two.
                     if wraparound_get(&sorted_roads, (idx1 as isize) - 1).id == r2.id
                         || wraparound_get(&sorted_roads, (idx1 as isize) + 1).id == r2.id
                     {
-                        TurnType::Right
+                        curbside_turn
                     } else {
                         TurnType::Straight
                     }
@@ -210,10 +220,12 @@ fn make_vehicle_turns(
                                     continue;
                                 }
                                 if let Some(mut t) = make_vehicle_turn(lanes, i.id, l1, *l2, tt) {
+                                    // Moving towards the curb
                                     if idx1 < idx2 {
-                                        t.turn_type = TurnType::LaneChangeRight;
+                                        t.turn_type =
+                                            driving_side.mirror(TurnType::LaneChangeRight);
                                     } else if idx1 > idx2 {
-                                        t.turn_type = TurnType::LaneChangeLeft;
+                                        t.turn_type = driving_side.mirror(TurnType::LaneChangeLeft);
                                     }
                                     result.push(Some(t));
                                 }
@@ -221,23 +233,18 @@ fn make_vehicle_turns(
                         }
                         all_incoming_lanes_covered = true;
                     }
-                    TurnType::Right => {
+                    TurnType::Right | TurnType::Left => {
+                        // Curbside turns definitely happen from the outermost lane, and turns
+                        // across traffic from the innermost.
+                        let from_idx = if tt == curbside_turn {
+                            incoming.len() - 1
+                        } else {
+                            0
+                        };
                         for (idx, l1) in incoming.iter().enumerate() {
                             for l2 in &outgoing {
                                 let turn = make_vehicle_turn(lanes, i.id, *l1, *l2, tt);
-                                if idx == incoming.len() - 1 {
-                                    result.push(turn);
-                                } else {
-                                    maybe_add_turns.push(turn);
-                                }
-                            }
-                        }
-                    }
-                    TurnType::Left => {
-                        for (idx, l1) in incoming.iter().enumerate() {
-                            for l2 in &outgoing {
-                                let turn = make_vehicle_turn(lanes, i.id, *l1, *l2, tt);
-                                if idx == 0 {
+                                if idx == from_idx {
                                     result.push(turn);
                                 } else {
                                     maybe_add_turns.push(turn);
@@ -294,8 +301,12 @@ fn make_walking_turns(
     i: &Intersection,
     all_roads: &Vec<Road>,
     lanes: &Vec<Lane>,
+    driving_side: DrivingSide,
     timer: &mut Timer,
 ) -> Vec<Turn> {
+    // With driving on the left, the sidewalk on the right side of traffic heading into the
+    // intersection belongs to the outgoing lanes instead. Otherwise, everything is the same.
+    let near = driving_side == DrivingSide::Right;
     let roads: Vec<&Road> = i
         .get_roads_sorted_by_incoming_angle(all_roads)
         .into_iter()
@@ -309,12 +320,14 @@ fn make_walking_turns(
         }
         // TODO Argh, duplicate logic for SharedSidewalkCorners
         for idx1 in 0..roads.len() {
-            if let Some(l1) = get_sidewalk(lanes, roads[idx1].incoming_lanes(i.id)) {
-                if let Some(l2) = get_sidewalk(
+            if let Some(l1) = get_sidewalk_for(lanes, roads[idx1], i.id, near) {
+                if let Some(l2) = get_sidewalk_for(
                     lanes,
-                    abstutil::wraparound_get(&roads, (idx1 as isize) - 1).outgoing_lanes(i.id),
+                    *abstutil::wraparound_get(&roads, (idx1 as isize) - 1),
+                    i.id,
+                    !near,
                 ) {
-                    if l1.last_pt() != l2.first_pt() {
+                    if l1.endpoint(i.id) != l2.endpoint(i.id) {
                         let geom = make_shared_sidewalk_corner(i, l1, l2, timer);
                         result.push(Turn {
                             id: turn_id(i.id, l1.id, l2.id),
@@ -337,8 +350,8 @@ fn make_walking_turns(
         return result;
     }
     if roads.len() == 1 {
-        if let Some(l1) = get_sidewalk(lanes, roads[0].incoming_lanes(i.id)) {
-            if let Some(l2) = get_sidewalk(lanes, roads[0].outgoing_lanes(i.id)) {
+        if let Some(l1) = get_sidewalk(lanes, lanes_on(roads[0], i.id, near)) {
+            if let Some(l2) = get_sidewalk(lanes, lanes_on(roads[0], i.id, !near)) {
                 let geom = make_shared_sidewalk_corner(i, l1, l2, timer);
                 result.push(Turn {
                     id: turn_id(i.id, l1.id, l2.id),
@@ -360,19 +373,21 @@ fn make_walking_turns(
     }
 
     for idx1 in 0..roads.len() {
-        if let Some(l1) = get_sidewalk(lanes, roads[idx1].incoming_lanes(i.id)) {
+        if let Some(l1) = get_sidewalk_for(lanes, roads[idx1], i.id, near) {
             // Make the crosswalk to the other side
-            if let Some(l2) = get_sidewalk(lanes, roads[idx1].outgoing_lanes(i.id)) {
+            if let Some(l2) = get_sidewalk(lanes, lanes_on(roads[idx1], i.id, !near)) {
                 result.extend(make_crosswalks(i.id, l1, l2));
             }
 
             // Find the shared corner
             // TODO -1 and not +1 is brittle... must be the angle sorting
-            if let Some(l2) = get_sidewalk(
+            if let Some(l2) = get_sidewalk_for(
                 lanes,
-                abstutil::wraparound_get(&roads, (idx1 as isize) - 1).outgoing_lanes(i.id),
+                *abstutil::wraparound_get(&roads, (idx1 as isize) - 1),
+                i.id,
+                !near,
             ) {
-                if l1.last_pt() != l2.first_pt() {
+                if l1.endpoint(i.id) != l2.endpoint(i.id) {
                     let geom = make_shared_sidewalk_corner(i, l1, l2, timer);
                     result.push(Turn {
                         id: turn_id(i.id, l1.id, l2.id),
@@ -391,7 +406,11 @@ fn make_walking_turns(
                 }
             } else if let Some(l2) = get_sidewalk(
                 lanes,
-                abstutil::wraparound_get(&roads, (idx1 as isize) - 1).incoming_lanes(i.id),
+                lanes_on(
+                    *abstutil::wraparound_get(&roads, (idx1 as isize) - 1),
+                    i.id,
+                    near,
+                ),
             ) {
                 // Adjacent road is missing a sidewalk on the near side, but has one on the far
                 // side
@@ -402,18 +421,30 @@ fn make_walking_turns(
                 // TODO Refactor and loop until we find something to connect it to?
                 if let Some(l2) = get_sidewalk(
                     lanes,
-                    abstutil::wraparound_get(&roads, (idx1 as isize) - 2).outgoing_lanes(i.id),
+                    lanes_on(
+                        *abstutil::wraparound_get(&roads, (idx1 as isize) - 2),
+                        i.id,
+                        !near,
+                    ),
                 ) {
                     result.extend(make_crosswalks(i.id, l1, l2));
                 } else if let Some(l2) = get_sidewalk(
                     lanes,
-                    abstutil::wraparound_get(&roads, (idx1 as isize) - 2).incoming_lanes(i.id),
+                    lanes_on(
+                        *abstutil::wraparound_get(&roads, (idx1 as isize) - 2),
+                        i.id,
+                        near,
+                    ),
                 ) {
                     result.extend(make_crosswalks(i.id, l1, l2));
                 } else if roads.len() > 3 {
                     if let Some(l2) = get_sidewalk(
                         lanes,
-                        abstutil::wraparound_get(&roads, (idx1 as isize) - 3).outgoing_lanes(i.id),
+                        lanes_on(
+                            *abstutil::wraparound_get(&roads, (idx1 as isize) - 3),
+                            i.id,
+                            !near,
+                        ),
                     ) {
                         result.extend(make_crosswalks(i.id, l1, l2));
                     }
@@ -530,15 +561,26 @@ fn make_shared_sidewalk_corner(
     l2: &Lane,
     timer: &mut Timer,
 ) -> PolyLine {
-    let baseline = PolyLine::new(vec![l1.last_pt(), l2.first_pt()]);
+    // Trail sidewalks might point the other way.
+    let pl1 = if l1.dst_i == i.id {
+        l1.lane_center_pts.clone()
+    } else {
+        l1.lane_center_pts.reversed()
+    };
+    let pl2 = if l2.src_i == i.id {
+        l2.lane_center_pts.clone()
+    } else {
+        l2.lane_center_pts.reversed()
+    };
+    let baseline = PolyLine::new(vec![pl1.last_pt(), pl2.first_pt()]);
 
     // Find all of the points on the intersection polygon between the two sidewalks.
-    let corner1 = l1.last_line().shift_right(LANE_THICKNESS / 2.0).pt2();
-    let corner2 = l2.first_line().shift_right(LANE_THICKNESS / 2.0).pt1();
+    let corner1 = pl1.last_line().shift_right(LANE_THICKNESS / 2.0).pt2();
+    let corner2 = pl2.first_line().shift_right(LANE_THICKNESS / 2.0).pt1();
 
     // The order of the points here seems backwards, but it's because we scan from corner2
     // to corner1 below.
-    let mut pts_between = vec![l2.first_pt()];
+    let mut pts_between = vec![pl2.first_pt()];
     // Intersection polygons are constructed in clockwise order, so do corner2 to corner1.
     if let Some(pts) =
         Pt2D::find_pts_between(&i.polygon.points(), corner2, corner1, Distance::meters(0.5))
@@ -562,7 +604,7 @@ fn make_shared_sidewalk_corner(
             );
         }
     }
-    pts_between.push(l1.last_pt());
+    pts_between.push(pl1.last_pt());
     pts_between.reverse();
     // Pretty big smoothing; I'm observing funky backtracking about 0.5m long.
     let mut final_pts = Pt2D::approx_dedupe(pts_between.clone(), Distance::meters(1.0));
@@ -576,9 +618,9 @@ fn make_shared_sidewalk_corner(
     }
     // The last point might be removed as a duplicate, but we want the start/end to exactly match
     // up at least.
-    if *final_pts.last().unwrap() != l2.first_pt() {
+    if *final_pts.last().unwrap() != pl2.first_pt() {
         final_pts.pop();
-        final_pts.push(l2.first_pt());
+        final_pts.push(pl2.first_pt());
     }
     if abstutil::contains_duplicates(&final_pts.iter().map(|pt| pt.to_hashable()).collect()) {
         timer.warn(format!("SharedSidewalkCorner between {} and {} has weird duplicate geometry, so just doing straight line", l1.id, l2.id));
@@ -605,6 +647,30 @@ fn get_sidewalk<'a>(lanes: &'a Vec<Lane>, children: &Vec<(LaneID, LaneType)>) ->
     None
 }
 
+fn lanes_on(road: &Road, i: IntersectionID, incoming: bool) -> &Vec<(LaneID, LaneType)> {
+    if incoming {
+        road.incoming_lanes(i)
+    } else {
+        road.outgoing_lanes(i)
+    }
+}
+
+// Trails only have one sidewalk, usable from either side of the road.
+fn get_sidewalk_for<'a>(
+    lanes: &'a Vec<Lane>,
+    road: &Road,
+    i: IntersectionID,
+    incoming: bool,
+) -> Option<&'a Lane> {
+    get_sidewalk(lanes, lanes_on(road, i, incoming)).or_else(|| {
+        if road.is_trail() {
+            get_sidewalk(lanes, lanes_on(road, i, !incoming))
+        } else {
+            None
+        }
+    })
+}
+
 fn filter_vehicle_lanes(lanes: &Vec<(LaneID, LaneType)>, preferred: LaneType) -> Vec<LaneID> {
     let preferred = filter_lanes(lanes, preferred);
     if !preferred.is_empty() {
@@ -1,13 +1,14 @@
 use crate::pathfind::Pathfinder;
-use crate::raw::{OriginalIntersection, OriginalRoad, RawMap};
+use crate::raw::{DrivingSide, OriginalIntersection, OriginalRoad, RawMap};
 use crate::{
     connectivity, make, Area, AreaID, Building, BuildingID, BusRoute, BusRouteID, BusStop,
     BusStopID, ControlStopSign, ControlTrafficSignal, EditCmd, EditEffects, Intersection,
-    IntersectionID, IntersectionType, Lane, LaneID, LaneType, MapEdits, Path, PathConstraints,
-    PathRequest, Position, Road, RoadID, Turn, TurnGroupID, TurnID, TurnType, LANE_THICKNESS,
+    IntersectionID, IntersectionType, Lane, LaneID, LaneType, MapEdits, ParkingPolicy, Path,
+    PathConstraints, PathRequest, Position, Road, RoadID, TravelTimes, Turn, TurnGroupID, TurnID,
+    TurnType, LANE_THICKNESS,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap, Error, Timer};
-use geom::{Bounds, Distance, GPSBounds, Polygon, Pt2D};
+use geom::{Bounds, Distance, GPSBounds, Polygon, Pt2D, Time};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 
@@ -42,9 +43,13 @@ pub struct Map {
     // TODO Argh, hack, initialization order is hard!
     pathfinder: Option<Pathfinder>,
     pathfinder_dirty: bool,
+    // Overrides free-flow times for car routing
+    #[serde(skip_serializing, skip_deserializing)]
+    travel_times: Option<TravelTimes>,
 
     name: String,
     edits: MapEdits,
+    driving_side: DrivingSide,
 }
 
 impl Map {
@@ -91,8 +96,10 @@ impl Map {
             turn_lookup: Vec::new(),
             pathfinder: None,
             pathfinder_dirty: false,
+            travel_times: None,
             name: "blank".to_string(),
             edits: MapEdits::new("blank".to_string()),
+            driving_side: DrivingSide::Right,
         }
     }
 
@@ -129,9 +136,9 @@ impl Map {
             m.traffic_signals = traffic_signals;
         }
 
-        // Here's a fun one: we can't set up walking_using_transit yet, because we haven't
-        // finalized bus stops and routes. We need the bus graph in place for that. So setup
-        // pathfinding in two stages.
+        // Here's a fun one: we can't set up transit planning yet, because we haven't finalized bus
+        // stops and routes. We need the bus graph in place for that. So setup pathfinding in two
+        // stages.
         timer.start("setup (most of) Pathfinder");
         m.pathfinder = Some(Pathfinder::new_without_transit(&m, timer));
         timer.stop("setup (most of) Pathfinder");
@@ -158,6 +165,19 @@ impl Map {
                     timer.warn(format!("Skipping route {}", r.name));
                 }
             }
+            // Pair up the two directions of each route
+            for idx in 0..m.bus_routes.len() {
+                let name = &m.bus_routes[idx].name;
+                let others: Vec<BusRouteID> = m
+                    .bus_routes
+                    .iter()
+                    .filter(|r| r.id.0 != idx && r.name == *name)
+                    .map(|r| r.id)
+                    .collect();
+                if others.len() == 1 {
+                    m.bus_routes[idx].opposite_route = Some(others[0]);
+                }
+            }
 
             // Remove orphaned bus stops
             let mut remove_stops = HashSet::new();
@@ -174,11 +194,11 @@ impl Map {
             }
         }
 
-        timer.start("setup rest of Pathfinder (walking with transit)");
+        timer.start("setup rest of Pathfinder (transit)");
         let mut pathfinder = m.pathfinder.take().unwrap();
-        pathfinder.setup_walking_with_transit(&m);
+        pathfinder.setup_transit(&m);
         m.pathfinder = Some(pathfinder);
-        timer.stop("setup rest of Pathfinder (walking with transit)");
+        timer.stop("setup rest of Pathfinder (transit)");
 
         timer.start("find parking blackholes");
         for (l, redirect) in connectivity::redirect_parking_blackholes(&m, timer) {
@@ -424,6 +444,10 @@ impl Map {
         &self.name
     }
 
+    pub fn get_driving_side(&self) -> DrivingSide {
+        self.driving_side
+    }
+
     pub fn all_bus_stops(&self) -> &BTreeMap<BusStopID, BusStop> {
         &self.bus_stops
     }
@@ -440,8 +464,10 @@ impl Map {
         &self.bus_routes
     }
 
-    pub fn get_bus_route(&self, name: &str) -> Option<&BusRoute> {
-        self.bus_routes.iter().find(|r| r.name == name)
+    // Both directions of a GTFS route share a name, so this returns every match, in BusRouteID
+    // order.
+    pub fn get_bus_routes(&self, name: &str) -> Vec<&BusRoute> {
+        self.bus_routes.iter().filter(|r| r.name == name).collect()
     }
 
     pub fn get_routes_serving_stop(&self, stop: BusStopID) -> Vec<&BusRoute> {
@@ -595,15 +621,17 @@ impl Map {
         self.pathfinder.as_ref().unwrap().pathfind(req, self)
     }
 
+    // Every bus ride as (board at, get off at, route), walking in between
     pub fn should_use_transit(
         &self,
         start: Position,
         end: Position,
-    ) -> Option<(BusStopID, BusStopID, BusRouteID)> {
+        depart: Time,
+    ) -> Option<Vec<(BusStopID, BusStopID, BusRouteID)>> {
         self.pathfinder
             .as_ref()
             .unwrap()
-            .should_use_transit(self, start, end)
+            .should_use_transit(self, start, end, depart)
     }
 
     // None for SharedSidewalkCorners
@@ -713,6 +741,16 @@ impl Map {
         )
     }
 
+    pub fn get_travel_times(&self) -> Option<&TravelTimes> {
+        self.travel_times.as_ref()
+    }
+
+    // Like edits, doesn't update pathfinding yet.
+    pub fn use_travel_times(&mut self, times: Option<TravelTimes>) {
+        self.travel_times = times;
+        self.pathfinder_dirty = true;
+    }
+
     pub fn recalculate_pathfinding_after_edits(&mut self, timer: &mut Timer) {
         if !self.pathfinder_dirty {
             return;
@@ -760,8 +798,10 @@ fn make_half_map(
         turn_lookup: Vec::new(),
         pathfinder: None,
         pathfinder_dirty: false,
+        travel_times: None,
         name: raw.name.clone(),
         edits: MapEdits::new(raw.name.clone()),
+        driving_side: raw.driving_side,
     };
 
     let road_id_mapping: BTreeMap<OriginalRoad, RoadID> = initial_map
@@ -783,6 +823,8 @@ fn make_half_map(
             // Might change later
             intersection_type: i.intersection_type,
             orig_id: i.id,
+            elevation: i.elevation,
+            crossing: i.crossing,
             incoming_lanes: Vec::new(),
             outgoing_lanes: Vec::new(),
             roads: i.roads.iter().map(|id| road_id_mapping[id]).collect(),
@@ -833,11 +875,12 @@ fn make_half_map(
             };
             // TODO probably different behavior for oneways
             // TODO need to factor in yellow center lines (but what's the right thing to even do?
-            // Reverse points for British-style driving on the left
             let width = LANE_THICKNESS * (0.5 + (offset as f64));
-            let lane_center_pts = unshifted_pts
-                .shift_right(width)
-                .with_context(timer, format!("shift for {}", id));
+            let lane_center_pts = match map.driving_side {
+                DrivingSide::Right => unshifted_pts.shift_right(width),
+                DrivingSide::Left => unshifted_pts.shift_left(width),
+            }
+            .with_context(timer, format!("shift for {}", id));
 
             map.lanes.push(Lane {
                 id,
@@ -849,6 +892,11 @@ fn make_half_map(
                 building_paths: Vec::new(),
                 bus_stops: Vec::new(),
                 parking_blackhole: None,
+                parking_policy: if lane.lane_type == LaneType::Parking {
+                    ParkingPolicy::from_osm(&road.osm_tags, !lane.reverse_pts, map.driving_side)
+                } else {
+                    ParkingPolicy::free()
+                },
             });
         }
         if road.get_name() == "???" {
@@ -882,7 +930,7 @@ fn make_half_map(
             continue;
         }
 
-        for t in make::make_all_turns(i, &map.roads, &map.lanes, timer) {
+        for t in make::make_all_turns(i, &map.roads, &map.lanes, map.driving_side, timer) {
             assert!(!map.turns.contains_key(&t.id));
             i.turns.insert(t.id);
             map.turns.insert(t.id, t);
@@ -1087,6 +1135,25 @@ impl EditCmd {
                 effects.changed_intersections.insert(id);
                 true
             }
+            EditCmd::ChangeParkingPolicy { id, policy, .. } => {
+                let lane = &mut map.lanes[id.0];
+                if &lane.parking_policy == policy {
+                    return false;
+                }
+
+                lane.parking_policy = policy.clone();
+                effects.changed_lanes.insert(*id);
+                true
+            }
+            EditCmd::ChangeOffstreetParkingPolicy { b, policy, .. } => {
+                let parking = map.buildings[b.0].parking.as_mut().unwrap();
+                if &parking.policy == policy {
+                    return false;
+                }
+
+                parking.policy = policy.clone();
+                true
+            }
         }
     }
 
@@ -1127,6 +1194,26 @@ impl EditCmd {
                 orig_it: *orig_it,
             }
             .apply(effects, map, timer),
+            EditCmd::ChangeParkingPolicy {
+                id,
+                policy,
+                orig_policy,
+            } => EditCmd::ChangeParkingPolicy {
+                id: *id,
+                policy: orig_policy.clone(),
+                orig_policy: policy.clone(),
+            }
+            .apply(effects, map, timer),
+            EditCmd::ChangeOffstreetParkingPolicy {
+                b,
+                policy,
+                orig_policy,
+            } => EditCmd::ChangeOffstreetParkingPolicy {
+                b: *b,
+                policy: orig_policy.clone(),
+                orig_policy: policy.clone(),
+            }
+            .apply(effects, map, timer),
         }
     }
 }
@@ -1157,7 +1244,7 @@ fn recalculate_turns(
         return;
     }
 
-    for t in make::make_all_turns(i, &map.roads, &map.lanes, timer) {
+    for t in make::make_all_turns(i, &map.roads, &map.lanes, map.driving_side, timer) {
         effects.added_turns.insert(t.id);
         i.turns.insert(t.id);
         if let Some(_existing_t) = old_turns.iter().find(|turn| turn.id == t.id) {
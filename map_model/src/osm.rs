@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 // These are common OSM keys. Keys used in just one or two places don't really need to be defined
 // here.
 
@@ -30,3 +32,12 @@ pub const SYNTHETIC_LANES: &str = "abst:synthetic_lanes";
 // Any roads might have these.
 pub const INFERRED_PARKING: &str = "abst:parking_inferred";
 pub const INFERRED_SIDEWALKS: &str = "abst:sidewalks_inferred";
+pub const INFERRED_MAXSPEED: &str = "abst:maxspeed_inferred";
+pub const INFERRED_LANES: &str = "abst:lanes_inferred";
+
+// Rings where traffic circulates one way. Mini-roundabouts are just a single node, so they aren't
+// included.
+pub fn is_roundabout(tags: &BTreeMap<String, String>) -> bool {
+    tags.get("junction") == Some(&"roundabout".to_string())
+        || tags.get("junction") == Some(&"circular".to_string())
+}
@@ -1,8 +1,14 @@
 use crate::pathfind::node_map::{deserialize_nodemap, NodeMap};
-use crate::{Lane, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Turn, TurnID};
+use crate::travel_times::{free_flow_lane, free_flow_turn};
+use crate::{
+    IntersectionID, Lane, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Turn, TurnID,
+    TurnPriority, TurnType,
+};
 use fast_paths::{FastGraph, InputGraph, PathCalculator};
+use geom::{Duration, Speed};
 use serde_derive::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::collections::HashMap;
 use thread_local::ThreadLocal;
 
 #[derive(Serialize, Deserialize)]
@@ -76,6 +82,8 @@ impl VehiclePathfinder {
         ))
     }
 
+    // Turn penalties depend on intersection control, so this must also run when only signals or
+    // stop signs change.
     pub fn apply_edits(&mut self, map: &Map) {
         // The NodeMap is just all lanes -- it won't change. So we can also reuse the node
         // ordering.
@@ -94,6 +102,10 @@ fn make_input_graph(
 ) -> InputGraph {
     let mut input_graph = InputGraph::new();
     let num_lanes = map.all_lanes().len();
+    let mut penalties = HashMap::new();
+    for i in map.all_intersections() {
+        penalties.extend(turn_penalties(map, i.id));
+    }
     for l in map.all_lanes() {
         let from = nodes.get(l.id);
         let mut any = false;
@@ -103,7 +115,7 @@ fn make_input_graph(
                 input_graph.add_edge(
                     from,
                     nodes.get(turn.id.dst),
-                    cost(l, turn, constraints, map),
+                    cost_with_penalty(l, turn, constraints, map, penalties[&turn.id]),
                 );
             }
         }
@@ -121,19 +133,30 @@ fn make_input_graph(
 }
 
 pub fn cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map: &Map) -> usize {
-    // TODO Could cost turns differently.
+    let penalty = turn_penalties(map, turn.id.parent)[&turn.id];
+    cost_with_penalty(lane, turn, constraints, map, penalty)
+}
 
+fn cost_with_penalty(
+    lane: &Lane,
+    turn: &Turn,
+    constraints: PathConstraints,
+    map: &Map,
+    turn_penalty: Duration,
+) -> usize {
     match constraints {
         PathConstraints::Car => {
-            // Prefer slightly longer route on faster roads
-            let t1 = lane.length() / map.get_r(lane.parent).get_speed_limit();
-            let t2 = turn.geom.length() / map.get_parent(turn.id.dst).get_speed_limit();
-            (t1 + t2).inner_seconds().round() as usize
+            // Prefer slightly longer route on faster roads, unless congestion was observed there.
+            let (t1, t2) = if let Some(times) = map.get_travel_times() {
+                (times.lane(lane.id, map), times.turn(turn.id, map))
+            } else {
+                (free_flow_lane(lane.id, map), free_flow_turn(turn.id, map))
+            };
+            (t1 + t2 + turn_penalty).inner_seconds().round() as usize
         }
         PathConstraints::Bike => {
             // Speed limits don't matter, bikes are usually constrained by their own speed limit.
             let dist = lane.length() + turn.geom.length();
-            // TODO Elevation gain is bad, loss is good.
             // TODO If we're on a driving lane, higher speed limit is worse.
             // TODO Bike lanes next to parking is dangerous.
 
@@ -148,8 +171,19 @@ pub fn cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map: &Map) -
                 1.5
             };
 
+            // Elevation gain is bad, loss is good, but not by as much.
+            let grade = lane.grade(map);
+            let grade_penalty = if grade > 0.0 {
+                1.0 + 10.0 * grade
+            } else {
+                (1.0 + 2.0 * grade).max(0.8)
+            };
+
+            // Express the time lost turning and waiting as the distance a bike could've gone
+            let dist = dist + turn_penalty * BIKE_SPEED_FOR_PENALTIES;
+
             // 1m resolution is fine
-            (lt_penalty * dist).inner_meters().round() as usize
+            (lt_penalty * grade_penalty * dist).inner_meters().round() as usize
         }
         PathConstraints::Bus => {
             // Like Car, but prefer bus lanes.
@@ -161,8 +195,79 @@ pub fn cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map: &Map) -
                 assert!(lane.is_driving());
                 1.1
             };
-            (lt_penalty * (t1 + t2)).inner_seconds().round() as usize
+            (lt_penalty * (t1 + t2) + turn_penalty)
+                .inner_seconds()
+                .round() as usize
         }
         PathConstraints::Pedestrian => unreachable!(),
     }
 }
+
+const BIKE_SPEED_FOR_PENALTIES: Speed = Speed::const_meters_per_second(4.0);
+// Stopping, looking, and waiting for a gap
+const STOP_SIGN_PENALTY: Duration = Duration::const_seconds(5.0);
+const YIELD_PENALTY: Duration = Duration::const_seconds(5.0);
+
+// Roughly how much time does each turn through an intersection cost, beyond just crossing it?
+// Finding the turn group at a signal means searching all of them, so do this once per intersection.
+fn turn_penalties(map: &Map, i: IntersectionID) -> HashMap<TurnID, Duration> {
+    // Crossing oncoming traffic takes longer
+    let mut penalties: HashMap<TurnID, Duration> = map
+        .get_turns_in_intersection(i)
+        .into_iter()
+        .map(|turn| {
+            let penalty = match map.get_driving_side().mirror(turn.turn_type) {
+                TurnType::Left => Duration::seconds(5.0),
+                TurnType::Right => Duration::seconds(2.0),
+                TurnType::LaneChangeLeft | TurnType::LaneChangeRight => Duration::seconds(1.0),
+                TurnType::Straight | TurnType::Crosswalk | TurnType::SharedSidewalkCorner => {
+                    Duration::ZERO
+                }
+            };
+            (turn.id, penalty)
+        })
+        .collect();
+
+    if let Some(sign) = map.maybe_get_stop_sign(i) {
+        for (t, penalty) in penalties.iter_mut() {
+            if sign.get_priority(*t, map) != TurnPriority::Protected {
+                *penalty += STOP_SIGN_PENALTY;
+            }
+        }
+    } else if let Some(signal) = map.maybe_get_traffic_signal(i) {
+        let cycle = signal.cycle_length();
+        for group in signal.turn_groups.values() {
+            // Average wait for a random arrival is red^2 / (2 * cycle). Only being able to yield
+            // is worse.
+            let mut green = Duration::ZERO;
+            let mut ever_protected = false;
+            for phase in &signal.phases {
+                match phase.get_priority_of_group(group.id) {
+                    TurnPriority::Protected => {
+                        green += phase.duration;
+                        ever_protected = true;
+                    }
+                    TurnPriority::Yield => {
+                        green += phase.duration;
+                    }
+                    TurnPriority::Banned => {}
+                }
+            }
+            let red = cycle - green;
+            let mut wait = Duration::ZERO;
+            if cycle > Duration::ZERO {
+                wait +=
+                    Duration::seconds(red.inner_seconds().powi(2) / (2.0 * cycle.inner_seconds()));
+            }
+            if !ever_protected {
+                wait += YIELD_PENALTY;
+            }
+            for t in &group.members {
+                if let Some(penalty) = penalties.get_mut(t) {
+                    *penalty += wait;
+                }
+            }
+        }
+    }
+    penalties
+}
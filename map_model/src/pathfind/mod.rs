@@ -1,15 +1,19 @@
 mod driving;
 mod node_map;
+mod transit;
 mod walking;
 
 pub use self::driving::cost;
 use self::driving::VehiclePathfinder;
+pub use self::transit::find_transit_rides;
+use self::transit::TransitPlanner;
 use self::walking::SidewalkPathfinder;
+pub use self::walking::WALKING_SPEED;
 use crate::{
     osm, BusRouteID, BusStopID, Lane, LaneID, LaneType, Map, Position, Traversable, TurnID,
 };
 use abstutil::Timer;
-use geom::{Distance, PolyLine};
+use geom::{Distance, PolyLine, Time};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::fmt;
@@ -187,6 +191,34 @@ impl Path {
         }
     }
 
+    // Only for vehicles changing lanes partway along their current lane. Trusting the caller to
+    // also fix up the next turn.
+    pub fn modify_current_step(&mut self, step: PathStep, map: &Map) {
+        self.total_length -= self.steps[0].as_traversable().length(map);
+        self.steps[0] = step;
+        self.total_length += self.steps[0].as_traversable().length(map);
+    }
+
+    // Keep the current step, then take a different turn and follow a new path from its
+    // destination. Trusting the caller that the turn starts from the current lane.
+    pub fn replace_after_current_step(&mut self, turn: TurnID, rest: Path, map: &Map) {
+        assert_eq!(rest.steps[0], PathStep::Lane(turn.dst));
+        let current = self.steps[0];
+        self.total_length = self.crossed_so_far
+            + current.as_traversable().length(map)
+            + map.get_t(turn).geom.length()
+            + rest.total_length;
+        self.steps = VecDeque::new();
+        self.steps.push_back(current);
+        self.steps.push_back(PathStep::Turn(turn));
+        self.steps.extend(rest.steps);
+        self.end_dist = rest.end_dist;
+    }
+
+    pub fn end_dist(&self) -> Distance {
+        self.end_dist
+    }
+
     pub fn current_step(&self) -> PathStep {
         self.steps[0]
     }
@@ -413,7 +445,7 @@ pub struct Pathfinder {
     bus_graph: VehiclePathfinder,
     walking_graph: SidewalkPathfinder,
     // TODO Option just during initialization! Ewww.
-    walking_with_transit_graph: Option<SidewalkPathfinder>,
+    transit: Option<TransitPlanner>,
 }
 
 impl Pathfinder {
@@ -433,7 +465,7 @@ impl Pathfinder {
         timer.stop("prepare pathfinding for buses");
 
         timer.start("prepare pathfinding for pedestrians");
-        let walking_graph = SidewalkPathfinder::new(map, false, &bus_graph);
+        let walking_graph = SidewalkPathfinder::new(map);
         timer.stop("prepare pathfinding for pedestrians");
 
         Pathfinder {
@@ -441,12 +473,12 @@ impl Pathfinder {
             bike_graph,
             bus_graph,
             walking_graph,
-            walking_with_transit_graph: None,
+            transit: None,
         }
     }
 
-    pub fn setup_walking_with_transit(&mut self, map: &Map) {
-        self.walking_with_transit_graph = Some(SidewalkPathfinder::new(map, true, &self.bus_graph));
+    pub fn setup_transit(&mut self, map: &Map) {
+        self.transit = Some(TransitPlanner::new(map, &self.bus_graph));
     }
 
     pub fn pathfind(&self, req: PathRequest, map: &Map) -> Option<Path> {
@@ -463,11 +495,12 @@ impl Pathfinder {
         map: &Map,
         start: Position,
         end: Position,
-    ) -> Option<(BusStopID, BusStopID, BusRouteID)> {
-        self.walking_with_transit_graph
+        depart: Time,
+    ) -> Option<Vec<(BusStopID, BusStopID, BusRouteID)>> {
+        self.transit
             .as_ref()
             .unwrap()
-            .should_use_transit(map, start, end)
+            .plan(map, &self.walking_graph, start, end, depart)
     }
 
     pub fn apply_edits(&mut self, map: &Map, timer: &mut Timer) {
@@ -484,14 +517,12 @@ impl Pathfinder {
         timer.stop("apply edits to bus pathfinding");
 
         timer.start("apply edits to pedestrian pathfinding");
-        self.walking_graph.apply_edits(map, &self.bus_graph);
+        self.walking_graph.apply_edits(map);
         timer.stop("apply edits to pedestrian pathfinding");
 
-        timer.start("apply edits to pedestrian using transit pathfinding");
-        self.walking_with_transit_graph
-            .as_mut()
-            .unwrap()
-            .apply_edits(map, &self.bus_graph);
-        timer.stop("apply edits to pedestrian using transit pathfinding");
+        // Bus routes take different roads now
+        timer.start("apply edits to transit planning");
+        self.transit = Some(TransitPlanner::new(map, &self.bus_graph));
+        timer.stop("apply edits to transit planning");
     }
 }
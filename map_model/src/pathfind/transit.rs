@@ -0,0 +1,272 @@
+use crate::pathfind::driving::VehiclePathfinder;
+use crate::pathfind::walking::SidewalkPathfinder;
+use crate::{
+    BusRoute, BusRouteID, BusStop, BusStopID, Map, PathConstraints, PathRequest, Position,
+};
+use geom::{Distance, Duration, Time};
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+
+// When a route has no timetable, guess how long somebody waits for the next bus.
+const UNSCHEDULED_WAIT: Duration = Duration::const_seconds(300.0);
+// Straight-line limit for walking to the first stop, from the last stop, or between stops to
+// transfer. Otherwise every stop on the map would need a walking path checked.
+const MAX_WALK_TO_STOP: Distance = Distance::const_meters(1000.0);
+
+// On average, somebody showing up at a random time waits half the time between buses.
+fn expected_wait(route: &BusRoute) -> Duration {
+    route
+        .average_headway()
+        .map(|headway| headway / 2.0)
+        .unwrap_or(UNSCHEDULED_WAIT)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TransitPlanner {
+    // For routes without a timetable, how long a bus takes to drive each of all_segments(). None
+    // if it can't.
+    unscheduled: BTreeMap<BusRouteID, Vec<Option<Duration>>>,
+}
+
+impl TransitPlanner {
+    pub fn new(map: &Map, bus_graph: &VehiclePathfinder) -> TransitPlanner {
+        let mut unscheduled = BTreeMap::new();
+        for route in map.get_all_bus_routes() {
+            if !route.schedule.is_empty() {
+                continue;
+            }
+            let times = route
+                .all_segments()
+                .into_iter()
+                .map(|(stop1, stop2)| {
+                    bus_graph
+                        .pathfind(
+                            &PathRequest {
+                                start: map.get_bs(stop1).driving_pos,
+                                end: map.get_bs(stop2).driving_pos,
+                                constraints: PathConstraints::Bus,
+                            },
+                            map,
+                        )
+                        .map(|(_, cost)| Duration::seconds(cost as f64))
+                })
+                .collect();
+            unscheduled.insert(route.id, times);
+        }
+        TransitPlanner { unscheduled }
+    }
+
+    // Rides with transfers in between, only if they'd arrive before just walking.
+    pub fn plan(
+        &self,
+        map: &Map,
+        walking: &SidewalkPathfinder,
+        start: Position,
+        end: Position,
+        depart: Time,
+    ) -> Option<Vec<(BusStopID, BusStopID, BusRouteID)>> {
+        let near = |pos: Position, stop: &BusStop| {
+            pos.pt(map).dist_to(stop.sidewalk_pos.pt(map)) <= MAX_WALK_TO_STOP
+        };
+        let walk_only = depart + walking.walking_time(start, end, map)?;
+        let access = map
+            .all_bus_stops()
+            .values()
+            .filter(|stop| near(start, stop))
+            .filter_map(|stop| {
+                Some((
+                    stop.id,
+                    walking.walking_time(start, stop.sidewalk_pos, map)?,
+                ))
+            })
+            .collect();
+
+        find_transit_rides(
+            depart,
+            walk_only,
+            access,
+            |stop, now| {
+                let mut rides = Vec::new();
+                for route in map.get_routes_serving_stop(stop) {
+                    for (next, arrive) in self.rides_from(route, stop, now) {
+                        rides.push((route.id, next, arrive));
+                    }
+                }
+                rides
+            },
+            |stop| {
+                let from = map.get_bs(stop).sidewalk_pos;
+                map.all_bus_stops()
+                    .values()
+                    .filter(|other| other.id != stop && near(from, other))
+                    .filter_map(|other| {
+                        Some((
+                            other.id,
+                            walking.walking_time(from, other.sidewalk_pos, map)?,
+                        ))
+                    })
+                    .collect()
+            },
+            |stop| {
+                let stop = map.get_bs(stop);
+                if near(end, stop) {
+                    walking.walking_time(stop.sidewalk_pos, end, map)
+                } else {
+                    None
+                }
+            },
+        )
+    }
+
+    // Every later stop somebody reaches by boarding a route at some time, and when they get there.
+    fn rides_from(&self, route: &BusRoute, stop: BusStopID, now: Time) -> Vec<(BusStopID, Time)> {
+        let segments = match self.unscheduled.get(&route.id) {
+            Some(segments) => segments,
+            None => {
+                return route.scheduled_rides_from(stop, now);
+            }
+        };
+        let mut rides = Vec::new();
+        let idx = match route.stops.iter().position(|s| *s == stop) {
+            Some(idx) => idx,
+            None => {
+                return rides;
+            }
+        };
+        // Loops wrap around, stopping short of the stop where somebody got on.
+        let count = if route.is_loop {
+            segments.len().saturating_sub(1)
+        } else {
+            segments.len() - idx
+        };
+        let mut arrive = now + expected_wait(route);
+        for step in 0..count {
+            match segments[(idx + step) % segments.len()] {
+                Some(dt) => {
+                    arrive = arrive + dt;
+                }
+                None => break,
+            }
+            rides.push((route.stops[(idx + step + 1) % route.stops.len()], arrive));
+        }
+        rides
+    }
+}
+
+// How somebody reached a stop
+#[derive(Clone, Copy)]
+enum Arrival {
+    // From the start, or from another stop to transfer
+    Walked(Option<BusStopID>),
+    // Boarded at some stop
+    Rode(BusStopID, BusRouteID),
+}
+
+// Searches stops by the earliest time somebody could stand at each one, like Dijkstra's. Returns
+// the rides reaching the destination soonest, as (board at, get off at, route), or None if nothing
+// beats arriving at walk_only. This doesn't touch the map, so it can be checked on its own.
+//
+// - access: stops to walk to from the start, and how long that takes
+// - ride: from a stop at some time, every (route, later stop, arrival) reachable without
+//   transferring
+// - transfer: other stops to walk to from some stop, and how long that takes
+// - egress: how long it takes to walk from some stop to the destination
+pub fn find_transit_rides<R, T, E>(
+    depart: Time,
+    walk_only: Time,
+    access: Vec<(BusStopID, Duration)>,
+    mut ride: R,
+    mut transfer: T,
+    mut egress: E,
+) -> Option<Vec<(BusStopID, BusStopID, BusRouteID)>>
+where
+    R: FnMut(BusStopID, Time) -> Vec<(BusRouteID, BusStopID, Time)>,
+    T: FnMut(BusStopID) -> Vec<(BusStopID, Duration)>,
+    E: FnMut(BusStopID) -> Option<Duration>,
+{
+    let mut best: HashMap<BusStopID, (Time, Arrival)> = HashMap::new();
+    let mut done: HashSet<BusStopID> = HashSet::new();
+    let mut queue: BinaryHeap<Reverse<(Time, BusStopID)>> = BinaryHeap::new();
+    let reach = |best: &mut HashMap<BusStopID, (Time, Arrival)>,
+                 queue: &mut BinaryHeap<Reverse<(Time, BusStopID)>>,
+                 stop: BusStopID,
+                 time: Time,
+                 how: Arrival| {
+        if best.get(&stop).map(|(t, _)| *t <= time).unwrap_or(false) {
+            return;
+        }
+        best.insert(stop, (time, how));
+        queue.push(Reverse((time, stop)));
+    };
+
+    for (stop, dt) in access {
+        reach(
+            &mut best,
+            &mut queue,
+            stop,
+            depart + dt,
+            Arrival::Walked(None),
+        );
+    }
+
+    let mut arrive = walk_only;
+    let mut last_stop = None;
+    while let Some(Reverse((now, stop))) = queue.pop() {
+        if now >= arrive {
+            break;
+        }
+        if !done.insert(stop) {
+            continue;
+        }
+        // Walking to the destination or another stop only makes sense right after riding; the
+        // walk from the start or previous stop would've been more direct.
+        if let Arrival::Rode(_, _) = best[&stop].1 {
+            if let Some(dt) = egress(stop) {
+                if now + dt < arrive {
+                    arrive = now + dt;
+                    last_stop = Some(stop);
+                }
+            }
+            for (next, dt) in transfer(stop) {
+                if now + dt < arrive {
+                    reach(
+                        &mut best,
+                        &mut queue,
+                        next,
+                        now + dt,
+                        Arrival::Walked(Some(stop)),
+                    );
+                }
+            }
+        }
+        for (route, next, time) in ride(stop, now) {
+            if time < arrive {
+                reach(
+                    &mut best,
+                    &mut queue,
+                    next,
+                    time,
+                    Arrival::Rode(stop, route),
+                );
+            }
+        }
+    }
+
+    let mut rides = Vec::new();
+    let mut stop = last_stop?;
+    loop {
+        match best[&stop].1 {
+            Arrival::Rode(board, route) => {
+                rides.push((board, stop, route));
+                stop = board;
+            }
+            Arrival::Walked(Some(from)) => {
+                stop = from;
+            }
+            Arrival::Walked(None) => break,
+        }
+    }
+    rides.reverse();
+    Some(rides)
+}
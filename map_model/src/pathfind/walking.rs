@@ -1,10 +1,7 @@
-use crate::pathfind::driving::VehiclePathfinder;
 use crate::pathfind::node_map::{deserialize_nodemap, NodeMap};
-use crate::{
-    BusRouteID, BusStopID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Position,
-};
+use crate::{LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Position};
 use fast_paths::{FastGraph, InputGraph, PathCalculator};
-use geom::{Distance, Speed};
+use geom::{Distance, Duration, Speed};
 use serde_derive::{Deserialize, Serialize};
 use std::cell::RefCell;
 use thread_local::ThreadLocal;
@@ -14,7 +11,6 @@ pub struct SidewalkPathfinder {
     graph: FastGraph,
     #[serde(deserialize_with = "deserialize_nodemap")]
     nodes: NodeMap<Node>,
-    use_transit: bool,
 
     #[serde(skip_serializing, skip_deserializing)]
     path_calc: ThreadLocal<RefCell<PathCalculator>>,
@@ -24,11 +20,13 @@ pub struct SidewalkPathfinder {
 enum Node {
     // false is src_i, true is dst_i
     SidewalkEndpoint(LaneID, bool),
-    RideBus(BusStopID),
 }
 
+// How fast people walk when planning trips
+pub const WALKING_SPEED: Speed = Speed::const_meters_per_second(1.34);
+
 impl SidewalkPathfinder {
-    pub fn new(map: &Map, use_transit: bool, bus_graph: &VehiclePathfinder) -> SidewalkPathfinder {
+    pub fn new(map: &Map) -> SidewalkPathfinder {
         let mut nodes = NodeMap::new();
         // We're assuming that to start with, no sidewalks are closed for construction!
         for l in map.all_lanes() {
@@ -37,26 +35,19 @@ impl SidewalkPathfinder {
                 nodes.get_or_insert(Node::SidewalkEndpoint(l.id, false));
             }
         }
-        if use_transit {
-            // Add a node for each bus stop.
-            for stop in map.all_bus_stops().values() {
-                nodes.get_or_insert(Node::RideBus(stop.id));
-            }
-        }
 
-        let graph = fast_paths::prepare(&make_input_graph(map, &nodes, use_transit, bus_graph));
+        let graph = fast_paths::prepare(&make_input_graph(map, &nodes));
         SidewalkPathfinder {
             graph,
             nodes,
-            use_transit,
             path_calc: ThreadLocal::new(),
         }
     }
 
-    pub fn apply_edits(&mut self, map: &Map, bus_graph: &VehiclePathfinder) {
-        // The NodeMap is all sidewalks and bus stops -- it won't change. So we can also reuse the
-        // node ordering.
-        let input_graph = make_input_graph(map, &self.nodes, self.use_transit, bus_graph);
+    pub fn apply_edits(&mut self, map: &Map) {
+        // The NodeMap is just all sidewalks -- it won't change. So we can also reuse the node
+        // ordering.
+        let input_graph = make_input_graph(map, &self.nodes);
         let node_ordering = self.graph.get_node_ordering();
         self.graph = fast_paths::prepare_with_order(&input_graph, &node_ordering).unwrap();
     }
@@ -102,14 +93,8 @@ impl SidewalkPathfinder {
         let mut steps: Vec<PathStep> = Vec::new();
 
         for pair in path.windows(2) {
-            let (l1, l1_endpt) = match pair[0] {
-                Node::SidewalkEndpoint(l, endpt) => (l, endpt),
-                Node::RideBus(_) => unreachable!(),
-            };
-            let l2 = match pair[1] {
-                Node::SidewalkEndpoint(l, _) => l,
-                Node::RideBus(_) => unreachable!(),
-            };
+            let Node::SidewalkEndpoint(l1, l1_endpt) = pair[0];
+            let Node::SidewalkEndpoint(l2, _) = pair[1];
 
             if l1 == l2 {
                 if l1_endpt {
@@ -153,40 +138,39 @@ impl SidewalkPathfinder {
         Some(Path::new(map, steps, req.end.dist_along()))
     }
 
-    // Attempt the pathfinding and see if we should ride a bus.
-    pub fn should_use_transit(
-        &self,
-        map: &Map,
-        start: Position,
-        end: Position,
-    ) -> Option<(BusStopID, BusStopID, BusRouteID)> {
-        let raw_path = fast_paths::calc_path(
-            &self.graph,
-            self.nodes.get(closest_node(start, map)),
-            self.nodes.get(closest_node(end, map)),
+    // Only counts the parts of the first and last sidewalks actually walked, unlike
+    // Path::total_length.
+    pub fn walking_time(&self, start: Position, end: Position, map: &Map) -> Option<Duration> {
+        let path = self.pathfind(
+            &PathRequest {
+                start,
+                end,
+                constraints: PathConstraints::Pedestrian,
+            },
+            map,
         )?;
-
-        let mut nodes = self.nodes.translate(&raw_path);
-        let mut first_stop = None;
-        for n in &nodes {
-            if let Node::RideBus(stop) = n {
-                first_stop = Some(*stop);
-                break;
-            }
+        let steps = path.get_steps();
+        if steps.len() == 1 {
+            let dist = if start.dist_along() < end.dist_along() {
+                end.dist_along() - start.dist_along()
+            } else {
+                start.dist_along() - end.dist_along()
+            };
+            return Some(dist / WALKING_SPEED);
         }
-        let first_stop = first_stop?;
-        let possible_routes = map.get_routes_serving_stop(first_stop);
 
-        nodes.reverse();
-        for n in nodes {
-            if let Node::RideBus(stop2) = n {
-                if let Some(route) = possible_routes.iter().find(|r| r.stops.contains(&stop2)) {
-                    assert_ne!(first_stop, stop2);
-                    return Some((first_stop, stop2, route.id));
-                }
-            }
+        let mut dist = Distance::ZERO;
+        for (idx, step) in steps.iter().enumerate() {
+            let len = step.as_traversable().length(map);
+            dist += match step {
+                PathStep::Lane(_) if idx == 0 => len - start.dist_along(),
+                PathStep::ContraflowLane(_) if idx == 0 => start.dist_along(),
+                PathStep::Lane(_) if idx == steps.len() - 1 => end.dist_along(),
+                PathStep::ContraflowLane(_) if idx == steps.len() - 1 => len - end.dist_along(),
+                _ => len,
+            };
         }
-        unreachable!()
+        Some(dist / WALKING_SPEED)
     }
 }
 
@@ -195,12 +179,7 @@ fn closest_node(pos: Position, map: &Map) -> Node {
     Node::SidewalkEndpoint(pos.lane(), dst_i)
 }
 
-fn make_input_graph(
-    map: &Map,
-    nodes: &NodeMap<Node>,
-    use_transit: bool,
-    bus_graph: &VehiclePathfinder,
-) -> InputGraph {
+fn make_input_graph(map: &Map, nodes: &NodeMap<Node>) -> InputGraph {
     let mut input_graph = InputGraph::new();
 
     for l in map.all_lanes() {
@@ -221,64 +200,11 @@ fn make_input_graph(
         }
     }
 
-    if use_transit {
-        // Connect bus stops with both sidewalk endpoints, using the appropriate distance.
-        for stop in map.all_bus_stops().values() {
-            let ride_bus = nodes.get(Node::RideBus(stop.id));
-            let lane = map.get_l(stop.sidewalk_pos.lane());
-            for endpt in &[true, false] {
-                let cost = if *endpt {
-                    to_s(lane.length() - stop.sidewalk_pos.dist_along())
-                } else {
-                    to_s(stop.sidewalk_pos.dist_along())
-                };
-                // Add some extra penalty (equivalent to 1m) to using a bus stop. Otherwise a path
-                // might try to pass through it uselessly.
-                let penalty = 100;
-                let sidewalk = nodes.get(Node::SidewalkEndpoint(lane.id, *endpt));
-                input_graph.add_edge(sidewalk, ride_bus, cost + penalty);
-                input_graph.add_edge(ride_bus, sidewalk, cost + penalty);
-            }
-        }
-
-        // Connect each adjacent stop along a route, with the cost based on how long it'll take a
-        // bus to drive between the stops. Optimistically assume no waiting time at a stop.
-        for route in map.get_all_bus_routes() {
-            for (stop1, stop2) in
-                route
-                    .stops
-                    .iter()
-                    .zip(route.stops.iter().skip(1))
-                    .chain(std::iter::once((
-                        route.stops.last().unwrap(),
-                        &route.stops[0],
-                    )))
-            {
-                let driving_cost = bus_graph
-                    .pathfind(
-                        &PathRequest {
-                            start: map.get_bs(*stop1).driving_pos,
-                            end: map.get_bs(*stop2).driving_pos,
-                            constraints: PathConstraints::Bus,
-                        },
-                        map,
-                    )
-                    .unwrap()
-                    .1;
-                input_graph.add_edge(
-                    nodes.get(Node::RideBus(*stop1)),
-                    nodes.get(Node::RideBus(*stop2)),
-                    driving_cost,
-                );
-            }
-        }
-    }
     input_graph.freeze();
     input_graph
 }
 
 fn to_s(dist: Distance) -> usize {
-    let walking_speed = Speed::meters_per_second(1.34);
-    let time = dist / walking_speed;
+    let time = dist / WALKING_SPEED;
     time.inner_seconds().round() as usize
 }
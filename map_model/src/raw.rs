@@ -1,7 +1,7 @@
 use crate::make::get_lane_types;
-use crate::{osm, AreaType, IntersectionType, OffstreetParking, RoadSpec};
+use crate::{osm, AreaType, CrossingType, IntersectionType, OffstreetParking, RoadSpec, TurnType};
 use abstutil::{deserialize_btreemap, retain_btreemap, serialize_btreemap, Error, Timer};
-use geom::{GPSBounds, Polygon, Pt2D};
+use geom::{Distance, GPSBounds, Polygon, Pt2D};
 use gtfs::Route;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
@@ -30,6 +30,53 @@ pub struct RawMap {
 
     pub boundary_polygon: Polygon,
     pub gps_bounds: GPSBounds,
+    #[serde(default)]
+    pub driving_side: DrivingSide,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrivingSide {
+    Right,
+    // Like the UK, Japan, and Australia
+    Left,
+}
+
+impl Default for DrivingSide {
+    fn default() -> DrivingSide {
+        DrivingSide::Right
+    }
+}
+
+impl DrivingSide {
+    // The turn that doesn't cross oncoming traffic
+    pub fn curbside_turn(self) -> TurnType {
+        match self {
+            DrivingSide::Right => TurnType::Right,
+            DrivingSide::Left => TurnType::Left,
+        }
+    }
+
+    // Everything is written assuming driving on the right. Swaps left and right turns to describe
+    // the same movement when driving on the left.
+    pub fn mirror(self, turn_type: TurnType) -> TurnType {
+        match (self, turn_type) {
+            (DrivingSide::Right, tt) => tt,
+            (DrivingSide::Left, TurnType::Right) => TurnType::Left,
+            (DrivingSide::Left, TurnType::Left) => TurnType::Right,
+            (DrivingSide::Left, TurnType::LaneChangeRight) => TurnType::LaneChangeLeft,
+            (DrivingSide::Left, TurnType::LaneChangeLeft) => TurnType::LaneChangeRight,
+            (DrivingSide::Left, tt) => tt,
+        }
+    }
+
+    // OSM tags like cycleway:right mean the right side of the way, looking along it. Returns the
+    // side that lanes pointing the same way are on, then the other side.
+    pub fn osm_sides(self) -> (&'static str, &'static str) {
+        match self {
+            DrivingSide::Right => ("right", "left"),
+            DrivingSide::Left => ("left", "right"),
+        }
+    }
 }
 
 // A way to refer to roads across many maps.
@@ -95,6 +142,7 @@ impl RawMap {
             // Some nonsense thing
             boundary_polygon: Polygon::rectangle(1.0, 1.0),
             gps_bounds: GPSBounds::new(),
+            driving_side: DrivingSide::Right,
         }
     }
 
@@ -314,10 +362,15 @@ impl RawMap {
             polygon: Vec::new(),
             roads: self.roads_per_intersection(id).into_iter().collect(),
             intersection_type: self.intersections[&id].intersection_type,
+            elevation: self.intersections[&id].elevation,
+            crossing: self.intersections[&id].crossing,
         };
         let mut roads = BTreeMap::new();
         for r in &i.roads {
-            roads.insert(*r, initial::Road::new(*r, &self.roads[r]));
+            roads.insert(
+                *r,
+                initial::Road::new(*r, &self.roads[r], self.driving_side),
+            );
         }
 
         let (i_pts, debug) = initial::intersection_polygon(&i, &mut roads, timer);
@@ -327,16 +380,16 @@ impl RawMap {
                 .values()
                 .map(|r| {
                     // A little of get_thick_polyline
-                    let pl = if r.fwd_width >= r.back_width {
+                    let pl = if r.right_width >= r.left_width {
                         r.trimmed_center_pts
-                            .shift_right((r.fwd_width - r.back_width) / 2.0)
+                            .shift_right((r.right_width - r.left_width) / 2.0)
                             .unwrap()
                     } else {
                         r.trimmed_center_pts
-                            .shift_left((r.back_width - r.fwd_width) / 2.0)
+                            .shift_left((r.left_width - r.right_width) / 2.0)
                             .unwrap()
                     };
-                    pl.make_polygons(r.fwd_width + r.back_width)
+                    pl.make_polygons(r.right_width + r.left_width)
                 })
                 .collect(),
             debug,
@@ -524,8 +577,8 @@ pub struct RawRoad {
 }
 
 impl RawRoad {
-    pub fn get_spec(&self) -> RoadSpec {
-        let (fwd, back) = get_lane_types(&self.osm_tags);
+    pub fn get_spec(&self, driving_side: DrivingSide) -> RoadSpec {
+        let (fwd, back) = get_lane_types(&self.osm_tags, driving_side);
         RoadSpec { fwd, back }
     }
 
@@ -540,6 +593,12 @@ pub struct RawIntersection {
     // RawMap; roads and intersections get merged and deleted.
     pub point: Pt2D,
     pub intersection_type: IntersectionType,
+    // Above sea level. Zero if there's no elevation data.
+    #[serde(default)]
+    pub elevation: Distance,
+    // Only for crossings in the middle of a road, or where a path crosses one
+    #[serde(default)]
+    pub crossing: Option<CrossingType>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
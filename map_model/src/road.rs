@@ -1,6 +1,7 @@
 use crate::raw::{OriginalRoad, RestrictionType};
 use crate::{
-    osm, BusStopID, IntersectionID, LaneID, LaneType, Map, PathConstraints, LANE_THICKNESS,
+    osm, BusStopID, DrivingSide, IntersectionID, LaneID, LaneType, Map, PathConstraints,
+    LANE_THICKNESS,
 };
 use abstutil::{Error, Warn};
 use geom::{Distance, PolyLine, Polygon, Speed};
@@ -214,12 +215,20 @@ impl Road {
     pub fn get_speed_limit(&self) -> Speed {
         // TODO Should probably cache this
         if let Some(limit) = self.osm_tags.get(osm::MAXSPEED) {
-            // TODO handle other units
-            if limit.ends_with(" mph") {
-                if let Ok(mph) = limit[0..limit.len() - 4].parse::<f64>() {
+            // Usually "25 mph", but sometimes "25mph"
+            if limit.ends_with("mph") {
+                if let Ok(mph) = limit.trim_end_matches("mph").trim().parse::<f64>() {
                     return Speed::miles_per_hour(mph);
                 }
             }
+            // Without units, OSM means km/h
+            let kph = limit
+                .trim_end_matches("km/h")
+                .trim_end_matches("kmh")
+                .trim();
+            if let Ok(kph) = kph.parse::<f64>() {
+                return Speed::km_per_hour(kph);
+            }
         }
 
         if self.osm_tags.get(osm::HIGHWAY) == Some(&"primary".to_string())
@@ -316,9 +325,12 @@ impl Road {
         search.iter().find(|(_, t)| lt == *t).map(|(id, _)| *id)
     }
 
-    pub fn get_thick_polyline(&self) -> Warn<(PolyLine, Distance)> {
-        let width_right = (self.children_forwards.len() as f64) * LANE_THICKNESS;
-        let width_left = (self.children_backwards.len() as f64) * LANE_THICKNESS;
+    pub fn get_thick_polyline(&self, map: &Map) -> Warn<(PolyLine, Distance)> {
+        let mut width_right = (self.children_forwards.len() as f64) * LANE_THICKNESS;
+        let mut width_left = (self.children_backwards.len() as f64) * LANE_THICKNESS;
+        if map.get_driving_side() == DrivingSide::Left {
+            std::mem::swap(&mut width_right, &mut width_left);
+        }
         let total_width = width_right + width_left;
         if width_right >= width_left {
             self.center_pts
@@ -331,8 +343,8 @@ impl Road {
         }
     }
 
-    pub fn get_thick_polygon(&self) -> Warn<Polygon> {
-        self.get_thick_polyline()
+    pub fn get_thick_polygon(&self, map: &Map) -> Warn<Polygon> {
+        self.get_thick_polyline(map)
             .map(|(pl, width)| pl.make_polygons(width))
     }
 
@@ -386,8 +398,13 @@ impl Road {
                 "tertiary_link" => 9,
 
                 "residential" => 5,
+                "service" => 3,
 
+                "cycleway" => 1,
                 "footway" => 1,
+                "path" => 1,
+                "pedestrian" => 1,
+                "steps" => 1,
 
                 "unclassified" => 0,
                 "road" => 0,
@@ -399,6 +416,18 @@ impl Road {
         }
     }
 
+    pub fn is_roundabout(&self) -> bool {
+        osm::is_roundabout(&self.osm_tags)
+    }
+
+    // Footways, cycleways, and other paths without any space for cars
+    pub fn is_trail(&self) -> bool {
+        self.children_forwards
+            .iter()
+            .chain(self.children_backwards.iter())
+            .all(|(_, lt)| *lt == LaneType::Sidewalk || *lt == LaneType::Biking)
+    }
+
     pub fn all_bus_stops(&self, map: &Map) -> Vec<BusStopID> {
         let mut stops = Vec::new();
         for id in self.all_lanes() {
@@ -35,6 +35,10 @@ pub struct ControlStopSign {
         deserialize_with = "deserialize_btreemap"
     )]
     pub roads: BTreeMap<RoadID, RoadWithStopSign>,
+    // Where roads enter a roundabout, the "stop signs" are really yield signs: entering traffic
+    // doesn't have to stop first, but gives way to traffic already circulating.
+    #[serde(default)]
+    pub roundabout: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -48,6 +52,7 @@ impl ControlStopSign {
         let mut ss = ControlStopSign {
             id,
             roads: BTreeMap::new(),
+            roundabout: false,
         };
         for r in &map.get_i(id).roads {
             let travel_lanes: Vec<LaneID> = map
@@ -73,6 +78,22 @@ impl ControlStopSign {
             }
         }
 
+        // Points along a roundabout's ring, where other roads enter
+        let ring_roads: Vec<RoadID> = map
+            .get_i(id)
+            .roads
+            .iter()
+            .filter(|r| map.get_r(**r).is_roundabout())
+            .cloned()
+            .collect();
+        if !ring_roads.is_empty() && ring_roads.len() < map.get_i(id).roads.len() {
+            ss.roundabout = true;
+            for (r, cfg) in ss.roads.iter_mut() {
+                cfg.must_stop = !ring_roads.contains(r);
+            }
+            return ss;
+        }
+
         if ss.roads.len() <= 2 {
             // Degenerate roads and deadends don't need any stop signs.
             return ss;
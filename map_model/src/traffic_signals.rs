@@ -1,9 +1,15 @@
-use crate::{IntersectionID, Map, RoadID, TurnGroup, TurnGroupID, TurnID, TurnPriority, TurnType};
+use crate::{
+    CrossingType, IntersectionID, Map, RoadID, TurnGroup, TurnGroupID, TurnID, TurnPriority,
+    TurnType,
+};
 use abstutil::{deserialize_btreemap, retain_btreeset, serialize_btreemap, Timer};
-use geom::{Duration, Time};
+use geom::{Duration, Speed, Time};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 
+// The sim has people walk between 2 and 3mph.
+const SLOWEST_PED_SPEED: Speed = Speed::const_meters_per_second(0.894);
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ControlTrafficSignal {
     pub id: IntersectionID,
@@ -22,6 +28,22 @@ pub struct Phase {
     pub protected_groups: BTreeSet<TurnGroupID>,
     pub yield_groups: BTreeSet<TurnGroupID>,
     pub duration: Duration,
+    // If present, the phase length depends on demand, and duration is only used when something
+    // needs a fixed-time approximation.
+    #[serde(default)]
+    pub actuated: Option<ActuatedPhase>,
+}
+
+// Vehicles are detected by virtual loop detectors at the end of each incoming lane.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ActuatedPhase {
+    pub min_green: Duration,
+    pub max_green: Duration,
+    // After min_green, every vehicle detected keeps the phase going this much longer, up to
+    // max_green. If there's a gap in arrivals this long, the phase ends.
+    pub extension: Duration,
+    // Don't even start the phase if nobody is waiting for any of its turns.
+    pub skip_if_no_demand: bool,
 }
 
 impl ControlTrafficSignal {
@@ -38,6 +60,9 @@ impl ControlTrafficSignal {
         id: IntersectionID,
     ) -> Vec<(String, ControlTrafficSignal)> {
         let mut results = Vec::new();
+        if let Some(ts) = ControlTrafficSignal::ped_call(map, id) {
+            results.push(("pedestrian call button".to_string(), ts));
+        }
         if let Some(ts) = ControlTrafficSignal::four_way_four_phase(map, id) {
             results.push(("four-phase".to_string(), ts));
         }
@@ -75,6 +100,11 @@ impl ControlTrafficSignal {
         cycle_length
     }
 
+    pub fn is_actuated(&self) -> bool {
+        self.phases.iter().any(|p| p.actuated.is_some())
+    }
+
+    // Only correct for fixed-time signals; the state of actuated signals lives in the simulation.
     pub fn current_phase_and_remaining_time(&self, now: Time) -> (usize, &Phase, Duration) {
         let mut now_offset = ((now + self.offset) - Time::START_OF_DAY) % self.cycle_length();
         for (idx, p) in self.phases.iter().enumerate() {
@@ -127,6 +157,21 @@ impl ControlTrafficSignal {
             for g in phase.yield_groups.iter().map(|g| &self.turn_groups[g]) {
                 assert!(g.turn_type != TurnType::Crosswalk);
             }
+
+            if let Some(ref a) = phase.actuated {
+                if a.min_green <= Duration::ZERO || a.min_green > a.max_green {
+                    return Err(format!(
+                        "Traffic signal {} has an actuated phase with min green {} and max green {}",
+                        self.id, a.min_green, a.max_green
+                    ));
+                }
+                if a.extension <= Duration::ZERO {
+                    return Err(format!(
+                        "Traffic signal {} has an actuated phase with no extension",
+                        self.id
+                    ));
+                }
+            }
         }
 
         Ok(self)
@@ -200,6 +245,36 @@ impl ControlTrafficSignal {
         ts.validate().ok()
     }
 
+    // For signalized crossings in the middle of a road. Traffic keeps going unless somebody's
+    // waiting to cross.
+    fn ped_call(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal> {
+        if map.get_i(i).crossing != Some(CrossingType::Signalized) {
+            return None;
+        }
+        let mut ts = ControlTrafficSignal::degenerate(map, i)?;
+        if ts.phases.len() != 2 {
+            return None;
+        }
+        // Pedestrians won't start crossing unless they'll finish before the light changes, so
+        // leave enough time for the slowest ones.
+        let longest_crossing = ts
+            .turn_groups
+            .keys()
+            .filter_map(|g| g.crosswalk)
+            .map(|t| map.get_t(t).geom.length() / SLOWEST_PED_SPEED)
+            .max()
+            .unwrap_or(Duration::ZERO);
+        let min_green = Duration::seconds(10.0).max(longest_crossing);
+        ts.phases[1].duration = min_green + Duration::seconds(5.0);
+        ts.phases[1].actuated = Some(ActuatedPhase {
+            min_green,
+            max_green: min_green + Duration::seconds(10.0),
+            extension: Duration::seconds(2.0),
+            skip_if_no_demand: true,
+        });
+        ts.validate().ok()
+    }
+
     fn three_way(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal> {
         if map.get_i(i).roads.len() != 3 {
             return None;
@@ -487,6 +562,15 @@ impl Phase {
             protected_groups: BTreeSet::new(),
             yield_groups: BTreeSet::new(),
             duration: Duration::seconds(30.0),
+            actuated: None,
+        }
+    }
+
+    // The longest this phase could last
+    pub fn max_duration(&self) -> Duration {
+        match self.actuated {
+            Some(ref a) => a.max_green,
+            None => self.duration,
         }
     }
 
@@ -582,6 +666,8 @@ fn make_phases(
         let mut phase = Phase::new();
 
         for (roads, turn_type, protected) in specs.into_iter() {
+            // The specs are written for driving on the right
+            let turn_type = map.get_driving_side().mirror(turn_type);
             for group in turn_groups.values() {
                 if !roads.contains(&group.id.from) || turn_type != group.turn_type {
                     continue;
@@ -0,0 +1,109 @@
+use crate::{LaneID, Map, TurnID};
+use abstutil::{deserialize_btreemap, serialize_btreemap, Timer};
+use geom::Duration;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Observed travel times for cars, used in place of free-flow times when routing. Anything missing
+// uses the free-flow time.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TravelTimes {
+    pub map_name: String,
+    pub name: String,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pub lanes: BTreeMap<LaneID, Duration>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pub turns: BTreeMap<TurnID, Duration>,
+}
+
+impl TravelTimes {
+    pub fn new(map_name: String, name: String) -> TravelTimes {
+        TravelTimes {
+            map_name,
+            name,
+            lanes: BTreeMap::new(),
+            turns: BTreeMap::new(),
+        }
+    }
+
+    pub fn load(map_name: &str, name: &str, timer: &mut Timer) -> TravelTimes {
+        abstutil::read_binary(abstutil::path_travel_times(map_name, name), timer)
+    }
+
+    pub fn save(&self) {
+        abstutil::write_binary(
+            abstutil::path_travel_times(&self.map_name, &self.name),
+            self,
+        );
+    }
+
+    pub fn lane(&self, l: LaneID, map: &Map) -> Duration {
+        self.lanes
+            .get(&l)
+            .cloned()
+            .unwrap_or_else(|| free_flow_lane(l, map))
+    }
+
+    pub fn turn(&self, t: TurnID, map: &Map) -> Duration {
+        self.turns
+            .get(&t)
+            .cloned()
+            .unwrap_or_else(|| free_flow_turn(t, map))
+    }
+
+    // Method of successive averages: move each time towards the observed time by some step size
+    // between 0 and 1. Returns the relative gap, the total change over the total old time, which
+    // is a measure of convergence.
+    pub fn average_with(&mut self, observed: &TravelTimes, step: f64, map: &Map) -> f64 {
+        let mut total_change = 0.0;
+        let mut total_old = 0.0;
+
+        let lanes: BTreeSet<LaneID> = self
+            .lanes
+            .keys()
+            .chain(observed.lanes.keys())
+            .cloned()
+            .collect();
+        for l in lanes {
+            let old = self.lane(l, map);
+            let new = old + (observed.lane(l, map) - old) * step;
+            total_change += (new - old).inner_seconds().abs();
+            total_old += old.inner_seconds();
+            self.lanes.insert(l, new);
+        }
+
+        let turns: BTreeSet<TurnID> = self
+            .turns
+            .keys()
+            .chain(observed.turns.keys())
+            .cloned()
+            .collect();
+        for t in turns {
+            let old = self.turn(t, map);
+            let new = old + (observed.turn(t, map) - old) * step;
+            total_change += (new - old).inner_seconds().abs();
+            total_old += old.inner_seconds();
+            self.turns.insert(t, new);
+        }
+
+        if total_old == 0.0 {
+            0.0
+        } else {
+            total_change / total_old
+        }
+    }
+}
+
+pub fn free_flow_lane(l: LaneID, map: &Map) -> Duration {
+    map.get_l(l).length() / map.get_parent(l).get_speed_limit()
+}
+
+pub fn free_flow_turn(t: TurnID, map: &Map) -> Duration {
+    map.get_t(t).geom.length() / map.get_parent(t.dst).get_speed_limit()
+}
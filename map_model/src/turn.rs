@@ -1,4 +1,4 @@
-use crate::{IntersectionID, LaneID, Map, RoadID, LANE_THICKNESS};
+use crate::{DrivingSide, IntersectionID, LaneID, Map, RoadID, LANE_THICKNESS};
 use abstutil::MultiMap;
 use geom::{Angle, Distance, PolyLine, Pt2D};
 use serde_derive::{Deserialize, Serialize};
@@ -237,7 +237,12 @@ impl TurnGroup {
         } else {
             offsets[offsets.len() / 2] as f64
         };
-        let pl = pl.shift_right(LANE_THICKNESS * (0.5 + offset)).unwrap();
+        let width = LANE_THICKNESS * (0.5 + offset);
+        let pl = match map.get_driving_side() {
+            DrivingSide::Right => pl.shift_right(width),
+            DrivingSide::Left => pl.shift_left(width),
+        }
+        .unwrap();
         let pl = if self
             .id
             .crosswalk
@@ -8,6 +8,8 @@ use std::io::{BufRead, BufReader, BufWriter, Write};
 
 #[derive(Serialize, Deserialize)]
 pub struct Trip {
+    // (hhno, pno): the household, and the person within it
+    pub person: (usize, usize),
     pub from: Endpoint,
     pub to: Endpoint,
     pub depart_at: Time,
@@ -39,7 +41,7 @@ pub enum Mode {
     Transit,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum Purpose {
     Home,
     Work,
@@ -63,9 +65,24 @@ pub fn import_trips(
 
     let mut trips = Vec::new();
     let (reader, done) = FileWithProgress::new(trips_path)?;
-    for rec in csv::Reader::from_reader(reader).records() {
+    let mut reader = csv::Reader::from_reader(reader);
+    // Other columns are read by position below, but find the person ones by name.
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| failure::format_err!("{} has no {} column", trips_path, name))
+    };
+    let (hhno, pno) = (column("hhno")?, column("pno")?);
+    for rec in reader.records() {
         let rec = rec?;
 
+        let person = (
+            rec[hhno].trim_end_matches(".0").parse::<usize>()?,
+            rec[pno].trim_end_matches(".0").parse::<usize>()?,
+        );
+
         // opcl
         let from = skip_fail!(parcels.get(rec[15].trim_end_matches(".0"))).clone();
         // dpcl
@@ -98,6 +115,7 @@ pub fn import_trips(
         let trip_dist = Distance::miles(rec[24].parse::<f64>()?);
 
         trips.push(Trip {
+            person,
             from,
             to,
             depart_at,
@@ -4,10 +4,12 @@ use abstutil::Timer;
 use geom::{Distance, Duration, LonLat, Polygon, Pt2D, Time};
 use map_model::{BuildingID, IntersectionID, Map, PathConstraints, Position};
 use sim::{DrivingGoal, Scenario, SidewalkSpot, SpawnTrip, TripSpec};
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 #[derive(Clone, Debug)]
 pub struct Trip {
+    // (household, person within it)
+    pub person: (usize, usize),
     pub from: TripEndpt,
     pub to: TripEndpt,
     pub depart_at: Time,
@@ -16,6 +18,8 @@ pub struct Trip {
     // These are an upper bound when TripEndpt::Border is involved.
     pub trip_time: Duration,
     pub trip_dist: Distance,
+    // Drive or bike to this building, then ride transit from there, leaving at this time
+    pub park_and_ride: Option<(BuildingID, Time)>,
 }
 
 #[derive(Clone, Debug)]
@@ -31,7 +35,57 @@ impl Trip {
         self.depart_at + self.trip_time
     }
 
+    fn to_park_and_ride(
+        &self,
+        lot: BuildingID,
+        transit_depart: Time,
+        map: &Map,
+    ) -> Option<SpawnTrip> {
+        let goal = self.to.end_sidewalk_spot(map);
+        let rides = map.should_use_transit(
+            map.get_b(lot).front_path.sidewalk,
+            goal.sidewalk_pos,
+            transit_depart,
+        )?;
+        match (self.mode, &self.from) {
+            (Mode::Drive, TripEndpt::Building(b)) => {
+                Some(SpawnTrip::ParkAndRide(self.depart_at, *b, lot, goal, rides))
+            }
+            (Mode::Drive, TripEndpt::Border(i, _)) => {
+                let start = TripSpec::spawn_car_at(
+                    Position::new(
+                        map.get_i(*i).get_outgoing_lanes(map, PathConstraints::Car)[0],
+                        Distance::ZERO,
+                    ),
+                    map,
+                )?;
+                Some(SpawnTrip::ParkAndRideFromBorder(
+                    self.depart_at,
+                    start,
+                    lot,
+                    goal,
+                    rides,
+                ))
+            }
+            (Mode::Bike, _) => Some(SpawnTrip::BikeAndRide(
+                self.depart_at,
+                self.from.start_sidewalk_spot(map),
+                lot,
+                goal,
+                rides,
+            )),
+            _ => None,
+        }
+    }
+
     pub fn to_spawn_trip(&self, map: &Map) -> Option<SpawnTrip> {
+        if let Some((lot, transit_depart)) = self.park_and_ride {
+            // If transit doesn't work out, just drive or bike the whole way.
+            if let Some(trip) = self.to_park_and_ride(lot, transit_depart, map) {
+                return Some(trip);
+            }
+        }
+
         match self.mode {
             Mode::Drive => match self.from {
                 TripEndpt::Border(i, _) => {
@@ -94,17 +148,10 @@ impl Trip {
             Mode::Transit => {
                 let start = self.from.start_sidewalk_spot(map);
                 let goal = self.to.end_sidewalk_spot(map);
-                if let Some((stop1, stop2, route)) =
-                    map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos)
+                if let Some(rides) =
+                    map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos, self.depart_at)
                 {
-                    Some(SpawnTrip::UsingTransit(
-                        self.depart_at,
-                        start,
-                        goal,
-                        route,
-                        stop1,
-                        stop2,
-                    ))
+                    Some(SpawnTrip::UsingTransit(self.depart_at, start, goal, rides))
                 } else {
                     //timer.warn(format!("{:?} not actually using transit, because pathfinding didn't find any useful route", trip));
                     Some(SpawnTrip::JustWalking(self.depart_at, start, goal))
@@ -242,6 +289,7 @@ pub fn clip_trips(map: &Map, timer: &mut Timer) -> (Vec<Trip>, HashMap<BuildingI
         )?;
 
         let trip = Trip {
+            person: trip.person,
             from,
             to,
             depart_at: trip.depart_at,
@@ -249,6 +297,7 @@ pub fn clip_trips(map: &Map, timer: &mut Timer) -> (Vec<Trip>, HashMap<BuildingI
             mode: trip.mode,
             trip_time: trip.trip_time,
             trip_dist: trip.trip_dist,
+            park_and_ride: None,
         };
 
         match (&trip.from, &trip.to) {
@@ -277,41 +326,89 @@ pub fn clip_trips(map: &Map, timer: &mut Timer) -> (Vec<Trip>, HashMap<BuildingI
     (trips, bldgs)
 }
 
+// PSRC splits park-and-ride into two trips: driving or biking to the lot, then transit from there.
+// Glue each one to the same person's next transit trip leaving the lot after they get there.
+pub fn chain_park_and_ride(trips: Vec<Trip>) -> Vec<Trip> {
+    let mut transit_from: HashMap<((usize, usize), BuildingID), Vec<usize>> = HashMap::new();
+    for (idx, trip) in trips.iter().enumerate() {
+        if let (Mode::Transit, Purpose::ParkAndRideTransfer, TripEndpt::Building(b)) =
+            (trip.mode, trip.purpose.0, &trip.from)
+        {
+            transit_from
+                .entry((trip.person, *b))
+                .or_insert_with(Vec::new)
+                .push(idx);
+        }
+    }
+
+    // First leg to second leg
+    let mut chained: HashMap<usize, usize> = HashMap::new();
+    let mut used: HashSet<usize> = HashSet::new();
+    for (idx, trip) in trips.iter().enumerate() {
+        if (trip.mode != Mode::Drive && trip.mode != Mode::Bike)
+            || trip.purpose.1 != Purpose::ParkAndRideTransfer
+        {
+            continue;
+        }
+        if let TripEndpt::Building(b) = trip.to {
+            if let Some(candidates) = transit_from.get_mut(&(trip.person, b)) {
+                // Trips are sorted by departure time.
+                if let Some(pos) = candidates
+                    .iter()
+                    .position(|other| trips[*other].depart_at >= trip.end_time())
+                {
+                    let other = candidates.remove(pos);
+                    chained.insert(idx, other);
+                    used.insert(other);
+                }
+            }
+        }
+    }
+
+    trips
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| !used.contains(idx))
+        .map(|(idx, trip)| {
+            if let Some(other) = chained.get(&idx) {
+                let transit = &trips[*other];
+                Trip {
+                    person: trip.person,
+                    from: trip.from.clone(),
+                    to: transit.to.clone(),
+                    depart_at: trip.depart_at,
+                    purpose: (trip.purpose.0, transit.purpose.1),
+                    mode: trip.mode,
+                    trip_time: transit.end_time() - trip.depart_at,
+                    trip_dist: trip.trip_dist + transit.trip_dist,
+                    park_and_ride: match trip.to {
+                        TripEndpt::Building(b) => Some((b, transit.depart_at)),
+                        TripEndpt::Border(_, _) => unreachable!(),
+                    },
+                }
+            } else {
+                trip.clone()
+            }
+        })
+        .collect()
+}
+
 pub fn trips_to_scenario(map: &Map, timer: &mut Timer) -> Scenario {
     let (trips, _) = clip_trips(map, timer);
-    // TODO Don't clone trips for parallelize
-    let individ_trips = timer
-        .parallelize("turn PSRC trips into SpawnTrips", trips.clone(), |trip| {
+    let trips = chain_park_and_ride(trips);
+    let individ_trips: Vec<SpawnTrip> = timer
+        .parallelize("turn PSRC trips into SpawnTrips", trips, |trip| {
             trip.to_spawn_trip(map)
         })
         .into_iter()
         .flatten()
         .collect();
 
-    // How many parked cars do we need to spawn near each building?
-    // TODO This assumes trips are instantaneous. At runtime, somebody might try to use a parked
-    // car from a building, but one hasn't been delivered yet.
     let mut individ_parked_cars = BTreeMap::new();
-    let mut avail_per_bldg = BTreeMap::new();
     for b in map.all_buildings() {
         individ_parked_cars.insert(b.id, 0);
-        avail_per_bldg.insert(b.id, 0);
-    }
-    for trip in trips {
-        if trip.mode != Mode::Drive {
-            continue;
-        }
-        if let TripEndpt::Building(b) = trip.from {
-            if avail_per_bldg[&b] > 0 {
-                *avail_per_bldg.get_mut(&b).unwrap() -= 1;
-            } else {
-                *individ_parked_cars.get_mut(&b).unwrap() += 1;
-            }
-        }
-        if let TripEndpt::Building(b) = trip.to {
-            *avail_per_bldg.get_mut(&b).unwrap() += 1;
-        }
     }
+    individ_parked_cars.extend(parked_cars_needed(&individ_trips));
 
     Scenario {
         scenario_name: "weekday_typical_traffic_from_psrc".to_string(),
@@ -324,3 +421,45 @@ pub fn trips_to_scenario(map: &Map, timer: &mut Timer) -> Scenario {
         individ_parked_cars,
     }
 }
+
+// How many parked cars do we need to spawn near each building? Buildings that don't need any are
+// left out. This looks at the trips actually spawned, since park-and-ride trips might've fallen
+// back to driving the whole way.
+// TODO This assumes trips are instantaneous. At runtime, somebody might try to use a parked
+// car from a building, but one hasn't been delivered yet.
+pub fn parked_cars_needed(trips: &[SpawnTrip]) -> BTreeMap<BuildingID, usize> {
+    let goal_bldg = |goal: &DrivingGoal| match goal {
+        DrivingGoal::ParkNear(b) => Some(*b),
+        DrivingGoal::Border(_, _) => None,
+    };
+
+    let mut needed = BTreeMap::new();
+    let mut avail_per_bldg: HashMap<BuildingID, usize> = HashMap::new();
+    for trip in trips {
+        // Where the car starts parked, and where it's left afterwards
+        let (from, to) = match trip {
+            SpawnTrip::MaybeUsingParkedCar(_, b, goal) => (Some(*b), goal_bldg(goal)),
+            SpawnTrip::CarAppearing {
+                goal,
+                is_bike: false,
+                ..
+            } => (None, goal_bldg(goal)),
+            // Park-and-ride leaves the car at the lot.
+            SpawnTrip::ParkAndRide(_, b, lot, _, _) => (Some(*b), Some(*lot)),
+            SpawnTrip::ParkAndRideFromBorder(_, _, lot, _, _) => (None, Some(*lot)),
+            _ => continue,
+        };
+        if let Some(b) = from {
+            let avail = avail_per_bldg.entry(b).or_insert(0);
+            if *avail > 0 {
+                *avail -= 1;
+            } else {
+                *needed.entry(b).or_insert(0) += 1;
+            }
+        }
+        if let Some(b) = to {
+            *avail_per_bldg.entry(b).or_insert(0) += 1;
+        }
+    }
+    needed
+}
@@ -12,6 +12,20 @@ fn main() {
     let mut timer = Timer::new(format!("precompute {}", load));
 
     let map = Map::new(load, use_fixes, &mut timer);
+
+    // Edits from before stable IDs refer to the old version of this map, so upgrade them before
+    // it's overwritten.
+    let legacy_edits = map_model::find_legacy_edits(map.get_name());
+    if !legacy_edits.is_empty() {
+        match abstutil::maybe_read_binary::<Map>(abstutil::path_map(map.get_name()), &mut timer) {
+            Ok(old_map) => map_model::upgrade_legacy_edits(&old_map, legacy_edits, &mut timer),
+            Err(err) => timer.warn(format!(
+                "Can't load the old map to upgrade edits {:?}: {}",
+                legacy_edits, err
+            )),
+        }
+    }
+
     timer.start("save map");
     map.save();
     timer.stop("save map");
@@ -1,12 +1,18 @@
-use crate::{AgentID, CarID, Event, TripID, TripMode, VehicleType};
+use crate::{AgentID, CarID, Event, PedestrianID, TripID, TripMode, VehicleType};
 use abstutil::Counter;
 use derivative::Derivative;
 use geom::{Distance, Duration, DurationHistogram, PercentageHistogram, Time};
 use map_model::{
-    BusRouteID, BusStopID, IntersectionID, Map, Path, PathRequest, RoadID, Traversable, TurnGroupID,
+    BusRouteID, BusStopID, IntersectionID, LaneID, Map, Path, PathRequest, RoadID, Traversable,
+    TurnGroupID, TurnID,
 };
 use serde_derive::{Deserialize, Serialize};
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+// Bump this whenever the serialized form of Analytics changes, so old prebaked results are
+// recomputed instead of misread.
+const PREBAKED_VERSION: usize = 1;
+const PREBAKED_MAGIC: &[u8; 8] = b"abstanly";
 
 #[derive(Clone, Serialize, Deserialize, Derivative)]
 pub struct Analytics {
@@ -14,13 +20,26 @@ pub struct Analytics {
     #[serde(skip_serializing, skip_deserializing)]
     pub(crate) test_expectations: VecDeque<Event>,
     pub bus_arrivals: Vec<(Time, CarID, BusRouteID, BusStopID)>,
-    pub bus_passengers_waiting: Vec<(Time, BusStopID, BusRouteID)>,
+    pub bus_passengers_waiting: Vec<(Time, PedestrianID, BusStopID, BusRouteID)>,
+    pub bus_boardings: Vec<(Time, PedestrianID, BusStopID, BusRouteID)>,
+    // Passengers left behind because the bus was full
+    pub bus_boardings_denied: Vec<(Time, CarID, BusRouteID, BusStopID)>,
+    // Actual arrival, bus, route, stop, scheduled departure
+    pub bus_scheduled_arrivals: Vec<(Time, CarID, BusRouteID, BusStopID, Time)>,
     // TODO Hack: No TripMode means aborted
     // Finish time, ID, mode (or None as aborted), trip duration
     pub finished_trips: Vec<(Time, TripID, Option<TripMode>, Duration)>,
     // TODO This subsumes finished_trips
     pub trip_log: Vec<(Time, TripID, Option<PathRequest>, String)>,
     pub intersection_delays: BTreeMap<IntersectionID, Vec<(Time, Duration)>>,
+    pub lane_changes: Vec<(Time, CarID, RoadID)>,
+    // Where cars were when they took a detour
+    pub reroutes: Vec<(Time, CarID, LaneID)>,
+    // Only for cars. Total time spent on each lane and turn, measured from entering it to entering
+    // the next thing, and the number of times it was crossed.
+    pub traversal_times: BTreeMap<Traversable, (Duration, usize)>,
+    #[serde(skip_serializing, skip_deserializing)]
+    last_entered: BTreeMap<CarID, (Traversable, Time)>,
 
     // After we restore from a savestate, don't record anything. This is only going to make sense
     // if savestates are only used for quickly previewing against prebaked results, where we have
@@ -43,6 +62,28 @@ pub struct ThruputStats {
 }
 
 impl Analytics {
+    pub fn save_prebaked(&self, map_name: &str, scenario_name: &str) {
+        abstutil::write_versioned_binary(
+            abstutil::path_prebaked_results(map_name, scenario_name),
+            PREBAKED_MAGIC,
+            PREBAKED_VERSION,
+            self,
+        );
+    }
+
+    pub fn load_prebaked(
+        map_name: &str,
+        scenario_name: &str,
+        timer: &mut abstutil::Timer,
+    ) -> Result<Analytics, std::io::Error> {
+        abstutil::maybe_read_versioned_binary(
+            abstutil::path_prebaked_results(map_name, scenario_name),
+            PREBAKED_MAGIC,
+            PREBAKED_VERSION,
+            timer,
+        )
+    }
+
     pub fn new() -> Analytics {
         Analytics {
             thruput_stats: ThruputStats {
@@ -55,9 +96,16 @@ impl Analytics {
             test_expectations: VecDeque::new(),
             bus_arrivals: Vec::new(),
             bus_passengers_waiting: Vec::new(),
+            bus_boardings: Vec::new(),
+            bus_boardings_denied: Vec::new(),
+            bus_scheduled_arrivals: Vec::new(),
             finished_trips: Vec::new(),
             trip_log: Vec::new(),
             intersection_delays: BTreeMap::new(),
+            lane_changes: Vec::new(),
+            reroutes: Vec::new(),
+            traversal_times: BTreeMap::new(),
+            last_entered: BTreeMap::new(),
             record_anything: true,
         }
     }
@@ -102,6 +150,28 @@ impl Analytics {
                     }
                 }
             };
+
+            // Travel times
+            if let AgentID::Car(c) = a {
+                if c.1 == VehicleType::Car {
+                    if let Some((prev, entered)) = self.last_entered.insert(c, (to, time)) {
+                        self.record_traversal(prev, time - entered);
+                    }
+                }
+            }
+        }
+        // The time spent on the old lane counts toward the new one, since that's where the car
+        // leaves the road from.
+        if let Event::CarChangedLanes(car, _, to) = ev {
+            if let Some(entry) = self.last_entered.get_mut(&car) {
+                entry.0 = Traversable::Lane(to);
+            }
+        }
+        // Parked cars keep their ID, so don't count the time parked when they start again.
+        if let Event::CarReachedParkingSpot(car, _) | Event::CarOrBikeReachedBorder(car, _) = ev {
+            if let Some((prev, entered)) = self.last_entered.remove(&car) {
+                self.record_traversal(prev, time - entered);
+            }
         }
 
         // Test expectations
@@ -115,9 +185,29 @@ impl Analytics {
             self.bus_arrivals.push((time, bus, route, stop));
         }
 
+        // Schedule adherence
+        if let Event::BusScheduledArrival(bus, route, stop, scheduled) = ev {
+            self.bus_scheduled_arrivals
+                .push((time, bus, route, stop, scheduled));
+        }
+
         // Bus passengers
-        if let Event::PedReachedBusStop(_, stop, route) = ev {
-            self.bus_passengers_waiting.push((time, stop, route));
+        if let Event::PedReachedBusStop(ped, stop, route) = ev {
+            self.bus_passengers_waiting.push((time, ped, stop, route));
+        }
+        if let Event::PedEntersBus(ped, _, route) = ev {
+            // They board wherever they were last waiting for this route.
+            if let Some((_, _, stop, _)) = self
+                .bus_passengers_waiting
+                .iter()
+                .rev()
+                .find(|(_, p, _, r)| *p == ped && *r == route)
+            {
+                self.bus_boardings.push((time, ped, *stop, route));
+            }
+        }
+        if let Event::PedDeniedBoarding(_, bus, route, stop) = ev {
+            self.bus_boardings_denied.push((time, bus, route, stop));
         }
 
         // Finished trips
@@ -135,6 +225,16 @@ impl Analytics {
                 .push((time, delay));
         }
 
+        // Lane changes
+        if let Event::CarChangedLanes(car, from, _) = ev {
+            self.lane_changes.push((time, car, map.get_l(from).parent));
+        }
+
+        // Reroutes
+        if let Event::CarRerouted(car, l) = ev {
+            self.reroutes.push((time, car, l));
+        }
+
         // TODO Kinda hacky, but these all consume the event, so kinda bundle em.
         match ev {
             Event::TripPhaseStarting(id, maybe_req, metadata) => {
@@ -155,6 +255,15 @@ impl Analytics {
         }
     }
 
+    fn record_traversal(&mut self, on: Traversable, dt: Duration) {
+        let entry = self
+            .traversal_times
+            .entry(on)
+            .or_insert((Duration::ZERO, 0));
+        entry.0 += dt;
+        entry.1 += 1;
+    }
+
     pub fn record_demand(&mut self, path: &Path, map: &Map) {
         for step in path.get_steps() {
             if let Traversable::Turn(t) = step.as_traversable() {
@@ -165,6 +274,26 @@ impl Analytics {
         }
     }
 
+    // Average time for cars to cross each lane and turn, including waiting at the end of lanes
+    pub fn observed_travel_times(
+        &self,
+    ) -> (BTreeMap<LaneID, Duration>, BTreeMap<TurnID, Duration>) {
+        let mut lanes = BTreeMap::new();
+        let mut turns = BTreeMap::new();
+        for (on, (total, count)) in &self.traversal_times {
+            let avg = *total / (*count as f64);
+            match on {
+                Traversable::Lane(l) => {
+                    lanes.insert(*l, avg);
+                }
+                Traversable::Turn(t) => {
+                    turns.insert(*t, avg);
+                }
+            }
+        }
+        (lanes, turns)
+    }
+
     // TODO If these ever need to be speeded up, just cache the histogram and index in the events
     // list.
 
@@ -213,7 +342,19 @@ impl Analytics {
     // Returns unsorted list of deltas, one for each trip finished in both worlds. Positive dt
     // means faster.
     pub fn finished_trip_deltas(&self, now: Time, baseline: &Analytics) -> Vec<Duration> {
-        let a: BTreeMap<TripID, Duration> = self
+        self.finished_trip_pairs(now, baseline)
+            .into_iter()
+            .map(|(_, _, before, after)| before - after)
+            .collect()
+    }
+
+    // (trip, mode, duration in the baseline, duration here) for each trip finished in both worlds
+    pub fn finished_trip_pairs(
+        &self,
+        now: Time,
+        baseline: &Analytics,
+    ) -> Vec<(TripID, TripMode, Duration, Duration)> {
+        let b: BTreeMap<TripID, Duration> = baseline
             .finished_trips
             .iter()
             .filter_map(|(t, id, mode, dt)| {
@@ -224,20 +365,16 @@ impl Analytics {
                 }
             })
             .collect();
-        let b: BTreeMap<TripID, Duration> = baseline
-            .finished_trips
+
+        self.finished_trips
             .iter()
             .filter_map(|(t, id, mode, dt)| {
-                if *t <= now && mode.is_some() {
-                    Some((*id, *dt))
-                } else {
-                    None
+                if *t > now {
+                    return None;
                 }
+                let mode = (*mode)?;
+                b.get(id).map(|before| (*id, mode, *before, *dt))
             })
-            .collect();
-
-        a.into_iter()
-            .filter_map(|(id, dt1)| b.get(&id).map(|dt2| *dt2 - dt1))
             .collect()
     }
 
@@ -297,33 +434,70 @@ impl Analytics {
         delays_to_stop
     }
 
-    // At some moment in time, what's the distribution of passengers waiting for a route like?
-    pub fn bus_passenger_delays(
+    // Time between consecutive buses of the same route arriving at a stop, over every route and
+    // stop
+    pub fn bus_headways(&self, now: Time) -> DurationHistogram {
+        let mut last_arrival: BTreeMap<(BusRouteID, BusStopID), Time> = BTreeMap::new();
+        let mut headways = DurationHistogram::new();
+        for (t, _, route, stop) in &self.bus_arrivals {
+            if *t > now {
+                break;
+            }
+            if let Some(prev) = last_arrival.insert((*route, *stop), *t) {
+                headways.add(*t - prev);
+            }
+        }
+        headways
+    }
+
+    // For each stop, (actual arrival, how late the bus was compared to the timetable). Negative
+    // means early. Only covers timetabled buses.
+    pub fn bus_schedule_adherence(
         &self,
         now: Time,
         r: BusRouteID,
-    ) -> BTreeMap<BusStopID, DurationHistogram> {
-        let mut waiting_per_stop = BTreeMap::new();
-        for (t, stop, route) in &self.bus_passengers_waiting {
+    ) -> BTreeMap<BusStopID, Vec<(Time, Duration)>> {
+        let mut per_stop: BTreeMap<BusStopID, Vec<(Time, Duration)>> = BTreeMap::new();
+        for (t, _, route, stop, scheduled) in &self.bus_scheduled_arrivals {
             if *t > now {
                 break;
             }
             if *route == r {
-                waiting_per_stop
+                per_stop
                     .entry(*stop)
                     .or_insert_with(Vec::new)
-                    .push(*t);
+                    .push((*t, *t - *scheduled));
             }
         }
+        per_stop
+    }
 
-        for (t, _, route, stop) in &self.bus_arrivals {
+    // At some moment in time, what's the distribution of passengers waiting for a route like?
+    pub fn bus_passenger_delays(
+        &self,
+        now: Time,
+        r: BusRouteID,
+    ) -> BTreeMap<BusStopID, DurationHistogram> {
+        // Somebody might not board the first bus to arrive, if it's full. With transfers, the
+        // same person might wait for several routes.
+        let mut boarded = BTreeSet::new();
+        for (t, ped, stop, route) in &self.bus_boardings {
             if *t > now {
                 break;
             }
-            if *route == r {
-                if let Some(ref mut times) = waiting_per_stop.get_mut(stop) {
-                    times.retain(|time| *time > *t);
-                }
+            boarded.insert((*ped, *stop, *route));
+        }
+
+        let mut waiting_per_stop = BTreeMap::new();
+        for (t, ped, stop, route) in &self.bus_passengers_waiting {
+            if *t > now {
+                break;
+            }
+            if *route == r && !boarded.contains(&(*ped, *stop, *route)) {
+                waiting_per_stop
+                    .entry(*stop)
+                    .or_insert_with(Vec::new)
+                    .push(*t);
             }
         }
 
@@ -343,6 +517,20 @@ impl Analytics {
             .collect()
     }
 
+    // How many times has somebody been left behind at each stop, because the bus was full?
+    pub fn bus_boardings_denied(&self, now: Time, r: BusRouteID) -> Counter<BusStopID> {
+        let mut per_stop = Counter::new();
+        for (t, _, route, stop) in &self.bus_boardings_denied {
+            if *t > now {
+                break;
+            }
+            if *route == r {
+                per_stop.inc(*stop);
+            }
+        }
+        per_stop
+    }
+
     // Slightly misleading -- TripMode::Transit means buses, not pedestrians taking transit
     pub fn throughput_road(
         &self,
@@ -1,17 +1,27 @@
 use crate::{AgentID, CarID, ParkingSpot, PedestrianID, TripID, TripMode};
-use geom::Duration;
+use geom::{Duration, Time};
 use map_model::{
     BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Path, PathRequest, Traversable,
 };
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Event {
     CarReachedParkingSpot(CarID, ParkingSpot),
     CarOrBikeReachedBorder(CarID, IntersectionID),
+    // From one lane to an adjacent one, partway along the road
+    CarChangedLanes(CarID, LaneID, LaneID),
+    // The rest of the path was replaced, starting from the end of this lane
+    CarRerouted(CarID, LaneID),
 
     BusArrivedAtStop(CarID, BusRouteID, BusStopID),
     BusDepartedFromStop(CarID, BusRouteID, BusStopID),
+    // Alongside BusArrivedAtStop, for buses running a timetabled trip. The time is when the bus was
+    // scheduled to depart the stop.
+    BusScheduledArrival(CarID, BusRouteID, BusStopID, Time),
 
     PedReachedParkingSpot(PedestrianID, ParkingSpot),
     PedReachedBuilding(PedestrianID, BuildingID),
@@ -19,6 +29,8 @@ pub enum Event {
     PedReachedBusStop(PedestrianID, BusStopID, BusRouteID),
     PedEntersBus(PedestrianID, CarID, BusRouteID),
     PedLeavesBus(PedestrianID, CarID, BusRouteID),
+    // The bus was full, so the pedestrian keeps waiting at the stop.
+    PedDeniedBoarding(PedestrianID, CarID, BusRouteID, BusStopID),
 
     BikeStoppedAtSidewalk(CarID, LaneID),
 
@@ -33,3 +45,200 @@ pub enum Event {
     // to plumb info into Analytics is Event.
     PathAmended(Path),
 }
+
+impl Event {
+    // Every possible name(). Keep in sync!
+    pub const NAMES: [&'static str; 21] = [
+        "CarReachedParkingSpot",
+        "CarOrBikeReachedBorder",
+        "CarChangedLanes",
+        "CarRerouted",
+        "BusArrivedAtStop",
+        "BusDepartedFromStop",
+        "BusScheduledArrival",
+        "PedReachedParkingSpot",
+        "PedReachedBuilding",
+        "PedReachedBorder",
+        "PedReachedBusStop",
+        "PedEntersBus",
+        "PedDeniedBoarding",
+        "PedLeavesBus",
+        "BikeStoppedAtSidewalk",
+        "AgentEntersTraversable",
+        "IntersectionDelayMeasured",
+        "TripFinished",
+        "TripAborted",
+        "TripPhaseStarting",
+        "PathAmended",
+    ];
+
+    // Stable names for filtering and exporting.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::CarReachedParkingSpot(_, _) => "CarReachedParkingSpot",
+            Event::CarOrBikeReachedBorder(_, _) => "CarOrBikeReachedBorder",
+            Event::CarChangedLanes(_, _, _) => "CarChangedLanes",
+            Event::CarRerouted(_, _) => "CarRerouted",
+            Event::BusArrivedAtStop(_, _, _) => "BusArrivedAtStop",
+            Event::BusDepartedFromStop(_, _, _) => "BusDepartedFromStop",
+            Event::BusScheduledArrival(_, _, _, _) => "BusScheduledArrival",
+            Event::PedReachedParkingSpot(_, _) => "PedReachedParkingSpot",
+            Event::PedReachedBuilding(_, _) => "PedReachedBuilding",
+            Event::PedReachedBorder(_, _) => "PedReachedBorder",
+            Event::PedReachedBusStop(_, _, _) => "PedReachedBusStop",
+            Event::PedEntersBus(_, _, _) => "PedEntersBus",
+            Event::PedDeniedBoarding(_, _, _, _) => "PedDeniedBoarding",
+            Event::PedLeavesBus(_, _, _) => "PedLeavesBus",
+            Event::BikeStoppedAtSidewalk(_, _) => "BikeStoppedAtSidewalk",
+            Event::AgentEntersTraversable(_, _) => "AgentEntersTraversable",
+            Event::IntersectionDelayMeasured(_, _) => "IntersectionDelayMeasured",
+            Event::TripFinished(_, _, _) => "TripFinished",
+            Event::TripAborted(_) => "TripAborted",
+            Event::TripPhaseStarting(_, _, _) => "TripPhaseStarting",
+            Event::PathAmended(_) => "PathAmended",
+        }
+    }
+}
+
+// Something that wants to see every event as the sim produces it, without waiting for Analytics.
+pub trait EventSink {
+    fn event(&mut self, time: Time, ev: &Event);
+
+    fn flush(&mut self) {}
+}
+
+// Sinks hold onto things like open files, so they don't survive cloning or savestating the sim.
+#[derive(Default)]
+pub(crate) struct EventSinks(Vec<Box<dyn EventSink>>);
+
+impl EventSinks {
+    pub fn add(&mut self, sink: Box<dyn EventSink>) {
+        self.0.push(sink);
+    }
+
+    pub fn event(&mut self, time: Time, ev: &Event) {
+        for sink in self.0.iter_mut() {
+            sink.event(time, ev);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        for sink in self.0.iter_mut() {
+            sink.flush();
+        }
+    }
+}
+
+impl Clone for EventSinks {
+    fn clone(&self) -> EventSinks {
+        EventSinks::default()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct EventFilter {
+    // Event::name. None means everything.
+    pub types: Option<BTreeSet<String>>,
+    // Both inclusive
+    pub start: Option<Time>,
+    pub end: Option<Time>,
+}
+
+impl EventFilter {
+    // Comma-separated, like TripFinished,BusArrivedAtStop
+    pub fn parse_types(list: &str) -> Result<BTreeSet<String>, String> {
+        let mut types = BTreeSet::new();
+        for name in list.split(',') {
+            if !Event::NAMES.contains(&name) {
+                return Err(format!(
+                    "Unknown event type {}; try one of {}",
+                    name,
+                    Event::NAMES.join(", ")
+                ));
+            }
+            types.insert(name.to_string());
+        }
+        Ok(types)
+    }
+
+    pub fn matches(&self, time: Time, ev: &Event) -> bool {
+        if let Some(ref types) = self.types {
+            if !types.contains(ev.name()) {
+                return false;
+            }
+        }
+        if let Some(t) = self.start {
+            if time < t {
+                return false;
+            }
+        }
+        if let Some(t) = self.end {
+            if time > t {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventLogFormat {
+    // One JSON object per line
+    Json,
+    // time, event name, and the event's fields as JSON
+    Csv,
+}
+
+// Writes events to a file as they happen.
+pub struct EventLog {
+    out: BufWriter<File>,
+    format: EventLogFormat,
+    filter: EventFilter,
+}
+
+impl EventLog {
+    pub fn new(path: &str, format: EventLogFormat, filter: EventFilter) -> Result<EventLog, Error> {
+        let mut out = BufWriter::new(File::create(path)?);
+        if format == EventLogFormat::Csv {
+            writeln!(out, "time_seconds,event,data")?;
+        }
+        Ok(EventLog {
+            out,
+            format,
+            filter,
+        })
+    }
+}
+
+impl EventSink for EventLog {
+    fn event(&mut self, time: Time, ev: &Event) {
+        if !self.filter.matches(time, ev) {
+            return;
+        }
+        match self.format {
+            EventLogFormat::Json => {
+                writeln!(
+                    self.out,
+                    "{{\"time_seconds\":{},\"event\":{}}}",
+                    time.inner_seconds(),
+                    abstutil::to_json_terse(ev)
+                )
+                .unwrap();
+            }
+            EventLogFormat::Csv => {
+                writeln!(
+                    self.out,
+                    "{},{},\"{}\"",
+                    time.inner_seconds(),
+                    ev.name(),
+                    abstutil::to_json_terse(ev).replace("\"", "\"\"")
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        self.out.flush().unwrap();
+    }
+}
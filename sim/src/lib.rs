@@ -4,21 +4,25 @@ mod make;
 mod mechanics;
 mod render;
 mod router;
+mod savestate;
 mod scheduler;
 mod sim;
 mod transit;
 mod trips;
 
 pub use self::analytics::{Analytics, TripPhase};
-pub use self::events::Event;
+pub(crate) use self::events::EventSinks;
+pub use self::events::{Event, EventFilter, EventLog, EventLogFormat, EventSink};
 pub use self::make::{
     ABTest, BorderSpawnOverTime, OriginDestination, Scenario, SeedParkedCars, SimFlags,
     SpawnOverTime, SpawnTrip, TripSpawner, TripSpec,
 };
+pub use self::mechanics::Kinematics;
 pub(crate) use self::mechanics::{
-    DrivingSimState, IntersectionSimState, ParkingSimState, WalkingSimState,
+    DrivingSimState, DrivingSimStateV1, IntersectionSimState, ParkingSimState, WalkingSimState,
 };
 pub(crate) use self::router::{ActionAtEnd, Router};
+pub use self::savestate::{SavestateHeader, SAVESTATE_VERSION};
 pub(crate) use self::scheduler::{Command, Scheduler};
 pub use self::sim::{Sim, SimOptions};
 pub(crate) use self::transit::TransitSimState;
@@ -30,7 +34,7 @@ pub use crate::render::{
     GetDrawAgents, PedCrowdLocation, UnzoomedAgent,
 };
 use abstutil::Cloneable;
-use geom::{Distance, Pt2D, Speed, Time};
+use geom::{Acceleration, Distance, Pt2D, Speed, Time};
 use map_model::{
     BuildingID, BusStopID, DirectedRoadID, IntersectionID, LaneID, Map, Path, PathConstraints,
     PathRequest, Position,
@@ -46,11 +50,22 @@ pub const MIN_CAR_LENGTH: Distance = Distance::const_meters(4.5);
 pub const MAX_CAR_LENGTH: Distance = Distance::const_meters(6.5);
 // Note this is more than MAX_CAR_LENGTH
 pub const BUS_LENGTH: Distance = Distance::const_meters(12.5);
+// Seated and standing
+pub const BUS_CAPACITY: usize = 60;
 
 // At all speeds (including at rest), cars must be at least this far apart, measured from front of
 // one car to the back of the other.
 pub const FOLLOWING_DISTANCE: Distance = Distance::const_meters(1.0);
 
+// Only used when SimOptions::use_acceleration is enabled. Braking values are comfortable, not
+// emergency, rates.
+pub const CAR_ACCEL: Acceleration = Acceleration::const_meters_per_second_squared(2.5);
+pub const CAR_DECEL: Acceleration = Acceleration::const_meters_per_second_squared(3.5);
+pub const BUS_ACCEL: Acceleration = Acceleration::const_meters_per_second_squared(1.2);
+pub const BUS_DECEL: Acceleration = Acceleration::const_meters_per_second_squared(2.0);
+pub const BIKE_ACCEL: Acceleration = Acceleration::const_meters_per_second_squared(1.0);
+pub const BIKE_DECEL: Acceleration = Acceleration::const_meters_per_second_squared(2.5);
+
 // The numeric ID must be globally unique, without considering VehicleType. VehicleType is bundled
 // for convenient debugging.
 // TODO Implement Eq, Hash, Ord manually to guarantee this.
@@ -139,6 +154,10 @@ pub struct Vehicle {
     pub vehicle_type: VehicleType,
     pub length: Distance,
     pub max_speed: Option<Speed>,
+    pub max_accel: Acceleration,
+    pub max_decel: Acceleration,
+    // Only for vehicles carrying passengers, not counting the driver
+    pub passenger_capacity: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -146,6 +165,9 @@ pub struct VehicleSpec {
     pub vehicle_type: VehicleType,
     pub length: Distance,
     pub max_speed: Option<Speed>,
+    pub max_accel: Acceleration,
+    pub max_decel: Acceleration,
+    pub passenger_capacity: Option<usize>,
 }
 
 impl VehicleSpec {
@@ -156,6 +178,9 @@ impl VehicleSpec {
             vehicle_type: self.vehicle_type,
             length: self.length,
             max_speed: self.max_speed,
+            max_accel: self.max_accel,
+            max_decel: self.max_decel,
+            passenger_capacity: self.passenger_capacity,
         }
     }
 }
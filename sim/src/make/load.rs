@@ -1,7 +1,7 @@
 use crate::{Scenario, Sim, SimOptions};
 use abstutil::CmdArgs;
 use geom::Duration;
-use map_model::{Map, MapEdits};
+use map_model::{Map, MapEdits, TravelTimes};
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
 
@@ -10,6 +10,8 @@ pub struct SimFlags {
     pub load: String,
     pub use_map_fixes: bool,
     pub rng_seed: Option<u8>,
+    // Route cars using travel times saved by a previous traffic assignment
+    pub travel_times: Option<String>,
     pub opts: SimOptions,
 }
 
@@ -21,6 +23,7 @@ impl SimFlags {
                 .unwrap_or_else(|| "../data/system/maps/montlake.bin".to_string()),
             use_map_fixes: !args.enabled("--nofixes"),
             rng_seed: args.optional_parse("--rng_seed", |s| s.parse()),
+            travel_times: args.optional("--travel_times"),
             opts: SimOptions {
                 run_name: args
                     .optional("--run_name")
@@ -29,6 +32,9 @@ impl SimFlags {
                 use_freeform_policy_everywhere: args.enabled("--freeform_policy"),
                 disable_block_the_box: args.enabled("--disable_block_the_box"),
                 recalc_lanechanging: !args.enabled("--dont_recalc_lc"),
+                use_acceleration: args.enabled("--use_acceleration"),
+                mid_block_lanechanging: args.enabled("--mid_block_lc"),
+                reroute_after_blocked: args.optional_parse("--reroute_after", Duration::parse),
             },
         }
     }
@@ -43,6 +49,7 @@ impl SimFlags {
             load: abstutil::path_map(map),
             use_map_fixes: true,
             rng_seed: Some(42),
+            travel_times: None,
             opts: SimOptions::new(run_name),
         }
     }
@@ -55,8 +62,8 @@ impl SimFlags {
         }
     }
 
-    // Convenience method to setup everything.
-    pub fn load(&self, timer: &mut abstutil::Timer) -> (Map, Sim, XorShiftRng) {
+    // Convenience method to setup everything. Fails for savestates that don't match their map.
+    pub fn load(&self, timer: &mut abstutil::Timer) -> Result<(Map, Sim, XorShiftRng), String> {
         let mut rng = self.make_rng();
 
         let mut opts = self.opts.clone();
@@ -64,20 +71,21 @@ impl SimFlags {
         if self.load.starts_with("../data/player/saves/") {
             timer.note(format!("Resuming from {}", self.load));
 
-            let mut sim: Sim = abstutil::read_binary(self.load.clone(), timer);
+            let header = Sim::read_savestate_header(&self.load)
+                .map_err(|err| format!("Can't load savestate {}: {}", self.load, err))?;
 
-            let mut map = Map::new(abstutil::path_map(&sim.map_name), false, timer);
-            if sim.edits_name != "no_edits" {
-                map.apply_edits(
-                    MapEdits::load(map.get_name(), &sim.edits_name, timer),
-                    timer,
-                );
+            let mut map = Map::new(abstutil::path_map(&header.map_name), false, timer);
+            if header.edits_name != "no_edits" {
+                let edits = MapEdits::load(&map, &header.edits_name, timer)
+                    .map_err(|err| format!("Can't load savestate {}: {}", self.load, err))?;
+                map.apply_edits(edits, timer);
                 map.mark_edits_fresh();
                 map.recalculate_pathfinding_after_edits(timer);
             }
-            sim.restore_paths(&map, timer);
+            let sim = Sim::load_savestate(self.load.clone(), &map, timer)
+                .map_err(|err| format!("Can't load savestate {}: {}", self.load, err))?;
 
-            (map, sim, rng)
+            Ok((map, sim, rng))
         } else if self.load.starts_with("../data/system/scenarios/") {
             timer.note(format!(
                 "Seeding the simulation from scenario {}",
@@ -86,7 +94,8 @@ impl SimFlags {
 
             let scenario: Scenario = abstutil::read_binary(self.load.clone(), timer);
 
-            let map = Map::new(abstutil::path_map(&scenario.map_name), false, timer);
+            let mut map = Map::new(abstutil::path_map(&scenario.map_name), false, timer);
+            self.use_travel_times(&mut map, timer);
 
             if opts.run_name == "unnamed" {
                 opts.run_name = scenario.scenario_name.clone();
@@ -94,31 +103,41 @@ impl SimFlags {
             let mut sim = Sim::new(&map, opts, timer);
             scenario.instantiate(&mut sim, &map, &mut rng, timer);
 
-            (map, sim, rng)
+            Ok((map, sim, rng))
         } else if self.load.starts_with(&abstutil::path_all_raw_maps())
             || self.load.starts_with(&abstutil::path_all_synthetic_maps())
         {
             timer.note(format!("Loading map {}", self.load));
 
-            let map = Map::new(self.load.clone(), self.use_map_fixes, timer);
+            let mut map = Map::new(self.load.clone(), self.use_map_fixes, timer);
+            self.use_travel_times(&mut map, timer);
 
             timer.start("create sim");
             let sim = Sim::new(&map, opts, timer);
             timer.stop("create sim");
 
-            (map, sim, rng)
+            Ok((map, sim, rng))
         } else if self.load.starts_with(&abstutil::path_all_maps()) {
             timer.note(format!("Loading map {}", self.load));
 
-            let map = Map::new(self.load.clone(), false, timer);
+            let mut map = Map::new(self.load.clone(), false, timer);
+            self.use_travel_times(&mut map, timer);
 
             timer.start("create sim");
             let sim = Sim::new(&map, opts, timer);
             timer.stop("create sim");
 
-            (map, sim, rng)
+            Ok((map, sim, rng))
         } else {
-            panic!("Don't know how to load {}", self.load);
+            Err(format!("Don't know how to load {}", self.load))
+        }
+    }
+
+    pub fn use_travel_times(&self, map: &mut Map, timer: &mut abstutil::Timer) {
+        if let Some(ref name) = self.travel_times {
+            timer.note(format!("Routing cars using travel times from {}", name));
+            map.use_travel_times(Some(TravelTimes::load(map.get_name(), name, timer)));
+            map.recalculate_pathfinding_after_edits(timer);
         }
     }
 }
@@ -1,6 +1,6 @@
 use crate::{
     CarID, DrivingGoal, ParkingSpot, SidewalkSpot, Sim, TripSpec, VehicleSpec, VehicleType,
-    BIKE_LENGTH, MAX_CAR_LENGTH, MIN_CAR_LENGTH,
+    BIKE_ACCEL, BIKE_DECEL, BIKE_LENGTH, CAR_ACCEL, CAR_DECEL, MAX_CAR_LENGTH, MIN_CAR_LENGTH,
 };
 use abstutil::{fork_rng, prettyprint_usize, Timer, WeightedUsizeChoice};
 use geom::{Distance, Duration, Speed, Time};
@@ -92,7 +92,11 @@ impl Scenario {
 
         if self.seed_buses {
             for route in map.get_all_bus_routes() {
-                sim.seed_bus_route(route, map, timer);
+                if route.schedule.is_empty() {
+                    sim.seed_bus_route(route, map, timer);
+                } else {
+                    sim.schedule_bus_route(route, map);
+                }
             }
         }
 
@@ -265,6 +269,9 @@ impl Scenario {
             vehicle_type: VehicleType::Car,
             length,
             max_speed: None,
+            max_accel: CAR_ACCEL,
+            max_decel: CAR_DECEL,
+            passenger_capacity: None,
         }
     }
 
@@ -278,6 +285,9 @@ impl Scenario {
             vehicle_type: VehicleType::Bike,
             length: BIKE_LENGTH,
             max_speed,
+            max_accel: BIKE_ACCEL,
+            max_decel: BIKE_DECEL,
+            passenger_capacity: None,
         }
     }
 
@@ -395,17 +405,15 @@ impl SpawnOverTime {
             if rng.gen_bool(self.percent_use_transit) {
                 // TODO This throws away some work. It also sequentially does expensive
                 // work right here.
-                if let Some((stop1, stop2, route)) =
-                    map.should_use_transit(start_spot.sidewalk_pos, goal.sidewalk_pos)
+                if let Some(rides) =
+                    map.should_use_transit(start_spot.sidewalk_pos, goal.sidewalk_pos, spawn_time)
                 {
                     sim.schedule_trip(
                         spawn_time,
                         TripSpec::UsingTransit {
                             start: start_spot,
-                            route,
-                            stop1,
-                            stop2,
                             goal,
+                            rides,
                             ped_speed: Scenario::rand_ped_speed(rng),
                         },
                         map,
@@ -461,17 +469,15 @@ impl BorderSpawnOverTime {
                 if rng.gen_bool(self.percent_use_transit) {
                     // TODO This throws away some work. It also sequentially does expensive
                     // work right here.
-                    if let Some((stop1, stop2, route)) =
-                        map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos)
+                    if let Some(rides) =
+                        map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos, spawn_time)
                     {
                         sim.schedule_trip(
                             spawn_time,
                             TripSpec::UsingTransit {
                                 start: start.clone(),
-                                route,
-                                stop1,
-                                stop2,
                                 goal,
+                                rides,
                                 ped_speed: Scenario::rand_ped_speed(rng),
                             },
                             map,
@@ -818,13 +824,35 @@ pub enum SpawnTrip {
     MaybeUsingParkedCar(Time, BuildingID, DrivingGoal),
     UsingBike(Time, SidewalkSpot, DrivingGoal),
     JustWalking(Time, SidewalkSpot, SidewalkSpot),
+    // Every ride as (board at, get off at, route)
     UsingTransit(
         Time,
         SidewalkSpot,
         SidewalkSpot,
-        BusRouteID,
-        BusStopID,
-        BusStopID,
+        Vec<(BusStopID, BusStopID, BusRouteID)>,
+    ),
+    // Start, where to park, goal, and rides from there
+    ParkAndRide(
+        Time,
+        BuildingID,
+        BuildingID,
+        SidewalkSpot,
+        Vec<(BusStopID, BusStopID, BusRouteID)>,
+    ),
+    // Like ParkAndRide, but the car appears at this position near a border
+    ParkAndRideFromBorder(
+        Time,
+        Position,
+        BuildingID,
+        SidewalkSpot,
+        Vec<(BusStopID, BusStopID, BusRouteID)>,
+    ),
+    BikeAndRide(
+        Time,
+        SidewalkSpot,
+        BuildingID,
+        SidewalkSpot,
+        Vec<(BusStopID, BusStopID, BusRouteID)>,
     ),
 }
 
@@ -876,14 +904,44 @@ impl SpawnTrip {
                     ped_speed: Scenario::rand_ped_speed(rng),
                 },
             ),
-            SpawnTrip::UsingTransit(depart, start, goal, route, stop1, stop2) => (
+            SpawnTrip::UsingTransit(depart, start, goal, rides) => (
                 depart,
                 TripSpec::UsingTransit {
                     start,
                     goal,
-                    route,
-                    stop1,
-                    stop2,
+                    rides,
+                    ped_speed: Scenario::rand_ped_speed(rng),
+                },
+            ),
+            SpawnTrip::ParkAndRide(depart, start_bldg, park_near, goal, rides) => (
+                depart,
+                TripSpec::ParkAndRide {
+                    start_bldg,
+                    park_near,
+                    goal,
+                    rides,
+                    ped_speed: Scenario::rand_ped_speed(rng),
+                },
+            ),
+            SpawnTrip::ParkAndRideFromBorder(depart, start_pos, park_near, goal, rides) => (
+                depart,
+                TripSpec::ParkAndRideFromBorder {
+                    start_pos,
+                    park_near,
+                    goal,
+                    rides,
+                    vehicle_spec: Scenario::rand_car(rng),
+                    ped_speed: Scenario::rand_ped_speed(rng),
+                },
+            ),
+            SpawnTrip::BikeAndRide(depart, start, park_near, goal, rides) => (
+                depart,
+                TripSpec::BikeAndRide {
+                    start,
+                    park_near,
+                    goal,
+                    rides,
+                    vehicle: Scenario::rand_bike(rng),
                     ped_speed: Scenario::rand_ped_speed(rng),
                 },
             ),
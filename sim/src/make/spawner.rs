@@ -43,9 +43,34 @@ pub enum TripSpec {
     UsingTransit {
         start: SidewalkSpot,
         goal: SidewalkSpot,
-        route: BusRouteID,
-        stop1: BusStopID,
-        stop2: BusStopID,
+        // (board at, get off at, route) for every ride, walking in between
+        rides: Vec<(BusStopID, BusStopID, BusRouteID)>,
+        ped_speed: Speed,
+    },
+    // Drive a parked car to somewhere near the first bus stop, then ride transit.
+    ParkAndRide {
+        start_bldg: BuildingID,
+        park_near: BuildingID,
+        goal: SidewalkSpot,
+        rides: Vec<(BusStopID, BusStopID, BusRouteID)>,
+        ped_speed: Speed,
+    },
+    // Like ParkAndRide, but the car appears at a border first.
+    ParkAndRideFromBorder {
+        start_pos: Position,
+        park_near: BuildingID,
+        goal: SidewalkSpot,
+        rides: Vec<(BusStopID, BusStopID, BusRouteID)>,
+        vehicle_spec: VehicleSpec,
+        ped_speed: Speed,
+    },
+    // Bike to a rack near the first bus stop, then ride transit.
+    BikeAndRide {
+        start: SidewalkSpot,
+        park_near: BuildingID,
+        goal: SidewalkSpot,
+        rides: Vec<(BusStopID, BusStopID, BusRouteID)>,
+        vehicle: VehicleSpec,
         ped_speed: Speed,
     },
 }
@@ -105,6 +130,21 @@ impl TripSpawner {
                     DrivingGoal::ParkNear(_) => {}
                 }
             }
+            TripSpec::ParkAndRideFromBorder {
+                start_pos,
+                vehicle_spec,
+                ..
+            } => {
+                if start_pos.dist_along() < vehicle_spec.length
+                    || start_pos.dist_along() >= map.get_l(start_pos.lane()).length()
+                {
+                    panic!(
+                        "Can't spawn a car at {} on {}",
+                        start_pos.dist_along(),
+                        start_pos.lane()
+                    );
+                }
+            }
             TripSpec::UsingParkedCar { spot, .. } => {
                 let car_id = parking.get_car_at_spot(*spot).unwrap().vehicle.id;
                 if self.parked_cars_claimed.contains(&car_id) {
@@ -173,7 +213,32 @@ impl TripSpawner {
                     }
                 }
             }
-            TripSpec::UsingTransit { .. } => {}
+            TripSpec::UsingTransit { .. } | TripSpec::ParkAndRide { .. } => {}
+            TripSpec::BikeAndRide {
+                start, park_near, ..
+            } => {
+                if SidewalkSpot::bike_from_bike_rack(start.sidewalk_pos.lane(), map).is_none() {
+                    println!(
+                        "Can't start biking from {}; no biking or driving lane nearby?",
+                        start.sidewalk_pos.lane()
+                    );
+                    return;
+                }
+                let last_lane = DrivingGoal::ParkNear(*park_near)
+                    .goal_pos(PathConstraints::Bike, map)
+                    .lane();
+                if map
+                    .get_parent(last_lane)
+                    .bike_to_sidewalk(last_lane)
+                    .is_none()
+                {
+                    println!(
+                        "Can't bike and ride via {}; no sidewalk near {}",
+                        park_near, last_lane
+                    );
+                    return;
+                }
+            }
         };
 
         self.trips.push((start_time, ped_id, car_id, spec));
@@ -417,13 +482,12 @@ impl TripSpawner {
                 }
                 TripSpec::UsingTransit {
                     start,
-                    route,
-                    stop1,
-                    stop2,
                     goal,
+                    rides,
                     ped_speed,
                 } => {
-                    let walk_to = SidewalkSpot::bus_stop(stop1, map);
+                    let walk_to = SidewalkSpot::bus_stop(rides[0].0, map);
+                    let legs = transit_legs(ped_id.unwrap(), ped_speed, rides, goal, map);
                     let trip = trips.new_trip(
                         start_time,
                         match start.connection {
@@ -434,11 +498,7 @@ impl TripSpawner {
                             SidewalkPOI::Border(i) => TripStart::Border(i),
                             _ => unreachable!(),
                         },
-                        vec![
-                            TripLeg::Walk(ped_id.unwrap(), ped_speed, walk_to.clone()),
-                            TripLeg::RideBus(ped_id.unwrap(), route, stop2),
-                            TripLeg::Walk(ped_id.unwrap(), ped_speed, goal),
-                        ],
+                        legs,
                     );
 
                     if let Some(path) = maybe_path {
@@ -462,6 +522,122 @@ impl TripSpawner {
                         trips.abort_trip_failed_start(trip);
                     }
                 }
+                TripSpec::ParkAndRide {
+                    start_bldg,
+                    park_near,
+                    goal,
+                    rides,
+                    ped_speed,
+                } => {
+                    // Like MaybeUsingParkedCar, the car is picked later. The legs after driving
+                    // are kept then.
+                    let walk_to = SidewalkSpot::deferred_parking_spot(
+                        start_bldg,
+                        DrivingGoal::ParkNear(park_near),
+                        map,
+                    );
+                    let mut legs = vec![TripLeg::Walk(ped_id.unwrap(), ped_speed, walk_to.clone())];
+                    legs.extend(transit_legs(ped_id.unwrap(), ped_speed, rides, goal, map));
+                    let trip = trips.new_trip(start_time, TripStart::Bldg(start_bldg), legs);
+
+                    scheduler.quick_push(
+                        start_time,
+                        Command::SpawnPed(CreatePedestrian {
+                            id: ped_id.unwrap(),
+                            speed: ped_speed,
+                            start: SidewalkSpot::building(start_bldg, map),
+                            goal: walk_to,
+                            // This is guaranteed to work, and is junk anyway.
+                            path: maybe_path.unwrap(),
+                            req,
+                            trip,
+                        }),
+                    );
+                }
+                TripSpec::ParkAndRideFromBorder {
+                    start_pos,
+                    park_near,
+                    goal,
+                    rides,
+                    vehicle_spec,
+                    ped_speed,
+                } => {
+                    // Like CarAppearing, the lot owns the car.
+                    let vehicle = vehicle_spec.make(car_id.unwrap(), Some(park_near));
+                    let drive_to = DrivingGoal::ParkNear(park_near);
+                    let mut legs = vec![TripLeg::Drive(vehicle.clone(), drive_to.clone())];
+                    legs.extend(transit_legs(ped_id.unwrap(), ped_speed, rides, goal, map));
+                    let trip_start = TripStart::Border(map.get_l(start_pos.lane()).src_i);
+                    let trip = trips.new_trip(start_time, trip_start, legs);
+                    if let Some(path) = maybe_path {
+                        let router = drive_to.make_router(path, map, vehicle.vehicle_type);
+                        scheduler.quick_push(
+                            start_time,
+                            Command::SpawnCar(
+                                CreateCar::for_appearing(vehicle, start_pos, router, req, trip),
+                                retry_if_no_room,
+                            ),
+                        );
+                    } else {
+                        timer.warn(format!(
+                            "ParkAndRideFromBorder trip couldn't find the first path {}",
+                            req
+                        ));
+                        trips.abort_trip_failed_start(trip);
+                    }
+                }
+                TripSpec::BikeAndRide {
+                    start,
+                    park_near,
+                    goal,
+                    rides,
+                    vehicle,
+                    ped_speed,
+                } => {
+                    let walk_to =
+                        SidewalkSpot::bike_from_bike_rack(start.sidewalk_pos.lane(), map).unwrap();
+                    let mut legs = vec![
+                        TripLeg::Walk(ped_id.unwrap(), ped_speed, walk_to.clone()),
+                        TripLeg::Drive(
+                            vehicle.make(car_id.unwrap(), None),
+                            DrivingGoal::ParkNear(park_near),
+                        ),
+                    ];
+                    legs.extend(transit_legs(ped_id.unwrap(), ped_speed, rides, goal, map));
+                    let trip = trips.new_trip(
+                        start_time,
+                        match start.connection {
+                            SidewalkPOI::Building(b) => TripStart::Bldg(b),
+                            SidewalkPOI::SuddenlyAppear => {
+                                TripStart::Border(map.get_l(start.sidewalk_pos.lane()).src_i)
+                            }
+                            SidewalkPOI::Border(i) => TripStart::Border(i),
+                            _ => unreachable!(),
+                        },
+                        legs,
+                    );
+
+                    if let Some(path) = maybe_path {
+                        scheduler.quick_push(
+                            start_time,
+                            Command::SpawnPed(CreatePedestrian {
+                                id: ped_id.unwrap(),
+                                speed: ped_speed,
+                                start,
+                                goal: walk_to,
+                                path,
+                                req,
+                                trip,
+                            }),
+                        );
+                    } else {
+                        timer.warn(format!(
+                            "BikeAndRide trip couldn't find the first path {}",
+                            req
+                        ));
+                        trips.abort_trip_failed_start(trip);
+                    }
+                }
             }
         }
 
@@ -512,6 +688,15 @@ impl TripSpec {
                     constraints,
                 }
             }
+            TripSpec::ParkAndRideFromBorder {
+                start_pos,
+                park_near,
+                ..
+            } => PathRequest {
+                start: *start_pos,
+                end: DrivingGoal::ParkNear(*park_near).goal_pos(PathConstraints::Car, map),
+                constraints: PathConstraints::Car,
+            },
             TripSpec::UsingParkedCar { start, spot, .. } => PathRequest {
                 start: start.sidewalk_pos,
                 end: SidewalkSpot::parking_spot(*spot, map, parking).sidewalk_pos,
@@ -519,7 +704,8 @@ impl TripSpec {
             },
             // Don't know where the parked car will be, so just make a dummy path that'll never
             // fail.
-            TripSpec::MaybeUsingParkedCar { start_bldg, .. } => {
+            TripSpec::MaybeUsingParkedCar { start_bldg, .. }
+            | TripSpec::ParkAndRide { start_bldg, .. } => {
                 let pos = map.get_b(*start_bldg).front_path.sidewalk;
                 PathRequest {
                     start: pos,
@@ -532,18 +718,41 @@ impl TripSpec {
                 end: goal.sidewalk_pos,
                 constraints: PathConstraints::Pedestrian,
             },
-            TripSpec::UsingBike { start, .. } => PathRequest {
-                start: start.sidewalk_pos,
-                end: SidewalkSpot::bike_from_bike_rack(start.sidewalk_pos.lane(), map)
-                    .unwrap()
-                    .sidewalk_pos,
-                constraints: PathConstraints::Pedestrian,
-            },
-            TripSpec::UsingTransit { start, stop1, .. } => PathRequest {
+            TripSpec::UsingBike { start, .. } | TripSpec::BikeAndRide { start, .. } => {
+                PathRequest {
+                    start: start.sidewalk_pos,
+                    end: SidewalkSpot::bike_from_bike_rack(start.sidewalk_pos.lane(), map)
+                        .unwrap()
+                        .sidewalk_pos,
+                    constraints: PathConstraints::Pedestrian,
+                }
+            }
+            TripSpec::UsingTransit { start, rides, .. } => PathRequest {
                 start: start.sidewalk_pos,
-                end: SidewalkSpot::bus_stop(*stop1, map).sidewalk_pos,
+                end: SidewalkSpot::bus_stop(rides[0].0, map).sidewalk_pos,
                 constraints: PathConstraints::Pedestrian,
             },
         }
     }
 }
+
+// Walk to each stop and ride, then walk to the goal.
+fn transit_legs(
+    ped: PedestrianID,
+    ped_speed: Speed,
+    rides: Vec<(BusStopID, BusStopID, BusRouteID)>,
+    goal: SidewalkSpot,
+    map: &Map,
+) -> Vec<TripLeg> {
+    let mut legs = Vec::new();
+    for (stop1, stop2, route) in rides {
+        legs.push(TripLeg::Walk(
+            ped,
+            ped_speed,
+            SidewalkSpot::bus_stop(stop1, map),
+        ));
+        legs.push(TripLeg::RideBus(ped, route, stop2));
+    }
+    legs.push(TripLeg::Walk(ped, ped_speed, goal));
+    legs
+}
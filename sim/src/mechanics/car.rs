@@ -1,9 +1,10 @@
+use crate::mechanics::kinematics::Kinematics;
 use crate::{
     AgentMetadata, CarStatus, DistanceInterval, DrawCarInput, ParkingSpot, Router, TimeInterval,
     TransitSimState, TripID, Vehicle, VehicleType,
 };
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{Map, Traversable, LANE_THICKNESS};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{DrivingSide, Map, Traversable, TurnPriority, LANE_THICKNESS};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
@@ -15,15 +16,60 @@ pub struct Car {
     pub trip: TripID,
     pub blocked_since: Option<Time>,
     pub started_at: Time,
+    // Only used by the acceleration model. How fast was the car going when it last finished
+    // Crossing?
+    pub speed_entering_queue: Speed,
+    // Only reroute once each time the car gets stuck.
+    pub last_rerouted: Option<Time>,
+    // The DrivingSimState::edits_generation that the rest of the path was last checked against.
+    pub path_checked_at: usize,
 
     // In reverse order -- most recently left is first. The sum length of these must be >=
     // vehicle.length.
     pub last_steps: VecDeque<Traversable>,
 }
 
+// Car as of savestate version 1
+#[derive(Deserialize)]
+pub struct CarV1 {
+    vehicle: Vehicle,
+    state: CarState,
+    router: Router,
+    trip: TripID,
+    blocked_since: Option<Time>,
+    started_at: Time,
+    speed_entering_queue: Speed,
+    last_rerouted: Option<Time>,
+    last_steps: VecDeque<Traversable>,
+}
+
+impl From<CarV1> for Car {
+    fn from(old: CarV1) -> Car {
+        Car {
+            vehicle: old.vehicle,
+            state: old.state,
+            router: old.router,
+            trip: old.trip,
+            blocked_since: old.blocked_since,
+            started_at: old.started_at,
+            speed_entering_queue: old.speed_entering_queue,
+            last_rerouted: old.last_rerouted,
+            // Behind DrivingSimStateV1's generation, so the path gets checked once.
+            path_checked_at: 0,
+            last_steps: old.last_steps,
+        }
+    }
+}
+
 impl Car {
     // Assumes the current head of the path is the thing to cross.
-    pub fn crossing_state(&self, start_dist: Distance, start_time: Time, map: &Map) -> CarState {
+    pub fn crossing_state(
+        &self,
+        start_dist: Distance,
+        start_time: Time,
+        use_acceleration: bool,
+        map: &Map,
+    ) -> CarState {
         let dist_int = DistanceInterval::new_driving(
             start_dist,
             if self.router.last_step() {
@@ -32,13 +78,26 @@ impl Car {
                 self.router.head().length(map)
             },
         );
-        self.crossing_state_with_end_dist(dist_int, start_time, map)
+        let stop_at_end = use_acceleration && self.must_stop_at_end(start_time, map);
+        self.make_crossing_state(dist_int, start_time, use_acceleration, stop_at_end, map)
     }
 
     pub fn crossing_state_with_end_dist(
         &self,
         dist_int: DistanceInterval,
         start_time: Time,
+        use_acceleration: bool,
+        map: &Map,
+    ) -> CarState {
+        self.make_crossing_state(dist_int, start_time, use_acceleration, false, map)
+    }
+
+    fn make_crossing_state(
+        &self,
+        dist_int: DistanceInterval,
+        start_time: Time,
+        use_acceleration: bool,
+        stop_at_end: bool,
         map: &Map,
     ) -> CarState {
         let on = self.router.head();
@@ -46,8 +105,79 @@ impl Car {
         if let Some(s) = self.vehicle.max_speed {
             speed = speed.min(s);
         }
-        let dt = (dist_int.end - dist_int.start) / speed;
-        CarState::Crossing(TimeInterval::new(start_time, start_time + dt), dist_int)
+        if self.vehicle.vehicle_type == VehicleType::Bike {
+            if let Traversable::Lane(l) = on {
+                speed = bike_speed_on_grade(speed, map.get_l(l).grade(map), on.speed_limit(map));
+            }
+        }
+        if !use_acceleration {
+            let dt = (dist_int.end - dist_int.start) / speed;
+            return CarState::Crossing(
+                TimeInterval::new(start_time, start_time + dt),
+                dist_int,
+                None,
+            );
+        }
+
+        let kinematics = Kinematics::new(
+            dist_int.end - dist_int.start,
+            self.current_speed(start_time),
+            speed,
+            if stop_at_end { Speed::ZERO } else { speed },
+            self.vehicle.max_accel,
+            self.vehicle.max_decel,
+        );
+        CarState::Crossing(
+            TimeInterval::new(start_time, start_time + kinematics.total_time()),
+            dist_int,
+            Some(kinematics),
+        )
+    }
+
+    // Only meaningful under the acceleration model.
+    fn current_speed(&self, now: Time) -> Speed {
+        match self.state {
+            CarState::Crossing(ref time_int, _, Some(ref kinematics)) => {
+                kinematics.speed_at(now - time_int.start)
+            }
+            // If the car finished crossing something just now and isn't blocked, it hasn't had
+            // to slow down.
+            CarState::Queued | CarState::WaitingToAdvance => {
+                if self.blocked_since == Some(now) {
+                    self.speed_entering_queue
+                } else {
+                    Speed::ZERO
+                }
+            }
+            CarState::Crossing(_, _, None)
+            | CarState::Unparking(_, _, _)
+            | CarState::Parking(_, _, _)
+            | CarState::Idling(_, _) => Speed::ZERO,
+        }
+    }
+
+    // Does the car need to brake to a stop at the end of the current step? Signals are
+    // pessimistic: if the turn isn't protected right now, assume the car will have to stop. Cars
+    // can't see the state of actuated signals, so always expect to stop there.
+    fn must_stop_at_end(&self, now: Time, map: &Map) -> bool {
+        match self.router.maybe_next() {
+            None => self.router.stops_at_end(),
+            Some(Traversable::Turn(t)) => {
+                if let Some(ref sign) = map.maybe_get_stop_sign(t.parent) {
+                    // Entering a roundabout, just slow down and look
+                    !sign.roundabout && sign.get_priority(t, map) == TurnPriority::Yield
+                } else if let Some(ref signal) = map.maybe_get_traffic_signal(t.parent) {
+                    if signal.is_actuated() {
+                        return true;
+                    }
+                    let (_, phase, _) = signal.current_phase_and_remaining_time(now);
+                    phase.get_priority_of_turn(t, signal) != TurnPriority::Protected
+                } else {
+                    false
+                }
+            }
+            Some(Traversable::Lane(_)) => false,
+        }
     }
 
     pub fn get_draw_car(
@@ -108,13 +238,16 @@ impl Car {
                         let driving_l = self.router.head().as_lane();
                         let parent = map.get_parent(driving_l);
                         // Is the parking lane to the left or right of the driving lane?
-                        let shift = if parent.dir_and_offset(driving_l).0
+                        let mut shift = if parent.dir_and_offset(driving_l).0
                             == parent.dir_and_offset(*parking_l).0
                         {
                             width
                         } else {
                             -width
                         };
+                        if map.get_driving_side() == DrivingSide::Left {
+                            shift = -shift;
+                        }
                         raw_body.shift_right(shift).unwrap()
                     }
                     ParkingSpot::Offstreet(b, _) => {
@@ -151,7 +284,7 @@ impl Car {
             status: match self.state {
                 CarState::Queued => CarStatus::Moving,
                 CarState::WaitingToAdvance => CarStatus::Moving,
-                CarState::Crossing(_, _) => CarStatus::Moving,
+                CarState::Crossing(_, _, _) => CarStatus::Moving,
                 // Eh they're technically moving, but this is a bit easier to spot
                 CarState::Unparking(_, _, _) => CarStatus::Parked,
                 CarState::Parking(_, _, _) => CarStatus::Parked,
@@ -187,7 +320,8 @@ impl Car {
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum CarState {
-    Crossing(TimeInterval, DistanceInterval),
+    // The kinematics are only present when using the acceleration model.
+    Crossing(TimeInterval, DistanceInterval, Option<Kinematics>),
     Queued,
     WaitingToAdvance,
     // Where's the front of the car while this is happening?
@@ -199,7 +333,7 @@ pub enum CarState {
 impl CarState {
     pub fn get_end_time(&self) -> Time {
         match self {
-            CarState::Crossing(ref time_int, _) => time_int.end,
+            CarState::Crossing(ref time_int, _, _) => time_int.end,
             CarState::Queued => unreachable!(),
             CarState::WaitingToAdvance => unreachable!(),
             CarState::Unparking(_, _, ref time_int) => time_int.end,
@@ -207,4 +341,42 @@ impl CarState {
             CarState::Idling(_, ref time_int) => time_int.end,
         }
     }
+
+    // Where's the front of a Crossing car at this time? Ignores other cars in the way.
+    pub fn crossing_front(&self, now: Time) -> Distance {
+        match self {
+            CarState::Crossing(ref time_int, ref dist_int, None) => {
+                // TODO Why percent_clamp_end? We process car updates in any order, so we might
+                // calculate this before moving this car from Crossing to another state.
+                dist_int.lerp(time_int.percent_clamp_end(now))
+            }
+            CarState::Crossing(ref time_int, ref dist_int, Some(ref kinematics)) => {
+                if now >= time_int.end {
+                    dist_int.end
+                } else {
+                    (dist_int.start + kinematics.dist_at(now - time_int.start)).min(dist_int.end)
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // Only meaningful for the acceleration model.
+    pub fn crossing_end_speed(&self) -> Speed {
+        match self {
+            CarState::Crossing(_, _, Some(ref kinematics)) => kinematics.end_speed(),
+            _ => Speed::ZERO,
+        }
+    }
+}
+
+// Bikes slow down a lot going uphill and speed up a little going downhill, but never past the speed
+// limit.
+fn bike_speed_on_grade(speed: Speed, grade: f64, speed_limit: Speed) -> Speed {
+    let factor = if grade > 0.0 {
+        (1.0 - 5.0 * grade).max(0.3)
+    } else {
+        (1.0 - 2.0 * grade).min(1.3)
+    };
+    (speed * factor).min(speed_limit)
 }
@@ -1,24 +1,25 @@
-use crate::mechanics::car::{Car, CarState};
+use crate::mechanics::car::{Car, CarState, CarV1};
 use crate::mechanics::Queue;
 use crate::{
     ActionAtEnd, AgentID, AgentMetadata, CarID, Command, CreateCar, DistanceInterval, DrawCarInput,
     Event, IntersectionSimState, ParkedCar, ParkingSimState, Scheduler, TimeInterval,
-    TransitSimState, TripManager, TripPositions, UnzoomedAgent, WalkingSimState,
+    TransitSimState, TripManager, TripPositions, UnzoomedAgent, VehicleType, WalkingSimState,
     FOLLOWING_DISTANCE,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{BuildingID, LaneID, Map, Path, PathStep, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{BuildingID, LaneID, Map, Path, PathStep, Position, Traversable};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashSet, VecDeque};
 
 const TIME_TO_UNPARK: Duration = Duration::const_seconds(10.0);
 const TIME_TO_PARK: Duration = Duration::const_seconds(15.0);
-const TIME_TO_WAIT_AT_STOP: Duration = Duration::const_seconds(10.0);
 
 // TODO Do something else.
 pub(crate) const BLIND_RETRY_TO_CREEP_FORWARDS: Duration = Duration::const_seconds(0.1);
 pub(crate) const BLIND_RETRY_TO_REACH_END_DIST: Duration = Duration::const_seconds(5.0);
+// How often a stuck car looks for a gap in an adjacent lane
+const RETRY_MID_BLOCK_LANECHANGE: Duration = Duration::const_seconds(5.0);
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct DrivingSimState {
@@ -35,15 +36,67 @@ pub struct DrivingSimState {
     events: Vec<Event>,
 
     recalc_lanechanging: bool,
+    use_acceleration: bool,
+    mid_block_lanechanging: bool,
+    reroute_after_blocked: Option<Duration>,
+    // Bumped by every round of live edits. Walking a car's whole path is too slow to do at every
+    // lane, so each car only checks when this changes.
+    edits_generation: usize,
+}
+
+// DrivingSimState as of savestate version 1
+#[derive(Deserialize)]
+pub struct DrivingSimStateV1 {
+    #[serde(deserialize_with = "deserialize_btreemap")]
+    cars: BTreeMap<CarID, CarV1>,
+    #[serde(deserialize_with = "deserialize_btreemap")]
+    queues: BTreeMap<Traversable, Queue>,
+    events: Vec<Event>,
+
+    recalc_lanechanging: bool,
+    use_acceleration: bool,
+    mid_block_lanechanging: bool,
+    reroute_after_blocked: Option<Duration>,
+}
+
+impl From<DrivingSimStateV1> for DrivingSimState {
+    fn from(old: DrivingSimStateV1) -> DrivingSimState {
+        DrivingSimState {
+            cars: old
+                .cars
+                .into_iter()
+                .map(|(id, car)| (id, car.into()))
+                .collect(),
+            queues: old.queues,
+            events: old.events,
+            recalc_lanechanging: old.recalc_lanechanging,
+            use_acceleration: old.use_acceleration,
+            mid_block_lanechanging: old.mid_block_lanechanging,
+            reroute_after_blocked: old.reroute_after_blocked,
+            // Version 1 doesn't say if there were live edits since each car last looked, so make
+            // everybody check once.
+            edits_generation: 1,
+        }
+    }
 }
 
 impl DrivingSimState {
-    pub fn new(map: &Map, recalc_lanechanging: bool) -> DrivingSimState {
+    pub fn new(
+        map: &Map,
+        recalc_lanechanging: bool,
+        use_acceleration: bool,
+        mid_block_lanechanging: bool,
+        reroute_after_blocked: Option<Duration>,
+    ) -> DrivingSimState {
         let mut sim = DrivingSimState {
             cars: BTreeMap::new(),
             queues: BTreeMap::new(),
             events: Vec::new(),
             recalc_lanechanging,
+            use_acceleration,
+            mid_block_lanechanging,
+            reroute_after_blocked,
+            edits_generation: 0,
         };
 
         for l in map.all_lanes() {
@@ -62,6 +115,11 @@ impl DrivingSimState {
         sim
     }
 
+    // Cars check the rest of their path the next time they're about to leave a lane.
+    pub fn handle_live_edits(&mut self) {
+        self.edits_generation += 1;
+    }
+
     // True if it worked
     pub fn start_car_on_lane(
         &mut self,
@@ -92,6 +150,10 @@ impl DrivingSimState {
                 last_steps: VecDeque::new(),
                 blocked_since: None,
                 started_at: now,
+                speed_entering_queue: Speed::ZERO,
+                last_rerouted: None,
+                // The path might've been calculated before the latest edits.
+                path_checked_at: 0,
                 trip: params.trip,
             };
             if let Some(p) = params.maybe_parked_car {
@@ -125,7 +187,7 @@ impl DrivingSimState {
                     }
                 }
 
-                car.state = car.crossing_state(params.start_dist, now, map);
+                car.state = car.crossing_state(params.start_dist, now, self.use_acceleration, map);
             }
             scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
             {
@@ -170,6 +232,14 @@ impl DrivingSimState {
         // Why is it safe to process cars in any order, rather than making sure to follow the order
         // of queues? Because of the invariant that distances should never suddenly jump when a car
         // has entered/exiting a queue.
+
+        // Live edits might've closed something ahead of a car about to advance, and there might be
+        // no other way to its goal.
+        if !self.path_still_valid_or_rerouted(id, now, map, intersections) {
+            self.strand_car(id, now, map, trips, transit, scheduler, intersections);
+            return;
+        }
+
         // This car might have reached the router's end distance, but maybe not -- might
         // actually be stuck behind other cars. We have to calculate the distances right now to
         // be sure.
@@ -197,6 +267,10 @@ impl DrivingSimState {
                 scheduler,
             );
             self.cars.insert(id, car);
+
+            if self.mid_block_lanechanging && !need_distances {
+                self.try_mid_block_lanechange(id, now, map, intersections, scheduler);
+            }
         }
 
         if need_distances {
@@ -242,7 +316,8 @@ impl DrivingSimState {
         scheduler: &mut Scheduler,
     ) -> bool {
         match car.state {
-            CarState::Crossing(_, _) => {
+            CarState::Crossing(_, _, _) => {
+                car.speed_entering_queue = car.state.crossing_end_speed();
                 car.state = CarState::Queued;
                 car.blocked_since = Some(now);
                 if car.router.last_step() {
@@ -274,14 +349,14 @@ impl DrivingSimState {
                         &mut self.events,
                     );
                 }
-                car.state = car.crossing_state(front, now, map);
+                car.state = car.crossing_state(front, now, self.use_acceleration, map);
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
             }
             CarState::Idling(dist, _) => {
                 car.router = transit.bus_departed_from_stop(car.vehicle.id);
                 self.events
                     .push(Event::PathAmended(car.router.get_path().clone()));
-                car.state = car.crossing_state(dist, now, map);
+                car.state = car.crossing_state(dist, now, self.use_acceleration, map);
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
 
                 // Update our follower, so they know we stopped idling.
@@ -302,6 +377,7 @@ impl DrivingSimState {
                                     // Since the follower was Queued, this must be where they are.
                                     dist - car.vehicle.length - FOLLOWING_DISTANCE,
                                     now,
+                                    self.use_acceleration,
                                     map,
                                 );
                                 follower.blocked_since = None;
@@ -314,7 +390,7 @@ impl DrivingSimState {
                         CarState::WaitingToAdvance => unreachable!(),
                         // They weren't blocked. Note that there's no way the Crossing state could jump
                         // forwards here; the leader is still in front of them.
-                        CarState::Crossing(_, _)
+                        CarState::Crossing(_, _, _)
                         | CarState::Unparking(_, _, _)
                         | CarState::Parking(_, _, _)
                         | CarState::Idling(_, _) => {}
@@ -345,7 +421,22 @@ impl DrivingSimState {
                             &car,
                         )),
                     ) {
-                        // Don't schedule a retry here.
+                        if self.maybe_reroute(car, now, map) {
+                            // Stop waiting for the old turn and immediately try the new one.
+                            intersections.cancel_request(AgentID::Car(car.vehicle.id), t);
+                            scheduler.update(now, Command::UpdateCar(car.vehicle.id));
+                        } else if let Some(threshold) = self.reroute_after_blocked {
+                            // The intersection will wake us up when the turn might work, but
+                            // that could take longer than we're willing to wait.
+                            let blocked_since = car.blocked_since.unwrap_or(now);
+                            if car.last_rerouted.map(|t| t < blocked_since).unwrap_or(true) {
+                                scheduler.update_if_sooner(
+                                    (blocked_since + threshold).max(now),
+                                    Command::UpdateCar(car.vehicle.id),
+                                );
+                            }
+                        }
+                        // Otherwise, don't schedule a retry here.
                         return false;
                     }
                 }
@@ -362,7 +453,7 @@ impl DrivingSimState {
                 let last_step =
                     car.router
                         .advance(&car.vehicle, parking, map, car.trip, &mut self.events);
-                car.state = car.crossing_state(Distance::ZERO, now, map);
+                car.state = car.crossing_state(Distance::ZERO, now, self.use_acceleration, map);
                 car.blocked_since = None;
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                 self.events.push(Event::AgentEntersTraversable(
@@ -383,6 +474,7 @@ impl DrivingSimState {
                                 car.vehicle.length + FOLLOWING_DISTANCE,
                             ),
                             now,
+                            self.use_acceleration,
                             map,
                         )
                         .get_end_time(),
@@ -428,7 +520,7 @@ impl DrivingSimState {
         // Just two cases here. In all cases, we leave the Queued state.
         car.blocked_since = None;
         match car.state {
-            CarState::Crossing(_, _)
+            CarState::Crossing(_, _, _)
             | CarState::Unparking(_, _, _)
             | CarState::Idling(_, _)
             | CarState::WaitingToAdvance => unreachable!(),
@@ -442,7 +534,12 @@ impl DrivingSimState {
                     &mut self.events,
                 ) {
                     Some(ActionAtEnd::VanishAtBorder(i)) => {
-                        trips.car_or_bike_reached_border(now, car.vehicle.id, i);
+                        if car.vehicle.vehicle_type == VehicleType::Bus {
+                            transit.bus_reached_border(car.vehicle.id);
+                            trips.bus_finished_service(now, car.vehicle.id);
+                        } else {
+                            trips.car_or_bike_reached_border(now, car.vehicle.id, i);
+                        }
                     }
                     Some(ActionAtEnd::AbortTrip) => {
                         trips.abort_trip_impossible_parking(car.vehicle.id);
@@ -462,7 +559,7 @@ impl DrivingSimState {
                         return true;
                     }
                     Some(ActionAtEnd::GotoLaneEnd) => {
-                        car.state = car.crossing_state(our_dist, now, map);
+                        car.state = car.crossing_state(our_dist, now, self.use_acceleration, map);
                         scheduler
                             .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                         return true;
@@ -471,21 +568,21 @@ impl DrivingSimState {
                         trips.bike_reached_end(now, car.vehicle.id, bike_rack, map, scheduler);
                     }
                     Some(ActionAtEnd::BusAtStop) => {
-                        transit.bus_arrived_at_stop(
+                        if let Some(depart_at) = transit.bus_arrived_at_stop(
                             now,
                             car.vehicle.id,
                             trips,
                             walking,
                             scheduler,
                             map,
-                        );
-                        car.state = CarState::Idling(
-                            our_dist,
-                            TimeInterval::new(now, now + TIME_TO_WAIT_AT_STOP),
-                        );
-                        scheduler
-                            .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
-                        return true;
+                        ) {
+                            car.state =
+                                CarState::Idling(our_dist, TimeInterval::new(now, depart_at));
+                            scheduler
+                                .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                            return true;
+                        }
+                        trips.bus_finished_service(now, car.vehicle.id);
                     }
                     None => {
                         scheduler.push(
@@ -497,8 +594,9 @@ impl DrivingSimState {
                         // to be slower otherwise. :(
                         /*
                         // If this car wasn't blocked at all, when would it reach its goal?
-                        let ideal_end_time = match car.crossing_state(our_dist, now, map) {
-                            CarState::Crossing(time_int, _) => time_int.end,
+                        let ideal_end_time =
+                            match car.crossing_state(our_dist, now, self.use_acceleration, map) {
+                            CarState::Crossing(time_int, _, _) => time_int.end,
                             _ => unreachable!(),
                         };
                         if ideal_end_time == now {
@@ -560,6 +658,54 @@ impl DrivingSimState {
         scheduler.cancel(Command::UpdateCar(c));
     }
 
+    // A car waiting at the end of a lane looks at the rest of its path once per round of live
+    // edits, and reroutes if it broke. False if there's no longer any way to the goal.
+    fn path_still_valid_or_rerouted(
+        &mut self,
+        id: CarID,
+        now: Time,
+        map: &Map,
+        intersections: &mut IntersectionSimState,
+    ) -> bool {
+        let car = &self.cars[&id];
+        if car.state != CarState::WaitingToAdvance || car.path_checked_at == self.edits_generation {
+            return true;
+        }
+        let mut car = self.cars.remove(&id).unwrap();
+        car.path_checked_at = self.edits_generation;
+        let ok = if car.router.path_still_valid(&car.vehicle, map) {
+            true
+        } else if let Traversable::Turn(t) = car.router.next() {
+            // The car might've already asked for the old turn.
+            intersections.cancel_request(AgentID::Car(id), t);
+            self.reroute(&mut car, now, map)
+        } else {
+            unreachable!()
+        };
+        self.cars.insert(id, car);
+        ok
+    }
+
+    // The car vanishes where it's waiting, along with anybody riding it.
+    fn strand_car(
+        &mut self,
+        id: CarID,
+        now: Time,
+        map: &Map,
+        trips: &mut TripManager,
+        transit: &mut TransitSimState,
+        scheduler: &mut Scheduler,
+        intersections: &mut IntersectionSimState,
+    ) {
+        if self.cars[&id].vehicle.vehicle_type == VehicleType::Bus {
+            for ped in transit.bus_stranded(id) {
+                trips.abort_trip_stranded(AgentID::Pedestrian(ped));
+            }
+        }
+        trips.abort_trip_stranded(AgentID::Car(id));
+        self.kill_stuck_car(id, now, map, scheduler, intersections);
+    }
+
     fn delete_car(
         &mut self,
         car: &mut Car,
@@ -587,6 +733,7 @@ impl DrivingSimState {
 
         // We might've scheduled one of those using BLIND_RETRY_TO_CREEP_FORWARDS.
         scheduler.cancel(Command::UpdateLaggyHead(car.vehicle.id));
+        scheduler.cancel(Command::RetryLaneChange(car.vehicle.id));
 
         // Update the follower so that they don't suddenly jump forwards.
         if idx != dists.len() - 1 {
@@ -596,12 +743,141 @@ impl DrivingSimState {
             // lead car's back is still sticking out. Need to still be bound by them, even
             // though they don't exist! If the leader just parked, then we're fine.
             match follower.state {
-                CarState::Queued | CarState::Crossing(_, _) => {
+                CarState::Queued | CarState::Crossing(_, _, _) => {
                     // If the follower was still Crossing, they might not've been blocked
                     // by leader yet. In that case, recalculating their Crossing state is a
                     // no-op. But if they were blocked, then this will prevent them from
                     // jumping forwards.
-                    follower.state = follower.crossing_state(follower_dist, now, map);
+                    follower.state =
+                        follower.crossing_state(follower_dist, now, self.use_acceleration, map);
+                    follower.blocked_since = None;
+                    scheduler.update(
+                        follower.state.get_end_time(),
+                        Command::UpdateCar(follower_id),
+                    );
+                }
+                // They weren't blocked
+                CarState::Unparking(_, _, _)
+                | CarState::Parking(_, _, _)
+                | CarState::Idling(_, _) => {}
+                CarState::WaitingToAdvance => unreachable!(),
+            }
+        }
+    }
+
+    // A car is stuck behind somebody partway along a lane. If an adjacent lane leading to the
+    // same place has fewer cars ahead and there's a gap to slot into right now, hop over. If not,
+    // keep looking every so often while the car waits.
+    pub fn try_mid_block_lanechange(
+        &mut self,
+        id: CarID,
+        now: Time,
+        map: &Map,
+        intersections: &mut IntersectionSimState,
+        scheduler: &mut Scheduler,
+    ) {
+        // This might be a stale retry
+        let car = match self.cars.get(&id) {
+            Some(car) => car,
+            None => {
+                return;
+            }
+        };
+        match car.state {
+            CarState::Queued => {}
+            _ => {
+                return;
+            }
+        }
+        if car.router.last_step() {
+            return;
+        }
+        // Don't bother if our back is still sticking out of the previous step.
+        if !car.last_steps.is_empty() {
+            scheduler.update(
+                now + RETRY_MID_BLOCK_LANECHANGE,
+                Command::RetryLaneChange(id),
+            );
+            return;
+        }
+        let from = match car.router.head() {
+            Traversable::Lane(l) => l,
+            Traversable::Turn(_) => {
+                return;
+            }
+        };
+
+        let dists =
+            self.queues[&Traversable::Lane(from)].get_car_positions(now, &self.cars, &self.queues);
+        let our_idx = dists.iter().position(|(c, _)| *c == id).unwrap();
+        if our_idx == 0 {
+            return;
+        }
+        let our_dist = dists[our_idx].1;
+
+        // (number of cars ahead, lane, distance along it)
+        let mut best: Option<(usize, LaneID, Distance)> = None;
+        for l in car.router.mid_block_lanechange_candidates(map) {
+            let new_dist = Position::new(from, our_dist)
+                .equiv_pos(l, car.vehicle.length, map)
+                .dist_along();
+            // Leave room for somebody entering the lane behind us.
+            if new_dist < car.vehicle.length + FOLLOWING_DISTANCE {
+                continue;
+            }
+            let queue = &self.queues[&Traversable::Lane(l)];
+            if !queue.room_for_car(car) {
+                continue;
+            }
+            if let Some(idx) = queue.get_idx_to_insert_car(
+                new_dist,
+                car.vehicle.length,
+                now,
+                &self.cars,
+                &self.queues,
+            ) {
+                // Only switch if it's actually better. Ties go to the first candidate.
+                if idx < our_idx && best.map(|(best_idx, _, _)| idx < best_idx).unwrap_or(true) {
+                    best = Some((idx, l, new_dist));
+                }
+            }
+        }
+        let (idx, to, new_dist) = match best {
+            Some(x) => x,
+            None => {
+                scheduler.update(
+                    now + RETRY_MID_BLOCK_LANECHANGE,
+                    Command::RetryLaneChange(id),
+                );
+                return;
+            }
+        };
+        // Whoever we cut in front of has to slow down for us.
+        let new_follower = {
+            let dists = self.queues[&Traversable::Lane(to)].get_car_positions(
+                now,
+                &self.cars,
+                &self.queues,
+            );
+            dists.get(idx).cloned()
+        };
+
+        let mut car = self.cars.remove(&id).unwrap();
+        {
+            let queue = self.queues.get_mut(&Traversable::Lane(from)).unwrap();
+            assert_eq!(queue.cars.remove(our_idx).unwrap(), id);
+            queue.free_reserved_space(&car);
+            intersections.space_freed(now, map.get_l(from).src_i, scheduler, map);
+        }
+
+        // Update the old follower so that they don't suddenly jump forwards.
+        if our_idx != dists.len() - 1 {
+            let (follower_id, follower_dist) = dists[our_idx + 1];
+            let mut follower = self.cars.get_mut(&follower_id).unwrap();
+            match follower.state {
+                CarState::Queued | CarState::Crossing(_, _, _) => {
+                    follower.state =
+                        follower.crossing_state(follower_dist, now, self.use_acceleration, map);
                     follower.blocked_since = None;
                     scheduler.update(
                         follower.state.get_end_time(),
@@ -615,6 +891,68 @@ impl DrivingSimState {
                 CarState::WaitingToAdvance => unreachable!(),
             }
         }
+
+        if let Some((follower_id, follower_dist)) = new_follower {
+            let mut follower = self.cars.get_mut(&follower_id).unwrap();
+            match follower.state {
+                CarState::Crossing(_, _, _) => {
+                    follower.state =
+                        follower.crossing_state(follower_dist, now, self.use_acceleration, map);
+                    scheduler.update(
+                        follower.state.get_end_time(),
+                        Command::UpdateCar(follower_id),
+                    );
+                }
+                // There was a gap, so they can't have been stuck right behind where we're going.
+                CarState::Queued
+                | CarState::WaitingToAdvance
+                | CarState::Unparking(_, _, _)
+                | CarState::Parking(_, _, _)
+                | CarState::Idling(_, _) => {}
+            }
+        }
+
+        car.router.change_current_lane(to, map);
+        // Do this before resetting blocked_since, so we keep the speed we had coming in.
+        car.state = car.crossing_state(new_dist, now, self.use_acceleration, map);
+        car.blocked_since = None;
+        scheduler.update(car.state.get_end_time(), Command::UpdateCar(id));
+        {
+            let queue = self.queues.get_mut(&Traversable::Lane(to)).unwrap();
+            queue.cars.insert(idx, id);
+            queue.reserved_length += car.vehicle.length + FOLLOWING_DISTANCE;
+        }
+        self.events.push(Event::CarChangedLanes(id, from, to));
+        self.cars.insert(id, car);
+    }
+
+    // For a car waiting at the end of its lane. When rerouting is enabled, reroutes once if the car
+    // has been blocked for too long. True if the path changed.
+    fn maybe_reroute(&mut self, car: &mut Car, now: Time, map: &Map) -> bool {
+        let blocked_since = car.blocked_since.unwrap_or(now);
+        let stuck = match self.reroute_after_blocked {
+            Some(threshold) => {
+                now - blocked_since >= threshold
+                    && car.last_rerouted.map(|t| t < blocked_since).unwrap_or(true)
+            }
+            None => false,
+        };
+        if !stuck {
+            return false;
+        }
+        self.reroute(car, now, map)
+    }
+
+    fn reroute(&mut self, car: &mut Car, now: Time, map: &Map) -> bool {
+        let blocked_since = car.blocked_since.unwrap_or(now);
+        car.last_rerouted = Some(now);
+        car.router.reroute(
+            &car.vehicle,
+            now - blocked_since,
+            &self.queues,
+            map,
+            &mut self.events,
+        )
     }
 
     pub fn update_laggy_head(
@@ -649,6 +987,7 @@ impl DrivingSimState {
                     .crossing_state_with_end_dist(
                         DistanceInterval::new_driving(our_dist, our_len),
                         now,
+                        self.use_acceleration,
                         map,
                     )
                     .get_end_time();
@@ -733,7 +1072,7 @@ impl DrivingSimState {
                         CarState::WaitingToAdvance => unreachable!(),
                         // They weren't blocked. Note that there's no way the Crossing state could jump
                         // forwards here; the leader vanished from the end of the traversable.
-                        CarState::Crossing(_, _)
+                        CarState::Crossing(_, _, _)
                         | CarState::Unparking(_, _, _)
                         | CarState::Parking(_, _, _)
                         | CarState::Idling(_, _) => {}
@@ -5,8 +5,8 @@ use abstutil::{deserialize_btreemap, serialize_btreemap};
 use derivative::Derivative;
 use geom::{Duration, Time};
 use map_model::{
-    ControlStopSign, ControlTrafficSignal, IntersectionID, LaneID, Map, TurnID, TurnPriority,
-    TurnType,
+    ControlStopSign, ControlTrafficSignal, CrossingType, IntersectionID, LaneID, Map, Phase,
+    TurnID, TurnPriority, TurnType,
 };
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashSet};
@@ -17,6 +17,11 @@ const WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL: Duration = Duration::const_seconds(0.
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct IntersectionSimState {
     state: BTreeMap<IntersectionID, State>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    signals: BTreeMap<IntersectionID, SignalState>,
     use_freeform_policy_everywhere: bool,
     force_queue_entry: bool,
     events: Vec<Event>,
@@ -35,6 +40,32 @@ struct State {
     waiting: BTreeMap<Request, Time>,
 }
 
+#[derive(Clone, Serialize, Deserialize, PartialEq)]
+struct SignalState {
+    current_phase: usize,
+    phase_started: Time,
+    // For actuated phases, this gets pushed back as vehicles keep arriving.
+    phase_ends: Time,
+    // The last time a vehicle arrived for a turn allowed during the current phase
+    last_detection: Option<Time>,
+}
+
+impl SignalState {
+    // Start out the same as a fixed-time signal would be.
+    fn new(signal: &ControlTrafficSignal, now: Time) -> SignalState {
+        let (idx, phase, remaining) = signal.current_phase_and_remaining_time(now);
+        SignalState {
+            current_phase: idx,
+            phase_started: now,
+            phase_ends: match phase.actuated {
+                Some(ref a) => now + a.min_green,
+                None => now + remaining,
+            },
+            last_detection: None,
+        }
+    }
+}
+
 impl IntersectionSimState {
     pub fn new(
         map: &Map,
@@ -44,6 +75,7 @@ impl IntersectionSimState {
     ) -> IntersectionSimState {
         let mut sim = IntersectionSimState {
             state: BTreeMap::new(),
+            signals: BTreeMap::new(),
             use_freeform_policy_everywhere,
             force_queue_entry: disable_block_the_box,
             events: Vec::new(),
@@ -58,12 +90,50 @@ impl IntersectionSimState {
                 },
             );
             if i.is_traffic_signal() && !use_freeform_policy_everywhere {
+                sim.signals.insert(
+                    i.id,
+                    SignalState::new(map.get_traffic_signal(i.id), Time::START_OF_DAY),
+                );
                 sim.update_intersection(Time::START_OF_DAY, i.id, map, scheduler);
             }
         }
         sim
     }
 
+    // Call after editing the map while the sim is running. New traffic signals start out the same
+    // as they would at the beginning of the day; edited signals keep their current phase if it
+    // still exists.
+    pub fn handle_live_edits(&mut self, now: Time, map: &Map, scheduler: &mut Scheduler) {
+        if self.use_freeform_policy_everywhere {
+            return;
+        }
+
+        let removed: Vec<IntersectionID> = self
+            .signals
+            .keys()
+            .filter(|i| map.maybe_get_traffic_signal(**i).is_none())
+            .cloned()
+            .collect();
+        for i in removed {
+            self.signals.remove(&i);
+            scheduler.cancel(Command::UpdateIntersection(i));
+        }
+
+        for i in map.all_intersections() {
+            if let Some(signal) = map.maybe_get_traffic_signal(i.id) {
+                let is_new = !self.signals.contains_key(&i.id);
+                let state = signal_state(&mut self.signals, signal, now);
+                if is_new {
+                    self.update_intersection(now, i.id, map, scheduler);
+                } else {
+                    // The phase might've been reset; wake up whoever can go now.
+                    scheduler.update(state.phase_ends.max(now), Command::UpdateIntersection(i.id));
+                    self.wakeup_waiting(now, i.id, scheduler, map);
+                }
+            }
+        }
+    }
+
     pub fn nobody_headed_towards(&self, lane: LaneID, i: IntersectionID) -> bool {
         !self.state[&i]
             .accepted
@@ -125,7 +195,13 @@ impl IntersectionSimState {
                 protected.push(req);
             }
         } else if let Some(ref signal) = map.maybe_get_traffic_signal(i) {
-            let (_, phase, _) = signal.current_phase_and_remaining_time(now);
+            let phase = match self.signals.get(&i) {
+                Some(state) if state.current_phase < signal.phases.len() => {
+                    &signal.phases[state.current_phase]
+                }
+                // The signal was edited and handle_live_edits hasn't caught up yet
+                _ => signal.current_phase_and_remaining_time(now).1,
+            };
             for (req, _) in all {
                 match phase.get_priority_of_turn(req.turn, signal) {
                     TurnPriority::Protected => {
@@ -166,19 +242,83 @@ impl IntersectionSimState {
         }
     }
 
-    // This is only triggered for traffic signals.
+    // This is only triggered for traffic signals, when the current phase might end.
     pub fn update_intersection(
-        &self,
+        &mut self,
         now: Time,
         id: IntersectionID,
         map: &Map,
         scheduler: &mut Scheduler,
     ) {
+        let signal = match map.maybe_get_traffic_signal(id) {
+            Some(signal) => signal,
+            // Edited into something else
+            None => {
+                return;
+            }
+        };
+        let waiting = &self.state[&id].waiting;
+        let state = signal_state(&mut self.signals, signal, now);
+        if now >= state.phase_ends {
+            // Keep an actuated phase going if vehicles are still arriving.
+            let extend_until = signal.phases[state.current_phase]
+                .actuated
+                .as_ref()
+                .and_then(|a| {
+                    let until = (state.last_detection? + a.extension)
+                        .min(state.phase_started + a.max_green);
+                    if until > now {
+                        Some(until)
+                    } else {
+                        None
+                    }
+                });
+            if let Some(until) = extend_until {
+                state.phase_ends = until;
+            } else {
+                let next = next_phase(signal, state.current_phase, waiting, map);
+                let phase = &signal.phases[next];
+                state.current_phase = next;
+                state.phase_started = now;
+                state.phase_ends = match phase.actuated {
+                    Some(ref a) => now + a.min_green,
+                    None => now + phase.duration,
+                };
+                state.last_detection = None;
+            }
+        }
+        let phase_ends = state.phase_ends;
+
         self.wakeup_waiting(now, id, scheduler, map);
-        let (_, _, remaining) = map
-            .get_traffic_signal(id)
-            .current_phase_and_remaining_time(now);
-        scheduler.push(now + remaining, Command::UpdateIntersection(id));
+        scheduler.push(phase_ends, Command::UpdateIntersection(id));
+    }
+
+    // Returns the current phase and how long until it might end. Actuated phases could last
+    // longer.
+    pub fn current_phase_and_remaining_time(
+        &self,
+        now: Time,
+        id: IntersectionID,
+        map: &Map,
+    ) -> (usize, Duration) {
+        let num_phases = map.get_traffic_signal(id).phases.len();
+        if let Some(ref state) = self
+            .signals
+            .get(&id)
+            .filter(|state| state.current_phase < num_phases)
+        {
+            let remaining = if state.phase_ends > now {
+                state.phase_ends - now
+            } else {
+                Duration::ZERO
+            };
+            (state.current_phase, remaining)
+        } else {
+            let (idx, _, remaining) = map
+                .get_traffic_signal(id)
+                .current_phase_and_remaining_time(now);
+            (idx, remaining)
+        }
     }
 
     // For cars: The head car calls this when they're at the end of the lane WaitingToAdvance. If
@@ -201,12 +341,40 @@ impl IntersectionSimState {
         //let debug = turn.parent == IntersectionID(64);
         let req = Request { agent, turn };
         let state = self.state.get_mut(&turn.parent).unwrap();
+        let arriving = !state.waiting.contains_key(&req);
         state.waiting.entry(req.clone()).or_insert(now);
 
         let allowed = if self.use_freeform_policy_everywhere {
             state.freeform_policy(&req, map)
         } else if let Some(ref signal) = map.maybe_get_traffic_signal(state.id) {
-            state.traffic_signal_policy(signal, &req, speed, now, map, scheduler)
+            let signal_state = signal_state(&mut self.signals, signal, now);
+            let phase = &signal.phases[signal_state.current_phase];
+            // Virtual loop detectors only notice vehicles.
+            let is_vehicle = match agent {
+                AgentID::Car(_) => true,
+                AgentID::Pedestrian(_) => false,
+            };
+            if arriving
+                && is_vehicle
+                && phase.get_priority_of_turn(turn, signal) != TurnPriority::Banned
+            {
+                signal_state.last_detection = Some(now);
+            }
+            let remaining_phase_time = if signal_state.phase_ends > now {
+                signal_state.phase_ends - now
+            } else {
+                Duration::ZERO
+            };
+            state.traffic_signal_policy(
+                signal,
+                phase,
+                remaining_phase_time,
+                &req,
+                speed,
+                now,
+                map,
+                scheduler,
+            )
         } else if let Some(ref sign) = map.maybe_get_stop_sign(state.id) {
             state.stop_sign_policy(sign, &req, now, map, scheduler)
         } else {
@@ -298,10 +466,38 @@ impl State {
             return false;
         }
 
+        // At a zebra crossing, vehicles give way to anybody waiting to cross.
+        let turn = map.get_t(req.turn);
+        if map.get_i(sign.id).crossing == Some(CrossingType::Zebra)
+            && !turn.between_sidewalks()
+            && self.waiting.keys().any(|other| {
+                let other_turn = map.get_t(other.turn);
+                other_turn.turn_type == TurnType::Crosswalk && other_turn.conflicts_with(turn)
+            })
+        {
+            return false;
+        }
+
         let our_priority = sign.get_priority(req.turn, map);
         assert!(our_priority != TurnPriority::Banned);
         let our_time = self.waiting[req];
 
+        if our_priority == TurnPriority::Yield && sign.roundabout {
+            // Don't stop, but give way to anybody circulating who's also waiting. When they
+            // finish their turn, wakeup_waiting will retry this one. If they're stuck because
+            // there's no room past their turn, only wait when heading for that same lane;
+            // otherwise a backed-up exit would stall the entry too.
+            return !self.waiting.keys().any(|other| {
+                let other_turn = map.get_t(other.turn);
+                other != req
+                    && !other_turn.between_sidewalks()
+                    && sign.get_priority(other.turn, map) == TurnPriority::Protected
+                    && other_turn.conflicts_with(turn)
+                    && (other.turn.dst == req.turn.dst
+                        || self.any_accepted_conflict_with(other.turn, map))
+            });
+        }
+
         if our_priority == TurnPriority::Yield && now < our_time + WAIT_AT_STOP_SIGN {
             // Since we have "ownership" of scheduling for req.agent, don't need to use
             // scheduler.update.
@@ -334,6 +530,8 @@ impl State {
     fn traffic_signal_policy(
         &self,
         signal: &ControlTrafficSignal,
+        phase: &Phase,
+        remaining_phase_time: Duration,
         req: &Request,
         speed: Speed,
         now: Time,
@@ -347,8 +545,6 @@ impl State {
             return true;
         }
 
-        let (_, phase, remaining_phase_time) = signal.current_phase_and_remaining_time(now);
-
         // Can't go at all this phase.
         let our_priority = phase.get_priority_of_turn(req.turn, signal);
         if our_priority == TurnPriority::Banned {
@@ -383,12 +579,13 @@ impl State {
 
         // Optimistically if nobody else is in the way, this is how long it'll take to finish the
         // turn. Don't start the turn if we won't finish by the time the light changes. If we get
-        // it wrong, that's fine -- block the box a bit.
+        // it wrong, that's fine -- block the box a bit. Actuated phases might last longer, but be
+        // pessimistic.
         let time_to_cross = turn.geom.length() / speed;
         if time_to_cross > remaining_phase_time {
             // Actually, we might have bigger problems...
-            if time_to_cross > phase.duration {
-                println!("OYYY! {:?} is impossible to fit into phase duration of {}. Allowing, but fix the policy!", req, phase.duration);
+            if time_to_cross > phase.max_duration() {
+                println!("OYYY! {:?} is impossible to fit into phase duration of {}. Allowing, but fix the policy!", req, phase.max_duration());
             } else {
                 return false;
             }
@@ -398,6 +595,52 @@ impl State {
     }
 }
 
+// Normally handle_live_edits keeps signal state in sync with the map, but don't crash if a signal
+// appears or loses phases underneath us.
+fn signal_state<'a>(
+    signals: &'a mut BTreeMap<IntersectionID, SignalState>,
+    signal: &ControlTrafficSignal,
+    now: Time,
+) -> &'a mut SignalState {
+    let state = signals
+        .entry(signal.id)
+        .or_insert_with(|| SignalState::new(signal, now));
+    if state.current_phase >= signal.phases.len() {
+        *state = SignalState::new(signal, now);
+    }
+    state
+}
+
+// Actuated phases with nobody waiting for them might be skipped.
+fn next_phase(
+    signal: &ControlTrafficSignal,
+    current: usize,
+    waiting: &BTreeMap<Request, Time>,
+    map: &Map,
+) -> usize {
+    let num_phases = signal.phases.len();
+    for offset in 1..=num_phases {
+        let idx = (current + offset) % num_phases;
+        let phase = &signal.phases[idx];
+        let skippable = phase
+            .actuated
+            .as_ref()
+            .map(|a| a.skip_if_no_demand)
+            .unwrap_or(false);
+        if !skippable
+            || waiting.keys().any(|req| {
+                map.get_t(req.turn).turn_type != TurnType::SharedSidewalkCorner
+                    && phase.get_priority_of_turn(req.turn, signal) != TurnPriority::Banned
+            })
+        {
+            return idx;
+        }
+    }
+    // Every phase can be skipped, and nobody's waiting for any of them. Don't start a phase nobody
+    // asked for; rest in the current one.
+    current
+}
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Debug)]
 struct Request {
     agent: AgentID,
@@ -0,0 +1,134 @@
+use geom::{Acceleration, Distance, Duration, Speed};
+use serde_derive::{Deserialize, Serialize};
+
+// Describes how a car's speed changes while it crosses some distance: accelerate (or not) from
+// start_speed to peak_speed, cruise, then brake to end_speed. Only used when
+// SimOptions::use_acceleration is enabled; otherwise cars instantly travel at their top speed.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Kinematics {
+    start_speed: Speed,
+    peak_speed: Speed,
+    end_speed: Speed,
+    accel: Acceleration,
+    decel: Acceleration,
+
+    // Cached from the above, in the order of the phases
+    accel_time: Duration,
+    cruise_time: Duration,
+    decel_time: Duration,
+    accel_dist: Distance,
+    cruise_dist: Distance,
+    total_dist: Distance,
+}
+
+impl Kinematics {
+    // accel and decel must be positive. If the car can't stop in time using decel, it'll brake
+    // harder; if it can't reach end_speed, it'll just finish going as fast as it can.
+    pub fn new(
+        dist: Distance,
+        start_speed: Speed,
+        cruise_speed: Speed,
+        end_speed: Speed,
+        accel: Acceleration,
+        decel: Acceleration,
+    ) -> Kinematics {
+        assert!(accel > Acceleration::ZERO && decel > Acceleration::ZERO);
+        let d = dist.inner_meters();
+        let vc = cruise_speed.inner_meters_per_second();
+        let a = accel.inner_meters_per_second_squared();
+        let mut b = decel.inner_meters_per_second_squared();
+        // TODO Entering a lane with a lower speed limit instantly slows the car down.
+        let v0 = start_speed.inner_meters_per_second().min(vc);
+        let mut v1 = end_speed.inner_meters_per_second().min(vc);
+
+        let vp = if d <= 0.0 {
+            v1 = v0;
+            v0
+        } else if v1 < v0 && (v0 * v0 - v1 * v1) / (2.0 * b) >= d {
+            // Have to slam on the brakes.
+            b = (v0 * v0 - v1 * v1) / (2.0 * d);
+            v0
+        } else if v1 > v0 && (v1 * v1 - v0 * v0) / (2.0 * a) >= d {
+            // Not enough room to reach the end speed.
+            v1 = (v0 * v0 + 2.0 * a * d).sqrt();
+            v1
+        } else {
+            // The speed we'd reach if we accelerated, then immediately braked.
+            let peak_squared = (d + v0 * v0 / (2.0 * a) + v1 * v1 / (2.0 * b))
+                / (1.0 / (2.0 * a) + 1.0 / (2.0 * b));
+            vc.min(peak_squared.sqrt()).max(v0).max(v1)
+        };
+
+        let accel_time = (vp - v0) / a;
+        let decel_time = (vp - v1) / b;
+        let accel_dist = (vp * vp - v0 * v0) / (2.0 * a);
+        let decel_dist = (vp * vp - v1 * v1) / (2.0 * b);
+        let cruise_dist = (d - accel_dist - decel_dist).max(0.0);
+        let cruise_time = if vp > 0.0 { cruise_dist / vp } else { 0.0 };
+
+        Kinematics {
+            start_speed: Speed::meters_per_second(v0),
+            peak_speed: Speed::meters_per_second(vp),
+            end_speed: Speed::meters_per_second(v1),
+            accel,
+            decel: Acceleration::meters_per_second_squared(b),
+
+            accel_time: Duration::seconds(accel_time),
+            cruise_time: Duration::seconds(cruise_time),
+            decel_time: Duration::seconds(decel_time),
+            accel_dist: Distance::meters(accel_dist),
+            cruise_dist: Distance::meters(cruise_dist),
+            total_dist: dist,
+        }
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.accel_time + self.cruise_time + self.decel_time
+    }
+
+    pub fn end_speed(&self) -> Speed {
+        self.end_speed
+    }
+
+    // How far has the car gone after some amount of time?
+    pub fn dist_at(&self, elapsed: Duration) -> Distance {
+        if elapsed <= Duration::ZERO {
+            return Distance::ZERO;
+        }
+        if elapsed >= self.total_time() {
+            return self.total_dist;
+        }
+
+        let v0 = self.start_speed.inner_meters_per_second();
+        let vp = self.peak_speed.inner_meters_per_second();
+        let a = self.accel.inner_meters_per_second_squared();
+        let b = self.decel.inner_meters_per_second_squared();
+
+        let dist = if elapsed < self.accel_time {
+            let t = elapsed.inner_seconds();
+            Distance::meters(v0 * t + 0.5 * a * t * t)
+        } else if elapsed < self.accel_time + self.cruise_time {
+            self.accel_dist + self.peak_speed * (elapsed - self.accel_time)
+        } else {
+            let t = (elapsed - self.accel_time - self.cruise_time).inner_seconds();
+            self.accel_dist + self.cruise_dist + Distance::meters(vp * t - 0.5 * b * t * t)
+        };
+        dist.min(self.total_dist)
+    }
+
+    pub fn speed_at(&self, elapsed: Duration) -> Speed {
+        if elapsed <= Duration::ZERO {
+            self.start_speed
+        } else if elapsed >= self.total_time() {
+            self.end_speed
+        } else if elapsed < self.accel_time {
+            self.start_speed + self.accel * elapsed
+        } else if elapsed < self.accel_time + self.cruise_time {
+            self.peak_speed
+        } else {
+            let speed =
+                self.peak_speed - self.decel * (elapsed - self.accel_time - self.cruise_time);
+            speed.max(self.end_speed)
+        }
+    }
+}
@@ -1,12 +1,14 @@
 mod car;
 mod driving;
 mod intersection;
+mod kinematics;
 mod parking;
 mod queue;
 mod walking;
 
-pub use self::driving::DrivingSimState;
+pub use self::driving::{DrivingSimState, DrivingSimStateV1};
 pub use self::intersection::IntersectionSimState;
+pub use self::kinematics::Kinematics;
 pub use self::parking::ParkingSimState;
 pub use self::queue::Queue;
 pub use self::walking::WalkingSimState;
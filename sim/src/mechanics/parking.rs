@@ -1,14 +1,20 @@
-use crate::{AgentMetadata, CarID, CarStatus, DrawCarInput, ParkedCar, ParkingSpot, Vehicle};
+use crate::{
+    AgentMetadata, CarID, CarStatus, DrawCarInput, ParkedCar, ParkingSpot, TripID, Vehicle,
+};
 use abstutil::{
     deserialize_btreemap, deserialize_multimap, serialize_btreemap, serialize_multimap, MultiMap,
     Timer,
 };
 use geom::{Distance, Duration, Pt2D};
 use map_model;
-use map_model::{BuildingID, Lane, LaneID, LaneType, Map, Position, Traversable};
+use map_model::{BuildingID, Lane, LaneID, LaneType, Map, ParkingPolicy, Position, Traversable};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 
+// How long drivers expect to stay when choosing parking, if nobody's scheduled to drive the car
+// away again.
+const EXPECTED_STAY: Duration = Duration::const_seconds(3600.0);
+
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct ParkingSimState {
     #[serde(
@@ -45,6 +51,10 @@ pub struct ParkingSimState {
         deserialize_with = "deserialize_multimap"
     )]
     driving_to_offstreet: MultiMap<LaneID, BuildingID>,
+
+    // How long the driver on each trip will stay parked. Derived from the trips, so it's not saved.
+    #[serde(skip_serializing, skip_deserializing)]
+    stays: BTreeMap<TripID, Duration>,
 }
 
 impl ParkingSimState {
@@ -62,6 +72,8 @@ impl ParkingSimState {
             driving_to_parking_lanes: MultiMap::new(),
             num_spots_per_offstreet: BTreeMap::new(),
             driving_to_offstreet: MultiMap::new(),
+
+            stays: BTreeMap::new(),
         };
         for l in map.all_lanes() {
             if let Some(lane) = ParkingLane::new(l, map, timer) {
@@ -219,6 +231,7 @@ impl ParkingSimState {
         &self,
         driving_pos: Position,
         vehicle: &Vehicle,
+        stay: Duration,
         map: &Map,
     ) -> Option<(ParkingSpot, Position)> {
         let mut maybe_spot = None;
@@ -230,7 +243,10 @@ impl ParkingSimState {
             let lane = &self.onstreet_lanes[l];
             // Bit hacky to enumerate here to conveniently get idx.
             for (idx, spot) in lane.spots().into_iter().enumerate() {
-                if self.is_free(spot) && parking_dist <= lane.dist_along_for_car(idx, vehicle) {
+                if self.is_free(spot)
+                    && self.can_park(spot, vehicle, stay, map)
+                    && parking_dist <= lane.dist_along_for_car(idx, vehicle)
+                {
                     maybe_spot = Some(spot);
                     break;
                 }
@@ -258,7 +274,7 @@ impl ParkingSimState {
 
             for idx in 0..self.num_spots_per_offstreet[&b] {
                 let spot = ParkingSpot::offstreet(*b, idx);
-                if self.is_free(spot) {
+                if self.is_free(spot) && self.can_park(spot, vehicle, stay, map) {
                     maybe_spot = Some(spot);
                     break;
                 }
@@ -269,6 +285,28 @@ impl ParkingSimState {
         Some((spot, self.spot_to_driving_pos(spot, vehicle, map)))
     }
 
+    pub fn get_policy<'a>(&self, spot: ParkingSpot, map: &'a Map) -> &'a ParkingPolicy {
+        match spot {
+            ParkingSpot::Onstreet(l, _) => &map.get_l(l).parking_policy,
+            ParkingSpot::Offstreet(b, _) => &map.get_b(b).parking.as_ref().unwrap().policy,
+        }
+    }
+
+    // Drivers respect permit zones and avoid spots they'd overstay.
+    fn can_park(&self, spot: ParkingSpot, vehicle: &Vehicle, stay: Duration, map: &Map) -> bool {
+        let policy = self.get_policy(spot, map);
+        policy.allows(vehicle.owner, map)
+            && policy.max_stay.map(|limit| limit >= stay).unwrap_or(true)
+    }
+
+    pub fn set_stays(&mut self, stays: BTreeMap<TripID, Duration>) {
+        self.stays = stays;
+    }
+
+    pub fn expected_stay(&self, trip: TripID) -> Duration {
+        self.stays.get(&trip).cloned().unwrap_or(EXPECTED_STAY)
+    }
+
     pub fn spot_to_driving_pos(&self, spot: ParkingSpot, vehicle: &Vehicle, map: &Map) -> Position {
         match spot {
             ParkingSpot::Onstreet(l, idx) => {
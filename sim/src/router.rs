@@ -1,13 +1,20 @@
 use crate::mechanics::Queue;
-use crate::{Event, ParkingSimState, ParkingSpot, SidewalkSpot, TripID, Vehicle};
-use geom::Distance;
+use crate::{Event, ParkingSimState, ParkingSpot, SidewalkSpot, TripID, Vehicle, VehicleType};
+use geom::{Distance, Duration};
 use map_model::{
     BuildingID, IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
-    Position, Traversable, TurnID,
+    Position, Traversable, TurnID, WALKING_SPEED,
 };
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap, VecDeque};
 
+// When rerouting, guess how long each car already queued on a lane delays anybody joining it.
+const DELAY_PER_QUEUED_CAR: Duration = Duration::const_seconds(2.0);
+// When looking for parking, how many lanes with a free spot to compare
+const MAX_PARKING_CANDIDATES: usize = 5;
+// How many cents drivers would pay to walk one second less. About $20/hour.
+const CENTS_PER_SECOND: f64 = 2000.0 / 3600.0;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Router {
     // Front is always the current step
@@ -28,7 +35,6 @@ pub enum ActionAtEnd {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 enum Goal {
     // Spot and cached distance along the last driving lane
-    // TODO Right now, the building is ignored when choosing the best spot.
     ParkNearBuilding {
         target: BuildingID,
         spot: Option<(ParkingSpot, Distance)>,
@@ -115,6 +121,16 @@ impl Router {
         }
     }
 
+    // Does the vehicle come to a stop at the end of the path, or just keep going?
+    pub fn stops_at_end(&self) -> bool {
+        match self.goal {
+            Goal::EndAtBorder { .. } => false,
+            Goal::ParkNearBuilding { .. }
+            | Goal::BikeThenStop { .. }
+            | Goal::FollowBusRoute { .. } => true,
+        }
+    }
+
     pub fn get_path(&self) -> &Path {
         &self.path
     }
@@ -169,9 +185,9 @@ impl Router {
                 }
             }
             Goal::ParkNearBuilding {
+                target,
                 ref mut spot,
                 ref mut stuck_end_dist,
-                ..
             } => {
                 if let Some(d) = stuck_end_dist {
                     if *d == front {
@@ -187,11 +203,33 @@ impl Router {
                 };
                 if need_new_spot {
                     let current_lane = self.path.current_step().as_lane();
-                    if let Some((new_spot, new_pos)) = parking.get_first_free_spot(
+                    let stay = parking.expected_stay(trip);
+                    let mut here = parking.get_first_free_spot(
                         Position::new(current_lane, front),
                         vehicle,
+                        stay,
                         map,
-                    ) {
+                    );
+                    // Free parking right here is as good as it gets. Otherwise, something nearby
+                    // might be cheaper, even after driving there.
+                    let elsewhere = match here {
+                        Some((s, _)) if parking.get_policy(s, map).is_free() => None,
+                        _ => path_to_free_parking_spot(
+                            current_lane,
+                            vehicle,
+                            target,
+                            stay,
+                            map,
+                            parking,
+                        ),
+                    };
+                    if let (Some((s1, _)), Some((_, _, _, cost2))) = (here, &elsewhere) {
+                        if *cost2 < parking_cost(s1, target, stay, parking, map) {
+                            here = None;
+                        }
+                    }
+
+                    if let Some((new_spot, new_pos)) = here {
                         events.push(Event::TripPhaseStarting(
                             trip,
                             Some(PathRequest {
@@ -203,9 +241,7 @@ impl Router {
                         ));
                         *spot = Some((new_spot, new_pos.dist_along()));
                     } else {
-                        if let Some((new_path_steps, new_spot, new_pos)) =
-                            path_to_free_parking_spot(current_lane, vehicle, map, parking)
-                        {
+                        if let Some((new_path_steps, new_spot, new_pos, _)) = elsewhere {
                             *spot = Some((new_spot, new_pos.dist_along()));
                             for step in new_path_steps {
                                 self.path.add(step, map);
@@ -292,7 +328,9 @@ impl Router {
             &parent.children_backwards
         };
 
-        let (_, turn1, best_lane, turn2) = siblings
+        // Live edits might've broken the path, in which case not even the original lane works. The
+        // car deals with that once it reaches the end of the lane.
+        let (_, turn1, best_lane, turn2) = match siblings
             .iter()
             .filter_map(|(l, lt)| {
                 let turn1 = TurnID {
@@ -317,7 +355,12 @@ impl Router {
                 }
             })
             .min_by_key(|(len, _, _, _)| *len)
-            .unwrap();
+        {
+            Some(x) => x,
+            None => {
+                return;
+            }
+        };
         // TODO Only switch if the target queue is some amount better; don't oscillate
         // unnecessarily.
         // TODO Better weight function... any slower vehicles in one?
@@ -330,57 +373,313 @@ impl Router {
         self.path.modify_step(3, PathStep::Turn(turn2), map);
     }
 
+    // Lanes immediately to the left or right of the current one that a vehicle could shift into
+    // partway along, without changing the rest of the path. Like opportunistically_lanechange,
+    // stick to the same lane type.
+    pub fn mid_block_lanechange_candidates(&self, map: &Map) -> Vec<LaneID> {
+        let (current_lane, current_turn) = {
+            let steps = self.path.get_steps();
+            if steps.len() < 3 {
+                return Vec::new();
+            }
+            match (steps[0], steps[1]) {
+                (PathStep::Lane(l), PathStep::Turn(t)) => (l, t),
+                _ => {
+                    return Vec::new();
+                }
+            }
+        };
+
+        let parent = map.get_parent(current_lane);
+        let siblings = if parent.is_forwards(current_lane) {
+            &parent.children_forwards
+        } else {
+            &parent.children_backwards
+        };
+        let idx = siblings
+            .iter()
+            .position(|(l, _)| *l == current_lane)
+            .unwrap();
+        let lt = siblings[idx].1;
+
+        let mut candidates = Vec::new();
+        if idx > 0 {
+            candidates.push(siblings[idx - 1]);
+        }
+        if idx + 1 < siblings.len() {
+            candidates.push(siblings[idx + 1]);
+        }
+        candidates
+            .into_iter()
+            .filter_map(|(l, other_lt)| {
+                let turn = TurnID {
+                    parent: current_turn.parent,
+                    src: l,
+                    dst: current_turn.dst,
+                };
+                if other_lt == lt && map.maybe_get_t(turn).is_some() {
+                    Some(l)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    // Must be one of mid_block_lanechange_candidates.
+    pub fn change_current_lane(&mut self, lane: LaneID, map: &Map) {
+        let current_turn = self.path.next_step().as_turn();
+        self.path.modify_current_step(PathStep::Lane(lane), map);
+        self.path.modify_step(
+            1,
+            PathStep::Turn(TurnID {
+                parent: current_turn.parent,
+                src: lane,
+                dst: current_turn.dst,
+            }),
+            map,
+        );
+    }
+
+    // Could the vehicle still follow the rest of its path? Edits can remove turns or change lane
+    // types out from under it.
+    pub fn path_still_valid(&self, vehicle: &Vehicle, map: &Map) -> bool {
+        let constraints = vehicle.vehicle_type.to_constraints();
+        self.path.get_steps().iter().skip(1).all(|step| match step {
+            PathStep::Lane(l) => map
+                .maybe_get_l(*l)
+                .map(|lane| constraints.can_use(lane, map))
+                .unwrap_or(false),
+            PathStep::Turn(t) => map.maybe_get_t(*t).is_some(),
+            PathStep::ContraflowLane(_) => true,
+        })
+    }
+
+    // Only for cars waiting at the end of their current lane. Tries every turn out of the current
+    // lane, pathfinds from there to the same destination, and picks whatever looks fastest given
+    // how many cars are queued along the way. Staying on the current path also pays for the time
+    // already spent blocked. Returns true if the path changed.
+    //
+    // Congestion only decides the next turn. The rest of each candidate is the usual pathfinding
+    // result, which knows nothing about queues, so a jam further along is only avoided if the
+    // usual path after one of the turns happens to miss it.
+    pub fn reroute(
+        &mut self,
+        vehicle: &Vehicle,
+        blocked_for: Duration,
+        queues: &BTreeMap<Traversable, Queue>,
+        map: &Map,
+        events: &mut Vec<Event>,
+    ) -> bool {
+        if vehicle.vehicle_type != VehicleType::Car {
+            return false;
+        }
+        let end_dist = match self.goal {
+            Goal::EndAtBorder { end_dist, .. } => end_dist,
+            Goal::ParkNearBuilding {
+                spot: Some((_, dist)),
+                stuck_end_dist: None,
+                ..
+            } => dist,
+            Goal::ParkNearBuilding {
+                spot: None,
+                stuck_end_dist: None,
+                ..
+            } => self.path.end_dist(),
+            _ => {
+                return false;
+            }
+        };
+        let (current_lane, current_turn) = {
+            let steps = self.path.get_steps();
+            if steps.len() < 3 {
+                return false;
+            }
+            match (steps[0], steps[1]) {
+                (PathStep::Lane(l), PathStep::Turn(t)) => (l, t),
+                _ => {
+                    return false;
+                }
+            }
+        };
+        let end = Position::new(self.path.last_step().as_lane(), end_dist);
+
+        let current_cost = if self.path_still_valid(vehicle, map) {
+            Some(
+                blocked_for
+                    + expected_time(self.path.get_steps().iter().skip(1).cloned(), queues, map),
+            )
+        } else {
+            None
+        };
+        let best = map
+            .get_turns_for(current_lane, PathConstraints::Car)
+            .into_iter()
+            .filter_map(|turn| {
+                let rest = map.pathfind(PathRequest {
+                    start: Position::new(turn.id.dst, Distance::ZERO),
+                    end,
+                    constraints: PathConstraints::Car,
+                })?;
+                let cost = expected_time(
+                    std::iter::once(PathStep::Turn(turn.id))
+                        .chain(rest.get_steps().iter().cloned()),
+                    queues,
+                    map,
+                );
+                Some((cost, turn.id, rest))
+            })
+            .min_by_key(|(cost, _, _)| *cost);
+        let (cost, turn, rest) = match best {
+            Some(x) => x,
+            None => {
+                return false;
+            }
+        };
+        if let Some(current) = current_cost {
+            if cost >= current
+                || (turn == current_turn
+                    && rest
+                        .get_steps()
+                        .iter()
+                        .eq(self.path.get_steps().iter().skip(2)))
+            {
+                return false;
+            }
+        }
+
+        self.path.replace_after_current_step(turn, rest, map);
+        events.push(Event::PathAmended(self.path.clone()));
+        events.push(Event::CarRerouted(vehicle.id, current_lane));
+        true
+    }
+
     pub fn replace_path_for_serialization(&mut self, path: Path) -> Path {
         std::mem::replace(&mut self.path, path)
     }
 }
 
+// Free-flow times, or loaded travel times if the map has them, plus a penalty for every car already
+// queued on each lane.
+fn expected_time<I: Iterator<Item = PathStep>>(
+    steps: I,
+    queues: &BTreeMap<Traversable, Queue>,
+    map: &Map,
+) -> Duration {
+    let mut total = Duration::ZERO;
+    for step in steps {
+        let trav = step.as_traversable();
+        total += match (map.get_travel_times(), trav) {
+            (Some(times), Traversable::Lane(l)) => times.lane(l, map),
+            (Some(times), Traversable::Turn(t)) => times.turn(t, map),
+            (None, _) => free_flow_time(trav, map),
+        };
+        if let Some(queue) = queues.get(&trav) {
+            if let Traversable::Lane(_) = trav {
+                total += DELAY_PER_QUEUED_CAR * (queue.cars.len() as f64);
+            }
+        }
+    }
+    total
+}
+
 // Unrealistically assumes the driver has knowledge of currently free parking spots, even if
 // they're far away. Since they don't reserve the spot in advance, somebody else can still beat
 // them there, producing some nice, realistic churn if there's too much contention.
+// Picks the cheapest of the first few lanes with a spot, weighing price against driving there and
+// walking to the target. Also returns that cost.
 // The first PathStep is the turn after start, NOT PathStep::Lane(start).
 fn path_to_free_parking_spot(
     start: LaneID,
     vehicle: &Vehicle,
+    target: BuildingID,
+    stay: Duration,
     map: &Map,
     parking: &ParkingSimState,
-) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
+) -> Option<(Vec<PathStep>, ParkingSpot, Position, Duration)> {
     let mut backrefs: HashMap<LaneID, TurnID> = HashMap::new();
+    // Free-flow time to reach the start of each lane. The car is already near the end of start.
+    let mut drive_time: HashMap<LaneID, Duration> = HashMap::new();
+    drive_time.insert(start, Duration::ZERO);
     // BFS, so we wind up vaguely closer to the start
     let mut queue: VecDeque<LaneID> = VecDeque::new();
     queue.push_back(start);
+    let mut best: Option<(LaneID, ParkingSpot, Position, Duration)> = None;
+    let mut candidates = 0;
 
     while !queue.is_empty() {
         let current = queue.pop_front().unwrap();
         // If the current lane has a spot open, we wouldn't be asking. This can happen if a spot
         // opens up on the 'start' lane, but behind the car.
         if current != start {
-            if let Some((spot, pos)) =
-                parking.get_first_free_spot(Position::new(current, Distance::ZERO), vehicle, map)
-            {
-                let mut steps = vec![PathStep::Lane(current)];
-                let mut current = current;
-                loop {
-                    if current == start {
-                        // Don't include PathStep::Lane(start)
-                        steps.pop();
-                        steps.reverse();
-                        return Some((steps, spot, pos));
-                    }
-                    let turn = backrefs[&current];
-                    steps.push(PathStep::Turn(turn));
-                    steps.push(PathStep::Lane(turn.src));
-                    current = turn.src;
+            if let Some((spot, pos)) = parking.get_first_free_spot(
+                Position::new(current, Distance::ZERO),
+                vehicle,
+                stay,
+                map,
+            ) {
+                let cost = drive_time[&current] + parking_cost(spot, target, stay, parking, map);
+                if best.map(|(_, _, _, c)| cost < c).unwrap_or(true) {
+                    best = Some((current, spot, pos, cost));
+                }
+                candidates += 1;
+                if candidates == MAX_PARKING_CANDIDATES {
+                    break;
                 }
             }
         }
+        let leave_current = if current == start {
+            drive_time[&current]
+        } else {
+            drive_time[&current] + free_flow_time(Traversable::Lane(current), map)
+        };
         for turn in map.get_turns_for(current, PathConstraints::Car) {
             if !backrefs.contains_key(&turn.id.dst) {
                 backrefs.insert(turn.id.dst, turn.id);
+                drive_time.insert(
+                    turn.id.dst,
+                    leave_current + free_flow_time(Traversable::Turn(turn.id), map),
+                );
                 queue.push_back(turn.id.dst);
             }
         }
     }
 
-    None
+    let (lane, spot, pos, cost) = best?;
+    let mut steps = vec![PathStep::Lane(lane)];
+    let mut current = lane;
+    loop {
+        if current == start {
+            // Don't include PathStep::Lane(start)
+            steps.pop();
+            steps.reverse();
+            return Some((steps, spot, pos, cost));
+        }
+        let turn = backrefs[&current];
+        steps.push(PathStep::Turn(turn));
+        steps.push(PathStep::Lane(turn.src));
+        current = turn.src;
+    }
+}
+
+fn free_flow_time(trav: Traversable, map: &Map) -> Duration {
+    trav.length(map) / trav.speed_limit(map)
+}
+
+// Walking from the spot to the target, plus the price for the whole stay converted to time
+fn parking_cost(
+    spot: ParkingSpot,
+    target: BuildingID,
+    stay: Duration,
+    parking: &ParkingSimState,
+    map: &Map,
+) -> Duration {
+    let walk = parking
+        .spot_to_sidewalk_pos(spot, map)
+        .pt(map)
+        .dist_to(map.get_b(target).front_path.sidewalk.pt(map))
+        / WALKING_SPEED;
+    let cents =
+        (parking.get_policy(spot, map).hourly_price_cents as f64) * (stay.inner_seconds() / 3600.0);
+    walk + Duration::seconds(cents / CENTS_PER_SECOND)
 }
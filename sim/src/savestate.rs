@@ -0,0 +1,192 @@
+use crate::{
+    DrivingSimStateV1, IntersectionSimState, ParkingSimState, Scheduler, Sim, TransitSimState,
+    TripManager, TripSpawner, WalkingSimState,
+};
+use bincode::Options;
+use geom::Time;
+use map_model::{IntersectionType, Map};
+use serde_derive::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+// Bump this whenever the serialized form of Sim (or anything inside it) changes, and teach
+// read_sim how to read the previous layout. tests/savestates holds one savestate per version;
+// add the new one there too.
+pub const SAVESTATE_VERSION: usize = 2;
+
+// Savestates from before headers existed just start with the Sim. Their layout isn't recorded
+// anywhere, so they're refused.
+const MAGIC: &[u8; 8] = b"abstsave";
+
+// Headers are tiny; anything bigger is garbage.
+const MAX_HEADER_BYTES: u64 = 1 << 16;
+
+// Written before the Sim itself, so it's cheap to check what a savestate needs before loading it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavestateHeader {
+    pub version: usize,
+    pub map_name: String,
+    pub edits_name: String,
+    // Edits with the same name might have changed since the savestate was made
+    pub edits_hash: u64,
+}
+
+impl SavestateHeader {
+    pub fn new(sim: &Sim, map: &Map) -> SavestateHeader {
+        SavestateHeader {
+            version: SAVESTATE_VERSION,
+            map_name: sim.map_name.clone(),
+            edits_name: sim.edits_name.clone(),
+            edits_hash: hash_edits(map),
+        }
+    }
+
+    // Can a savestate with this header be loaded on top of this map?
+    pub fn check(&self, map: &Map) -> Result<(), String> {
+        if self.version > SAVESTATE_VERSION {
+            return Err(format!(
+                "Savestate is version {}, but only up to {} is supported",
+                self.version, SAVESTATE_VERSION
+            ));
+        }
+        if &self.map_name != map.get_name() {
+            return Err(format!(
+                "Savestate is for map {}, not {}",
+                self.map_name,
+                map.get_name()
+            ));
+        }
+        if self.edits_name != map.get_edits().edits_name {
+            return Err(format!(
+                "Savestate is for edits {}, not {}",
+                self.edits_name,
+                map.get_edits().edits_name
+            ));
+        }
+        if self.edits_hash != hash_edits(map) {
+            return Err(format!(
+                "Edits {} have changed since the savestate was made",
+                self.edits_name
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub fn write_savestate(path: &str, header: &SavestateHeader, sim: &Sim) -> Result<(), String> {
+    std::fs::create_dir_all(std::path::Path::new(path).parent().unwrap())
+        .map_err(|err| err.to_string())?;
+    let mut file = BufWriter::new(File::create(path).map_err(|err| err.to_string())?);
+    file.write_all(MAGIC).map_err(|err| err.to_string())?;
+    bincode::serialize_into(&mut file, header).map_err(|err| err.to_string())?;
+    bincode::serialize_into(&mut file, sim).map_err(|err| err.to_string())
+}
+
+// Leaves the reader positioned at the start of the Sim. Also returns the size of the file.
+fn open(path: &str) -> Result<(SavestateHeader, BufReader<File>, u64), String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let file_size = file.metadata().map_err(|err| err.to_string())?.len();
+    let mut reader = BufReader::new(file);
+    let mut magic = [0; 8];
+    if reader.read_exact(&mut magic).is_err() || &magic != MAGIC {
+        return Err(format!(
+            "{} predates versioned savestates, so its layout is unknown. Make a new one.",
+            path
+        ));
+    }
+    let header: SavestateHeader = limited(MAX_HEADER_BYTES)
+        .deserialize_from(&mut reader)
+        .map_err(|err| format!("{} has a corrupt header: {}", path, err))?;
+    Ok((header, reader, file_size))
+}
+
+pub fn read_header(path: &str) -> Result<SavestateHeader, String> {
+    Ok(open(path)?.0)
+}
+
+pub fn read_savestate(path: &str, map: &Map) -> Result<Sim, String> {
+    let (header, reader, file_size) = open(path)?;
+    header.check(map)?;
+    // Nothing in the Sim can be bigger than the whole file, so a corrupt length can't make bincode
+    // allocate something huge.
+    read_sim(header.version, file_size, reader)
+        .map_err(|err| format!("Couldn't read {}: {}", path, err))
+}
+
+// The migration hook. Fields can't be skipped or defaulted in bincode, so when the layout changes,
+// keep a frozen copy of the old structs around, read that, and fill in anything new.
+fn read_sim<R: Read>(version: usize, limit: u64, reader: R) -> Result<Sim, String> {
+    match version {
+        1 => limited(limit)
+            .deserialize_from::<_, SimV1>(reader)
+            .map(Sim::from)
+            .map_err(|err| err.to_string()),
+        2 => limited(limit)
+            .deserialize_from(reader)
+            .map_err(|err| err.to_string()),
+        _ => Err(format!("Savestate header claims version {}", version)),
+    }
+}
+
+// Version 2 started tracking which round of live edits each car last checked its path against.
+#[derive(Deserialize)]
+pub struct SimV1 {
+    pub(crate) driving: DrivingSimStateV1,
+    pub(crate) parking: ParkingSimState,
+    pub(crate) walking: WalkingSimState,
+    pub(crate) intersections: IntersectionSimState,
+    pub(crate) transit: TransitSimState,
+    pub(crate) trips: TripManager,
+    pub(crate) spawner: TripSpawner,
+    pub(crate) scheduler: Scheduler,
+    pub(crate) time: Time,
+    pub(crate) car_id_counter: usize,
+    pub(crate) ped_id_counter: usize,
+    pub(crate) map_name: String,
+    pub(crate) edits_name: String,
+    pub(crate) run_name: String,
+    pub(crate) step_count: usize,
+}
+
+// The same encoding bincode::serialize_into uses, but refusing to read more than some number of
+// bytes.
+fn limited(limit: u64) -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit)
+}
+
+// Summarizes the effects of the map's edits, not the list of commands that produced them, so
+// re-saving the same edits doesn't change it. FNV-1a, since std's hasher isn't stable across
+// releases.
+fn hash_edits(map: &Map) -> u64 {
+    let edits = map.get_edits();
+    let mut lines = Vec::new();
+    for l in edits.original_lts.keys() {
+        lines.push(format!("{} {:?}", l, map.get_l(*l).lane_type));
+    }
+    for l in &edits.reversed_lanes {
+        lines.push(format!("{} to {}", l, map.get_l(*l).dst_i));
+    }
+    for l in edits.original_parking_policies.keys() {
+        lines.push(format!("{} {:?}", l, map.get_l(*l).parking_policy));
+    }
+    for b in edits.original_offstreet_policies.keys() {
+        lines.push(format!("{} {:?}", b, map.get_b(*b).parking));
+    }
+    for i in &edits.changed_intersections {
+        lines.push(match map.get_i(*i).intersection_type {
+            IntersectionType::StopSign => format!("{:?}", map.get_stop_sign(*i)),
+            IntersectionType::TrafficSignal => format!("{:?}", map.get_traffic_signal(*i)),
+            it => format!("{} {:?}", i, it),
+        });
+    }
+
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in lines.join("\n").bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
@@ -17,6 +17,8 @@ pub enum Command {
     UpdatePed(PedestrianID),
     UpdateIntersection(IntersectionID),
     Savestate(Duration),
+    // Look for a gap in an adjacent lane again
+    RetryLaneChange(CarID),
 }
 
 impl Command {
@@ -36,6 +38,7 @@ impl Command {
             Command::UpdatePed(id) => CommandType::Ped(*id),
             Command::UpdateIntersection(id) => CommandType::Intersection(*id),
             Command::Savestate(_) => CommandType::Savestate,
+            Command::RetryLaneChange(id) => CommandType::CarLaneChange(*id),
         }
     }
 }
@@ -49,6 +52,7 @@ pub enum CommandType {
     Ped(PedestrianID),
     Intersection(IntersectionID),
     Savestate,
+    CarLaneChange(CarID),
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -152,6 +156,16 @@ impl Scheduler {
         });
     }
 
+    // Like update, but if the command is already queued earlier, leaves it alone.
+    pub fn update_if_sooner(&mut self, new_time: Time, cmd: Command) {
+        if let Some((_, existing_time)) = self.queued_commands.get(&cmd.to_type()) {
+            if *existing_time <= new_time {
+                return;
+            }
+        }
+        self.update(new_time, cmd);
+    }
+
     pub fn cancel(&mut self, cmd: Command) {
         // It's fine if a previous command hasn't actually been scheduled.
         self.queued_commands.remove(&cmd.to_type());
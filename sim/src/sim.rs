@@ -1,17 +1,19 @@
+use crate::savestate::{self, SimV1};
 use crate::{
     AgentID, AgentMetadata, Analytics, CarID, Command, CreateCar, DrawCarInput, DrawPedCrowdInput,
-    DrawPedestrianInput, DrivingGoal, DrivingSimState, Event, GetDrawAgents, IntersectionSimState,
-    ParkedCar, ParkingSimState, ParkingSpot, PedestrianID, Router, Scheduler, SidewalkPOI,
-    SidewalkSpot, TransitSimState, TripCount, TripEnd, TripID, TripLeg, TripManager, TripMode,
-    TripPositions, TripResult, TripSpawner, TripSpec, TripStart, UnzoomedAgent, VehicleSpec,
-    VehicleType, WalkingSimState, BUS_LENGTH,
+    DrawPedestrianInput, DrivingGoal, DrivingSimState, Event, EventSink, EventSinks, GetDrawAgents,
+    IntersectionSimState, ParkedCar, ParkingSimState, ParkingSpot, PedestrianID, Router,
+    SavestateHeader, Scheduler, SidewalkPOI, SidewalkSpot, TransitSimState, TripCount, TripEnd,
+    TripID, TripLeg, TripManager, TripMode, TripPositions, TripResult, TripSpawner, TripSpec,
+    TripStart, UnzoomedAgent, VehicleSpec, VehicleType, WalkingSimState, BUS_ACCEL, BUS_CAPACITY,
+    BUS_DECEL, BUS_LENGTH,
 };
 use abstutil::Timer;
 use derivative::Derivative;
 use geom::{Distance, Duration, PolyLine, Pt2D, Time};
 use map_model::{
     BuildingID, BusRoute, BusRouteID, IntersectionID, LaneID, Map, Path, PathConstraints,
-    PathRequest, PathStep, Traversable,
+    PathRequest, PathStep, Position, Traversable,
 };
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashSet};
@@ -20,6 +22,8 @@ use std::time::Instant;
 
 // TODO Do something else.
 const BLIND_RETRY_TO_SPAWN: Duration = Duration::const_seconds(5.0);
+// Timetabled buses appear this long before their first departure.
+const TIME_TO_REACH_FIRST_STOP: Duration = Duration::const_seconds(60.0);
 
 #[derive(Serialize, Deserialize, Clone, Derivative)]
 #[derivative(PartialEq)]
@@ -55,6 +59,10 @@ pub struct Sim {
     #[derivative(PartialEq = "ignore")]
     #[serde(skip_serializing, skip_deserializing)]
     analytics: Analytics,
+
+    #[derivative(PartialEq = "ignore")]
+    #[serde(skip_serializing, skip_deserializing)]
+    event_sinks: EventSinks,
 }
 
 #[derive(Clone)]
@@ -64,6 +72,15 @@ pub struct SimOptions {
     pub use_freeform_policy_everywhere: bool,
     pub disable_block_the_box: bool,
     pub recalc_lanechanging: bool,
+    // Model acceleration and braking, instead of instantly moving at the speed limit. Off by
+    // default, so older results can be reproduced.
+    pub use_acceleration: bool,
+    // Let cars stuck behind somebody shift to an adjacent lane partway along a road, not just
+    // pick a lane at intersections.
+    pub mid_block_lanechanging: bool,
+    // Cars blocked at the end of a lane for this long try to find a way around the congestion
+    // from their next intersection.
+    pub reroute_after_blocked: Option<Duration>,
 }
 
 impl SimOptions {
@@ -74,6 +91,9 @@ impl SimOptions {
             use_freeform_policy_everywhere: false,
             disable_block_the_box: false,
             recalc_lanechanging: true,
+            use_acceleration: false,
+            mid_block_lanechanging: false,
+            reroute_after_blocked: None,
         }
     }
 }
@@ -86,7 +106,13 @@ impl Sim {
             scheduler.push(Time::START_OF_DAY + d, Command::Savestate(d));
         }
         Sim {
-            driving: DrivingSimState::new(map, opts.recalc_lanechanging),
+            driving: DrivingSimState::new(
+                map,
+                opts.recalc_lanechanging,
+                opts.use_acceleration,
+                opts.mid_block_lanechanging,
+                opts.reroute_after_blocked,
+            ),
             parking: ParkingSimState::new(map, timer),
             walking: WalkingSimState::new(),
             intersections: IntersectionSimState::new(
@@ -111,6 +137,7 @@ impl Sim {
             trip_positions: None,
 
             analytics: Analytics::new(),
+            event_sinks: EventSinks::default(),
         }
     }
 
@@ -141,12 +168,22 @@ impl Sim {
             TripSpec::UsingParkedCar { .. }
             | TripSpec::MaybeUsingParkedCar { .. }
             | TripSpec::JustWalking { .. }
-            | TripSpec::UsingTransit { .. } => {
+            | TripSpec::UsingTransit { .. }
+            | TripSpec::ParkAndRide { .. } => {
                 let id = PedestrianID(self.ped_id_counter);
                 self.ped_id_counter += 1;
                 (Some(id), None)
             }
-            TripSpec::UsingBike { .. } => {
+            TripSpec::ParkAndRideFromBorder {
+                ref vehicle_spec, ..
+            } => {
+                let ped = PedestrianID(self.ped_id_counter);
+                self.ped_id_counter += 1;
+                let car = CarID(self.car_id_counter, vehicle_spec.vehicle_type);
+                self.car_id_counter += 1;
+                (Some(ped), Some(car))
+            }
+            TripSpec::UsingBike { .. } | TripSpec::BikeAndRide { .. } => {
                 let ped = PedestrianID(self.ped_id_counter);
                 self.ped_id_counter += 1;
                 let car = CarID(self.car_id_counter, VehicleType::Bike);
@@ -169,6 +206,7 @@ impl Sim {
             timer,
             retry_if_no_room,
         );
+        self.parking.set_stays(self.trips.parking_stays());
     }
 
     pub fn get_free_spots(&self, l: LaneID) -> Vec<ParkingSpot> {
@@ -226,6 +264,9 @@ impl Sim {
                 vehicle_type: VehicleType::Bus,
                 length: BUS_LENGTH,
                 max_speed: None,
+                max_accel: BUS_ACCEL,
+                max_decel: BUS_DECEL,
+                passenger_capacity: Some(BUS_CAPACITY),
             }
             .make(id, None);
 
@@ -234,7 +275,7 @@ impl Sim {
             // Same for this TripStart, though it doesn't matter too much.
             let trip = self.trips.new_trip(
                 self.time,
-                TripStart::Border(map.get_l(path.current_step().as_lane()).src_i),
+                bus_trip_start(path.current_step().as_lane(), route.id, map),
                 vec![TripLeg::ServeBusRoute(id, route.id)],
             );
 
@@ -251,12 +292,10 @@ impl Sim {
                     l
                 } else {
                     path.shift(map);
-                    // TODO Technically should update request, but it shouldn't matter
                     continue;
                 };
                 if map.get_l(start_lane).length() < vehicle.length {
                     path.shift(map);
-                    // TODO Technically should update request, but it shouldn't matter
                     continue;
                 }
 
@@ -266,7 +305,11 @@ impl Sim {
                     CreateCar {
                         start_dist: vehicle.length,
                         vehicle: vehicle.clone(),
-                        req: req.clone(),
+                        req: PathRequest {
+                            start: Position::new(start_lane, vehicle.length),
+                            end: req.end,
+                            constraints: req.constraints,
+                        },
                         router: Router::follow_bus_route(path.clone(), end_dist),
                         maybe_parked_car: None,
                         trip,
@@ -277,7 +320,12 @@ impl Sim {
                     &mut self.scheduler,
                 ) {
                     self.trips.agent_starting_trip_leg(AgentID::Car(id), trip);
-                    self.transit.bus_created(id, route.id, next_stop_idx);
+                    self.transit.bus_created(
+                        id,
+                        route.id,
+                        next_stop_idx,
+                        vehicle.passenger_capacity.unwrap(),
+                    );
                     self.analytics.record_demand(&path, map);
                     results.push(id);
                     return results;
@@ -293,11 +341,140 @@ impl Sim {
         results
     }
 
+    // Dispatch one bus for every trip in the route's timetable, appearing a little before its first
+    // stop. Returns the buses, though they won't exist until later.
+    pub fn schedule_bus_route(&mut self, route: &BusRoute, map: &Map) -> Vec<CarID> {
+        let stops = self.transit.create_empty_route(route, map);
+        let mut results: Vec<CarID> = Vec::new();
+
+        for schedule in &route.schedule {
+            let (first_stop, first_departure) = schedule[0];
+            if first_departure < self.time {
+                continue;
+            }
+
+            let id = CarID(self.car_id_counter, VehicleType::Bus);
+            let vehicle = VehicleSpec {
+                vehicle_type: VehicleType::Bus,
+                length: BUS_LENGTH,
+                max_speed: None,
+                max_accel: BUS_ACCEL,
+                max_decel: BUS_DECEL,
+                passenger_capacity: Some(BUS_CAPACITY),
+            }
+            .make(id, None);
+
+            // Approach the first stop from the previous one on the route, if there is one.
+            let (req, mut path, end_dist) = if let Some((_, req, path, end_dist)) =
+                stops.iter().find(|(next, _, _, _)| *next == first_stop)
+            {
+                (req.clone(), path.clone(), *end_dist)
+            } else if let Some((req, path)) =
+                approach_bus_stop(map.get_bs(route.stops[first_stop]).driving_pos, map)
+            {
+                let end_dist = req.end.dist_along();
+                (req, path, end_dist)
+            } else {
+                continue;
+            };
+            self.car_id_counter += 1;
+
+            // Ideally start on the same lane as the stop. Otherwise, start from the beginning of
+            // the path, on the first lane long enough.
+            if end_dist >= vehicle.length {
+                while !path.is_last_step() {
+                    path.shift(map);
+                }
+            } else {
+                while !path.is_last_step() {
+                    if let PathStep::Lane(l) = path.current_step() {
+                        if map.get_l(l).length() >= vehicle.length {
+                            break;
+                        }
+                    }
+                    path.shift(map);
+                }
+            }
+            let start_lane = path.current_step().as_lane();
+            let req = PathRequest {
+                start: Position::new(start_lane, vehicle.length),
+                end: req.end,
+                constraints: req.constraints,
+            };
+
+            let trip = self.trips.new_trip(
+                first_departure,
+                bus_trip_start(start_lane, route.id, map),
+                vec![TripLeg::ServeBusRoute(id, route.id)],
+            );
+            self.transit.bus_scheduled(
+                id,
+                route.id,
+                schedule.clone(),
+                vehicle.passenger_capacity.unwrap(),
+            );
+            self.scheduler.push(
+                first_departure
+                    .clamped_sub(TIME_TO_REACH_FIRST_STOP)
+                    .max(self.time),
+                Command::SpawnCar(
+                    CreateCar {
+                        start_dist: vehicle.length,
+                        vehicle,
+                        req,
+                        router: Router::follow_bus_route(path, end_dist),
+                        maybe_parked_car: None,
+                        trip,
+                    },
+                    true,
+                ),
+            );
+            results.push(id);
+        }
+        results
+    }
+
     pub fn set_name(&mut self, name: String) {
         self.run_name = name;
     }
 }
 
+// Only buses coming in from the edge of the map really start at a border.
+fn bus_trip_start(start: LaneID, route: BusRouteID, map: &Map) -> TripStart {
+    let i = map.get_l(start).src_i;
+    if map.get_i(i).is_border() {
+        TripStart::Border(i)
+    } else {
+        TripStart::ServeBusRoute(route)
+    }
+}
+
+// For routes that don't loop, there's no previous stop to start from. Come from the beginning of
+// the stop's lane, or failing that, a lane leading into it.
+fn approach_bus_stop(stop: Position, map: &Map) -> Option<(PathRequest, Path)> {
+    let mut starts = Vec::new();
+    if stop.dist_along() >= BUS_LENGTH {
+        starts.push(Position::new(stop.lane(), BUS_LENGTH));
+    }
+    for turn in map.get_turns_to_lane(stop.lane()) {
+        let src = map.get_l(turn.id.src);
+        if src.length() >= BUS_LENGTH {
+            starts.push(Position::new(src.id, BUS_LENGTH));
+        }
+    }
+    for start in starts {
+        let req = PathRequest {
+            start,
+            end: stop,
+            constraints: PathConstraints::Bus,
+        };
+        if let Some(path) = map.pathfind(req.clone()) {
+            return Some((req, path));
+        }
+    }
+    None
+}
+
 // Drawing
 impl GetDrawAgents for Sim {
     fn time(&self) -> Time {
@@ -372,7 +549,7 @@ impl Sim {
             if let Some(t) = savestate_at {
                 if time > t {
                     self.time = t;
-                    self.save();
+                    self.save(map);
                     savestate_at = None;
                 }
             }
@@ -396,6 +573,10 @@ impl Sim {
                         if let Some(parked_car) = create_car.maybe_parked_car {
                             self.parking.remove_parked_car(parked_car);
                         }
+                        // Only timetabled buses are spawned this way.
+                        if create_car.vehicle.vehicle_type == VehicleType::Bus {
+                            self.transit.bus_spawned(create_car.vehicle.id);
+                        }
                         events.push(Event::TripPhaseStarting(
                             create_car.trip,
                             Some(create_car.req.clone()),
@@ -431,28 +612,16 @@ impl Sim {
                             };
                             if let Some(path) = map.pathfind(create_ped.req.clone()) {
                                 create_ped.path = path;
-                                let mut legs = vec![
+                                let legs = vec![
                                     TripLeg::Walk(
                                         create_ped.id,
                                         create_ped.speed,
                                         create_ped.goal.clone(),
                                     ),
-                                    TripLeg::Drive(
-                                        parked_car.vehicle.clone(),
-                                        driving_goal.clone(),
-                                    ),
+                                    TripLeg::Drive(parked_car.vehicle.clone(), driving_goal),
                                 ];
-                                match driving_goal {
-                                    DrivingGoal::ParkNear(b) => {
-                                        legs.push(TripLeg::Walk(
-                                            create_ped.id,
-                                            create_ped.speed,
-                                            SidewalkSpot::building(b, map),
-                                        ));
-                                    }
-                                    DrivingGoal::Border(_, _) => {}
-                                }
-                                self.trips.dynamically_override_legs(create_ped.trip, legs);
+                                self.trips
+                                    .dynamically_override_legs(create_ped.trip, legs, map);
                                 true
                             } else {
                                 println!(
@@ -541,6 +710,15 @@ impl Sim {
                         &mut self.scheduler,
                     );
                 }
+                Command::RetryLaneChange(car) => {
+                    self.driving.try_mid_block_lanechange(
+                        car,
+                        self.time,
+                        map,
+                        &mut self.intersections,
+                        &mut self.scheduler,
+                    );
+                }
                 Command::UpdatePed(ped) => {
                     self.walking.update_ped(
                         ped,
@@ -572,12 +750,13 @@ impl Sim {
             events.extend(self.walking.collect_events());
             events.extend(self.intersections.collect_events());
             for ev in events {
+                self.event_sinks.event(self.time, &ev);
                 self.analytics.event(ev, self.time, map);
             }
         }
         if let Some(t) = savestate_at {
             self.time = t;
-            self.save();
+            self.save(map);
         }
         self.time = target_time;
 
@@ -615,6 +794,22 @@ impl Sim {
         }
     }
 
+    // The map was edited without restarting the sim.
+    pub fn handle_live_edits(&mut self, map: &Map) {
+        self.driving.handle_live_edits();
+        self.intersections
+            .handle_live_edits(self.time, map, &mut self.scheduler);
+    }
+
+    // Every event from now on is also passed to this sink.
+    pub fn add_event_sink(&mut self, sink: Box<dyn EventSink>) {
+        self.event_sinks.add(sink);
+    }
+
+    pub fn flush_event_sinks(&mut self) {
+        self.event_sinks.flush();
+    }
+
     pub fn dump_before_abort(&self) {
         println!(
             "********************************************************************************"
@@ -727,7 +922,7 @@ impl Sim {
         )
     }
 
-    pub fn save(&mut self) -> String {
+    pub fn save(&mut self, map: &Map) -> String {
         let restore = self.scheduler.before_savestate();
 
         if true {
@@ -767,7 +962,11 @@ impl Sim {
         }
 
         let path = self.save_path(self.time);
-        abstutil::write_binary(path.clone(), self);
+        if let Err(err) = savestate::write_savestate(&path, &SavestateHeader::new(self, map), self)
+        {
+            panic!("Can't write savestate {}: {}", path, err);
+        }
+        println!("Wrote {}", path);
 
         self.scheduler.after_savestate(restore);
 
@@ -782,16 +981,22 @@ impl Sim {
         abstutil::find_next_file(self.save_path(base_time))
     }
 
-    pub fn load_savestate(
-        path: String,
-        map: &Map,
-        timer: &mut Timer,
-    ) -> Result<Sim, std::io::Error> {
-        let mut sim: Sim = abstutil::maybe_read_binary(path, timer)?;
+    // Refuses savestates made for a different map or edits, or by a newer version.
+    pub fn load_savestate(path: String, map: &Map, timer: &mut Timer) -> Result<Sim, String> {
+        timer.start(format!("read savestate {}", path));
+        let result = savestate::read_savestate(&path, map);
+        timer.stop(format!("read savestate {}", path));
+        let mut sim = result?;
         sim.restore_paths(map, timer);
+        sim.parking.set_stays(sim.trips.parking_stays());
         Ok(sim)
     }
 
+    // Cheap; only reads the start of the file.
+    pub fn read_savestate_header(path: &str) -> Result<SavestateHeader, String> {
+        savestate::read_header(path)
+    }
+
     pub fn restore_paths(&mut self, map: &Map, timer: &mut Timer) {
         let paths = timer.parallelize(
             "calculate paths",
@@ -802,6 +1007,32 @@ impl Sim {
     }
 }
 
+impl From<SimV1> for Sim {
+    fn from(old: SimV1) -> Sim {
+        Sim {
+            driving: old.driving.into(),
+            parking: old.parking,
+            walking: old.walking,
+            intersections: old.intersections,
+            transit: old.transit,
+            trips: old.trips,
+            spawner: old.spawner,
+            scheduler: old.scheduler,
+            time: old.time,
+            car_id_counter: old.car_id_counter,
+            ped_id_counter: old.ped_id_counter,
+            map_name: old.map_name,
+            edits_name: old.edits_name,
+            run_name: old.run_name,
+            step_count: old.step_count,
+            trip_positions: None,
+            // What deserializing a Sim would produce
+            analytics: Analytics::default(),
+            event_sinks: EventSinks::default(),
+        }
+    }
+}
+
 // Queries of all sorts
 impl Sim {
     pub fn time(&self) -> Time {
@@ -992,6 +1223,16 @@ impl Sim {
         self.intersections.get_accepted_agents(id)
     }
 
+    // For traffic signals
+    pub fn current_phase_and_remaining_time(
+        &self,
+        id: IntersectionID,
+        map: &Map,
+    ) -> (usize, Duration) {
+        self.intersections
+            .current_phase_and_remaining_time(self.time, id, map)
+    }
+
     pub fn location_of_buses(&self, route: BusRouteID, map: &Map) -> Vec<(CarID, Pt2D)> {
         let mut results = Vec::new();
         for (car, _) in self.transit.buses_for_route(route) {
@@ -1,11 +1,19 @@
 use crate::{CarID, Event, PedestrianID, Router, Scheduler, TripManager, WalkingSimState};
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::{Distance, Time};
+use geom::{Distance, Duration, Time};
 use map_model::{
-    BusRoute, BusRouteID, BusStopID, Map, Path, PathConstraints, PathRequest, Position,
+    BusRoute, BusRouteID, BusStopID, IntersectionID, Map, Path, PathConstraints, PathRequest,
+    Position,
 };
 use serde_derive::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
+
+// How long a bus waits at the end of a route before turning around
+const LAYOVER_TIME: Duration = Duration::const_seconds(300.0);
+// Dwell time at a stop is a fixed minimum, plus some time for each passenger getting on or off.
+const TIME_TO_WAIT_AT_STOP: Duration = Duration::const_seconds(10.0);
+const TIME_PER_BOARDING: Duration = Duration::const_seconds(3.0);
+const TIME_PER_ALIGHTING: Duration = Duration::const_seconds(2.0);
 
 // These index stops along a route, not stops along a single sidewalk.
 type StopIdx = usize;
@@ -14,15 +22,16 @@ type StopIdx = usize;
 struct StopForRoute {
     id: BusStopID,
     driving_pos: Position,
-    req: PathRequest,
-    path_to_next_stop: Path,
-    next_stop_idx: StopIdx,
+    // (next stop, request, path). None for the last stop of a route that doesn't loop.
+    next: Option<(StopIdx, PathRequest, Path)>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 struct Route {
     stops: Vec<StopForRoute>,
     buses: Vec<CarID>,
+    is_loop: bool,
+    opposite_route: Option<BusRouteID>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
@@ -31,13 +40,36 @@ struct Bus {
     route: BusRouteID,
     // Where does each passenger want to deboard?
     passengers: Vec<(PedestrianID, BusStopID)>,
+    capacity: usize,
     state: BusState,
+    // For buses running one timetabled trip, the remaining (stop, departure time). The bus
+    // leaves the map after the last one.
+    schedule: Option<VecDeque<(StopIdx, Time)>>,
+}
+
+impl Bus {
+    fn is_full(&self) -> bool {
+        self.passengers.len() >= self.capacity
+    }
+
+    // Will the bus stop at some point after the current stop?
+    fn will_stop_at(&self, route: &Route, current: StopIdx, stop: BusStopID) -> bool {
+        match self.schedule {
+            Some(ref schedule) => schedule.iter().any(|(idx, _)| route.stops[*idx].id == stop),
+            None => route.is_loop || route.stops[current + 1..].iter().any(|s| s.id == stop),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 enum BusState {
     DrivingToStop(StopIdx),
     AtStop(StopIdx),
+    // Done with the route. After waiting, follow the router to the first stop of another route,
+    // or to a border.
+    AtTerminus(StopIdx, Router, Option<BusRouteID>),
+    // Not serving any route; just driving off the map
+    Leaving,
 }
 
 // This kind of acts like TripManager, managing transitions... but a bit more statefully.
@@ -48,6 +80,12 @@ pub struct TransitSimState {
         deserialize_with = "deserialize_btreemap"
     )]
     buses: BTreeMap<CarID, Bus>,
+    // Timetabled buses that haven't spawned yet
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pending_buses: BTreeMap<CarID, Bus>,
     #[serde(
         serialize_with = "serialize_btreemap",
         deserialize_with = "deserialize_btreemap"
@@ -67,6 +105,7 @@ impl TransitSimState {
     pub fn new() -> TransitSimState {
         TransitSimState {
             buses: BTreeMap::new(),
+            pending_buses: BTreeMap::new(),
             routes: BTreeMap::new(),
             peds_waiting: BTreeMap::new(),
             events: Vec::new(),
@@ -74,7 +113,7 @@ impl TransitSimState {
     }
 
     // Returns (next stop, first path, end distance for next stop) for all of the stops in the
-    // route.
+    // route that lead somewhere.
     pub fn create_empty_route(
         &mut self,
         bus_route: &BusRoute,
@@ -84,32 +123,37 @@ impl TransitSimState {
 
         let route = Route {
             buses: Vec::new(),
+            is_loop: bus_route.is_loop,
+            opposite_route: bus_route.opposite_route,
             stops: bus_route
                 .stops
                 .iter()
                 .enumerate()
                 .map(|(idx, stop1_id)| {
                     let stop1 = map.get_bs(*stop1_id);
-                    let stop2_idx = if idx + 1 == bus_route.stops.len() {
-                        0
+                    let stop2_idx = if idx + 1 < bus_route.stops.len() {
+                        Some(idx + 1)
+                    } else if bus_route.is_loop {
+                        Some(0)
                     } else {
-                        idx + 1
+                        None
                     };
-                    let req = PathRequest {
-                        start: stop1.driving_pos,
-                        end: map.get_bs(bus_route.stops[stop2_idx]).driving_pos,
-                        constraints: PathConstraints::Bus,
-                    };
-                    let path = map.pathfind(req.clone()).expect(&format!(
-                        "No route between bus stops {:?} and {:?}",
-                        stop1_id, bus_route.stops[stop2_idx]
-                    ));
+                    let next = stop2_idx.map(|stop2_idx| {
+                        let req = PathRequest {
+                            start: stop1.driving_pos,
+                            end: map.get_bs(bus_route.stops[stop2_idx]).driving_pos,
+                            constraints: PathConstraints::Bus,
+                        };
+                        let path = map.pathfind(req.clone()).expect(&format!(
+                            "No route between bus stops {:?} and {:?}",
+                            stop1_id, bus_route.stops[stop2_idx]
+                        ));
+                        (stop2_idx, req, path)
+                    });
                     StopForRoute {
                         id: *stop1_id,
                         driving_pos: stop1.driving_pos,
-                        req,
-                        path_to_next_stop: path,
-                        next_stop_idx: stop2_idx,
+                        next,
                     }
                 })
                 .collect(),
@@ -118,20 +162,27 @@ impl TransitSimState {
         let stops = route
             .stops
             .iter()
-            .map(|s| {
-                (
-                    s.next_stop_idx,
-                    s.req.clone(),
-                    s.path_to_next_stop.clone(),
-                    route.stops[s.next_stop_idx].driving_pos.dist_along(),
-                )
+            .filter_map(|s| {
+                let (next_idx, req, path) = s.next.clone()?;
+                Some((
+                    next_idx,
+                    req,
+                    path,
+                    route.stops[next_idx].driving_pos.dist_along(),
+                ))
             })
             .collect();
         self.routes.insert(bus_route.id, route);
         stops
     }
 
-    pub fn bus_created(&mut self, bus: CarID, route: BusRouteID, next_stop_idx: StopIdx) {
+    pub fn bus_created(
+        &mut self,
+        bus: CarID,
+        route: BusRouteID,
+        next_stop_idx: StopIdx,
+        capacity: usize,
+    ) {
         self.routes.get_mut(&route).unwrap().buses.push(bus);
         self.buses.insert(
             bus,
@@ -139,11 +190,42 @@ impl TransitSimState {
                 car: bus,
                 route,
                 passengers: Vec::new(),
+                capacity,
                 state: BusState::DrivingToStop(next_stop_idx),
+                schedule: None,
             },
         );
     }
 
+    // The bus will spawn later, right before the first stop in its schedule.
+    pub fn bus_scheduled(
+        &mut self,
+        bus: CarID,
+        route: BusRouteID,
+        schedule: Vec<(StopIdx, Time)>,
+        capacity: usize,
+    ) {
+        self.pending_buses.insert(
+            bus,
+            Bus {
+                car: bus,
+                route,
+                passengers: Vec::new(),
+                capacity,
+                state: BusState::DrivingToStop(schedule[0].0),
+                schedule: Some(VecDeque::from(schedule)),
+            },
+        );
+    }
+
+    pub fn bus_spawned(&mut self, bus: CarID) {
+        let bus = self.pending_buses.remove(&bus).unwrap();
+        self.routes.get_mut(&bus.route).unwrap().buses.push(bus.car);
+        self.buses.insert(bus.car, bus);
+    }
+
+    // Returns the earliest time the bus may leave the stop, after passengers get on and off, or None
+    // if the bus is done and should vanish right here.
     pub fn bus_arrived_at_stop(
         &mut self,
         now: Time,
@@ -152,7 +234,7 @@ impl TransitSimState {
         walking: &mut WalkingSimState,
         scheduler: &mut Scheduler,
         map: &Map,
-    ) {
+    ) -> Option<Time> {
         let mut bus = self.buses.get_mut(&id).unwrap();
         match bus.state {
             BusState::DrivingToStop(stop_idx) => {
@@ -161,25 +243,88 @@ impl TransitSimState {
                 self.events
                     .push(Event::BusArrivedAtStop(id, bus.route, stop1));
 
+                // Don't leave before the timetable says to.
+                let mut depart_at = now;
+                let mut finished = self.routes[&bus.route].stops[stop_idx].next.is_none();
+                if let Some(ref mut schedule) = bus.schedule {
+                    if schedule
+                        .front()
+                        .map(|(idx, _)| *idx == stop_idx)
+                        .unwrap_or(false)
+                    {
+                        let (_, t) = schedule.pop_front().unwrap();
+                        self.events
+                            .push(Event::BusScheduledArrival(id, bus.route, stop1, t));
+                        depart_at = t;
+                    }
+                    finished = schedule.is_empty();
+                }
+
                 // Deboard existing passengers.
                 let mut still_riding = Vec::new();
+                let mut dwell = TIME_TO_WAIT_AT_STOP;
                 for (ped, stop2) in bus.passengers.drain(..) {
                     if stop1 == stop2 {
                         self.events.push(Event::PedLeavesBus(ped, id, bus.route));
                         trips.ped_left_bus(now, ped, map, scheduler);
+                        dwell = dwell + TIME_PER_ALIGHTING;
                     } else {
                         still_riding.push((ped, stop2));
                     }
                 }
                 bus.passengers = still_riding;
+                depart_at = depart_at.max(now + dwell);
+
+                if finished {
+                    assert!(bus.passengers.is_empty());
+                    let here = self.routes[&bus.route].stops[stop_idx].driving_pos;
+                    // Buses without a timetable keep serving the route in the other direction.
+                    // Timetabled trips are all separate buses, so just leave.
+                    let routes = &self.routes;
+                    let turn_around = if bus.schedule.is_none() {
+                        routes[&bus.route].opposite_route.and_then(|r| {
+                            let first_stop = &routes.get(&r)?.stops[0];
+                            path_to_stop(here, first_stop.driving_pos, map)
+                                .map(|router| (router, r))
+                        })
+                    } else {
+                        None
+                    };
+                    if let Some((router, next_route)) = turn_around {
+                        bus.state = BusState::AtTerminus(stop_idx, router, Some(next_route));
+                        return Some(depart_at.max(now + LAYOVER_TIME));
+                    }
+                    if let Some(router) = path_to_border(here, map) {
+                        bus.state = BusState::AtTerminus(stop_idx, router, None);
+                        return Some(depart_at);
+                    }
+
+                    let route = bus.route;
+                    self.buses.remove(&id);
+                    self.routes
+                        .get_mut(&route)
+                        .unwrap()
+                        .buses
+                        .retain(|b| *b != id);
+                    return None;
+                }
 
                 // Board new passengers.
                 let mut still_waiting = Vec::new();
                 for (ped, route, stop2, started_waiting) in
                     self.peds_waiting.remove(&stop1).unwrap_or_else(Vec::new)
                 {
-                    if bus.route == route {
+                    if bus.route != route
+                        || !bus.will_stop_at(&self.routes[&route], stop_idx, stop2)
+                    {
+                        still_waiting.push((ped, route, stop2, started_waiting));
+                    } else if bus.is_full() {
+                        self.events
+                            .push(Event::PedDeniedBoarding(ped, id, route, stop1));
+                        still_waiting.push((ped, route, stop2, started_waiting));
+                    } else {
                         bus.passengers.push((ped, stop2));
+                        dwell = dwell + TIME_PER_BOARDING;
                         self.events.push(Event::PedEntersBus(ped, id, route));
                         let trip = trips.ped_boarded_bus(ped, walking);
                         self.events.push(Event::TripPhaseStarting(
@@ -191,35 +336,76 @@ impl TransitSimState {
                             }),
                             format!("{} riding {}", ped, route),
                         ));
-                    } else {
-                        still_waiting.push((ped, route, stop2, started_waiting));
                     }
                 }
                 self.peds_waiting.insert(stop1, still_waiting);
+                Some(depart_at.max(now + dwell))
             }
-            BusState::AtStop(_) => unreachable!(),
-        };
+            BusState::AtStop(_) | BusState::AtTerminus(_, _, _) | BusState::Leaving => {
+                unreachable!()
+            }
+        }
     }
 
     pub fn bus_departed_from_stop(&mut self, id: CarID) -> Router {
         let mut bus = self.buses.get_mut(&id).unwrap();
-        match bus.state {
-            BusState::DrivingToStop(_) => unreachable!(),
+        match bus.state.clone() {
+            BusState::DrivingToStop(_) | BusState::Leaving => unreachable!(),
             BusState::AtStop(stop_idx) => {
                 let route = &self.routes[&bus.route];
                 let stop = &route.stops[stop_idx];
+                let (next_idx, _, path) = stop.next.as_ref().unwrap();
 
-                bus.state = BusState::DrivingToStop(stop.next_stop_idx);
+                bus.state = BusState::DrivingToStop(*next_idx);
                 self.events
                     .push(Event::BusDepartedFromStop(id, bus.route, stop.id));
                 Router::follow_bus_route(
-                    stop.path_to_next_stop.clone(),
-                    route.stops[stop.next_stop_idx].driving_pos.dist_along(),
+                    path.clone(),
+                    route.stops[*next_idx].driving_pos.dist_along(),
                 )
             }
+            BusState::AtTerminus(stop_idx, router, next_route) => {
+                let old_route = bus.route;
+                self.events.push(Event::BusDepartedFromStop(
+                    id,
+                    old_route,
+                    self.routes[&old_route].stops[stop_idx].id,
+                ));
+                self.routes
+                    .get_mut(&old_route)
+                    .unwrap()
+                    .buses
+                    .retain(|b| *b != id);
+                if let Some(r) = next_route {
+                    bus.route = r;
+                    bus.state = BusState::DrivingToStop(0);
+                    self.routes.get_mut(&r).unwrap().buses.push(id);
+                } else {
+                    bus.state = BusState::Leaving;
+                }
+                router
+            }
         }
     }
 
+    // The bus finished its service and left the map.
+    pub fn bus_reached_border(&mut self, id: CarID) {
+        let bus = self.buses.remove(&id).unwrap();
+        assert!(bus.state == BusState::Leaving);
+    }
+
+    // Live edits cut the bus off from where it was going, so it vanishes. Returns the passengers,
+    // who vanish with it.
+    pub fn bus_stranded(&mut self, id: CarID) -> Vec<PedestrianID> {
+        let bus = self.buses.remove(&id).unwrap();
+        self.routes
+            .get_mut(&bus.route)
+            .unwrap()
+            .buses
+            .retain(|b| *b != id);
+        bus.passengers.into_iter().map(|(ped, _)| ped).collect()
+    }
+
     // If true, the pedestrian boarded a bus immediately.
     pub fn ped_waiting_for_bus(
         &mut self,
@@ -233,7 +419,12 @@ impl TransitSimState {
         let route = &self.routes[&route_id];
         for bus in &route.buses {
             if let BusState::AtStop(idx) = self.buses[bus].state {
-                if route.stops[idx].id == stop1 {
+                if route.stops[idx].id == stop1 && self.buses[bus].will_stop_at(route, idx, stop2) {
+                    if self.buses[bus].is_full() {
+                        self.events
+                            .push(Event::PedDeniedBoarding(ped, *bus, route_id, stop1));
+                        continue;
+                    }
                     self.buses
                         .get_mut(bus)
                         .unwrap()
@@ -273,13 +464,14 @@ impl TransitSimState {
                 .map(|bus| {
                     let stop = match self.buses[bus].state {
                         BusState::DrivingToStop(idx) => {
-                            if idx == 0 {
+                            if idx == 0 && r.is_loop {
                                 r.stops.len() - 1
                             } else {
-                                idx - 1
+                                idx.saturating_sub(1)
                             }
                         }
-                        BusState::AtStop(idx) => idx,
+                        BusState::AtStop(idx) | BusState::AtTerminus(idx, _, _) => idx,
+                        BusState::Leaving => unreachable!(),
                     };
                     (*bus, stop)
                 })
@@ -289,3 +481,59 @@ impl TransitSimState {
         }
     }
 }
+
+fn path_to_stop(start: Position, end: Position, map: &Map) -> Option<Router> {
+    if start.lane() == end.lane() && start.dist_along() >= end.dist_along() {
+        // The stop is behind the bus, so it has to go around the block. Finish this lane, then let
+        // the pathfinder figure out the rest from whichever next lane is shortest.
+        let mut path = map.pathfind(PathRequest {
+            start,
+            end: Position::new(start.lane(), map.get_l(start.lane()).length()),
+            constraints: PathConstraints::Bus,
+        })?;
+        let (turn, rest) = map
+            .get_turns_for(start.lane(), PathConstraints::Bus)
+            .into_iter()
+            .filter_map(|turn| {
+                let rest = map.pathfind(PathRequest {
+                    start: Position::new(turn.id.dst, Distance::ZERO),
+                    end,
+                    constraints: PathConstraints::Bus,
+                })?;
+                Some((turn.id, rest))
+            })
+            .min_by_key(|(turn, rest)| map.get_t(*turn).geom.length() + rest.total_length())?;
+        path.replace_after_current_step(turn, rest, map);
+        return Some(Router::follow_bus_route(path, end.dist_along()));
+    }
+    let path = map.pathfind(PathRequest {
+        start,
+        end,
+        constraints: PathConstraints::Bus,
+    })?;
+    Some(Router::follow_bus_route(path, end.dist_along()))
+}
+
+// Head to the closest border that can be reached.
+fn path_to_border(start: Position, map: &Map) -> Option<Router> {
+    let pt = start.pt(map);
+    let mut borders: Vec<(Distance, IntersectionID)> = map
+        .all_outgoing_borders()
+        .into_iter()
+        .map(|i| (i.polygon.center().dist_to(pt), i.id))
+        .collect();
+    borders.sort_by_key(|(dist, _)| *dist);
+    for (_, i) in borders {
+        for l in map.get_i(i).get_incoming_lanes(map, PathConstraints::Bus) {
+            let end_dist = map.get_l(l).length();
+            if let Some(path) = map.pathfind(PathRequest {
+                start,
+                end: Position::new(l, end_dist),
+                constraints: PathConstraints::Bus,
+            }) {
+                return Some(Router::end_at_border(path, end_dist, i));
+            }
+        }
+    }
+    None
+}
@@ -4,7 +4,7 @@ use crate::{
     Vehicle, VehicleType, WalkingSimState,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
-use geom::{Speed, Time};
+use geom::{Duration, Speed, Time};
 use map_model::{
     BuildingID, BusRouteID, BusStopID, IntersectionID, Map, PathConstraints, PathRequest, Position,
 };
@@ -96,11 +96,25 @@ impl TripManager {
         id
     }
 
-    pub fn dynamically_override_legs(&mut self, id: TripID, legs: Vec<TripLeg>) {
+    // This is only for peds using a previously unknown parked car. The walk to the deferred
+    // parking spot is replaced by these legs. If nothing else was planned, walk from wherever the
+    // car parks to the destination. Otherwise keep the rest of the trip, like riding transit.
+    pub fn dynamically_override_legs(&mut self, id: TripID, legs: Vec<TripLeg>, map: &Map) {
         let trip = &mut self.trips[id.0];
+        let rest: Vec<TripLeg> = trip.legs.drain(..).skip(1).collect();
         trip.legs = VecDeque::from(legs);
-        // This is only for peds using a previously unknown parked car
+        if !rest.is_empty() {
+            trip.legs.extend(rest);
+            return;
+        }
         trip.mode = TripMode::Drive;
+        let walk_after = match (&trip.legs[0], &trip.legs[1]) {
+            (TripLeg::Walk(ped, speed, _), TripLeg::Drive(_, DrivingGoal::ParkNear(b))) => {
+                Some(TripLeg::Walk(*ped, *speed, SidewalkSpot::building(*b, map)))
+            }
+            _ => None,
+        };
+        trip.legs.extend(walk_after);
     }
 
     pub fn agent_starting_trip_leg(&mut self, agent: AgentID, trip: TripID) {
@@ -435,6 +449,18 @@ impl TripManager {
         ));
     }
 
+    // Buses vanish at a border after finishing their route, or in place if there's no way out.
+    pub fn bus_finished_service(&mut self, now: Time, bus: CarID) {
+        let trip = &mut self.trips[self.active_trip_mode.remove(&AgentID::Car(bus)).unwrap().0];
+        match trip.legs.pop_front().unwrap() {
+            TripLeg::ServeBusRoute(id, _) => assert_eq!(id, bus),
+            _ => unreachable!(),
+        };
+        assert!(trip.legs.is_empty());
+        trip.finished_at = Some(now);
+        self.num_bus_trips -= 1;
+    }
+
     pub fn abort_trip_failed_start(&mut self, id: TripID) {
         self.trips[id.0].aborted = true;
         if !self.trips[id.0].is_bus_trip() {
@@ -451,6 +477,18 @@ impl TripManager {
         self.events.push(Event::TripAborted(trip));
     }
 
+    // Live edits cut the agent off from its goal.
+    pub fn abort_trip_stranded(&mut self, agent: AgentID) {
+        let trip = self.active_trip_mode.remove(&agent).unwrap();
+        self.trips[trip.0].aborted = true;
+        if self.trips[trip.0].is_bus_trip() {
+            self.num_bus_trips -= 1;
+        } else {
+            self.unfinished_trips -= 1;
+        }
+        self.events.push(Event::TripAborted(trip));
+    }
+
     pub fn active_agents(&self) -> Vec<AgentID> {
         self.active_trip_mode.keys().cloned().collect()
     }
@@ -512,6 +550,52 @@ impl TripManager {
         self.unfinished_trips == 0
     }
 
+    // Drivers parking near a building stay until the next trip that leaves there in a parked car.
+    // Measured from the start of the trip, so it's a bit generous. Trips with nobody scheduled to
+    // leave afterwards are left out.
+    pub fn parking_stays(&self) -> BTreeMap<TripID, Duration> {
+        let mut departures: BTreeMap<BuildingID, Vec<Time>> = BTreeMap::new();
+        for trip in &self.trips {
+            if let (TripStart::Bldg(b), Some(TripLeg::Walk(_, _, ref spot))) =
+                (&trip.start, trip.legs.front())
+            {
+                match spot.connection {
+                    SidewalkPOI::ParkingSpot(_) | SidewalkPOI::DeferredParkingSpot(_, _) => {
+                        departures
+                            .entry(*b)
+                            .or_insert_with(Vec::new)
+                            .push(trip.spawned_at);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut stays = BTreeMap::new();
+        for trip in &self.trips {
+            if trip.finished_at.is_some() || trip.aborted {
+                continue;
+            }
+            // Before somebody finds their parked car, only the deferred spot knows where they're
+            // going.
+            let target = trip.legs.iter().find_map(|leg| match leg {
+                TripLeg::Drive(_, DrivingGoal::ParkNear(b)) => Some(*b),
+                TripLeg::Walk(_, _, ref spot) => match spot.connection {
+                    SidewalkPOI::DeferredParkingSpot(_, DrivingGoal::ParkNear(b)) => Some(b),
+                    _ => None,
+                },
+                _ => None,
+            });
+            if let Some(leave) = target
+                .and_then(|b| departures.get(&b))
+                .and_then(|times| times.iter().filter(|t| **t > trip.spawned_at).min())
+            {
+                stays.insert(trip.id, *leave - trip.spawned_at);
+            }
+        }
+        stays
+    }
+
     pub fn collect_events(&mut self) -> Vec<Event> {
         std::mem::replace(&mut self.events, Vec::new())
     }
@@ -719,6 +803,8 @@ impl std::fmt::Display for TripMode {
 pub enum TripStart {
     Bldg(BuildingID),
     Border(IntersectionID),
+    // Buses that appear partway into the map, right before a stop
+    ServeBusRoute(BusRouteID),
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
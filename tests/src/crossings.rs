@@ -0,0 +1,248 @@
+use crate::runner::{record_events, RecordedEvents, TestRunner};
+use crate::synthetic::SyntheticMap;
+use abstutil::Timer;
+use geom::{Distance, Duration, Speed, Time};
+use map_model::raw::DrivingSide;
+use map_model::{
+    BuildingID, CrossingType, IntersectionID, IntersectionType, LaneID, LaneType, Map,
+    PathConstraints, PathRequest, PathStep, Position, Traversable, TurnType,
+};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use sim::{AgentID, DrivingGoal, Event, Scenario, SidewalkSpot, Sim, SimOptions, TripSpec};
+
+pub fn run(t: &mut TestRunner) {
+    t.run_fast("walk_across_mid_block", |_| {
+        for crossing in &[
+            None,
+            Some(CrossingType::Zebra),
+            Some(CrossingType::Signalized),
+        ] {
+            let m = MidBlock::new("walk_across_mid_block", *crossing);
+            let path = m
+                .map
+                .pathfind(PathRequest {
+                    start: m.ped_start(),
+                    end: m.map.get_b(m.bldg).front_path.sidewalk,
+                    constraints: PathConstraints::Pedestrian,
+                })
+                .unwrap_or_else(|| panic!("No path across {:?} crossing", crossing));
+            let crosses = path.get_steps().iter().any(|step| match step {
+                PathStep::Turn(t) => {
+                    t.parent == m.x && m.map.get_t(*t).turn_type == TurnType::Crosswalk
+                }
+                _ => false,
+            });
+            if !crosses {
+                panic!(
+                    "Path with a {:?} crossing walks around instead: {:?}",
+                    crossing,
+                    path.get_steps()
+                );
+            }
+        }
+    });
+
+    t.run_slow("cars_yield_at_zebra", |h| {
+        let m = MidBlock::new("cars_yield_at_zebra", Some(CrossingType::Zebra));
+        let ped_appears = Time::START_OF_DAY + Duration::seconds(30.0);
+        let (mut sim, events) = m.sim("cars_yield_at_zebra", ped_appears);
+        h.setup_done(&mut sim, &m.map);
+        sim.just_run_until_done(&m.map, Some(Duration::minutes(10)));
+
+        // Walking 5m takes 5s. Afterwards, nobody should make the pedestrian wait long.
+        let crossed = m.ped_crossed(&events.borrow());
+        if crossed > ped_appears + Duration::seconds(15.0) {
+            panic!("Pedestrian only crossed at {}", crossed);
+        }
+        let cars = m.cars_through(&events.borrow());
+        if !cars.iter().any(|t| *t < crossed) || !cars.iter().any(|t| *t > crossed) {
+            panic!(
+                "Cars didn't drive through before and after {}: {:?}",
+                crossed, cars
+            );
+        }
+    });
+
+    t.run_slow("signalized_crossing_on_demand", |h| {
+        let m = MidBlock::new(
+            "signalized_crossing_on_demand",
+            Some(CrossingType::Signalized),
+        );
+        let signal = m.map.get_traffic_signal(m.x);
+        if signal.phases.len() != 2
+            || !signal.phases[1]
+                .actuated
+                .as_ref()
+                .map(|a| a.skip_if_no_demand)
+                .unwrap_or(false)
+        {
+            panic!(
+                "Signalized crossing doesn't wait for pedestrians: {:?}",
+                signal
+            );
+        }
+
+        let ped_appears = Time::START_OF_DAY + Duration::seconds(60.0);
+        let (mut sim, events) = m.sim("signalized_crossing_on_demand", ped_appears);
+        h.setup_done(&mut sim, &m.map);
+        // Until somebody wants to cross, traffic keeps the green.
+        while sim.time() < ped_appears {
+            sim.step(&m.map, Duration::seconds(1.0));
+            let (phase, _) = sim.current_phase_and_remaining_time(m.x, &m.map);
+            if phase != 0 {
+                panic!(
+                    "Switched to phase {} at {} with nobody waiting",
+                    phase,
+                    sim.time()
+                );
+            }
+        }
+        sim.just_run_until_done(&m.map, Some(Duration::minutes(10)));
+
+        // The walk takes 5s and the current phase has to run out first.
+        let crossed = m.ped_crossed(&events.borrow());
+        if crossed > ped_appears + Duration::seconds(60.0) {
+            panic!("Pedestrian only crossed at {}", crossed);
+        }
+        let cars = m.cars_through(&events.borrow());
+        if !cars.iter().any(|t| *t > crossed) {
+            panic!(
+                "No cars drove through after the pedestrian crossed: {:?}",
+                cars
+            );
+        }
+    });
+}
+
+// A straight road with a crossing X in the middle, and a building on the far side of X. Lanes:
+// one driving and one sidewalk each way.
+struct MidBlock {
+    map: Map,
+    x: IntersectionID,
+    bldg: BuildingID,
+    // Towards the east, on the south side
+    r1_sidewalk: LaneID,
+    // Traffic in each direction, as (start lane, end at this border, on this lane)
+    flows: Vec<(LaneID, IntersectionID, LaneID)>,
+}
+
+impl MidBlock {
+    fn new(name: &str, crossing: Option<CrossingType>) -> MidBlock {
+        let mut m = SyntheticMap::new(name, DrivingSide::Right);
+        let a = m.intersection(100.0, 500.0, IntersectionType::Border);
+        let x = m.intersection(
+            500.0,
+            500.0,
+            if crossing == Some(CrossingType::Signalized) {
+                IntersectionType::TrafficSignal
+            } else {
+                IntersectionType::StopSign
+            },
+        );
+        m.raw.intersections.get_mut(&x).unwrap().crossing = crossing;
+        let b = m.intersection(900.0, 500.0, IntersectionType::Border);
+        let lanes = vec![LaneType::Driving, LaneType::Sidewalk];
+        let r1 = m.road(a, x, lanes.clone(), lanes.clone());
+        let r2 = m.road(x, b, lanes.clone(), lanes);
+        // North of the road, just past X
+        m.building(530.0, 470.0);
+        let map = m.build();
+
+        let road = |orig| map.all_roads().iter().find(|r| r.orig_id == orig).unwrap();
+        let (r1, r2) = (road(r1), road(r2));
+        let lane = |children: &Vec<(LaneID, LaneType)>, lt| {
+            children.iter().find(|(_, t)| *t == lt).unwrap().0
+        };
+        MidBlock {
+            x: r1.dst_i,
+            bldg: map.all_buildings()[0].id,
+            r1_sidewalk: lane(&r1.children_forwards, LaneType::Sidewalk),
+            flows: vec![
+                (
+                    lane(&r1.children_forwards, LaneType::Driving),
+                    r2.dst_i,
+                    lane(&r2.children_forwards, LaneType::Driving),
+                ),
+                (
+                    lane(&r2.children_backwards, LaneType::Driving),
+                    r1.src_i,
+                    lane(&r1.children_backwards, LaneType::Driving),
+                ),
+            ],
+            map,
+        }
+    }
+
+    // On the south side, 5m before X
+    fn ped_start(&self) -> Position {
+        let len = self.map.get_l(self.r1_sidewalk).length();
+        Position::new(self.r1_sidewalk, len - Distance::meters(5.0))
+    }
+
+    // Heavy traffic both ways, staggered so that without yielding, some car is always in the way.
+    // Also one pedestrian crossing at X to the building.
+    fn sim(&self, name: &str, ped_appears: Time) -> (Sim, RecordedEvents) {
+        let mut sim = Sim::new(&self.map, SimOptions::new(name), &mut Timer::throwaway());
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        for i in 0..60 {
+            for (idx, (start, border, end)) in self.flows.iter().enumerate() {
+                sim.schedule_trip(
+                    Time::START_OF_DAY + Duration::seconds(0.5 * (i as f64) + 0.25 * (idx as f64)),
+                    TripSpec::CarAppearing {
+                        start_pos: Position::new(*start, Distance::meters(7.0)),
+                        goal: DrivingGoal::Border(*border, *end),
+                        vehicle_spec: Scenario::rand_car(&mut rng),
+                        ped_speed: Scenario::rand_ped_speed(&mut rng),
+                    },
+                    &self.map,
+                );
+            }
+        }
+        let start = self.ped_start();
+        sim.schedule_trip(
+            ped_appears,
+            TripSpec::JustWalking {
+                start: SidewalkSpot::suddenly_appear(start.lane(), start.dist_along(), &self.map),
+                goal: SidewalkSpot::building(self.bldg, &self.map),
+                ped_speed: Speed::meters_per_second(1.0),
+            },
+            &self.map,
+        );
+        sim.spawn_all_trips(&self.map, &mut Timer::throwaway(), true);
+
+        let events = record_events(&mut sim);
+        (sim, events)
+    }
+
+    // When the pedestrian started crossing at X
+    fn ped_crossed(&self, events: &[(Time, Event)]) -> Time {
+        events
+            .iter()
+            .find_map(|(time, ev)| match ev {
+                Event::AgentEntersTraversable(AgentID::Pedestrian(_), Traversable::Turn(t))
+                    if t.parent == self.x
+                        && self.map.get_t(*t).turn_type == TurnType::Crosswalk =>
+                {
+                    Some(*time)
+                }
+                _ => None,
+            })
+            .expect("Pedestrian never crossed")
+    }
+
+    // When cars drove through X
+    fn cars_through(&self, events: &[(Time, Event)]) -> Vec<Time> {
+        events
+            .iter()
+            .filter_map(|(time, ev)| match ev {
+                Event::AgentEntersTraversable(AgentID::Car(_), Traversable::Turn(t))
+                    if t.parent == self.x =>
+                {
+                    Some(*time)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,106 @@
+use crate::runner::{record_events, TestRunner};
+use crate::sim_determinism::savestate_fixture;
+use geom::{Duration, Time};
+use sim::{Event, EventFilter, EventLog, EventLogFormat};
+
+pub fn run(t: &mut TestRunner) {
+    t.run_fast("event_logs_round_trip", |_| {
+        let dir = std::env::temp_dir();
+        let json_path = dir.join("abst_events.json").to_str().unwrap().to_string();
+        let csv_path = dir.join("abst_events.csv").to_str().unwrap().to_string();
+        let at = |secs| Time::START_OF_DAY + Duration::seconds(secs);
+        let filter = EventFilter {
+            types: Some(EventFilter::parse_types("TripFinished,AgentEntersTraversable").unwrap()),
+            start: Some(at(40.0)),
+            end: Some(at(80.0)),
+        };
+
+        let (map, mut sim) = savestate_fixture();
+        let recorded = record_events(&mut sim);
+        sim.add_event_sink(Box::new(
+            EventLog::new(&json_path, EventLogFormat::Json, EventFilter::default()).unwrap(),
+        ));
+        sim.add_event_sink(Box::new(
+            EventLog::new(&csv_path, EventLogFormat::Csv, filter).unwrap(),
+        ));
+        sim.just_run_until_done(&map, Some(Duration::minutes(10)));
+        sim.flush_event_sinks();
+        let recorded = recorded.borrow();
+
+        for (_, ev) in recorded.iter() {
+            if !Event::NAMES.contains(&ev.name()) {
+                panic!("Event::NAMES is missing {}", ev.name());
+            }
+        }
+
+        // Unfiltered, everything comes back exactly.
+        let json: Vec<(Time, Event)> = std::fs::read_to_string(&json_path)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let obj: serde_json::Value = serde_json::from_str(line).unwrap();
+                (
+                    at(obj["time_seconds"].as_f64().unwrap()),
+                    serde_json::from_value(obj["event"].clone()).unwrap(),
+                )
+            })
+            .collect();
+        if json != *recorded {
+            panic!(
+                "The JSON log has {} events, but {} happened, or they differ",
+                json.len(),
+                recorded.len()
+            );
+        }
+
+        let csv = std::fs::read_to_string(&csv_path).unwrap();
+        let mut lines = csv.lines();
+        if lines.next() != Some("time_seconds,event,data") {
+            panic!("The CSV log has the wrong header: {:?}", csv.lines().next());
+        }
+        let csv: Vec<(Time, Event)> = lines
+            .map(|line| {
+                let cols: Vec<&str> = line.splitn(3, ',').collect();
+                let data = cols[2].trim_start_matches('"').trim_end_matches('"');
+                let ev: Event = serde_json::from_str(&data.replace("\"\"", "\"")).unwrap();
+                if ev.name() != cols[1] {
+                    panic!("{} is labeled {}", line, cols[1]);
+                }
+                (at(cols[0].parse::<f64>().unwrap()), ev)
+            })
+            .collect();
+        let expected: Vec<(Time, Event)> = recorded
+            .iter()
+            .filter(|(time, ev)| {
+                *time >= at(40.0)
+                    && *time <= at(80.0)
+                    && (ev.name() == "TripFinished" || ev.name() == "AgentEntersTraversable")
+            })
+            .cloned()
+            .collect();
+        if expected.is_empty() || expected.len() == recorded.len() {
+            panic!("The filter should keep some events, but not all of them");
+        }
+        if csv != expected {
+            panic!(
+                "The filtered CSV log has {} events, but should have {}",
+                csv.len(),
+                expected.len()
+            );
+        }
+    });
+
+    t.run_fast("event_types_are_validated", |_| {
+        if let Err(err) = EventFilter::parse_types("TripFinished,BusArrivedAtStop") {
+            panic!("Real event types were rejected: {}", err);
+        }
+        match EventFilter::parse_types("TripFinished,TripFinshed") {
+            Ok(types) => panic!("A typo wasn't caught: {:?}", types),
+            Err(err) => {
+                if !err.contains("TripFinshed") {
+                    panic!("The error doesn't name the bad type: {}", err);
+                }
+            }
+        }
+    });
+}
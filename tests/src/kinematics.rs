@@ -0,0 +1,193 @@
+use crate::runner::TestRunner;
+use crate::synthetic::SyntheticMap;
+use abstutil::Timer;
+use geom::{Acceleration, Distance, Duration, Speed, Time};
+use map_model::raw::DrivingSide;
+use map_model::{IntersectionType, LaneType, Position, Traversable, TurnPriority};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use sim::{DrivingGoal, GetDrawAgents, Kinematics, Scenario, Sim, SimOptions, TripSpec};
+
+pub fn run(t: &mut TestRunner) {
+    t.run_fast("kinematics_profiles", |_| {
+        let secs = Duration::seconds;
+        let mps = Speed::meters_per_second;
+        let mps2 = Acceleration::meters_per_second_squared;
+        // (what, profile, [(elapsed, expected distance, expected speed)])
+        let cases = vec![
+            (
+                // 5s and 25m to reach 10m/s, then 62.5m at that speed, then 2.5s and 12.5m to stop
+                "accelerate, cruise, and brake",
+                Kinematics::new(
+                    Distance::meters(100.0),
+                    mps(0.0),
+                    mps(10.0),
+                    mps(0.0),
+                    mps2(2.0),
+                    mps2(4.0),
+                ),
+                vec![
+                    (secs(2.0), 4.0, 4.0),
+                    (secs(5.0), 25.0, 10.0),
+                    (secs(11.25), 87.5, 10.0),
+                    (secs(12.25), 95.5, 6.0),
+                    (secs(13.75), 100.0, 0.0),
+                    (secs(20.0), 100.0, 0.0),
+                ],
+            ),
+            (
+                // Too short to reach cruising speed: peak at sqrt(20) m/s halfway
+                "accelerate and immediately brake",
+                Kinematics::new(
+                    Distance::meters(10.0),
+                    mps(0.0),
+                    mps(10.0),
+                    mps(0.0),
+                    mps2(2.0),
+                    mps2(2.0),
+                ),
+                vec![
+                    (secs(20.0_f64.sqrt() / 2.0), 5.0, 20.0_f64.sqrt()),
+                    (secs(20.0_f64.sqrt()), 10.0, 0.0),
+                ],
+            ),
+            (
+                // Stopping from 10m/s at 4m/s^2 takes 12.5m, so brake at 5m/s^2 instead
+                "brake harder than comfortable",
+                Kinematics::new(
+                    Distance::meters(10.0),
+                    mps(10.0),
+                    mps(10.0),
+                    mps(0.0),
+                    mps2(2.0),
+                    mps2(4.0),
+                ),
+                vec![(secs(1.0), 7.5, 5.0), (secs(2.0), 10.0, 0.0)],
+            ),
+            (
+                // Already at the speed limit, no need to stop
+                "cruise the whole way",
+                Kinematics::new(
+                    Distance::meters(30.0),
+                    mps(10.0),
+                    mps(10.0),
+                    mps(10.0),
+                    mps2(2.0),
+                    mps2(4.0),
+                ),
+                vec![(secs(1.5), 15.0, 10.0), (secs(3.0), 30.0, 10.0)],
+            ),
+        ];
+
+        for (what, k, expected) in cases {
+            let (_, total_dist, end_speed) = *expected.iter().last().unwrap();
+            if (k.dist_at(k.total_time()).inner_meters() - total_dist).abs() > 0.001
+                || (k.end_speed().inner_meters_per_second() - end_speed).abs() > 0.001
+            {
+                panic!(
+                    "{}: ends at {} going {}, after {}",
+                    what,
+                    k.dist_at(k.total_time()),
+                    k.end_speed(),
+                    k.total_time()
+                );
+            }
+            for (elapsed, dist, speed) in expected {
+                if (k.dist_at(elapsed).inner_meters() - dist).abs() > 0.001
+                    || (k.speed_at(elapsed).inner_meters_per_second() - speed).abs() > 0.001
+                {
+                    panic!(
+                        "{}: after {}, expected {}m at {}m/s, but got {} at {}",
+                        what,
+                        elapsed,
+                        dist,
+                        speed,
+                        k.dist_at(elapsed),
+                        k.speed_at(elapsed)
+                    );
+                }
+            }
+        }
+    });
+
+    t.run_fast("car_stops_exactly_at_stop_sign", |_| {
+        // A car heading east through an all-way stop
+        let mut m = SyntheticMap::new("all_way_stop", DrivingSide::Right);
+        let lanes = vec![LaneType::Driving];
+        let center = m.intersection(500.0, 500.0, IntersectionType::StopSign);
+        let west = m.intersection(300.0, 500.0, IntersectionType::Border);
+        let east = m.intersection(700.0, 500.0, IntersectionType::Border);
+        let from = m.road(west, center, lanes.clone(), lanes.clone());
+        let to = m.road(center, east, lanes.clone(), lanes.clone());
+        for &y in &[300.0, 700.0] {
+            let i = m.intersection(500.0, y, IntersectionType::Border);
+            m.road(i, center, lanes.clone(), lanes.clone());
+        }
+        let map = m.build();
+        let road = |orig| map.all_roads().iter().find(|r| r.orig_id == orig).unwrap();
+        let (start, end) = (
+            road(from).children_forwards[0].0,
+            road(to).children_forwards[0].0,
+        );
+        let turn = map.get_turns_for(start, map_model::PathConstraints::Car)[0].id;
+        if map.get_stop_sign(turn.parent).get_priority(turn, &map) != TurnPriority::Yield {
+            panic!("{} should have to stop", turn);
+        }
+
+        let mut opts = SimOptions::new("all_way_stop");
+        opts.use_acceleration = true;
+        let mut sim = Sim::new(&map, opts, &mut Timer::throwaway());
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        sim.schedule_trip(
+            Time::START_OF_DAY,
+            TripSpec::CarAppearing {
+                start_pos: Position::new(start, Distance::meters(7.0)),
+                goal: DrivingGoal::Border(map.get_l(end).dst_i, end),
+                vehicle_spec: Scenario::rand_car(&mut rng),
+                ped_speed: Scenario::rand_ped_speed(&mut rng),
+            },
+            &map,
+        );
+        sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
+
+        // Where's the front of the car on the first lane, every 0.1s?
+        let lane = map.get_l(start);
+        let mut dists = Vec::new();
+        while sim.time() < Time::START_OF_DAY + Duration::minutes(1) {
+            sim.step(&map, Duration::seconds(0.1));
+            let car = match sim.get_all_draw_cars(&map).pop() {
+                Some(car) => car,
+                None => break,
+            };
+            if car.on != Traversable::Lane(start) {
+                break;
+            }
+            let (dist, _) = lane
+                .lane_center_pts
+                .dist_along_of_point(car.body.last_pt())
+                .unwrap();
+            dists.push(dist);
+        }
+
+        // It should roll up to the end of the lane and stop there, not brake early or overshoot.
+        let idx = match dists
+            .iter()
+            .position(|d| (*d - lane.length()).abs() < Distance::meters(0.01))
+        {
+            Some(idx) => idx,
+            None => panic!("The car never reached the stop sign: {:?}", dists),
+        };
+        if idx == 0 || dists[idx] - dists[idx - 1] > Distance::meters(0.05) {
+            panic!(
+                "The car was still going fast right before the stop sign: {:?}",
+                dists
+            );
+        }
+        if dists[..idx].windows(2).any(|pair| pair[1] <= pair[0]) {
+            panic!("The car stopped before the stop sign: {:?}", dists);
+        }
+        if dists.len() - idx < 3 {
+            panic!("The car didn't wait at the stop sign: {:?}", dists);
+        }
+    });
+}
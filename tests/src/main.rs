@@ -1,9 +1,17 @@
+mod crossings;
+mod events;
 mod geom;
+mod kinematics;
 mod map_conversion;
+mod map_edits;
 mod parking;
+mod reports;
+mod roundabouts;
 mod runner;
+mod signals;
 mod sim_completion;
 mod sim_determinism;
+mod synthetic;
 mod transit;
 mod trips;
 
@@ -29,10 +37,17 @@ fn main() {
 
     let mut t = runner::TestRunner::new(flags);
 
+    crossings::run(t.suite("crossings"));
+    events::run(t.suite("events"));
     geom::run(t.suite("geom"));
+    kinematics::run(t.suite("kinematics"));
     map_conversion::run(t.suite("map_conversion"));
+    map_edits::run(t.suite("map_edits"));
     parking::run(t.suite("parking"));
+    reports::run(t.suite("reports"));
+    roundabouts::run(t.suite("roundabouts"));
     sim_completion::run(t.suite("sim_completion"));
+    signals::run(t.suite("signals"));
     sim_determinism::run(t.suite("sim_determinism"));
     transit::run(t.suite("transit"));
     trips::run(t.suite("trips"));
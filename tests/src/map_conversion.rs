@@ -1,4 +1,15 @@
 use crate::runner::TestRunner;
+use crate::synthetic::{osm_side, SyntheticMap};
+use abstutil::Timer;
+use convert_osm::{Elevation, ImportProfile};
+use geom::{Distance, LonLat, Pt2D, Speed};
+use map_model::raw::{DrivingSide, RawRoad};
+use map_model::{osm, IntersectionType, LaneID, LaneType, Map, TurnType};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Write;
+use tiff::encoder::{colortype, TiffEncoder};
+use tiff::tags::Tag;
 
 pub fn run(t: &mut TestRunner) {
     t.run_slow("convert_osm_twice", |_| {
@@ -6,10 +17,14 @@ pub fn run(t: &mut TestRunner) {
             osm: "../data/input/osm/montlake.osm".to_string(),
             parking_shapes: Some("../data/input/blockface.bin".to_string()),
             offstreet_parking: Some("../data/input/offstreet_parking.kml".to_string()),
+            parking_policies: None,
             sidewalks: Some("../data/input/sidewalks.bin".to_string()),
             gtfs: Some("../data/input/google_transit_2018_18_08".to_string()),
+            gtfs_service_day: Some("monday".to_string()),
             neighborhoods: Some("../data/input/neighborhoods.geojson".to_string()),
             clip: Some(abstutil::path_polygon("montlake")),
+            profile: None,
+            elevation: None,
             output: "convert_osm_twice.bin".to_string(),
         };
 
@@ -24,6 +39,408 @@ pub fn run(t: &mut TestRunner) {
         }
     });
 
+    t.run_fast("elevation_from_srtm", |_| {
+        let dir = std::env::temp_dir().join("abst_srtm_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        // 3x3 samples half a degree apart, from the northwest corner
+        let samples: Vec<i16> = vec![100, 200, 300, 100, 200, 300, 0, 0, -32768];
+        let mut file = File::create(dir.join("N47W123.hgt")).unwrap();
+        for x in samples {
+            file.write_all(&x.to_be_bytes()).unwrap();
+        }
+        drop(file);
+
+        let mut elevation = Elevation::new(dir.to_str().unwrap(), &mut Timer::throwaway());
+        let mut height =
+            |lon, lat| elevation.height(LonLat::new(lon, lat), &mut Timer::throwaway());
+        expect_height(height(-122.9, 47.9), Some(120.0));
+        expect_height(height(-122.5, 47.5), Some(200.0));
+        expect_height(height(-122.75, 47.75), Some(150.0));
+        // Next to a void
+        expect_height(height(-122.25, 47.25), None);
+        // No tile
+        expect_height(height(-121.5, 47.5), None);
+    });
+
+    t.run_fast("elevation_from_geotiff", |_| {
+        let path = std::env::temp_dir().join("abst_geotiff_test.tif");
+        write_geotiff(path.to_str().unwrap(), 2);
+        let mut elevation = Elevation::new(path.to_str().unwrap(), &mut Timer::throwaway());
+        let mut height =
+            |lon, lat| elevation.height(LonLat::new(lon, lat), &mut Timer::throwaway());
+        // Samples are at the center of each 0.1 degree pixel
+        expect_height(height(-122.2, 47.6), Some(30.0));
+        expect_height(height(-122.225, 47.625), Some(20.0));
+        expect_height(height(-122.175, 47.6), Some(32.5));
+        // Next to GDAL's nodata value
+        expect_height(height(-122.1, 47.6), None);
+        // Outside the sample centers
+        expect_height(height(-122.28, 47.65), None);
+
+        // Projected rasters aren't understood
+        write_geotiff(path.to_str().unwrap(), 1);
+        let mut elevation = Elevation::new(path.to_str().unwrap(), &mut Timer::throwaway());
+        expect_height(
+            elevation.height(LonLat::new(-122.2, 47.6), &mut Timer::throwaway()),
+            None,
+        );
+    });
+
+    t.run_fast("import_profiles", |_| {
+        let load = |name: &str| -> ImportProfile {
+            abstutil::read_json(abstutil::path_profile(name), &mut Timer::throwaway())
+        };
+        let seattle = load("seattle");
+        let europe = load("europe");
+        let uk = load("uk");
+        let canada = load("canada");
+        for (name, profile) in &[
+            ("seattle", &seattle),
+            ("europe", &europe),
+            ("uk", &uk),
+            ("canada", &canada),
+        ] {
+            if profile.name != *name {
+                panic!("{}.json is named {}", name, profile.name);
+            }
+        }
+        if uk.driving_side != DrivingSide::Left {
+            panic!("The UK profile doesn't drive on the left");
+        }
+
+        let residential = vec![("highway", "residential")];
+        expect_inferred(&seattle, &residential, "ds/ds", None);
+        expect_inferred(&seattle, &[("highway", "primary")], "ds/ds", Some("40 mph"));
+        expect_inferred(
+            &seattle,
+            &[("highway", "motorway"), ("oneway", "yes")],
+            "d/",
+            None,
+        );
+        expect_inferred(
+            &seattle,
+            &[("highway", "secondary"), ("junction", "roundabout")],
+            "ds/",
+            Some("40 mph"),
+        );
+        expect_inferred(
+            &europe,
+            &[("highway", "motorway"), ("oneway", "yes")],
+            "dd/",
+            Some("120"),
+        );
+        expect_inferred(&europe, &residential, "ds/ds", Some("30"));
+        expect_inferred(
+            &europe,
+            &[("highway", "tertiary"), ("oneway", "yes")],
+            "ds/s",
+            Some("50"),
+        );
+        // Whatever OSM already says wins
+        expect_inferred(
+            &canada,
+            &[
+                ("highway", "motorway"),
+                ("lanes", "6"),
+                ("maxspeed", "90"),
+                ("sidewalk", "right"),
+            ],
+            "ddds/ddd",
+            Some("90"),
+        );
+        let mut bikes_ok = load("seattle");
+        bikes_ok.bikes.allowed_on_footways = true;
+        expect_trail(&seattle, "footway", "s/");
+        expect_trail(&bikes_ok, "footway", "bs/b");
+
+        // OSM's left and right say where things are, whichever side traffic drives on.
+        let tags = vec![
+            ("highway", "residential"),
+            ("cycleway:left", "lane"),
+            ("sidewalk", "left"),
+            ("parking:lane:right", "parallel"),
+        ];
+        for profile in &[&seattle, &uk] {
+            let sides = lanes_by_osm_side(profile, &tags);
+            let expected = vec![
+                (
+                    "left",
+                    vec![LaneType::Driving, LaneType::Biking, LaneType::Sidewalk],
+                ),
+                ("right", vec![LaneType::Driving, LaneType::Parking]),
+            ]
+            .into_iter()
+            .collect();
+            if sides != expected {
+                panic!(
+                    "{} profile put lanes on the wrong sides: {:?}",
+                    profile.name, sides
+                );
+            }
+        }
+        // The sidewalk around a roundabout is on the outside, next to the traffic.
+        let roundabout = vec![("highway", "secondary"), ("junction", "roundabout")];
+        expect_inferred(&uk, &roundabout, "ds/", Some("30 mph"));
+    });
+
+    t.run_fast("maxspeed_units", |_| {
+        let mut m = SyntheticMap::new("maxspeed_units", DrivingSide::Right);
+        let mut i1 = m.intersection(100.0, 100.0, IntersectionType::Border);
+        let mut expected = Vec::new();
+        for (idx, (maxspeed, speed)) in vec![
+            ("25 mph", Speed::miles_per_hour(25.0)),
+            ("30mph", Speed::miles_per_hour(30.0)),
+            ("50", Speed::km_per_hour(50.0)),
+            ("60 km/h", Speed::km_per_hour(60.0)),
+            ("70kmh", Speed::km_per_hour(70.0)),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let i2 = m.intersection(
+                100.0 + 100.0 * (idx + 1) as f64,
+                100.0,
+                IntersectionType::StopSign,
+            );
+            let lanes = vec![LaneType::Driving, LaneType::Sidewalk];
+            let r = m.road(i1, i2, lanes.clone(), lanes);
+            m.raw
+                .roads
+                .get_mut(&r)
+                .unwrap()
+                .osm_tags
+                .insert(osm::MAXSPEED.to_string(), maxspeed.to_string());
+            expected.push((r, maxspeed, speed));
+            i1 = i2;
+        }
+        let map = m.build();
+        for (orig, maxspeed, speed) in expected {
+            let r = map.all_roads().iter().find(|r| r.orig_id == orig).unwrap();
+            let actual = r.get_speed_limit();
+            if (actual.inner_meters_per_second() - speed.inner_meters_per_second()).abs() > 0.001 {
+                panic!(
+                    "maxspeed={} should be {}, but got {}",
+                    maxspeed, speed, actual
+                );
+            }
+        }
+    });
+
+    t.run_fast("dropped_highway_types", |_| {
+        let kept = [
+            vec![("highway", "residential")],
+            vec![("highway", "service"), ("service", "alley")],
+            vec![("highway", "footway")],
+            vec![("highway", "cycleway")],
+        ];
+        let dropped = [
+            vec![("highway", "living_street")],
+            vec![("highway", "track")],
+            vec![("highway", "bridleway")],
+            vec![("highway", "proposed")],
+            vec![("highway", "construction")],
+            vec![("highway", "service"), ("service", "driveway")],
+            vec![("highway", "service"), ("service", "parking_aisle")],
+            vec![("highway", "footway"), ("footway", "sidewalk")],
+            vec![("highway", "footway"), ("footway", "crossing")],
+            vec![("highway", "path"), ("access", "private")],
+            vec![("highway", "pedestrian"), ("area", "yes")],
+            vec![("building", "yes")],
+        ];
+
+        // Every way is a separate straight line, named after its index. Two lone nodes widen the
+        // map's bounds, so that no way ends right on the boundary.
+        let mut xml = String::from("<?xml version='1.0' encoding='UTF-8'?>\n<osm version='0.6'>\n");
+        xml.push_str("  <node id='1000' lat='47.63' lon='-122.32'/>\n");
+        xml.push_str("  <node id='1001' lat='47.66' lon='-122.29'/>\n");
+        for (idx, tags) in kept.iter().chain(dropped.iter()).enumerate() {
+            let lat = 47.64 + 0.0005 * (idx as f64);
+            for (node, lon) in &[(2 * idx + 1, -122.31), (2 * idx + 2, -122.30)] {
+                xml.push_str(&format!(
+                    "  <node id='{}' lat='{}' lon='{}'/>\n",
+                    node, lat, lon
+                ));
+            }
+            xml.push_str(&format!(
+                "  <way id='{}'>\n    <nd ref='{}'/>\n    <nd ref='{}'/>\n",
+                idx + 1,
+                2 * idx + 1,
+                2 * idx + 2
+            ));
+            for (k, v) in tags {
+                xml.push_str(&format!("    <tag k='{}' v='{}'/>\n", k, v));
+            }
+            xml.push_str(&format!("    <tag k='name' v='{}'/>\n", idx));
+            xml.push_str("  </way>\n");
+        }
+        xml.push_str("</osm>\n");
+        let path = std::env::temp_dir().join("abst_dropped_highway_types.osm");
+        std::fs::write(&path, xml).unwrap();
+
+        let raw = convert_osm::convert(
+            &convert_osm::Flags {
+                osm: path.to_str().unwrap().to_string(),
+                parking_shapes: None,
+                offstreet_parking: None,
+                parking_policies: None,
+                sidewalks: None,
+                gtfs: None,
+                gtfs_service_day: None,
+                neighborhoods: None,
+                clip: None,
+                profile: None,
+                elevation: None,
+                output: "dropped_highway_types.bin".to_string(),
+            },
+            &mut Timer::throwaway(),
+        );
+        let mut names: Vec<usize> = raw
+            .roads
+            .values()
+            .map(|r| r.osm_tags[osm::NAME].parse::<usize>().unwrap())
+            .collect();
+        names.sort();
+        names.dedup();
+        let expected: Vec<usize> = (0..kept.len()).collect();
+        if names != expected {
+            let describe = |idxs: Vec<usize>| -> Vec<_> {
+                idxs.into_iter()
+                    .map(|idx| kept.iter().chain(dropped.iter()).nth(idx).unwrap())
+                    .collect()
+            };
+            panic!(
+                "Imported roads for {:?}, but expected {:?}",
+                describe(names),
+                describe(expected)
+            );
+        }
+    });
+
+    t.run_fast("clip_while_reading", |_| {
+        // An osmosis polygon
+        let mut poly = String::from("clip_while_reading\n1\n");
+        for (lon, lat) in &[
+            (-122.31, 47.64),
+            (-122.30, 47.64),
+            (-122.30, 47.65),
+            (-122.31, 47.65),
+            (-122.31, 47.64),
+        ] {
+            poly.push_str(&format!("    {}    {}\n", lon, lat));
+        }
+        poly.push_str("END\nEND\n");
+        let clip = std::env::temp_dir().join("abst_clip_while_reading.poly");
+        std::fs::write(&clip, poly).unwrap();
+        let osm = TinyOsm {
+            nodes: vec![
+                (1, -122.308, 47.645),
+                (2, -122.305, 47.645),
+                (3, -122.305, 47.642),
+                // Outside the clipping polygon
+                (4, -122.295, 47.645),
+                (5, -122.29, 47.66),
+                (6, -122.28, 47.66),
+            ],
+            ways: vec![
+                (10, vec![1, 2], "west"),
+                (11, vec![3, 2], "south"),
+                (12, vec![2, 4], "east"),
+                (13, vec![5, 6], "outside"),
+            ],
+            // The second one is entirely outside
+            restrictions: vec![(20, 10, 2, 11), (21, 13, 5, 13)],
+        };
+        let convert = |path: String| {
+            convert_osm::convert(
+                &convert_osm::Flags {
+                    osm: path,
+                    parking_shapes: None,
+                    offstreet_parking: None,
+                    parking_policies: None,
+                    sidewalks: None,
+                    gtfs: None,
+                    gtfs_service_day: None,
+                    neighborhoods: None,
+                    clip: Some(clip.to_str().unwrap().to_string()),
+                    profile: None,
+                    elevation: None,
+                    output: "clip_while_reading.bin".to_string(),
+                },
+                &mut Timer::throwaway(),
+            )
+        };
+
+        let xml = convert(osm.write_xml("abst_clip_while_reading.osm"));
+        let name = |r| xml.roads[r].osm_tags[osm::NAME].as_str();
+        let names: BTreeSet<&str> = xml.roads.keys().map(name).collect();
+        if names != vec!["east", "south", "west"].into_iter().collect() {
+            panic!(
+                "Imported roads {:?}, but expected east, south, and west",
+                names
+            );
+        }
+        // The road leaving the polygon is trimmed to it
+        let bounds = xml.boundary_polygon.get_bounds();
+        for (id, r) in &xml.roads {
+            if r.center_points.iter().any(|pt| pt.x() > bounds.max_x + 0.1) {
+                panic!("{} extends past the clipping polygon", name(id));
+            }
+        }
+        let mut restrictions = Vec::new();
+        for (id, r) in &xml.roads {
+            for (_, to) in &r.turn_restrictions {
+                restrictions.push((name(id), name(to)));
+            }
+        }
+        if restrictions != vec![("west", "south")] {
+            panic!(
+                "Imported turn restrictions {:?}, but expected west to south",
+                restrictions
+            );
+        }
+
+        // .osm.pbf is read separately, but should give the same result.
+        let pbf = convert(osm.write_pbf("abst_clip_while_reading.osm.pbf"));
+        if abstutil::to_json(&xml) != abstutil::to_json(&pbf) {
+            abstutil::write_json("clip_xml.json".to_string(), &xml);
+            abstutil::write_json("clip_pbf.json".to_string(), &pbf);
+            panic!("clip_xml.json and clip_pbf.json differ");
+        }
+    });
+
+    t.run_fast("turn_groups_follow_driving_side", |_| {
+        for side in &[DrivingSide::Right, DrivingSide::Left] {
+            let map = four_way(*side);
+            let i = map
+                .all_intersections()
+                .iter()
+                .find(|i| i.is_traffic_signal())
+                .unwrap();
+            for group in map.get_traffic_signal(i.id).turn_groups.values() {
+                if group.turn_type == TurnType::Crosswalk {
+                    continue;
+                }
+                // The group's center should start between its source lanes, wherever they are.
+                let mut srcs: Vec<LaneID> = group.members.iter().map(|t| t.src).collect();
+                srcs.sort();
+                srcs.dedup();
+                let (sum_x, sum_y) = srcs.iter().fold((0.0, 0.0), |(x, y), l| {
+                    let pt = map.get_l(*l).last_pt();
+                    (x + pt.x(), y + pt.y())
+                });
+                let n = srcs.len() as f64;
+                let expected = Pt2D::new(sum_x / n, sum_y / n);
+                let actual = group.src_center_and_width(&map).0.first_pt();
+                if actual.dist_to(expected) > Distance::meters(0.1) {
+                    panic!(
+                        "On a {:?} map, {:?} starts at {}, but its lanes are centered at {}",
+                        side, group.id, actual, expected
+                    );
+                }
+            }
+        }
+    });
+
     t.run_slow("raw_to_map_twice", |_| {
         let map1 = map_model::Map::new(
             abstutil::path_raw_map("montlake"),
@@ -60,3 +477,328 @@ pub fn run(t: &mut TestRunner) {
         );
     });
 }
+
+// Fills in tags for a road using a profile, then checks the lanes and speed limit that result.
+fn expect_inferred(
+    profile: &ImportProfile,
+    tags: &[(&str, &str)],
+    lanes: &str,
+    maxspeed: Option<&str>,
+) {
+    let road = inferred_road(profile, tags, false);
+    let actual = road.get_spec(profile.driving_side).to_string();
+    if actual != lanes {
+        panic!(
+            "{} profile on {:?} gave lanes {}, but expected {}. Tags: {:?}",
+            profile.name, tags, actual, lanes, road.osm_tags
+        );
+    }
+    if road.osm_tags.get(osm::MAXSPEED).map(|s| s.as_str()) != maxspeed {
+        panic!(
+            "{} profile on {:?} gave maxspeed {:?}, but expected {:?}",
+            profile.name,
+            tags,
+            road.osm_tags.get(osm::MAXSPEED),
+            maxspeed
+        );
+    }
+}
+
+fn expect_trail(profile: &ImportProfile, highway: &str, lanes: &str) {
+    let road = inferred_road(profile, &[("highway", highway)], true);
+    let actual = road.get_spec(profile.driving_side).to_string();
+    if actual != lanes {
+        panic!(
+            "{} profile on a {} gave lanes {}, but expected {}",
+            profile.name, highway, actual, lanes
+        );
+    }
+}
+
+fn inferred_road(profile: &ImportProfile, tags: &[(&str, &str)], trail: bool) -> RawRoad {
+    let mut osm_tags: BTreeMap<String, String> = tags
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    profile.apply(&mut osm_tags, trail);
+    RawRoad {
+        center_points: Vec::new(),
+        osm_tags,
+        turn_restrictions: Vec::new(),
+    }
+}
+
+// Fills in tags for a road using a profile, then builds it heading east. Returns the lanes on each
+// side from the center outwards, with sides named the way OSM does.
+fn lanes_by_osm_side(
+    profile: &ImportProfile,
+    tags: &[(&str, &str)],
+) -> BTreeMap<&'static str, Vec<LaneType>> {
+    let road = inferred_road(profile, tags, false);
+    let mut m = SyntheticMap::new(&format!("sides_{}", profile.name), profile.driving_side);
+    let i1 = m.intersection(100.0, 500.0, IntersectionType::Border);
+    let i2 = m.intersection(500.0, 500.0, IntersectionType::Border);
+    let tags: Vec<(&str, &str)> = road
+        .osm_tags
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    m.osm_road(i1, i2, &tags);
+    let map = m.build();
+
+    let mut sides = BTreeMap::new();
+    for l in map.all_roads()[0].all_lanes() {
+        sides
+            .entry(osm_side(&map, l))
+            .or_insert_with(Vec::new)
+            .push(map.get_l(l).lane_type);
+    }
+    sides
+}
+
+// A traffic signal with two driving lanes each way in all four directions
+fn four_way(side: DrivingSide) -> Map {
+    let mut m = SyntheticMap::new(&format!("four_way_{:?}", side), side);
+    let center = m.intersection(500.0, 500.0, IntersectionType::TrafficSignal);
+    for &(x, y) in &[
+        (500.0, 300.0),
+        (700.0, 500.0),
+        (500.0, 700.0),
+        (300.0, 500.0),
+    ] {
+        let border = m.intersection(x, y, IntersectionType::Border);
+        let lanes = vec![LaneType::Driving, LaneType::Driving, LaneType::Sidewalk];
+        m.road(border, center, lanes.clone(), lanes);
+    }
+    m.build()
+}
+
+// A few residential roads and turn restrictions, written as OSM XML or PBF
+struct TinyOsm {
+    // (ID, longitude, latitude)
+    nodes: Vec<(i64, f64, f64)>,
+    // (ID, nodes, name)
+    ways: Vec<(i64, Vec<i64>, &'static str)>,
+    // (ID, from way, via node, to way)
+    restrictions: Vec<(i64, i64, i64, i64)>,
+}
+
+impl TinyOsm {
+    fn way_tags(name: &str) -> Vec<(&str, &str)> {
+        vec![("highway", "residential"), ("name", name)]
+    }
+
+    fn restriction_tags() -> Vec<(&'static str, &'static str)> {
+        vec![("type", "restriction"), ("restriction", "no_left_turn")]
+    }
+
+    // Returns the path
+    fn write_xml(&self, filename: &str) -> String {
+        let mut xml = String::from("<?xml version='1.0' encoding='UTF-8'?>\n<osm version='0.6'>\n");
+        let tag = |xml: &mut String, (k, v): (&str, &str)| {
+            xml.push_str(&format!("    <tag k='{}' v='{}'/>\n", k, v));
+        };
+        for (id, lon, lat) in &self.nodes {
+            xml.push_str(&format!(
+                "  <node id='{}' lat='{}' lon='{}'/>\n",
+                id, lat, lon
+            ));
+        }
+        for (id, nodes, name) in &self.ways {
+            xml.push_str(&format!("  <way id='{}'>\n", id));
+            for node in nodes {
+                xml.push_str(&format!("    <nd ref='{}'/>\n", node));
+            }
+            for kv in TinyOsm::way_tags(name) {
+                tag(&mut xml, kv);
+            }
+            xml.push_str("  </way>\n");
+        }
+        for (id, from, via, to) in &self.restrictions {
+            xml.push_str(&format!("  <relation id='{}'>\n", id));
+            xml.push_str(&format!(
+                "    <member type='way' ref='{}' role='from'/>\n",
+                from
+            ));
+            xml.push_str(&format!(
+                "    <member type='node' ref='{}' role='via'/>\n",
+                via
+            ));
+            xml.push_str(&format!(
+                "    <member type='way' ref='{}' role='to'/>\n",
+                to
+            ));
+            for kv in TinyOsm::restriction_tags() {
+                tag(&mut xml, kv);
+            }
+            xml.push_str("  </relation>\n");
+        }
+        xml.push_str("</osm>\n");
+        let path = std::env::temp_dir().join(filename);
+        std::fs::write(&path, xml).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    // Hand-encodes the protobufs from https://wiki.openstreetmap.org/wiki/PBF_Format, as a single
+    // uncompressed OSMData block. Returns the path.
+    fn write_pbf(&self, filename: &str) -> String {
+        // Index 0 isn't used for real strings.
+        let mut strings: Vec<String> = vec![String::new()];
+        // Each group holds one kind of object. Coordinates are in units of 100 nanodegrees, the
+        // default granularity.
+        let mut nodes = Vec::new();
+        for (id, lon, lat) in &self.nodes {
+            let mut node = Vec::new();
+            pb_uint(&mut node, 1, zigzag(*id));
+            pb_uint(&mut node, 8, zigzag((lat * 1e7).round() as i64));
+            pb_uint(&mut node, 9, zigzag((lon * 1e7).round() as i64));
+            pb_bytes(&mut nodes, 1, &node);
+        }
+        let mut ways = Vec::new();
+        for (id, refs, name) in &self.ways {
+            let mut way = Vec::new();
+            pb_uint(&mut way, 1, *id as u64);
+            pb_tags(&mut way, &mut strings, TinyOsm::way_tags(name));
+            pb_packed(&mut way, 8, deltas(refs));
+            pb_bytes(&mut ways, 3, &way);
+        }
+        let mut relations = Vec::new();
+        for (id, from, via, to) in &self.restrictions {
+            let mut rel = Vec::new();
+            pb_uint(&mut rel, 1, *id as u64);
+            pb_tags(&mut rel, &mut strings, TinyOsm::restriction_tags());
+            let roles: Vec<u64> = ["from", "via", "to"]
+                .iter()
+                .map(|role| string_id(&mut strings, role))
+                .collect();
+            pb_packed(&mut rel, 8, roles);
+            pb_packed(&mut rel, 9, deltas(&[*from, *via, *to]));
+            // Way, node, way
+            pb_packed(&mut rel, 10, vec![1, 0, 1]);
+            pb_bytes(&mut relations, 4, &rel);
+        }
+
+        let mut string_table = Vec::new();
+        for s in &strings {
+            pb_bytes(&mut string_table, 1, s.as_bytes());
+        }
+        let mut block = Vec::new();
+        pb_bytes(&mut block, 1, &string_table);
+        for group in &[nodes, ways, relations] {
+            pb_bytes(&mut block, 2, group);
+        }
+        let mut blob = Vec::new();
+        pb_bytes(&mut blob, 1, &block);
+        pb_uint(&mut blob, 2, block.len() as u64);
+        let mut header = Vec::new();
+        pb_bytes(&mut header, 1, b"OSMData");
+        pb_uint(&mut header, 3, blob.len() as u64);
+
+        let path = std::env::temp_dir().join(filename);
+        let mut file = File::create(&path).unwrap();
+        file.write_all(&(header.len() as u32).to_be_bytes())
+            .unwrap();
+        file.write_all(&header).unwrap();
+        file.write_all(&blob).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+}
+
+fn pb_varint(buf: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        buf.push((x as u8) | 0x80);
+        x >>= 7;
+    }
+    buf.push(x as u8);
+}
+
+fn pb_uint(buf: &mut Vec<u8>, field: u64, x: u64) {
+    pb_varint(buf, field << 3);
+    pb_varint(buf, x);
+}
+
+fn pb_bytes(buf: &mut Vec<u8>, field: u64, data: &[u8]) {
+    pb_varint(buf, (field << 3) | 2);
+    pb_varint(buf, data.len() as u64);
+    buf.extend_from_slice(data);
+}
+
+fn pb_packed<I: IntoIterator<Item = u64>>(buf: &mut Vec<u8>, field: u64, xs: I) {
+    let mut data = Vec::new();
+    for x in xs {
+        pb_varint(&mut data, x);
+    }
+    pb_bytes(buf, field, &data);
+}
+
+// Tags refer to the block's table of strings.
+fn pb_tags(buf: &mut Vec<u8>, strings: &mut Vec<String>, tags: Vec<(&str, &str)>) {
+    let ids: Vec<(u64, u64)> = tags
+        .into_iter()
+        .map(|(k, v)| (string_id(strings, k), string_id(strings, v)))
+        .collect();
+    pb_packed(buf, 2, ids.iter().map(|(k, _)| *k));
+    pb_packed(buf, 3, ids.iter().map(|(_, v)| *v));
+}
+
+fn string_id(strings: &mut Vec<String>, s: &str) -> u64 {
+    if let Some(idx) = strings.iter().position(|x| x == s) {
+        return idx as u64;
+    }
+    strings.push(s.to_string());
+    (strings.len() - 1) as u64
+}
+
+// For sint64
+fn zigzag(x: i64) -> u64 {
+    ((x << 1) ^ (x >> 63)) as u64
+}
+
+// IDs in ways and relations are stored as differences from the previous one.
+fn deltas(ids: &[i64]) -> Vec<u64> {
+    let mut prev = 0;
+    ids.iter()
+        .map(|id| {
+            let delta = zigzag(id - prev);
+            prev = *id;
+            delta
+        })
+        .collect()
+}
+
+fn expect_height(actual: Option<Distance>, expected: Option<f64>) {
+    let ok = match (actual, expected) {
+        (Some(a), Some(e)) => (a.inner_meters() - e).abs() < 0.01,
+        (None, None) => true,
+        _ => false,
+    };
+    if !ok {
+        panic!("Expected a height of {:?}, but got {:?}", expected, actual);
+    }
+}
+
+// 3x2 pixels, 0.1 degrees wide, with the northwest corner at (-122.3, 47.7)
+fn write_geotiff(path: &str, model_type: u16) {
+    let mut tiff = TiffEncoder::new(File::create(path).unwrap()).unwrap();
+    let mut image = tiff.new_image::<colortype::Gray32Float>(3, 2).unwrap();
+    let geokeys: Vec<u16> = vec![1, 1, 0, 1, 1024, 0, 1, model_type];
+    image
+        .encoder()
+        .write_tag(Tag::GeoKeyDirectoryTag, &geokeys[..])
+        .unwrap();
+    image
+        .encoder()
+        .write_tag(Tag::ModelPixelScaleTag, &[0.1, 0.1, 0.0][..])
+        .unwrap();
+    image
+        .encoder()
+        .write_tag(
+            Tag::ModelTiepointTag,
+            &[0.0, 0.0, 0.0, -122.3, 47.7, 0.0][..],
+        )
+        .unwrap();
+    image.encoder().write_tag(Tag::GdalNodata, "-9999").unwrap();
+    image
+        .write_data(&[10.0, 20.0, 30.0, 40.0, 50.0, -9999.0])
+        .unwrap();
+}
@@ -0,0 +1,349 @@
+use crate::runner::{record_events, RecordedEvents, TestRunner};
+use crate::synthetic::SyntheticMap;
+use abstutil::Timer;
+use geom::{Distance, Duration, Time};
+use map_model::raw::{DrivingSide, OriginalIntersection, RawMap};
+use map_model::{
+    EditCmd, IntersectionID, IntersectionType, LaneID, LaneType, Map, MapEdits, PermanentMapEdits,
+    Position, Traversable,
+};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use sim::{AgentID, DrivingGoal, Event, Scenario, Sim, SimOptions, TripSpec};
+
+pub fn run(t: &mut TestRunner) {
+    t.run_slow("edits_survive_regenerating_map", |_| {
+        let (map1, map2, _) = montlake_with_shifted_ids();
+        let exists = |i: IntersectionID| {
+            let orig = map1.get_i(i);
+            map2.all_intersections()
+                .iter()
+                .any(|other| other.orig_id == orig.orig_id && other.roads.len() == orig.roads.len())
+        };
+
+        let l = map1
+            .all_lanes()
+            .iter()
+            .find(|l| l.lane_type == LaneType::Driving && exists(l.src_i) && exists(l.dst_i))
+            .unwrap();
+        let ss = map1
+            .all_intersections()
+            .iter()
+            .filter(|i| exists(i.id))
+            .find_map(|i| map1.maybe_get_stop_sign(i.id))
+            .unwrap();
+        let mut ss = ss.clone();
+        let cfg = ss.roads.values_mut().next().unwrap();
+        cfg.must_stop = !cfg.must_stop;
+        let closed = map1
+            .all_intersections()
+            .iter()
+            .rev()
+            .find(|i| exists(i.id))
+            .unwrap();
+
+        let mut edits = MapEdits::new(map1.get_name().to_string());
+        edits.edits_name = "round_trip".to_string();
+        edits.commands = vec![
+            EditCmd::ChangeLaneType {
+                id: l.id,
+                lt: LaneType::Biking,
+                orig_lt: LaneType::Driving,
+            },
+            EditCmd::ReverseLane {
+                l: l.id,
+                dst_i: l.src_i,
+            },
+            EditCmd::ChangeStopSign(ss.clone()),
+            EditCmd::CloseIntersection {
+                id: closed.id,
+                orig_it: closed.intersection_type,
+            },
+        ];
+
+        let (edits2, unmatched) = through_json(&edits, &map1, &map2);
+        if !unmatched.is_empty() {
+            panic!("Edits didn't match the regenerated map: {:?}", unmatched);
+        }
+        if edits2.commands.len() != edits.commands.len() {
+            panic!(
+                "{} edits came back as {}",
+                edits.commands.len(),
+                edits2.commands.len()
+            );
+        }
+        let same_lane = |l1: LaneID, l2: LaneID| {
+            let (r1, r2) = (map1.get_parent(l1), map2.get_parent(l2));
+            r1.orig_id == r2.orig_id && r1.dir_and_offset(l1) == r2.dir_and_offset(l2)
+        };
+        let same_i = |i1: IntersectionID, i2: IntersectionID| {
+            map1.get_i(i1).orig_id == map2.get_i(i2).orig_id
+        };
+        let mut any_id_changed = false;
+        for (cmd1, cmd2) in edits.commands.iter().zip(edits2.commands.iter()) {
+            let ok = match (cmd1, cmd2) {
+                (
+                    EditCmd::ChangeLaneType {
+                        id: l1, lt: lt1, ..
+                    },
+                    EditCmd::ChangeLaneType {
+                        id: l2, lt: lt2, ..
+                    },
+                ) => {
+                    any_id_changed |= l1 != l2;
+                    same_lane(*l1, *l2) && lt1 == lt2
+                }
+                (
+                    EditCmd::ReverseLane { l: l1, dst_i: i1 },
+                    EditCmd::ReverseLane { l: l2, dst_i: i2 },
+                ) => same_lane(*l1, *l2) && same_i(*i1, *i2),
+                (EditCmd::ChangeStopSign(ss1), EditCmd::ChangeStopSign(ss2)) => {
+                    any_id_changed |= ss1.id != ss2.id;
+                    same_i(ss1.id, ss2.id)
+                        && ss1.roads.iter().all(|(r, cfg)| {
+                            let orig = map1.get_r(*r).orig_id;
+                            ss2.roads.iter().any(|(r2, cfg2)| {
+                                map2.get_r(*r2).orig_id == orig && cfg2.must_stop == cfg.must_stop
+                            })
+                        })
+                }
+                (
+                    EditCmd::CloseIntersection { id: i1, .. },
+                    EditCmd::CloseIntersection { id: i2, .. },
+                ) => {
+                    any_id_changed |= i1 != i2;
+                    same_i(*i1, *i2)
+                }
+                _ => false,
+            };
+            if !ok {
+                panic!("{:?} came back as {:?}", cmd1, cmd2);
+            }
+        }
+        if !any_id_changed {
+            panic!("Regenerating the map didn't change any IDs, so this doesn't test anything");
+        }
+    });
+
+    t.run_slow("unmatched_edits_are_reported", |_| {
+        let (map1, map2, removed) = montlake_with_shifted_ids();
+        let removed_i = map1
+            .all_intersections()
+            .iter()
+            .find(|i| i.orig_id == removed)
+            .unwrap();
+        let kept_i = map1
+            .all_intersections()
+            .iter()
+            .rev()
+            .find(|i| i.orig_id != removed)
+            .unwrap();
+        let removed_lane = map1
+            .get_r(*removed_i.roads.iter().next().unwrap())
+            .all_lanes()[0];
+
+        let mut edits = MapEdits::new(map1.get_name().to_string());
+        edits.edits_name = "unmatched".to_string();
+        edits.commands = vec![
+            EditCmd::CloseIntersection {
+                id: removed_i.id,
+                orig_it: removed_i.intersection_type,
+            },
+            EditCmd::CloseIntersection {
+                id: kept_i.id,
+                orig_it: kept_i.intersection_type,
+            },
+            EditCmd::ChangeLaneType {
+                id: removed_lane,
+                lt: LaneType::Construction,
+                orig_lt: map1.get_l(removed_lane).lane_type,
+            },
+        ];
+
+        let (edits2, unmatched) = through_json(&edits, &map1, &map2);
+        if edits2.commands.len() != 1 || unmatched.len() != 2 {
+            panic!(
+                "Expected 1 edit to match and 2 not to, but got {:?} and {:?}",
+                edits2.commands, unmatched
+            );
+        }
+        if !unmatched.iter().all(|err| err.starts_with("Can't find")) {
+            panic!("Unexpected reasons for unmatched edits: {:?}", unmatched);
+        }
+    });
+
+    t.run_fast("live_edits_reroute_cars", |_| {
+        let mut d = Detour::new("live_edits_reroute_cars");
+        let (mut sim, events) = d.drive_then_close(d.direct);
+        sim.just_run_until_done(&d.map, Some(Duration::minutes(5)));
+
+        let entered = entered_lanes(&events);
+        if entered.contains(&d.direct) || !entered.contains(&d.detour) {
+            panic!(
+                "The car didn't take the detour around the closed lane: {:?}",
+                entered
+            );
+        }
+        if !events
+            .borrow()
+            .iter()
+            .any(|(_, ev)| ev.name() == "CarRerouted")
+        {
+            panic!("The car never rerouted");
+        }
+    });
+
+    t.run_fast("live_edits_strand_cars", |_| {
+        // Nothing else leads to the border
+        let mut d = Detour::new("live_edits_strand_cars");
+        let (mut sim, events) = d.drive_then_close(d.end);
+        sim.just_run_until_done(&d.map, Some(Duration::minutes(5)));
+
+        if entered_lanes(&events).contains(&d.end) {
+            panic!("The car drove onto the closed lane");
+        }
+        if !events
+            .borrow()
+            .iter()
+            .any(|(_, ev)| ev.name() == "TripAborted")
+        {
+            panic!("The car's trip wasn't aborted");
+        }
+    });
+}
+
+// West to east, with a short way and a long way around in the middle:
+//
+//          Z ---- W
+//          |      |
+//   A ---- X ---- Y ---- B
+struct Detour {
+    map: Map,
+    start: LaneID,
+    direct: LaneID,
+    detour: LaneID,
+    end: LaneID,
+    border: IntersectionID,
+}
+
+impl Detour {
+    fn new(name: &str) -> Detour {
+        let mut m = SyntheticMap::new(name, DrivingSide::Right);
+        let a = m.intersection(100.0, 500.0, IntersectionType::Border);
+        let x = m.intersection(300.0, 500.0, IntersectionType::StopSign);
+        let y = m.intersection(500.0, 500.0, IntersectionType::StopSign);
+        let b = m.intersection(700.0, 500.0, IntersectionType::Border);
+        let z = m.intersection(300.0, 200.0, IntersectionType::StopSign);
+        let w = m.intersection(500.0, 200.0, IntersectionType::StopSign);
+        let lanes = vec![LaneType::Driving];
+        let start = m.road(a, x, lanes.clone(), lanes.clone());
+        let direct = m.road(x, y, lanes.clone(), lanes.clone());
+        let detour = m.road(x, z, lanes.clone(), lanes.clone());
+        m.road(z, w, lanes.clone(), lanes.clone());
+        m.road(w, y, lanes.clone(), lanes.clone());
+        let end = m.road(y, b, lanes.clone(), lanes);
+        let map = m.build();
+
+        let fwd = |orig| {
+            let r = map.all_roads().iter().find(|r| r.orig_id == orig).unwrap();
+            r.children_forwards[0].0
+        };
+        Detour {
+            start: fwd(start),
+            direct: fwd(direct),
+            detour: fwd(detour),
+            end: fwd(end),
+            border: map.get_l(fwd(end)).dst_i,
+            map,
+        }
+    }
+
+    // One car heads from A to B. Once it's partway along the first road, the lane is closed to
+    // traffic without restarting the sim.
+    fn drive_then_close(&mut self, lane: LaneID) -> (Sim, RecordedEvents) {
+        let mut sim = Sim::new(
+            &self.map,
+            SimOptions::new(self.map.get_name()),
+            &mut Timer::throwaway(),
+        );
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        sim.schedule_trip(
+            Time::START_OF_DAY,
+            TripSpec::CarAppearing {
+                start_pos: Position::new(self.start, Distance::meters(7.0)),
+                goal: DrivingGoal::Border(self.border, self.end),
+                vehicle_spec: Scenario::rand_car(&mut rng),
+                ped_speed: Scenario::rand_ped_speed(&mut rng),
+            },
+            &self.map,
+        );
+        sim.spawn_all_trips(&self.map, &mut Timer::throwaway(), true);
+        let events = record_events(&mut sim);
+        sim.step(&self.map, Duration::seconds(5.0));
+
+        let mut edits = self.map.get_edits().clone();
+        edits.commands.push(EditCmd::ChangeLaneType {
+            id: lane,
+            lt: LaneType::Construction,
+            orig_lt: LaneType::Driving,
+        });
+        self.map.apply_edits(edits, &mut Timer::throwaway());
+        self.map
+            .recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+        sim.handle_live_edits(&self.map);
+        (sim, events)
+    }
+}
+
+fn entered_lanes(events: &RecordedEvents) -> Vec<LaneID> {
+    events
+        .borrow()
+        .iter()
+        .filter_map(|(_, ev)| match ev {
+            Event::AgentEntersTraversable(AgentID::Car(_), Traversable::Lane(l)) => Some(*l),
+            _ => None,
+        })
+        .collect()
+}
+
+// Builds montlake twice: as usual, then without its first intersection (and roads there), so that
+// IDs afterwards shift. Also returns the removed intersection.
+fn montlake_with_shifted_ids() -> (Map, Map, OriginalIntersection) {
+    let mut timer = Timer::throwaway();
+    let mut raw: RawMap = abstutil::read_binary(abstutil::path_raw_map("montlake"), &mut timer);
+    raw.apply_all_fixes(&mut timer);
+    let path1 = std::env::temp_dir().join("abst_edits_map1.json");
+    abstutil::write_json(path1.to_str().unwrap().to_string(), &raw);
+
+    let removed = *raw
+        .intersections
+        .keys()
+        .find(|i| !raw.roads_per_intersection(**i).is_empty())
+        .unwrap();
+    for r in raw.roads_per_intersection(removed) {
+        raw.delete_road(r);
+    }
+    raw.delete_intersection(removed);
+    let path2 = std::env::temp_dir().join("abst_edits_map2.json");
+    abstutil::write_json(path2.to_str().unwrap().to_string(), &raw);
+
+    (
+        Map::new(path1.to_str().unwrap().to_string(), false, &mut timer),
+        Map::new(path2.to_str().unwrap().to_string(), false, &mut timer),
+        removed,
+    )
+}
+
+// Saves edits for one map, then reads them back for another.
+fn through_json(edits: &MapEdits, map1: &Map, map2: &Map) -> (MapEdits, Vec<String>) {
+    let path = std::env::temp_dir().join("abst_edits.json");
+    let path = path.to_str().unwrap();
+    abstutil::write_json(
+        path.to_string(),
+        &PermanentMapEdits::from_edits(edits, map1),
+    );
+    match PermanentMapEdits::read(path, map2) {
+        Ok(perma) => perma.into_edits(map2),
+        Err(err) => panic!("Couldn't read {}: {}", path, err),
+    }
+}
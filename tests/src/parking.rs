@@ -1,4 +1,15 @@
-use crate::runner::TestRunner;
+use crate::runner::{record_events, TestRunner};
+use crate::synthetic::{osm_side, SyntheticMap};
+use abstutil::Timer;
+use geom::{Distance, Duration, Time};
+use map_model::raw::{DrivingSide, OriginalBuilding, OriginalRoad};
+use map_model::{
+    BuildingID, IntersectionType, LaneID, LaneType, Map, ParkingPolicy, Position, RoadID,
+};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use sim::{DrivingGoal, Event, ParkingSpot, Scenario, Sim, SimOptions, TripSpec};
+use std::collections::BTreeMap;
 /*use abstutil::Timer;
 use geom::Duration;
 use sim::{DrivingGoal, Event, ParkingSpot, Scenario, SidewalkSpot, SimFlags, TripSpec};*/
@@ -8,12 +19,155 @@ use sim::{DrivingGoal, Event, ParkingSpot, Scenario, SidewalkSpot, SimFlags, Tri
 // TODO ped walks to a garage to start driving somewhere else
 // TODO two peds leave same bldg at around the same time, contend for owned cars
 
-pub fn run(_t: &mut TestRunner) {
+pub fn run(t: &mut TestRunner) {
+    t.run_fast("parking_policy_from_osm", |_| {
+        for (fee, cents) in &[
+            ("no", 0),
+            ("yes", 200),
+            ("2.50", 250),
+            ("2.50 USD/hour", 250),
+            ("USD 1.75", 175),
+        ] {
+            let policy = ParkingPolicy::from_osm(
+                &tags(&[("parking:lane:both:fee", fee)]),
+                true,
+                DrivingSide::Right,
+            );
+            if policy.hourly_price_cents != *cents {
+                panic!(
+                    "fee={} should cost {} cents, not {}",
+                    fee, cents, policy.hourly_price_cents
+                );
+            }
+        }
+
+        for (maxstay, minutes) in &[
+            ("2 hours", Some(120.0)),
+            ("1 h", Some(60.0)),
+            ("90 min", Some(90.0)),
+            ("45", Some(45.0)),
+            ("2 days", None),
+            ("unlimited", None),
+        ] {
+            let policy = ParkingPolicy::from_osm(
+                &tags(&[("parking:lane:both:maxstay", maxstay)]),
+                true,
+                DrivingSide::Right,
+            );
+            if policy.max_stay != minutes.map(|m| Duration::seconds(m * 60.0)) {
+                panic!(
+                    "maxstay={} should be {:?} minutes, not {:?}",
+                    maxstay, minutes, policy.max_stay
+                );
+            }
+        }
+
+        // Forwards lanes are on the right. Tags for one side win over both.
+        let osm_tags = tags(&[
+            ("parking:lane:right:fee", "1"),
+            ("parking:lane:both:fee", "3"),
+            ("parking:lane:left:residents", "K"),
+        ]);
+        let right = ParkingPolicy::from_osm(&osm_tags, true, DrivingSide::Right);
+        let left = ParkingPolicy::from_osm(&osm_tags, false, DrivingSide::Right);
+        if right.hourly_price_cents != 100
+            || right.permit_zone.is_some()
+            || left.hourly_price_cents != 300
+            || left.permit_zone != Some("K".to_string())
+        {
+            panic!("Sides mixed up: right is {:?}, left is {:?}", right, left);
+        }
+        // Driving on the left, forwards lanes are on the left.
+        if ParkingPolicy::from_osm(&osm_tags, true, DrivingSide::Left) != left
+            || ParkingPolicy::from_osm(&osm_tags, false, DrivingSide::Left) != right
+        {
+            panic!("Sides not mirrored when driving on the left");
+        }
+
+        // Check where the parking lanes wind up on a real road, too.
+        for side in &[DrivingSide::Right, DrivingSide::Left] {
+            let mut m = SyntheticMap::new(&format!("parking_sides_{:?}", side), *side);
+            let i1 = m.intersection(100.0, 500.0, IntersectionType::Border);
+            let i2 = m.intersection(500.0, 500.0, IntersectionType::Border);
+            m.osm_road(
+                i1,
+                i2,
+                &[
+                    ("parking:lane:both", "parallel"),
+                    ("parking:lane:right:fee", "1"),
+                    ("parking:lane:left:residents", "K"),
+                ],
+            );
+            let map = m.build();
+            for l in map.all_lanes() {
+                if l.lane_type != LaneType::Parking {
+                    continue;
+                }
+                let policy = &l.parking_policy;
+                let ok = match osm_side(&map, l.id) {
+                    "right" => policy.hourly_price_cents == 100 && policy.permit_zone.is_none(),
+                    _ => policy.is_free() && policy.permit_zone == Some("K".to_string()),
+                };
+                if !ok {
+                    panic!(
+                        "Driving on the {:?}, parking on the {} got {:?}",
+                        side,
+                        osm_side(&map, l.id),
+                        policy
+                    );
+                }
+            }
+        }
+    });
+
+    t.run_slow("parking_price_weighed_against_walking", |h| {
+        // Paying $5 for an hour is worth a few minutes of walking. For free, parking right at the
+        // building is best.
+        for (fee, park_at_building) in &[("5", false), ("no", true)] {
+            let m = ParkingLoop::new(
+                "parking_price_weighed_against_walking",
+                &[("parking:lane:both:fee", fee)],
+            );
+            let mut sim = m.sim("parking_price_weighed_against_walking", None);
+            if *fee == "5" {
+                h.setup_done(&mut sim, &m.map);
+            }
+            let parked = m.run(sim);
+            if (parked == m.at_building) != *park_at_building {
+                panic!(
+                    "With fee={}, parked on {} ({} is next to the building)",
+                    fee, parked, m.at_building
+                );
+            }
+        }
+    });
+
+    t.run_slow("parking_time_limit_depends_on_stay", |h| {
+        // Only somebody leaving again soon fits into a 30 minute spot.
+        for leave_after in &[None, Some(Duration::minutes(10))] {
+            let m = ParkingLoop::new(
+                "parking_time_limit_depends_on_stay",
+                &[("parking:lane:both:maxstay", "30 min")],
+            );
+            let mut sim = m.sim("parking_time_limit_depends_on_stay", *leave_after);
+            if leave_after.is_some() {
+                h.setup_done(&mut sim, &m.map);
+            }
+            let parked = m.run(sim);
+            if (parked == m.at_building) != leave_after.is_some() {
+                panic!(
+                    "Leaving again after {:?}, parked on {} (the 30 minute spots are on {})",
+                    leave_after, parked, m.at_building
+                );
+            }
+        }
+    });
+
     // TODO Lots of boilerplate between these two. Can we do better?
 
     /*t.run_slow("park_on_goal_st", |h| {
         let (map, mut sim, mut rng) = SimFlags::synthetic_test("parking_test", "park_on_goal_st")
-            .load(&mut Timer::throwaway());
+            .load(&mut Timer::throwaway()).unwrap();
         let north_bldg = map.bldg("north").id;
         let south_bldg = map.bldg("south").id;
         let north_parking = map.parking_lane("north", 23).id;
@@ -35,7 +189,7 @@ pub fn run(_t: &mut TestRunner) {
             &map,
         );
         sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
-        h.setup_done(&sim);
+        h.setup_done(&mut sim, &map);
 
         sim.run_until_expectations_met(
             &map,
@@ -51,7 +205,7 @@ pub fn run(_t: &mut TestRunner) {
     t.run_slow("wander_around_for_parking", |h| {
         let (map, mut sim, mut rng) =
             SimFlags::synthetic_test("parking_test", "wander_around_for_parking")
-                .load(&mut Timer::throwaway());
+                .load(&mut Timer::throwaway()).unwrap();
         let north_bldg = map.bldg("north").id;
         let south_bldg = map.bldg("south").id;
         let north_parking = map.parking_lane("north", 23).id;
@@ -73,7 +227,7 @@ pub fn run(_t: &mut TestRunner) {
             &map,
         );
         sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
-        h.setup_done(&sim);
+        h.setup_done(&mut sim, &map);
 
         sim.run_until_expectations_met(
             &map,
@@ -86,3 +240,123 @@ pub fn run(_t: &mut TestRunner) {
         sim.just_run_until_done(&map, Some(Duration::minutes(1)));
     });*/
 }
+
+fn tags(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+// A block of two-way roads with parking, entered from a border to the west. Somebody drives in
+// to visit a building on the north edge of the block; the parking along there can be changed.
+struct ParkingLoop {
+    map: Map,
+    bldg: BuildingID,
+    at_building: RoadID,
+    start: LaneID,
+}
+
+impl ParkingLoop {
+    fn new(name: &str, at_building_tags: &[(&str, &str)]) -> ParkingLoop {
+        let mut m = SyntheticMap::new(name, DrivingSide::Right);
+        let border = m.intersection(100.0, 300.0, IntersectionType::Border);
+        let corners: Vec<_> = [
+            (300.0, 300.0),
+            (700.0, 300.0),
+            (700.0, 700.0),
+            (300.0, 700.0),
+        ]
+        .iter()
+        .map(|(x, y)| m.intersection(*x, *y, IntersectionType::StopSign))
+        .collect();
+        let lanes = vec![LaneType::Driving, LaneType::Parking, LaneType::Sidewalk];
+        let entrance = m.road(border, corners[0], lanes.clone(), lanes.clone());
+        let mut sides = Vec::new();
+        for idx in 0..4 {
+            sides.push(m.road(
+                corners[idx],
+                corners[(idx + 1) % 4],
+                lanes.clone(),
+                lanes.clone(),
+            ));
+        }
+        for (k, v) in at_building_tags {
+            m.raw
+                .roads
+                .get_mut(&sides[0])
+                .unwrap()
+                .osm_tags
+                .insert(k.to_string(), v.to_string());
+        }
+        // Inside the block, along the north side
+        let bldg = m.building(500.0, 335.0);
+        let map = m.build();
+
+        let road = |orig: OriginalRoad| map.all_roads().iter().find(|r| r.orig_id == orig).unwrap();
+        let entrance = road(entrance);
+        ParkingLoop {
+            bldg: find_bldg(&map, bldg),
+            at_building: road(sides[0]).id,
+            start: entrance
+                .children_forwards
+                .iter()
+                .find(|(_, lt)| *lt == LaneType::Driving)
+                .unwrap()
+                .0,
+            map,
+        }
+    }
+
+    // One car driving to the building. If it's leaving again, the same building has a trip out in
+    // a parked car later.
+    fn sim(&self, name: &str, leave_after: Option<Duration>) -> Sim {
+        let mut sim = Sim::new(&self.map, SimOptions::new(name), &mut Timer::throwaway());
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        sim.schedule_trip(
+            Time::START_OF_DAY,
+            TripSpec::CarAppearing {
+                start_pos: Position::new(self.start, Distance::meters(10.0)),
+                goal: DrivingGoal::ParkNear(self.bldg),
+                vehicle_spec: Scenario::rand_car(&mut rng),
+                ped_speed: Scenario::rand_ped_speed(&mut rng),
+            },
+            &self.map,
+        );
+        if let Some(dt) = leave_after {
+            let back = self.map.get_parent(self.start).children_backwards[0].0;
+            sim.schedule_trip(
+                Time::START_OF_DAY + dt,
+                TripSpec::MaybeUsingParkedCar {
+                    start_bldg: self.bldg,
+                    goal: DrivingGoal::Border(self.map.get_l(back).dst_i, back),
+                    ped_speed: Scenario::rand_ped_speed(&mut rng),
+                },
+                &self.map,
+            );
+        }
+        sim.spawn_all_trips(&self.map, &mut Timer::throwaway(), false);
+        sim
+    }
+
+    // Returns the road where the car parked.
+    fn run(&self, mut sim: Sim) -> RoadID {
+        let events = record_events(&mut sim);
+        sim.just_run_until_done(&self.map, Some(Duration::minutes(30)));
+        let parked = events.borrow().iter().find_map(|(_, ev)| match ev {
+            Event::CarReachedParkingSpot(_, ParkingSpot::Onstreet(l, _)) => {
+                Some(self.map.get_l(*l).parent)
+            }
+            _ => None,
+        });
+        parked.expect("The car never parked")
+    }
+}
+
+fn find_bldg(map: &Map, orig: OriginalBuilding) -> BuildingID {
+    map.all_buildings()
+        .iter()
+        .find(|b| b.osm_way_id == orig.osm_way_id)
+        .unwrap()
+        .id
+}
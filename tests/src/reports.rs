@@ -0,0 +1,230 @@
+use crate::runner::TestRunner;
+use crate::synthetic::SyntheticMap;
+use geom::{Distance, Duration, Time};
+use map_model::raw::DrivingSide;
+use map_model::{IntersectionType, LaneType, Position};
+use sim::{ABTest, Analytics, DrivingGoal, Scenario, SidewalkSpot, SpawnTrip, TripID, TripMode};
+use std::collections::BTreeSet;
+
+pub fn run(t: &mut TestRunner) {
+    t.run_fast("ab_test_report_pairs_trips", |_| {
+        let test = ABTest {
+            test_name: "report".to_string(),
+            map_name: "montlake".to_string(),
+            scenario_name: "weekday".to_string(),
+            edits1_name: "before".to_string(),
+            edits2_name: "after".to_string(),
+        };
+        let at = |secs| Time::START_OF_DAY + Duration::seconds(secs);
+        let secs = Duration::seconds;
+        let now = at(600.0);
+
+        // Trip 0 is faster and trip 1 slower. Trip 2 was aborted in the baseline, trips 3 and 4
+        // only finished on one side, and trip 5 finished too late the second time.
+        let mut before = Analytics::new();
+        before.finished_trips = vec![
+            (at(50.0), TripID(5), Some(TripMode::Drive), secs(50.0)),
+            (at(100.0), TripID(0), Some(TripMode::Drive), secs(100.0)),
+            (at(150.0), TripID(3), Some(TripMode::Walk), secs(150.0)),
+            (at(200.0), TripID(1), Some(TripMode::Drive), secs(200.0)),
+            (at(210.0), TripID(2), None, secs(210.0)),
+        ];
+        let mut after = Analytics::new();
+        after.finished_trips = vec![
+            (at(80.0), TripID(0), Some(TripMode::Drive), secs(80.0)),
+            (at(120.0), TripID(2), Some(TripMode::Walk), secs(120.0)),
+            (at(260.0), TripID(1), Some(TripMode::Drive), secs(260.0)),
+            (at(300.0), TripID(4), Some(TripMode::Bike), secs(300.0)),
+            (at(900.0), TripID(5), Some(TripMode::Drive), secs(900.0)),
+        ];
+
+        let report = headless::ab_test::make_report(&test, now, &before, &after);
+        let lines: Vec<&str> = report.lines().collect();
+        let expect_line = |line: String| {
+            if !lines.contains(&line.as_str()) {
+                panic!("The report is missing \"{}\":\n{}", line, report);
+            }
+        };
+        expect_line(format!("  1 faster: {}", describe(&[secs(20.0)])));
+        expect_line(format!("  1 slower: {}", describe(&[secs(60.0)])));
+        expect_line("  0 unchanged".to_string());
+        expect_line(format!("  Total time saved: {}", secs(-40.0)));
+        expect_line("  aborted: 1 before, 0 after".to_string());
+
+        // Only the trips finished in both, biggest losses first
+        let trips: Vec<&str> = lines
+            .iter()
+            .skip_while(|l| **l != "Trips:")
+            .skip(1)
+            .cloned()
+            .collect();
+        let expected = vec![
+            format!(
+                "  {} (drive): {} -> {} ({})",
+                TripID(1),
+                secs(200.0),
+                secs(260.0),
+                secs(60.0)
+            ),
+            format!(
+                "  {} (drive): {} -> {} ({})",
+                TripID(0),
+                secs(100.0),
+                secs(80.0),
+                secs(-20.0)
+            ),
+        ];
+        if trips != expected {
+            panic!("Expected trips {:?}, but got {:?}", expected, trips);
+        }
+    });
+
+    t.run_fast("batch_writes_one_row_per_run", |_| {
+        // Batch runs load maps by name, so save a small one where they'd look.
+        let mut m = SyntheticMap::new("batch_test", DrivingSide::Right);
+        let a = m.intersection(100.0, 500.0, IntersectionType::Border);
+        let x = m.intersection(400.0, 500.0, IntersectionType::StopSign);
+        let b = m.intersection(700.0, 500.0, IntersectionType::Border);
+        let lanes = vec![LaneType::Driving, LaneType::Parking, LaneType::Sidewalk];
+        m.road(a, x, lanes.clone(), lanes.clone());
+        m.road(x, b, lanes.clone(), lanes);
+        for &(x, y) in &[
+            (250.0, 470.0),
+            (250.0, 530.0),
+            (550.0, 470.0),
+            (550.0, 530.0),
+        ] {
+            m.building(x, y);
+        }
+        let map = m.build();
+        map.save();
+
+        // Cars crossing both ways and people walking between buildings, at fixed times
+        let mut scenario = Scenario::empty(&map, "commute");
+        let (r1, r2) = (&map.all_roads()[0], &map.all_roads()[1]);
+        let lane = |lanes: &Vec<(_, LaneType)>| {
+            lanes
+                .iter()
+                .find(|(_, lt)| *lt == LaneType::Driving)
+                .unwrap()
+                .0
+        };
+        let bldgs = map.all_buildings();
+        for i in 0..5 {
+            let depart = Time::START_OF_DAY + Duration::seconds(10.0 * (i as f64));
+            scenario.individ_trips.push(SpawnTrip::CarAppearing {
+                depart,
+                start: Position::new(lane(&r1.children_forwards), Distance::meters(7.0)),
+                goal: DrivingGoal::Border(r2.dst_i, lane(&r2.children_forwards)),
+                is_bike: false,
+            });
+            scenario.individ_trips.push(SpawnTrip::CarAppearing {
+                depart,
+                start: Position::new(lane(&r2.children_backwards), Distance::meters(7.0)),
+                goal: DrivingGoal::Border(r1.src_i, lane(&r1.children_backwards)),
+                is_bike: false,
+            });
+            scenario.individ_trips.push(SpawnTrip::JustWalking(
+                depart,
+                SidewalkSpot::building(bldgs[i % 2].id, &map),
+                SidewalkSpot::building(bldgs[2 + i % 2].id, &map),
+            ));
+        }
+        scenario.save();
+
+        let dir = std::env::temp_dir();
+        let manifest = dir.join("abst_batch.json").to_str().unwrap().to_string();
+        let output = dir.join("abst_batch.csv").to_str().unwrap().to_string();
+        let run_batch = |time_limit_secs: f64| -> Result<String, std::io::Error> {
+            std::fs::write(
+                &manifest,
+                format!(
+                    r#"{{
+                        "maps": ["batch_test"],
+                        "scenarios": ["commute"],
+                        "seeds": [1, 2],
+                        "options": [{{"name": "default"}}, {{"name": "accel", "use_acceleration": true}}],
+                        "time_limit_secs": {}
+                    }}"#,
+                    time_limit_secs
+                ),
+            )?;
+            headless::batch::run(manifest.clone(), output.clone())?;
+            std::fs::read_to_string(&output)
+        };
+        let result = run_batch(3600.0);
+        // The people walking take about 5 minutes.
+        let cut_short = run_batch(120.0);
+        std::fs::remove_file(abstutil::path_map("batch_test")).unwrap();
+        std::fs::remove_file(abstutil::path_scenario("batch_test", "commute")).unwrap();
+        let csv = result.unwrap();
+        let cut_short = cut_short.unwrap();
+        let mut lines = csv.lines();
+        let mut expected_header = vec![
+            "map", "edits", "scenario", "seed", "options", "end_time", "finished",
+        ]
+        .into_iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>();
+        for mode in &["walk", "bike", "transit", "drive"] {
+            for col in &["count", "p50", "p90", "p99"] {
+                expected_header.push(format!("{}_{}", mode, col));
+            }
+        }
+        expected_header.push("aborted_trips".to_string());
+        for col in &["count", "p50", "p90", "p99"] {
+            expected_header.push(format!("bus_headway_{}", col));
+        }
+        if lines.next() != Some(expected_header.join(",").as_str()) {
+            panic!("Wrong header: {:?}", csv.lines().next());
+        }
+
+        let mut runs = BTreeSet::new();
+        for line in lines {
+            let cols: Vec<&str> = line.split(',').collect();
+            if cols.len() != expected_header.len() {
+                panic!("{} has {} columns", line, cols.len());
+            }
+            if cols[..3] != ["batch_test", "no_edits", "commute"] || cols[6] != "true" {
+                panic!("Unexpected row {}", line);
+            }
+            if cols[7] != "5" || cols[19] != "10" {
+                panic!("Expected 5 walking and 10 driving trips in {}", line);
+            }
+            runs.insert((cols[3].to_string(), cols[4].to_string()));
+        }
+        let expected_runs: BTreeSet<(String, String)> = vec![
+            ("1", "default"),
+            ("1", "accel"),
+            ("2", "default"),
+            ("2", "accel"),
+        ]
+        .into_iter()
+        .map(|(seed, opts)| (seed.to_string(), opts.to_string()))
+        .collect();
+        if runs != expected_runs || csv.lines().count() != 5 {
+            panic!(
+                "Expected one row per run {:?}, but got:\n{}",
+                expected_runs, csv
+            );
+        }
+
+        for line in cut_short.lines().skip(1) {
+            let cols: Vec<&str> = line.split(',').collect();
+            if cols[5] != "00:02:00.0" || cols[6] != "false" || cols[7] != "0" {
+                panic!("The run should've stopped at the time limit: {}", line);
+            }
+        }
+        if cut_short.lines().count() != 5 {
+            panic!("Runs over the time limit are still reported:\n{}", cut_short);
+        }
+    });
+}
+
+fn describe(durations: &[Duration]) -> String {
+    let mut distrib = geom::DurationHistogram::new();
+    for d in durations {
+        distrib.add(*d);
+    }
+    distrib.describe()
+}
@@ -0,0 +1,234 @@
+use crate::runner::{record_events, RecordedEvents, TestRunner};
+use crate::synthetic::SyntheticMap;
+use abstutil::Timer;
+use geom::{Distance, Duration, Time};
+use map_model::raw::DrivingSide;
+use map_model::{
+    osm, IntersectionID, IntersectionType, LaneID, LaneType, Map, Position, RoadID, Traversable,
+};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use sim::{AgentID, DrivingGoal, Event, Scenario, SidewalkSpot, Sim, SimOptions, TripSpec};
+
+pub fn run(t: &mut TestRunner) {
+    t.run_slow("roundabout_keeps_flowing", |h| {
+        let r = Roundabout::new("roundabout_keeps_flowing", "25 mph");
+        let mut sim = r.new_sim("roundabout_keeps_flowing");
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        // Heavy traffic from every entry to the exit across the ring, and people walking most of
+        // the way around
+        for i in 0..100 {
+            for from in 0..4 {
+                r.drive(
+                    &mut sim,
+                    &mut rng,
+                    Time::START_OF_DAY + Duration::seconds(3.0 * (i as f64) + 0.5 * (from as f64)),
+                    from,
+                    (from + 2) % 4,
+                );
+            }
+        }
+        for i in 0..10 {
+            for idx in 0..4 {
+                let sidewalk = r.legs[idx].2;
+                let goal = r.legs[(idx + 3) % 4].1;
+                sim.schedule_trip(
+                    Time::START_OF_DAY + Duration::seconds(20.0 * (i as f64)),
+                    TripSpec::JustWalking {
+                        start: SidewalkSpot::suddenly_appear(
+                            sidewalk,
+                            r.map.get_l(sidewalk).length() - Distance::meters(20.0),
+                            &r.map,
+                        ),
+                        goal: SidewalkSpot::end_at_border(goal, &r.map).unwrap(),
+                        ped_speed: Scenario::rand_ped_speed(&mut rng),
+                    },
+                    &r.map,
+                );
+            }
+        }
+        sim.spawn_all_trips(&r.map, &mut Timer::throwaway(), true);
+        h.setup_done(&mut sim, &r.map);
+
+        // Entering traffic gives way, so the ring itself never gridlocks.
+        sim.just_run_until_done(&r.map, Some(Duration::minutes(30)));
+    });
+
+    t.run_slow("roundabout_entry_passes_blocked_exit", |h| {
+        // Nobody can leave north quickly, so traffic heading there backs up onto the ring.
+        let r = Roundabout::new("roundabout_entry_passes_blocked_exit", "2 mph");
+        let mut sim = r.new_sim("roundabout_entry_passes_blocked_exit");
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        for i in 0..100 {
+            let at = Time::START_OF_DAY + Duration::seconds(2.0 * (i as f64));
+            r.drive(&mut sim, &mut rng, at, 3, 0);
+        }
+        // Entering from the north crosses the path of anybody leaving there, but heads the other
+        // way around the ring.
+        for i in 0..20 {
+            let at = Time::START_OF_DAY + Duration::seconds(30.0 + 5.0 * (i as f64));
+            r.drive(&mut sim, &mut rng, at, 0, 1);
+        }
+        sim.spawn_all_trips(&r.map, &mut Timer::throwaway(), true);
+        let events = record_events(&mut sim);
+        h.setup_done(&mut sim, &r.map);
+        sim.just_run_until_done(&r.map, Some(Duration::minutes(30)));
+
+        // It takes many minutes to clear the backup, but the ring is free past the exit, so nobody
+        // entering there should wait for it.
+        let (entered, exited) = r.through_north(&events);
+        let last_entered = *entered.last().unwrap();
+        let last_exited = *exited.last().unwrap();
+        if entered.len() != 20 || last_entered > Time::START_OF_DAY + Duration::minutes(4) {
+            panic!(
+                "Cars entered the ring from the north at {:?}, and the exit cleared at {}",
+                entered, last_exited
+            );
+        }
+        if last_exited < last_entered {
+            panic!(
+                "The exit cleared at {}, before the last car entered at {}, so this doesn't test \
+                 anything",
+                last_exited, last_entered
+            );
+        }
+    });
+}
+
+// Four intersections on a ring, where traffic circulates north, west, south, east. The west,
+// south, and east ones have a two-way road out to a border. From the north one, a one-way road
+// leaves to the northwest, and another one enters from the northeast. The exit is split in two, so
+// that its speed limit doesn't slow down turns at the ring itself.
+struct Roundabout {
+    map: Map,
+    north: IntersectionID,
+    // The first half of the exit to the northwest
+    north_exit: LaneID,
+    // Starting from the north: the incoming driving lane, the border to leave by, a sidewalk
+    // leading to the ring, and the outgoing driving lane ending at that border
+    legs: Vec<(LaneID, IntersectionID, LaneID, LaneID)>,
+}
+
+impl Roundabout {
+    // The far half of the exit to the northwest has this speed limit.
+    fn new(name: &str, north_exit_speed: &str) -> Roundabout {
+        let mut m = SyntheticMap::new(name, DrivingSide::Right);
+        let ring = [
+            m.intersection(500.0, 400.0, IntersectionType::StopSign),
+            m.intersection(400.0, 500.0, IntersectionType::StopSign),
+            m.intersection(500.0, 600.0, IntersectionType::StopSign),
+            m.intersection(600.0, 500.0, IntersectionType::StopSign),
+        ];
+        let one_way = vec![LaneType::Driving, LaneType::Sidewalk];
+        let sidewalk = vec![LaneType::Sidewalk];
+        for idx in 0..4 {
+            let r = m.road(
+                ring[idx],
+                ring[(idx + 1) % 4],
+                one_way.clone(),
+                sidewalk.clone(),
+            );
+            m.raw
+                .roads
+                .get_mut(&r)
+                .unwrap()
+                .osm_tags
+                .insert("junction".to_string(), "roundabout".to_string());
+        }
+
+        let midway = m.intersection(450.0, 300.0, IntersectionType::StopSign);
+        let nw = m.intersection(400.0, 200.0, IntersectionType::Border);
+        let ne = m.intersection(700.0, 100.0, IntersectionType::Border);
+        let north_exit = m.road(ring[0], midway, one_way.clone(), sidewalk.clone());
+        let slow = m.road(midway, nw, one_way.clone(), sidewalk.clone());
+        m.raw
+            .roads
+            .get_mut(&slow)
+            .unwrap()
+            .osm_tags
+            .insert(osm::MAXSPEED.to_string(), north_exit_speed.to_string());
+        let north_entry = m.road(ne, ring[0], one_way, sidewalk);
+
+        let two_way = vec![LaneType::Driving, LaneType::Sidewalk];
+        let mut others = Vec::new();
+        for (idx, (x, y)) in vec![(100.0, 500.0), (500.0, 900.0), (900.0, 500.0)]
+            .into_iter()
+            .enumerate()
+        {
+            let border = m.intersection(x, y, IntersectionType::Border);
+            others.push(m.road(border, ring[idx + 1], two_way.clone(), two_way.clone()));
+        }
+        let map = m.build();
+
+        let road = |orig| map.all_roads().iter().find(|r| r.orig_id == orig).unwrap();
+        let lane = |r: RoadID, fwd: bool, lt: LaneType| {
+            let r = map.get_r(r);
+            let children = if fwd {
+                &r.children_forwards
+            } else {
+                &r.children_backwards
+            };
+            children.iter().find(|(_, t)| *t == lt).unwrap().0
+        };
+        let (exit, slow, entry) = (road(north_exit), road(slow), road(north_entry));
+        let mut legs = vec![(
+            lane(entry.id, true, LaneType::Driving),
+            slow.dst_i,
+            lane(entry.id, true, LaneType::Sidewalk),
+            lane(slow.id, true, LaneType::Driving),
+        )];
+        for orig in others {
+            let r = road(orig);
+            legs.push((
+                lane(r.id, true, LaneType::Driving),
+                r.src_i,
+                lane(r.id, true, LaneType::Sidewalk),
+                lane(r.id, false, LaneType::Driving),
+            ));
+        }
+        Roundabout {
+            north: exit.src_i,
+            north_exit: lane(exit.id, true, LaneType::Driving),
+            legs,
+            map,
+        }
+    }
+
+    fn new_sim(&self, name: &str) -> Sim {
+        Sim::new(&self.map, SimOptions::new(name), &mut Timer::throwaway())
+    }
+
+    // Drive from one leg to another
+    fn drive(&self, sim: &mut Sim, rng: &mut XorShiftRng, at: Time, from: usize, to: usize) {
+        let (start, _, _, _) = self.legs[from];
+        let (_, border, _, end) = self.legs[to];
+        sim.schedule_trip(
+            at,
+            TripSpec::CarAppearing {
+                start_pos: Position::new(start, Distance::meters(7.0)),
+                goal: DrivingGoal::Border(border, end),
+                vehicle_spec: Scenario::rand_car(rng),
+                ped_speed: Scenario::rand_ped_speed(rng),
+            },
+            &self.map,
+        );
+    }
+
+    // When cars entered the ring from the north, and when cars left north
+    fn through_north(&self, events: &RecordedEvents) -> (Vec<Time>, Vec<Time>) {
+        let (entry, exit) = (self.legs[0].0, self.north_exit);
+        let mut entered = Vec::new();
+        let mut exited = Vec::new();
+        for (time, ev) in events.borrow().iter() {
+            if let Event::AgentEntersTraversable(AgentID::Car(_), Traversable::Turn(t)) = ev {
+                if t.parent == self.north && t.src == entry {
+                    entered.push(*time);
+                }
+                if t.parent == self.north && t.dst == exit {
+                    exited.push(*time);
+                }
+            }
+        }
+        (entered, exited)
+    }
+}
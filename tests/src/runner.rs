@@ -1,10 +1,13 @@
 // https://github.com/rust-lang/rust/issues/50297 would hopefully obsolete this approach.
 
 use gag::Redirect;
-use map_model::{BuildingID, LaneID};
+use geom::Time;
+use map_model::{BuildingID, LaneID, Map};
 use rand_xorshift::XorShiftRng;
-use sim::{CarID, ParkingSpot, Scenario, Sim};
+use sim::{CarID, Event, EventSink, ParkingSpot, Scenario, Sim};
+use std::cell::RefCell;
 use std::io::Write;
+use std::rc::Rc;
 use termion;
 use termion::color;
 
@@ -248,11 +251,11 @@ pub struct TestHelper {
 }
 
 impl TestHelper {
-    pub fn setup_done(&mut self, sim: &mut Sim) {
+    pub fn setup_done(&mut self, sim: &mut Sim, map: &Map) {
         if self.debug_with_savestate.is_some() {
             panic!("Can't call setup_done twice in one test");
         }
-        self.debug_with_savestate = Some(sim.save());
+        self.debug_with_savestate = Some(sim.save(map));
     }
 
     pub fn seed_parked_cars(
@@ -279,3 +282,20 @@ pub enum Filter {
     Slow,
     Fast,
 }
+
+pub type RecordedEvents = Rc<RefCell<Vec<(Time, Event)>>>;
+
+// Keeps every event from now on, to check afterwards.
+pub fn record_events(sim: &mut Sim) -> RecordedEvents {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    sim.add_event_sink(Box::new(Recorder(events.clone())));
+    events
+}
+
+struct Recorder(RecordedEvents);
+
+impl EventSink for Recorder {
+    fn event(&mut self, time: Time, ev: &Event) {
+        self.0.borrow_mut().push((time, ev.clone()));
+    }
+}
@@ -0,0 +1,409 @@
+use crate::runner::TestRunner;
+use crate::synthetic::SyntheticMap;
+use abstutil::Timer;
+use geom::{Distance, Duration, Speed, Time};
+use map_model::raw::DrivingSide;
+use map_model::{
+    coordination, ActuatedPhase, ControlTrafficSignal, EditCmd, IntersectionID, IntersectionType,
+    LaneType, Map, PathConstraints, PathRequest, PathStep, Phase, Position, Road, RoadID,
+    TurnGroupID, TurnType,
+};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use sim::{DrivingGoal, Scenario, Sim, SimOptions, TripSpec};
+use std::collections::BTreeSet;
+
+pub fn run(t: &mut TestRunner) {
+    t.run_fast("green_wave_offsets_follow_travel_time", |_| {
+        // Three signals along an east-west corridor, unevenly spaced, each with a side street
+        let mut m = SyntheticMap::new("green_wave", DrivingSide::Right);
+        let lanes = vec![LaneType::Driving, LaneType::Sidewalk];
+        let mut prev = m.intersection(50.0, 500.0, IntersectionType::Border);
+        let mut signals = Vec::new();
+        for x in &[200.0, 380.0, 650.0] {
+            let i = m.intersection(*x, 500.0, IntersectionType::TrafficSignal);
+            m.road(prev, i, lanes.clone(), lanes.clone());
+            let north = m.intersection(*x, 350.0, IntersectionType::Border);
+            let south = m.intersection(*x, 650.0, IntersectionType::Border);
+            m.road(north, i, lanes.clone(), lanes.clone());
+            m.road(i, south, lanes.clone(), lanes.clone());
+            signals.push(i);
+            prev = i;
+        }
+        let east = m.intersection(720.0, 500.0, IntersectionType::Border);
+        m.road(prev, east, lanes.clone(), lanes);
+        let mut map = m.build();
+
+        let corridor: Vec<IntersectionID> = signals
+            .into_iter()
+            .map(|orig| {
+                map.all_intersections()
+                    .iter()
+                    .find(|i| i.orig_id == orig)
+                    .unwrap()
+                    .id
+            })
+            .collect();
+        // Make the side street go first at the middle signal
+        let mut middle = map.get_traffic_signal(corridor[1]).clone();
+        middle.phases.rotate_left(1);
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(EditCmd::ChangeTrafficSignal(middle));
+        map.apply_edits(edits, &mut Timer::throwaway());
+
+        let speed = Speed::meters_per_second(10.0);
+        let cycle = Duration::seconds(90.0);
+        let results = match coordination::green_wave(&map, &corridor, speed, Some(cycle)) {
+            Ok(results) => results,
+            Err(err) => panic!("Couldn't coordinate {:?}: {}", corridor, err),
+        };
+
+        // A vehicle leaving the first signal as its corridor phase starts should reach every
+        // signal right as that signal's corridor phase starts.
+        let mut travel_time = Duration::ZERO;
+        let mut phase_starts = Vec::new();
+        for (idx, signal) in results.iter().enumerate() {
+            if signal.id != corridor[idx] {
+                panic!("Signals came back out of order: {} at {}", signal.id, idx);
+            }
+            if signal.cycle_length() != cycle {
+                panic!("{} has cycle length {}", signal.id, signal.cycle_length());
+            }
+            let from = if idx > 0 {
+                let r = road_between(&map, corridor[idx - 1], corridor[idx]);
+                travel_time += map.get_r(r).center_pts.length() / speed;
+                Some(r)
+            } else {
+                None
+            };
+            let to = corridor
+                .get(idx + 1)
+                .map(|next| road_between(&map, corridor[idx], *next));
+            let phase_start = corridor_phase_start(signal, from, to);
+            phase_starts.push(phase_start);
+
+            let arrival = (travel_time + signal.offset) % cycle;
+            let diff = (arrival - phase_start).inner_seconds().abs();
+            if diff > 0.01 && (diff - cycle.inner_seconds()).abs() > 0.01 {
+                panic!(
+                    "{} has offset {}. Arriving after {} is {} into the cycle, but the corridor \
+                     phase starts at {}",
+                    signal.id, signal.offset, travel_time, arrival, phase_start
+                );
+            }
+        }
+        if phase_starts[1] == Duration::ZERO {
+            panic!("The middle signal should start with the side street");
+        }
+
+        if coordination::green_wave(&map, &[corridor[0], corridor[2]], speed, None).is_ok() {
+            panic!("Coordinated signals that aren't adjacent");
+        }
+    });
+
+    t.run_fast("long_red_left_loses_to_three_rights", |_| {
+        // With a stop sign, turn left directly. When the left only gets a short yield phase at a
+        // signal, going straight and around the block is faster.
+        for &(it, expect_loop) in &[
+            (IntersectionType::StopSign, false),
+            (IntersectionType::TrafficSignal, true),
+        ] {
+            let (map, req, around_the_block) = three_rights(it);
+            let path = match map.pathfind(req.clone()) {
+                Some(path) => path,
+                None => panic!("No path for {}", req),
+            };
+            let goes_around = path.get_steps().iter().any(|step| match step {
+                PathStep::Lane(l) => map.get_l(*l).parent == around_the_block,
+                _ => false,
+            });
+            if goes_around != expect_loop {
+                panic!(
+                    "With a {:?}, going around the block is {}, but should be {}: {:?}",
+                    it,
+                    goes_around,
+                    expect_loop,
+                    path.get_steps()
+                );
+            }
+        }
+    });
+
+    t.run_fast("actuated_signal_gaps_out_extends_and_skips", |_| {
+        let close = |d: Duration, secs: f64| (d.inner_seconds() - secs).abs() < 0.15;
+        let describe = |phases: &Vec<(Time, usize, Duration)>| {
+            phases
+                .iter()
+                .map(|(start, idx, d)| format!("phase {} at {} for {}", idx, start, d))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        // Nobody's around, so every phase ends at min_green.
+        let phases = run_actuated_signal(false, false, false);
+        if phases.len() < 10 || phases.iter().any(|(_, _, d)| !close(*d, 5.0)) {
+            panic!("Phases should gap out after 5s: {}", describe(&phases));
+        }
+
+        // Cars keep arriving from the south, so the north-south phase lasts until max_green. The
+        // empty east-west phase still gets its min_green.
+        let phases = run_actuated_signal(false, true, false);
+        let ns: Vec<Duration> = phases
+            .iter()
+            .filter(|(_, idx, _)| *idx == 0)
+            .map(|(_, _, d)| *d)
+            .collect();
+        if !ns.iter().any(|d| close(*d, 20.0))
+            || ns.iter().any(|d| d.inner_seconds() > 20.15)
+            || phases
+                .iter()
+                .any(|(_, idx, d)| *idx == 1 && !close(*d, 5.0))
+        {
+            panic!(
+                "North-south should extend to 20s, and east-west should stay 5s: {}",
+                describe(&phases)
+            );
+        }
+
+        // When east-west can be skipped, it only runs once, for the one car heading east.
+        let phases = run_actuated_signal(true, true, true);
+        let ew: Vec<Time> = phases
+            .iter()
+            .filter(|(_, idx, _)| *idx == 1)
+            .map(|(start, _, _)| *start)
+            .collect();
+        if ew.len() != 1 || ew[0] < Time::START_OF_DAY + Duration::seconds(50.0) {
+            panic!(
+                "The east-west phase should only run after the eastbound car appears: {}",
+                describe(&phases)
+            );
+        }
+    });
+}
+
+// Runs a signal for 2 minutes. Phase 0 protects going straight north and south, phase 1 east and
+// west. Both are actuated, with min_green 5s, max_green 20s, and extension 3s. Optionally, a car
+// appears every 2s south of the signal, heading north, and one car heads east at 50s. Returns when
+// each phase started, which phase, and how long it lasted, except for the last unfinished one.
+fn run_actuated_signal(
+    skip_if_no_demand: bool,
+    northbound_cars: bool,
+    eastbound_car: bool,
+) -> Vec<(Time, usize, Duration)> {
+    let mut m = SyntheticMap::new("actuated_signal", DrivingSide::Right);
+    let lanes = vec![LaneType::Driving];
+    let center = m.intersection(500.0, 500.0, IntersectionType::TrafficSignal);
+    let south = m.intersection(500.0, 700.0, IntersectionType::Border);
+    let north = m.intersection(500.0, 300.0, IntersectionType::Border);
+    let west = m.intersection(300.0, 500.0, IntersectionType::Border);
+    let east = m.intersection(700.0, 500.0, IntersectionType::Border);
+    let from_south = m.road(south, center, lanes.clone(), lanes.clone());
+    let to_north = m.road(center, north, lanes.clone(), lanes.clone());
+    let from_west = m.road(west, center, lanes.clone(), lanes.clone());
+    let to_east = m.road(center, east, lanes.clone(), lanes);
+    let mut map = m.build();
+
+    let road = |orig| map.all_roads().iter().find(|r| r.orig_id == orig).unwrap();
+    let (from_south, to_north) = (road(from_south), road(to_north));
+    let north_south = [from_south.id, to_north.id];
+    let i = from_south.dst_i;
+    // (depart, start lane, end lane)
+    let mut trips = Vec::new();
+    if northbound_cars {
+        for step in 0..60 {
+            trips.push((
+                Time::START_OF_DAY + Duration::seconds(2.0 * (step as f64)),
+                from_south.children_forwards[0].0,
+                to_north.children_forwards[0].0,
+            ));
+        }
+    }
+    if eastbound_car {
+        trips.push((
+            Time::START_OF_DAY + Duration::seconds(50.0),
+            road(from_west).children_forwards[0].0,
+            road(to_east).children_forwards[0].0,
+        ));
+    }
+
+    let mut signal = map.get_traffic_signal(i).clone();
+    let mut phases = vec![Phase::new(), Phase::new()];
+    for g in signal.turn_groups.values() {
+        let phase = &mut phases[if north_south.contains(&g.id.from) {
+            0
+        } else {
+            1
+        }];
+        if g.turn_type == TurnType::Straight {
+            phase.protected_groups.insert(g.id);
+        } else {
+            phase.yield_groups.insert(g.id);
+        }
+    }
+    for phase in &mut phases {
+        phase.actuated = Some(ActuatedPhase {
+            min_green: Duration::seconds(5.0),
+            max_green: Duration::seconds(20.0),
+            extension: Duration::seconds(3.0),
+            skip_if_no_demand,
+        });
+    }
+    signal.phases = phases;
+    let signal = match signal.validate() {
+        Ok(signal) => signal,
+        Err(err) => panic!("Bad signal: {}", err),
+    };
+    let mut edits = map.get_edits().clone();
+    edits.commands.push(EditCmd::ChangeTrafficSignal(signal));
+    map.apply_edits(edits, &mut Timer::throwaway());
+    map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+
+    let mut sim = Sim::new(
+        &map,
+        SimOptions::new("actuated_signal"),
+        &mut Timer::throwaway(),
+    );
+    let mut rng = XorShiftRng::seed_from_u64(42);
+    for (depart, start, end) in trips {
+        sim.schedule_trip(
+            depart,
+            TripSpec::CarAppearing {
+                start_pos: Position::new(start, Distance::meters(7.0)),
+                goal: DrivingGoal::Border(map.get_l(end).dst_i, end),
+                vehicle_spec: Scenario::rand_car(&mut rng),
+                ped_speed: Scenario::rand_ped_speed(&mut rng),
+            },
+            &map,
+        );
+    }
+    sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
+
+    let mut phases = Vec::new();
+    let mut current = (
+        Time::START_OF_DAY,
+        sim.current_phase_and_remaining_time(i, &map).0,
+    );
+    while sim.time() < Time::START_OF_DAY + Duration::minutes(2) {
+        sim.step(&map, Duration::seconds(0.1));
+        let idx = sim.current_phase_and_remaining_time(i, &map).0;
+        if idx != current.1 {
+            phases.push((current.0, current.1, sim.time() - current.0));
+            current = (sim.time(), idx);
+        }
+    }
+    phases
+}
+
+// A car heading north wants to go west at an intersection. Instead, it could go straight and
+// turn right 3 times around a small block. Returns the path request and the road behind the block.
+fn three_rights(it: IntersectionType) -> (Map, PathRequest, RoadID) {
+    let mut m = SyntheticMap::new(&format!("three_rights_{:?}", it), DrivingSide::Right);
+    let lanes = vec![LaneType::Driving, LaneType::Sidewalk];
+    let center = m.intersection(400.0, 600.0, it);
+    let south = m.intersection(400.0, 800.0, IntersectionType::Border);
+    let west = m.intersection(200.0, 600.0, IntersectionType::Border);
+    let north = m.intersection(400.0, 540.0, IntersectionType::StopSign);
+    let north_east = m.intersection(460.0, 540.0, IntersectionType::StopSign);
+    let east = m.intersection(460.0, 600.0, IntersectionType::StopSign);
+    let from = m.road(south, center, lanes.clone(), lanes.clone());
+    let to = m.road(center, west, lanes.clone(), lanes.clone());
+    let up = m.road(center, north, lanes.clone(), lanes.clone());
+    let behind = m.road(north, north_east, lanes.clone(), lanes.clone());
+    m.road(north_east, east, lanes.clone(), lanes.clone());
+    let back = m.road(east, center, lanes.clone(), lanes);
+    let mut map = m.build();
+
+    let road = |orig| map.all_roads().iter().find(|r| r.orig_id == orig).unwrap();
+    let driving = |r: &Road| {
+        r.children_forwards
+            .iter()
+            .find(|(_, lt)| *lt == LaneType::Driving)
+            .unwrap()
+            .0
+    };
+    let (start, end) = (driving(road(from)), driving(road(to)));
+    let (from, to, up, back) = (road(from).id, road(to).id, road(up).id, road(back).id);
+    let behind = road(behind).id;
+
+    if it == IntersectionType::TrafficSignal {
+        // 90s phases going straight each way, then 5s for crosswalks, with everything else
+        // yielding
+        let mut signal = map.get_traffic_signal(map.get_l(start).dst_i).clone();
+        let groups: Vec<TurnGroupID> = signal.turn_groups.keys().cloned().collect();
+        let straight = |r1: RoadID, r2: RoadID| -> BTreeSet<TurnGroupID> {
+            groups
+                .iter()
+                .filter(|g| {
+                    g.crosswalk.is_none()
+                        && ((g.from == r1 && g.to == r2) || (g.from == r2 && g.to == r1))
+                })
+                .cloned()
+                .collect()
+        };
+        let mut north_south = Phase::new();
+        north_south.protected_groups = straight(from, up);
+        north_south.duration = Duration::seconds(90.0);
+        let mut east_west = Phase::new();
+        east_west.protected_groups = straight(back, to);
+        east_west.duration = Duration::seconds(90.0);
+        let mut rest = Phase::new();
+        for g in &groups {
+            if g.crosswalk.is_some() {
+                rest.protected_groups.insert(*g);
+            } else if !north_south.protected_groups.contains(g)
+                && !east_west.protected_groups.contains(g)
+            {
+                rest.yield_groups.insert(*g);
+            }
+        }
+        rest.duration = Duration::seconds(5.0);
+        signal.phases = vec![north_south, east_west, rest];
+        let signal = match signal.validate() {
+            Ok(signal) => signal,
+            Err(err) => panic!("Bad signal: {}", err),
+        };
+
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(EditCmd::ChangeTrafficSignal(signal));
+        map.apply_edits(edits, &mut Timer::throwaway());
+        map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+    }
+
+    let req = PathRequest {
+        start: Position::new(start, Distance::ZERO),
+        end: Position::new(end, map.get_l(end).length()),
+        constraints: PathConstraints::Car,
+    };
+    (map, req, behind)
+}
+
+fn road_between(map: &Map, i1: IntersectionID, i2: IntersectionID) -> RoadID {
+    *map.get_i(i1)
+        .roads
+        .iter()
+        .find(|r| {
+            let r = map.get_r(**r);
+            (r.src_i == i1 && r.dst_i == i2) || (r.src_i == i2 && r.dst_i == i1)
+        })
+        .unwrap()
+}
+
+// When the first phase protecting straight movement along the corridor begins
+fn corridor_phase_start(
+    signal: &ControlTrafficSignal,
+    from: Option<RoadID>,
+    to: Option<RoadID>,
+) -> Duration {
+    let mut start = Duration::ZERO;
+    for phase in &signal.phases {
+        if phase.protected_groups.iter().any(|g| {
+            g.crosswalk.is_none()
+                && from.map(|r| g.from == r).unwrap_or(true)
+                && to.map(|r| g.to == r).unwrap_or(true)
+        }) {
+            return start;
+        }
+        start += phase.duration;
+    }
+    panic!("No phase at {} protects the corridor", signal.id);
+}
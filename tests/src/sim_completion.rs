@@ -1,15 +1,67 @@
 use crate::runner::TestRunner;
 use abstutil::Timer;
 use geom::Duration;
-use sim::{Scenario, SimFlags};
+use map_model::{ActuatedPhase, EditCmd};
+use sim::{Scenario, Sim, SimFlags};
 
 pub fn run(t: &mut TestRunner) {
     t.run_slow("small_spawn_completes", |h| {
         let mut flags = SimFlags::for_test("aorta_model_completes");
         flags.opts.savestate_every = Some(Duration::seconds(30.0));
-        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway());
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway()).unwrap();
         Scenario::small_run(&map).instantiate(&mut sim, &map, &mut rng, &mut Timer::throwaway());
-        h.setup_done(&mut sim);
+        h.setup_done(&mut sim, &map);
+        sim.just_run_until_done(&map, Some(Duration::minutes(70)));
+    });
+
+    t.run_slow("small_spawn_completes_with_mid_block_lanechanging", |h| {
+        let mut flags = SimFlags::for_test("mid_block_lanechanging_completes");
+        flags.opts.mid_block_lanechanging = true;
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway()).unwrap();
+        Scenario::small_run(&map).instantiate(&mut sim, &map, &mut rng, &mut Timer::throwaway());
+        h.setup_done(&mut sim, &map);
+        sim.just_run_until_done(&map, Some(Duration::minutes(70)));
+        if sim.get_analytics().lane_changes.is_empty() {
+            panic!("Nobody changed lanes partway along a road");
+        }
+    });
+
+    t.run_slow("small_spawn_completes_with_rerouting", |h| {
+        let mut flags = SimFlags::for_test("rerouting_completes");
+        flags.opts.reroute_after_blocked = Some(Duration::seconds(30.0));
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway()).unwrap();
+        Scenario::small_run(&map).instantiate(&mut sim, &map, &mut rng, &mut Timer::throwaway());
+        h.setup_done(&mut sim, &map);
+        sim.just_run_until_done(&map, Some(Duration::minutes(70)));
+        if sim.get_analytics().reroutes.is_empty() {
+            panic!("Nobody rerouted after being stuck");
+        }
+    });
+
+    t.run_slow("small_spawn_completes_with_actuated_signals", |h| {
+        let flags = SimFlags::for_test("actuated_signals_complete");
+        let (mut map, _, mut rng) = flags.load(&mut Timer::throwaway()).unwrap();
+        let mut edits = map.get_edits().clone();
+        for i in map.all_intersections() {
+            if let Some(signal) = map.maybe_get_traffic_signal(i.id) {
+                let mut signal = signal.clone();
+                for phase in signal.phases.iter_mut() {
+                    phase.actuated = Some(ActuatedPhase {
+                        min_green: Duration::seconds(5.0),
+                        max_green: phase.duration.max(Duration::seconds(5.0)),
+                        extension: Duration::seconds(3.0),
+                        skip_if_no_demand: true,
+                    });
+                }
+                edits.commands.push(EditCmd::ChangeTrafficSignal(signal));
+            }
+        }
+        map.apply_edits(edits, &mut Timer::throwaway());
+        map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+
+        let mut sim = Sim::new(&map, flags.opts.clone(), &mut Timer::throwaway());
+        Scenario::small_run(&map).instantiate(&mut sim, &map, &mut rng, &mut Timer::throwaway());
+        h.setup_done(&mut sim, &map);
         sim.just_run_until_done(&map, Some(Duration::minutes(70)));
     });
 }
@@ -1,12 +1,20 @@
 use crate::runner::TestRunner;
+use crate::synthetic::SyntheticMap;
 use abstutil::Timer;
-use geom::Duration;
-use sim::{Scenario, Sim, SimFlags, SimOptions};
+use geom::{Distance, Duration, Time};
+use map_model::raw::DrivingSide;
+use map_model::{EditCmd, IntersectionType, LaneType, Map, Position};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use sim::{
+    DrivingGoal, Scenario, SidewalkSpot, Sim, SimFlags, SimOptions, TripSpec, SAVESTATE_VERSION,
+};
 
 pub fn run(t: &mut TestRunner) {
     t.run_slow("serialization", |_| {
-        let (map, mut sim, mut rng) =
-            SimFlags::for_test("serialization").load(&mut Timer::throwaway());
+        let (map, mut sim, mut rng) = SimFlags::for_test("serialization")
+            .load(&mut Timer::throwaway())
+            .unwrap();
         Scenario::small_run(&map).instantiate(&mut sim, &map, &mut rng, &mut Timer::throwaway());
 
         // Does savestating produce the same string?
@@ -15,47 +23,51 @@ pub fn run(t: &mut TestRunner) {
         assert_eq!(save1, save2);
     });
 
-    t.run_slow("from_scratch", |_| {
-        println!("Creating two simulations");
-        let flags = SimFlags::for_test("from_scratch_1");
-        let (map, mut sim1, _) = flags.load(&mut Timer::throwaway());
-        let mut sim2 = Sim::new(
-            &map,
-            SimOptions::new("from_scratch_2"),
-            &mut Timer::throwaway(),
-        );
-        Scenario::small_run(&map).instantiate(
-            &mut sim1,
-            &map,
-            &mut flags.make_rng(),
-            &mut Timer::throwaway(),
-        );
-        Scenario::small_run(&map).instantiate(
-            &mut sim2,
-            &map,
-            &mut flags.make_rng(),
-            &mut Timer::throwaway(),
-        );
+    for &(name, use_acceleration) in &[
+        ("from_scratch", false),
+        ("from_scratch_with_acceleration", true),
+    ] {
+        t.run_slow(name, |_| {
+            println!("Creating two simulations");
+            let mut flags = SimFlags::for_test(&format!("{}_1", name));
+            flags.opts.use_acceleration = use_acceleration;
+            let (map, mut sim1, _) = flags.load(&mut Timer::throwaway()).unwrap();
+            let mut opts = SimOptions::new(&format!("{}_2", name));
+            opts.use_acceleration = use_acceleration;
+            let mut sim2 = Sim::new(&map, opts, &mut Timer::throwaway());
+            Scenario::small_run(&map).instantiate(
+                &mut sim1,
+                &map,
+                &mut flags.make_rng(),
+                &mut Timer::throwaway(),
+            );
+            Scenario::small_run(&map).instantiate(
+                &mut sim2,
+                &map,
+                &mut flags.make_rng(),
+                &mut Timer::throwaway(),
+            );
 
-        let dt = Duration::seconds(0.1);
-        for _ in 1..600 {
-            if sim1 != sim2 {
-                // TODO need to sort dicts in json output to compare
-                panic!(
-                    "sim state differs between {} and {}",
-                    sim1.save(),
-                    sim2.save()
-                );
+            let dt = Duration::seconds(0.1);
+            for _ in 1..600 {
+                if sim1 != sim2 {
+                    // TODO need to sort dicts in json output to compare
+                    panic!(
+                        "sim state differs between {} and {}",
+                        sim1.save(&map),
+                        sim2.save(&map)
+                    );
+                }
+                sim1.step(&map, dt);
+                sim2.step(&map, dt);
             }
-            sim1.step(&map, dt);
-            sim2.step(&map, dt);
-        }
-    });
+        });
+    }
 
     t.run_slow("with_savestating", |_| {
         println!("Creating two simulations");
         let flags = SimFlags::for_test("with_savestating_1");
-        let (map, mut sim1, _) = flags.load(&mut Timer::throwaway());
+        let (map, mut sim1, _) = flags.load(&mut Timer::throwaway()).unwrap();
         let mut sim2 = Sim::new(
             &map,
             SimOptions::new("with_savestating_2"),
@@ -80,20 +92,23 @@ pub fn run(t: &mut TestRunner) {
         if sim1 != sim2 {
             panic!(
                 "sim state differs between {} and {}",
-                sim1.save(),
-                sim2.save()
+                sim1.save(&map),
+                sim2.save(&map)
             );
         }
 
-        let sim1_save = sim1.save();
+        let sim1_save = sim1.save(&map);
+        let header = Sim::read_savestate_header(&sim1_save).unwrap();
+        assert_eq!(header.version, SAVESTATE_VERSION);
+        assert_eq!(&header.map_name, map.get_name());
 
         sim1.step(&map, Duration::seconds(30.0));
 
         if sim1 == sim2 {
             panic!(
                 "sim state unexpectly the same -- {} and {}",
-                sim1.save(),
-                sim2.save()
+                sim1.save(&map),
+                sim2.save(&map)
             );
         }
 
@@ -103,11 +118,144 @@ pub fn run(t: &mut TestRunner) {
         if sim3 != sim2 {
             panic!(
                 "sim state differs between {} and {}",
-                sim3.save(),
-                sim2.save()
+                sim3.save(&map),
+                sim2.save(&map)
             );
         }
 
         std::fs::remove_file(sim1_save).unwrap();
     });
+
+    t.run_fast("old_savestates_load", |_| {
+        let (map, _) = savestate_fixture();
+        for version in 1..=SAVESTATE_VERSION {
+            let path = format!("savestates/v{}.bin", version);
+            let mut sim = Sim::load_savestate(path.clone(), &map, &mut Timer::throwaway())
+                .unwrap_or_else(|err| panic!("Couldn't load {}: {}", path, err));
+            if sim.time() != Time::START_OF_DAY + Duration::seconds(30.0) {
+                panic!("{} was saved at 30s, but loaded at {}", path, sim.time());
+            }
+            sim.just_run_until_done(&map, Some(Duration::minutes(10)));
+        }
+
+        // Before headers, savestates were just the Sim
+        let (map, sim) = savestate_fixture();
+        let path = "/tmp/headerless_savestate.bin".to_string();
+        abstutil::write_binary(path.clone(), &sim);
+        match Sim::load_savestate(path, &map, &mut Timer::throwaway()) {
+            Ok(_) => panic!("Loaded a savestate without a header"),
+            Err(err) => {
+                if !err.contains("predates versioned savestates") {
+                    panic!("Wrong error for a savestate without a header: {}", err);
+                }
+            }
+        }
+    });
+
+    t.run_slow("savestate_refuses_other_map_or_edits", |_| {
+        let (mut map, mut sim, _) = SimFlags::for_test("savestate_refuses_other_map_or_edits")
+            .load(&mut Timer::throwaway())
+            .unwrap();
+        let path = sim.save(&map);
+
+        let mut header = Sim::read_savestate_header(&path).unwrap();
+        header.map_name = "some_other_map".to_string();
+        if header.check(&map).is_ok() {
+            panic!("A savestate for another map passed the check");
+        }
+
+        // Same edits name, different edits
+        let l = map
+            .all_lanes()
+            .iter()
+            .find(|l| l.lane_type == LaneType::Driving)
+            .unwrap()
+            .id;
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(EditCmd::ChangeLaneType {
+            id: l,
+            lt: LaneType::Construction,
+            orig_lt: LaneType::Driving,
+        });
+        map.apply_edits(edits, &mut Timer::throwaway());
+        match Sim::load_savestate(path, &map, &mut Timer::throwaway()) {
+            Ok(_) => panic!("Loaded a savestate made before the edits changed"),
+            Err(err) => {
+                if !err.contains("have changed") {
+                    panic!("Refused the savestate for the wrong reason: {}", err);
+                }
+            }
+        }
+    });
+}
+
+// Cars both ways through a stop sign, and people walking along. For every savestate version,
+// tests/savestates has one of these saved at 30s, made with the code from then. Before changing
+// the layout, save the old version with this.
+pub fn savestate_fixture() -> (Map, Sim) {
+    let mut m = SyntheticMap::new("savestate_fixture", DrivingSide::Right);
+    let a = m.intersection(300.0, 500.0, IntersectionType::Border);
+    let x = m.intersection(500.0, 500.0, IntersectionType::StopSign);
+    let b = m.intersection(700.0, 500.0, IntersectionType::Border);
+    let lanes = vec![LaneType::Driving, LaneType::Sidewalk];
+    m.road(a, x, lanes.clone(), lanes.clone());
+    m.road(x, b, lanes.clone(), lanes);
+    let map = m.build();
+
+    let mut sim = Sim::new(
+        &map,
+        SimOptions::new("savestate_fixture"),
+        &mut Timer::throwaway(),
+    );
+    let mut rng = XorShiftRng::seed_from_u64(42);
+    let (r1, r2) = (&map.all_roads()[0], &map.all_roads()[1]);
+    let lane = |lanes: &Vec<(_, LaneType)>, lt| lanes.iter().find(|(_, t)| *t == lt).unwrap().0;
+    // (start, end) lanes, east and then west
+    let flows = vec![
+        (
+            lane(&r1.children_forwards, LaneType::Driving),
+            lane(&r2.children_forwards, LaneType::Driving),
+            lane(&r1.children_forwards, LaneType::Sidewalk),
+            r2.dst_i,
+        ),
+        (
+            lane(&r2.children_backwards, LaneType::Driving),
+            lane(&r1.children_backwards, LaneType::Driving),
+            lane(&r2.children_backwards, LaneType::Sidewalk),
+            r1.src_i,
+        ),
+    ];
+    for i in 0..10 {
+        for (start, end, sidewalk, border) in &flows {
+            let at = Time::START_OF_DAY + Duration::seconds(5.0 * (i as f64));
+            sim.schedule_trip(
+                at,
+                TripSpec::CarAppearing {
+                    start_pos: Position::new(*start, Distance::meters(7.0)),
+                    goal: DrivingGoal::Border(*border, *end),
+                    vehicle_spec: Scenario::rand_car(&mut rng),
+                    ped_speed: Scenario::rand_ped_speed(&mut rng),
+                },
+                &map,
+            );
+            if i % 5 == 0 {
+                sim.schedule_trip(
+                    at,
+                    TripSpec::JustWalking {
+                        start: SidewalkSpot::suddenly_appear(
+                            *sidewalk,
+                            Distance::meters(10.0),
+                            &map,
+                        ),
+                        goal: SidewalkSpot::end_at_border(*border, &map).unwrap(),
+                        ped_speed: Scenario::rand_ped_speed(&mut rng),
+                    },
+                    &map,
+                );
+            }
+        }
+    }
+    sim.spawn_all_trips(&map, &mut Timer::throwaway(), true);
+    sim.step(&map, Duration::seconds(30.0));
+    (map, sim)
 }
@@ -0,0 +1,159 @@
+use abstutil::Timer;
+use geom::{Distance, GPSBounds, LonLat, Polygon, Pt2D};
+use gtfs::Route;
+use map_model::raw::{
+    DrivingSide, OriginalBuilding, OriginalIntersection, OriginalRoad, RawBuilding,
+    RawIntersection, RawMap, RawRoad,
+};
+use map_model::{osm, IntersectionType, LaneID, LaneType, Map, RoadSpec};
+use std::collections::BTreeMap;
+
+// Builds small maps from scratch, so tests don't need any input data. Points are in meters.
+pub struct SyntheticMap {
+    pub raw: RawMap,
+}
+
+impl SyntheticMap {
+    pub fn new(name: &str, driving_side: DrivingSide) -> SyntheticMap {
+        let mut raw = RawMap::blank(name.to_string());
+        raw.driving_side = driving_side;
+        // About 1km square; only used to convert to and from GPS
+        raw.gps_bounds = GPSBounds::new();
+        raw.gps_bounds.update(LonLat::new(-122.31, 47.64));
+        raw.gps_bounds.update(LonLat::new(-122.3, 47.65));
+        raw.boundary_polygon = Polygon::rectangle(1000.0, 1000.0);
+        SyntheticMap { raw }
+    }
+
+    pub fn intersection(
+        &mut self,
+        x: f64,
+        y: f64,
+        intersection_type: IntersectionType,
+    ) -> OriginalIntersection {
+        let id = OriginalIntersection {
+            osm_node_id: (self.raw.intersections.len() + 1) as i64,
+        };
+        self.raw.intersections.insert(
+            id,
+            RawIntersection {
+                point: Pt2D::new(x, y),
+                intersection_type,
+                elevation: Distance::ZERO,
+                crossing: None,
+            },
+        );
+        id
+    }
+
+    // Lanes are listed from the center of the road outwards.
+    pub fn road(
+        &mut self,
+        i1: OriginalIntersection,
+        i2: OriginalIntersection,
+        fwd: Vec<LaneType>,
+        back: Vec<LaneType>,
+    ) -> OriginalRoad {
+        let spec = RoadSpec { fwd, back }.to_string();
+        self.osm_road(
+            i1,
+            i2,
+            &[(osm::SYNTHETIC, "true"), (osm::SYNTHETIC_LANES, &spec)],
+        )
+    }
+
+    // The lanes come from OSM tags, like cycleway:left or parking:lane:both.
+    pub fn osm_road(
+        &mut self,
+        i1: OriginalIntersection,
+        i2: OriginalIntersection,
+        tags: &[(&str, &str)],
+    ) -> OriginalRoad {
+        let id = OriginalRoad {
+            osm_way_id: (self.raw.roads.len() + 1) as i64,
+            i1,
+            i2,
+        };
+        let mut osm_tags = BTreeMap::new();
+        osm_tags.insert(osm::ENDPT_FWD.to_string(), "true".to_string());
+        osm_tags.insert(osm::ENDPT_BACK.to_string(), "true".to_string());
+        osm_tags.insert(osm::OSM_WAY_ID.to_string(), id.osm_way_id.to_string());
+        osm_tags.insert(osm::NAME.to_string(), format!("Road {}", id.osm_way_id));
+        osm_tags.insert(osm::MAXSPEED.to_string(), "25 mph".to_string());
+        for (k, v) in tags {
+            osm_tags.insert(k.to_string(), v.to_string());
+        }
+        self.raw.roads.insert(
+            id,
+            RawRoad {
+                center_points: vec![
+                    self.raw.intersections[&i1].point,
+                    self.raw.intersections[&i2].point,
+                ],
+                osm_tags,
+                turn_restrictions: Vec::new(),
+            },
+        );
+        id
+    }
+
+    // Like one direction of a GTFS route, with no timetable. Stops snap to the closest sidewalk.
+    pub fn bus_route(&mut self, name: &str, stops: &[(f64, f64)]) {
+        let gps_bounds = &self.raw.gps_bounds;
+        self.raw.bus_routes.push(Route {
+            name: name.to_string(),
+            long_name: name.to_string(),
+            route_type: "Bus".to_string(),
+            color: None,
+            agency: None,
+            stops: stops
+                .iter()
+                .map(|(x, y)| Pt2D::new(*x, *y).forcibly_to_gps(gps_bounds))
+                .collect(),
+            trips: Vec::new(),
+        });
+    }
+
+    // A small square building; its front path goes to the closest sidewalk.
+    pub fn building(&mut self, x: f64, y: f64) -> OriginalBuilding {
+        let id = OriginalBuilding {
+            osm_way_id: (self.raw.buildings.len() + 1) as i64,
+        };
+        self.raw.buildings.insert(
+            id,
+            RawBuilding {
+                polygon: Polygon::rectangle_centered(
+                    Pt2D::new(x, y),
+                    Distance::meters(10.0),
+                    Distance::meters(10.0),
+                ),
+                osm_tags: BTreeMap::new(),
+                parking: None,
+            },
+        );
+        id
+    }
+
+    pub fn build(&self) -> Map {
+        let path = std::env::temp_dir().join(format!("abst_synthetic_{}.json", self.raw.name));
+        let path = path.to_str().unwrap().to_string();
+        abstutil::write_json(path.clone(), &self.raw);
+        Map::new(path, false, &mut Timer::throwaway())
+    }
+}
+
+// Which side of its road a lane is on, as OSM would say: looking from the first intersection the
+// way was drawn with towards the second.
+pub fn osm_side(map: &Map, l: LaneID) -> &'static str {
+    let r = map.get_parent(l);
+    let (from, to) = (r.center_pts.first_pt(), r.center_pts.last_pt());
+    let (mid, lane) = (r.center_pts.middle(), map.get_l(l).lane_center_pts.middle());
+    // y points down
+    let cross =
+        (to.x() - from.x()) * (lane.y() - mid.y()) - (to.y() - from.y()) * (lane.x() - mid.x());
+    if cross > 0.0 {
+        "right"
+    } else {
+        "left"
+    }
+}
@@ -1,23 +1,33 @@
-use crate::runner::TestRunner;
+use crate::runner::{record_events, TestRunner};
+use crate::synthetic::SyntheticMap;
 use abstutil::Timer;
 use geom::{Duration, Time};
-use sim::{Event, Scenario, SidewalkSpot, SimFlags, TripSpec};
+use map_model::raw::DrivingSide;
+use map_model::{
+    find_transit_rides, BusRoute, BusRouteID, BusStopID, IntersectionType, LaneID, LaneType,
+};
+use sim::{
+    Event, Scenario, SidewalkSpot, Sim, SimFlags, SimOptions, TripSpec, TripStart, BUS_CAPACITY,
+};
 
 pub fn run(t: &mut TestRunner) {
     t.run_slow("bus_reaches_stops", |h| {
         let mut flags = SimFlags::for_test("bus_reaches_stops");
         flags.opts.savestate_every = Some(Duration::seconds(30.0));
-        let (map, mut sim, _) = flags.load(&mut Timer::throwaway());
-        let route = map.get_bus_route("49").unwrap();
+        let (map, mut sim, _) = flags.load(&mut Timer::throwaway()).unwrap();
+        let route = map.get_bus_routes("49")[0];
         let buses = sim.seed_bus_route(route, &map, &mut Timer::throwaway());
         let bus = buses[0];
-        h.setup_done(&mut sim);
+        h.setup_done(&mut sim, &map);
 
         let mut expectations: Vec<Event> = Vec::new();
         // TODO assert stuff about other buses as well, although the timing is a little unclear
         for stop in route.stops.iter().skip(1) {
             expectations.push(Event::BusArrivedAtStop(bus, route.id, *stop));
-            expectations.push(Event::BusDepartedFromStop(bus, route.id, *stop));
+            // The last stop is the end of the line; the bus lays over for a while there.
+            if stop != route.stops.last().unwrap() {
+                expectations.push(Event::BusDepartedFromStop(bus, route.id, *stop));
+            }
         }
 
         sim.run_until_expectations_met(&map, expectations, Duration::minutes(10));
@@ -25,11 +35,39 @@ pub fn run(t: &mut TestRunner) {
         sim.just_run_until_done(&map, Some(Duration::minutes(11)));
     });
 
+    t.run_slow("bus_turns_around_at_terminus", |h| {
+        let mut flags = SimFlags::for_test("bus_turns_around_at_terminus");
+        flags.opts.savestate_every = Some(Duration::seconds(30.0));
+        let (map, mut sim, _) = flags.load(&mut Timer::throwaway()).unwrap();
+        let routes = map.get_bus_routes("49");
+        let route = routes[0];
+        assert!(!route.is_loop);
+        let opposite = map.get_br(route.opposite_route.unwrap());
+        if routes.len() != 2 || opposite.id != routes[1].id {
+            panic!("Route 49 should have exactly two directions, paired up");
+        }
+        let buses = sim.seed_bus_route(route, &map, &mut Timer::throwaway());
+        sim.seed_bus_route(opposite, &map, &mut Timer::throwaway());
+        let bus = buses[0];
+        let terminus = *route.stops.last().unwrap();
+        h.setup_done(&mut sim, &map);
+
+        sim.run_until_expectations_met(
+            &map,
+            vec![
+                Event::BusArrivedAtStop(bus, route.id, terminus),
+                Event::BusDepartedFromStop(bus, route.id, terminus),
+                Event::BusArrivedAtStop(bus, opposite.id, opposite.stops[0]),
+            ],
+            Duration::minutes(30),
+        );
+    });
+
     t.run_slow("ped_uses_bus", |h| {
         let mut flags = SimFlags::for_test("ped_uses_bus");
         flags.opts.savestate_every = Some(Duration::seconds(30.0));
-        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway());
-        let route = map.get_bus_route("49").unwrap();
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway()).unwrap();
+        let route = map.get_bus_routes("49")[0];
         let buses = sim.seed_bus_route(route, &map, &mut Timer::throwaway());
         let bus = buses[0];
         let ped_stop1 = route.stops[1];
@@ -50,10 +88,8 @@ pub fn run(t: &mut TestRunner) {
                 Time::START_OF_DAY,
                 TripSpec::UsingTransit {
                     start: SidewalkSpot::building(start_bldg, &map),
-                    route: route.id,
-                    stop1: ped_stop1,
-                    stop2: ped_stop2,
                     goal: SidewalkSpot::building(goal_bldg, &map),
+                    rides: vec![(ped_stop1, ped_stop2, route.id)],
                     ped_speed: Scenario::rand_ped_speed(&mut rng),
                 },
                 &map,
@@ -61,12 +97,12 @@ pub fn run(t: &mut TestRunner) {
             .0
             .unwrap();
         sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
-        h.setup_done(&mut sim);
+        h.setup_done(&mut sim, &map);
 
         sim.run_until_expectations_met(
             &map,
             vec![
-                Event::PedReachedBusStop(ped, ped_stop1),
+                Event::PedReachedBusStop(ped, ped_stop1, route.id),
                 Event::BusArrivedAtStop(bus, route.id, ped_stop1),
                 Event::PedEntersBus(ped, bus, route.id),
                 Event::BusDepartedFromStop(bus, route.id, ped_stop1),
@@ -79,4 +115,327 @@ pub fn run(t: &mut TestRunner) {
             Duration::minutes(9),
         );
     });
+
+    t.run_slow("full_bus_leaves_peds_behind", |h| {
+        let flags = SimFlags::for_test("full_bus_leaves_peds_behind");
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway()).unwrap();
+        let route = map.get_bus_routes("49")[0];
+        let buses = sim.seed_bus_route(route, &map, &mut Timer::throwaway());
+        let bus = buses[0];
+        let ped_stop1 = route.stops[1];
+        let ped_stop2 = route.stops[2];
+        let start_bldg = *map
+            .get_l(map.get_bs(ped_stop1).sidewalk_pos.lane())
+            .building_paths
+            .last()
+            .unwrap();
+        let goal_bldg = map
+            .get_l(map.get_bs(ped_stop2).sidewalk_pos.lane())
+            .building_paths[0];
+        let left_behind = 10;
+        for _ in 0..BUS_CAPACITY + left_behind {
+            sim.schedule_trip(
+                Time::START_OF_DAY,
+                TripSpec::UsingTransit {
+                    start: SidewalkSpot::building(start_bldg, &map),
+                    goal: SidewalkSpot::building(goal_bldg, &map),
+                    rides: vec![(ped_stop1, ped_stop2, route.id)],
+                    ped_speed: Scenario::rand_ped_speed(&mut rng),
+                },
+                &map,
+            );
+        }
+        sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
+        h.setup_done(&mut sim, &map);
+
+        sim.run_until_expectations_met(
+            &map,
+            vec![Event::BusDepartedFromStop(bus, route.id, ped_stop1)],
+            Duration::minutes(9),
+        );
+        let analytics = sim.get_analytics();
+        let denied = analytics
+            .bus_boardings_denied(sim.time(), route.id)
+            .get(ped_stop1);
+        if denied < left_behind {
+            panic!("Only {} boardings were denied at {}", denied, ped_stop1);
+        }
+        let still_waiting = analytics
+            .bus_passenger_delays(sim.time(), route.id)
+            .get(&ped_stop1)
+            .map(|delays| delays.count())
+            .unwrap_or(0);
+        if still_waiting != left_behind {
+            panic!(
+                "{} people are still waiting at {}, but {} should be",
+                still_waiting, ped_stop1, left_behind
+            );
+        }
+    });
+
+    t.run_fast("bus_goes_around_the_block_to_turn_around", |_| {
+        // A clockwise block of one-way roads. Route 1 ends partway down the east side, and the
+        // other direction starts a little before that, behind the bus.
+        let mut m = SyntheticMap::new("bus_goes_around_the_block", DrivingSide::Right);
+        let nw = m.intersection(300.0, 300.0, IntersectionType::StopSign);
+        let ne = m.intersection(700.0, 300.0, IntersectionType::StopSign);
+        let se = m.intersection(700.0, 700.0, IntersectionType::StopSign);
+        let sw = m.intersection(300.0, 700.0, IntersectionType::StopSign);
+        for &(i1, i2) in &[(nw, ne), (ne, se), (se, sw), (sw, nw)] {
+            m.road(
+                i1,
+                i2,
+                vec![LaneType::Driving, LaneType::Sidewalk],
+                Vec::new(),
+            );
+        }
+        m.bus_route("1", &[(500.0, 300.0), (700.0, 500.0)]);
+        m.bus_route("1", &[(700.0, 400.0), (300.0, 500.0)]);
+        let map = m.build();
+
+        let routes = map.get_bus_routes("1");
+        if routes.len() != 2 || routes[0].opposite_route != Some(routes[1].id) {
+            panic!("Both directions of route 1 should exist and be paired up");
+        }
+        let (route, opposite) = (routes[0], routes[1]);
+        let terminus = *route.stops.last().unwrap();
+        let next_stop = opposite.stops[0];
+        let (here, there) = (
+            map.get_bs(terminus).driving_pos,
+            map.get_bs(next_stop).driving_pos,
+        );
+        if here.lane() != there.lane() || here.dist_along() <= there.dist_along() {
+            panic!("The next stop should be behind the terminus on the same lane");
+        }
+
+        let mut sim = Sim::new(
+            &map,
+            SimOptions::new("bus_goes_around_the_block"),
+            &mut Timer::throwaway(),
+        );
+        let bus = sim.seed_bus_route(route, &map, &mut Timer::throwaway())[0];
+        sim.seed_bus_route(opposite, &map, &mut Timer::throwaway());
+        sim.run_until_expectations_met(
+            &map,
+            vec![
+                Event::BusArrivedAtStop(bus, route.id, terminus),
+                Event::BusDepartedFromStop(bus, route.id, terminus),
+                Event::BusArrivedAtStop(bus, opposite.id, next_stop),
+            ],
+            Duration::minutes(10),
+        );
+    });
+
+    t.run_fast("gtfs_timetable_dispatches_buses", |_| {
+        let at = |h, m| Time::START_OF_DAY + Duration::hours(h) + Duration::minutes(m);
+        // The fixture has one route in both directions, with stops along a street in Montlake. The
+        // eastbound weekday trip runs every 10 minutes from 8 to 8:30, and another one skips the
+        // first stop. There's also a weekend trip.
+        let routes = gtfs::load("gtfs/montlake", Some("monday"));
+        if routes.len() != 2 || routes.iter().any(|r| r.name != "43") {
+            panic!("Expected both directions of route 43, but got {:?}", routes);
+        }
+        let east_trips = vec![
+            vec![(0, at(8, 0)), (1, at(8, 2)), (2, at(8, 4))],
+            vec![(0, at(8, 10)), (1, at(8, 12)), (2, at(8, 14))],
+            vec![(0, at(8, 20)), (1, at(8, 22)), (2, at(8, 24))],
+            vec![(1, at(8, 45)), (2, at(8, 47))],
+        ];
+        if routes[0].trips != vec![vec![(0, at(8, 5)), (1, at(8, 7))]]
+            || routes[1].stops.len() != 3
+            || routes[1].trips != east_trips
+        {
+            panic!("Monday's timetable is wrong: {:?}", routes);
+        }
+        let every_day = gtfs::load("gtfs/montlake", None);
+        if every_day[1].trips.len() != 5 {
+            panic!(
+                "Without a service day, the weekend trip should be included too: {:?}",
+                every_day[1].trips
+            );
+        }
+
+        // The same street, in the same place. The eastbound stops are on the south side.
+        let mut m = SyntheticMap::new("gtfs_timetable", DrivingSide::Right);
+        let w = m.intersection(100.0, 500.0, IntersectionType::Border);
+        let m1 = m.intersection(300.0, 500.0, IntersectionType::StopSign);
+        let m2 = m.intersection(500.0, 500.0, IntersectionType::StopSign);
+        let e = m.intersection(700.0, 500.0, IntersectionType::Border);
+        for &(i1, i2) in &[(w, m1), (m1, m2), (m2, e)] {
+            m.road(
+                i1,
+                i2,
+                vec![LaneType::Driving, LaneType::Sidewalk],
+                vec![LaneType::Driving, LaneType::Sidewalk],
+            );
+        }
+        m.raw.bus_routes = routes;
+        let map = m.build();
+        let routes = map.get_bus_routes("43");
+        if routes.len() != 2 || routes[1].stops.len() != 3 || routes[1].schedule != east_trips {
+            panic!("Route 43 didn't survive map import: {:?}", routes);
+        }
+
+        let mut sim = Sim::new(
+            &map,
+            SimOptions::new("gtfs_timetable"),
+            &mut Timer::throwaway(),
+        );
+        let events = record_events(&mut sim);
+        let mut buses = Vec::new();
+        for route in &routes {
+            for (bus, trip) in sim
+                .schedule_bus_route(route, &map)
+                .into_iter()
+                .zip(route.schedule.iter())
+            {
+                buses.push((bus, route, trip));
+            }
+        }
+        if buses.len() != 5 {
+            panic!("Expected 5 buses, but got {:?}", buses.len());
+        }
+        sim.step(&map, at(9, 0) - Time::START_OF_DAY);
+
+        let events = events.borrow();
+        for (bus, route, trip) in buses {
+            // Every bus leaves every stop right on time.
+            for (idx, scheduled) in trip {
+                let stop = route.stops[*idx];
+                let departed = events.iter().find_map(|(t, ev)| match ev {
+                    Event::BusDepartedFromStop(b, _, s) if *b == bus && *s == stop => Some(*t),
+                    _ => None,
+                });
+                if departed != Some(*scheduled) {
+                    panic!(
+                        "{} should've left {} at {}, but left at {:?}",
+                        bus, stop, scheduled, departed
+                    );
+                }
+            }
+
+            // Buses appear on the lane of their first stop. Only eastbound buses starting at the
+            // first stop come in from the edge of the map.
+            let first_lane = map.get_bs(route.stops[trip[0].0]).driving_pos.lane();
+            let (trip_id, req) = events
+                .iter()
+                .find_map(|(_, ev)| match ev {
+                    Event::TripPhaseStarting(id, req, desc) if *desc == bus.to_string() => {
+                        Some((*id, req.clone().unwrap()))
+                    }
+                    _ => None,
+                })
+                .unwrap();
+            if req.start.lane() != first_lane {
+                panic!("{} started at {:?}, not on {}", bus, req.start, first_lane);
+            }
+            let expected_start = if route.id == routes[1].id && trip[0].0 == 0 {
+                TripStart::Border(map.get_l(first_lane).src_i)
+            } else {
+                TripStart::ServeBusRoute(route.id)
+            };
+            let start = sim.trip_endpoints(trip_id).0;
+            if start != expected_start {
+                panic!(
+                    "{} should start at {:?}, not {:?}",
+                    bus, expected_start, start
+                );
+            }
+        }
+    });
+
+    t.run_fast("transit_itinerary_with_transfer", |_| {
+        let stop = |idx| BusStopID {
+            sidewalk: LaneID(idx),
+            idx: 0,
+        };
+        let (a, b, c, d) = (stop(0), stop(1), stop(2), stop(3));
+        let at = |h, m| Time::START_OF_DAY + Duration::hours(h) + Duration::minutes(m);
+        // Route 0 goes from a to b, then somebody walks to c and takes route 1 to d. Route 2 goes
+        // straight from a to d, but slowly.
+        let routes = vec![
+            fake_route(
+                0,
+                vec![a, b],
+                vec![
+                    vec![(0, at(10, 0)), (1, at(10, 10))],
+                    vec![(0, at(10, 20)), (1, at(10, 30))],
+                ],
+            ),
+            fake_route(
+                1,
+                vec![c, d],
+                vec![
+                    vec![(0, at(10, 15)), (1, at(10, 25))],
+                    vec![(0, at(10, 45)), (1, at(10, 55))],
+                ],
+            ),
+            fake_route(2, vec![a, d], vec![vec![(0, at(10, 5)), (1, at(10, 40))]]),
+        ];
+        let plan = |depart: Time, walk_only: Time| {
+            find_transit_rides(
+                depart,
+                walk_only,
+                vec![(a, Duration::minutes(2))],
+                |stop, now| {
+                    let mut rides = Vec::new();
+                    for route in &routes {
+                        for (next, arrive) in route.scheduled_rides_from(stop, now) {
+                            rides.push((route.id, next, arrive));
+                        }
+                    }
+                    rides
+                },
+                |stop| {
+                    if stop == b {
+                        vec![(c, Duration::minutes(3))]
+                    } else {
+                        Vec::new()
+                    }
+                },
+                |stop| {
+                    if stop == d {
+                        Some(Duration::minutes(2))
+                    } else {
+                        None
+                    }
+                },
+            )
+        };
+
+        // Catching the first bus makes the transfer in time, arriving at 10:27.
+        let rides = plan(at(9, 58), at(12, 0));
+        let expected = vec![(a, b, BusRouteID(0)), (c, d, BusRouteID(1))];
+        if rides != Some(expected.clone()) {
+            panic!("Expected {:?}, but got {:?}", expected, rides);
+        }
+
+        // Missing it means the transfer wouldn't arrive until 10:57, so the slow route is better.
+        let rides = plan(at(10, 1), at(12, 0));
+        let expected = vec![(a, d, BusRouteID(2))];
+        if rides != Some(expected.clone()) {
+            panic!("Expected {:?}, but got {:?}", expected, rides);
+        }
+
+        // Walking the whole way is faster.
+        let rides = plan(at(9, 58), at(10, 20));
+        if rides.is_some() {
+            panic!("Walking should've been faster than {:?}", rides);
+        }
+    });
+}
+
+fn fake_route(id: usize, stops: Vec<BusStopID>, schedule: Vec<Vec<(usize, Time)>>) -> BusRoute {
+    BusRoute {
+        id: BusRouteID(id),
+        name: id.to_string(),
+        long_name: id.to_string(),
+        route_type: "bus".to_string(),
+        color: None,
+        agency: None,
+        stops,
+        is_loop: false,
+        opposite_route: None,
+        schedule,
+    }
 }
@@ -1,14 +1,22 @@
-use crate::runner::TestRunner;
+use crate::runner::{record_events, TestRunner};
+use crate::synthetic::SyntheticMap;
 use abstutil::Timer;
-use geom::{Duration, Time};
-use map_model::{BuildingID, IntersectionID};
-use sim::{DrivingGoal, Event, Scenario, SidewalkSpot, SimFlags, TripSpec};
+use geom::{Distance, Duration, Pt2D, Time};
+use map_model::raw::DrivingSide;
+use map_model::{
+    BuildingID, IntersectionID, IntersectionType, LaneType, PathConstraints, PathRequest, Position,
+    Traversable,
+};
+use popdat::psrc::{Mode, Purpose};
+use popdat::{chain_park_and_ride, parked_cars_needed, Trip, TripEndpt};
+use sim::{DrivingGoal, Event, Scenario, SidewalkSpot, SimFlags, SpawnTrip, TripSpec};
+use std::collections::BTreeMap;
 
 pub fn run(t: &mut TestRunner) {
     t.run_slow("bike_from_border", |h| {
         let mut flags = SimFlags::for_test("bike_from_border");
         flags.opts.savestate_every = Some(Duration::seconds(30.0));
-        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway());
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway()).unwrap();
         // TODO Hardcoding IDs is fragile
         let goal_bldg = BuildingID(319);
         let (ped, bike) = sim.schedule_trip(
@@ -22,7 +30,7 @@ pub fn run(t: &mut TestRunner) {
             &map,
         );
         sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
-        h.setup_done(&mut sim);
+        h.setup_done(&mut sim, &map);
 
         sim.run_until_expectations_met(
             &map,
@@ -37,4 +45,297 @@ pub fn run(t: &mut TestRunner) {
         );
         sim.just_run_until_done(&map, Some(Duration::minutes(1)));
     });
+
+    t.run_slow("car_traversal_times", |h| {
+        let flags = SimFlags::for_test("car_traversal_times");
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway()).unwrap();
+        let vehicle = Scenario::rand_car(&mut rng);
+        // Drive between the first two borders that're connected
+        let (start_pos, goal_i, goal_lane) = map
+            .all_incoming_borders()
+            .into_iter()
+            .find_map(|i1| {
+                let start = *i1.get_outgoing_lanes(&map, PathConstraints::Car).first()?;
+                let start_pos = Position::new(start, vehicle.length);
+                map.all_outgoing_borders()
+                    .into_iter()
+                    .filter(|i2| i2.id != i1.id)
+                    .find_map(|i2| {
+                        let goal = *i2.get_incoming_lanes(&map, PathConstraints::Car).first()?;
+                        if goal == start {
+                            return None;
+                        }
+                        map.pathfind(PathRequest {
+                            start: start_pos,
+                            end: Position::new(goal, map.get_l(goal).length()),
+                            constraints: PathConstraints::Car,
+                        })?;
+                        Some((start_pos, i2.id, goal))
+                    })
+            })
+            .unwrap();
+        let (_, car) = sim.schedule_trip(
+            Time::START_OF_DAY,
+            TripSpec::CarAppearing {
+                start_pos,
+                goal: DrivingGoal::Border(goal_i, goal_lane),
+                vehicle_spec: vehicle,
+                ped_speed: Scenario::rand_ped_speed(&mut rng),
+            },
+            &map,
+        );
+        sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
+        h.setup_done(&mut sim, &map);
+
+        sim.run_until_expectations_met(
+            &map,
+            vec![Event::CarOrBikeReachedBorder(car.unwrap(), goal_i)],
+            Duration::minutes(30),
+        );
+
+        let times = &sim.get_analytics().traversal_times;
+        // The last lane is only finished at the border
+        match times.get(&Traversable::Lane(goal_lane)) {
+            Some((_, 1)) => {}
+            x => panic!("Last lane {} has traversals {:?}", goal_lane, x),
+        }
+        for (on, (total, count)) in times {
+            if *count != 1 {
+                panic!("{:?} was crossed {} times by one car", on, count);
+            }
+            // Alone on the map, the car still can't beat the speed limit
+            if let Traversable::Lane(l) = on {
+                let lane = map.get_l(*l);
+                let fastest = lane.length() / map.get_r(lane.parent).get_speed_limit();
+                if *total < fastest - Duration::seconds(0.1) {
+                    panic!(
+                        "Crossed {} in {}, but the speed limit allows {}",
+                        l, total, fastest
+                    );
+                }
+            }
+        }
+    });
+
+    t.run_fast("chain_park_and_ride_per_person", |_| {
+        let lot = BuildingID(5);
+        let at = |hr: usize, min: usize| {
+            Time::START_OF_DAY + Duration::hours(hr) + Duration::minutes(min)
+        };
+        let trips = chain_park_and_ride(vec![
+            // The second person leaves first, but gets to the lot after the first person's bus.
+            psrc_trip((2, 1), 1, 5, at(7, 55), Duration::minutes(15), Mode::Drive),
+            psrc_trip((1, 1), 0, 5, at(8, 0), Duration::minutes(10), Mode::Drive),
+            psrc_trip(
+                (1, 1),
+                5,
+                6,
+                at(8, 12),
+                Duration::minutes(20),
+                Mode::Transit,
+            ),
+            psrc_trip(
+                (2, 1),
+                5,
+                7,
+                at(8, 15),
+                Duration::minutes(20),
+                Mode::Transit,
+            ),
+            // The third person has a transit trip leaving before they arrive.
+            psrc_trip((3, 1), 2, 5, at(9, 0), Duration::minutes(20), Mode::Drive),
+            psrc_trip(
+                (3, 1),
+                5,
+                8,
+                at(9, 10),
+                Duration::minutes(20),
+                Mode::Transit,
+            ),
+            psrc_trip(
+                (3, 1),
+                5,
+                9,
+                at(9, 30),
+                Duration::minutes(20),
+                Mode::Transit,
+            ),
+        ]);
+
+        let summary: Vec<_> = trips
+            .iter()
+            .map(|trip| match (&trip.from, &trip.to) {
+                (TripEndpt::Building(b1), TripEndpt::Building(b2)) => {
+                    (b1.0, b2.0, trip.park_and_ride)
+                }
+                _ => unreachable!(),
+            })
+            .collect();
+        let expected = vec![
+            (1, 7, Some((lot, at(8, 15)))),
+            (0, 6, Some((lot, at(8, 12)))),
+            (2, 9, Some((lot, at(9, 30)))),
+            (5, 8, None),
+        ];
+        if summary != expected {
+            panic!("Chained {:?}, but expected {:?}", summary, expected);
+        }
+    });
+
+    t.run_fast("parked_car_follows_park_and_ride_fallback", |_| {
+        // No buses here, so park-and-ride falls back to driving the whole way.
+        let mut m = SyntheticMap::new(
+            "parked_car_follows_park_and_ride_fallback",
+            DrivingSide::Right,
+        );
+        let i1 = m.intersection(100.0, 500.0, IntersectionType::Border);
+        let i2 = m.intersection(900.0, 500.0, IntersectionType::Border);
+        let lanes = vec![LaneType::Driving, LaneType::Parking, LaneType::Sidewalk];
+        m.road(i1, i2, lanes.clone(), lanes);
+        for x in &[200.0, 500.0, 800.0] {
+            m.building(*x, 470.0);
+        }
+        let map = m.build();
+        let bldg = |x: f64| {
+            map.all_buildings()
+                .iter()
+                .min_by_key(|b| b.polygon.center().dist_to(Pt2D::new(x, 470.0)))
+                .unwrap()
+                .id
+        };
+        let (home, lot, work) = (bldg(200.0), bldg(500.0), bldg(800.0));
+
+        let mut trip = psrc_trip(
+            (1, 1),
+            home.0,
+            work.0,
+            Time::START_OF_DAY,
+            Duration::minutes(10),
+            Mode::Drive,
+        );
+        trip.park_and_ride = Some((lot, Time::START_OF_DAY + Duration::minutes(5)));
+        let fallback = trip.to_spawn_trip(&map).unwrap();
+        match fallback {
+            SpawnTrip::MaybeUsingParkedCar(_, b, DrivingGoal::ParkNear(goal))
+                if b == home && goal == work => {}
+            ref x => panic!("Park-and-ride without buses became {:?}", x),
+        }
+
+        let later = Time::START_OF_DAY + Duration::hours(1);
+        let needed = parked_cars_needed(&[
+            fallback,
+            // The car went to work, not the lot.
+            SpawnTrip::MaybeUsingParkedCar(later, work, DrivingGoal::ParkNear(home)),
+            // This one really left a car at the lot.
+            SpawnTrip::ParkAndRide(
+                later,
+                home,
+                lot,
+                SidewalkSpot::building(work, &map),
+                Vec::new(),
+            ),
+            SpawnTrip::MaybeUsingParkedCar(later, lot, DrivingGoal::ParkNear(home)),
+        ]);
+        let mut expected = BTreeMap::new();
+        // The second trip from home uses the car that came back.
+        expected.insert(home, 1);
+        if needed != expected {
+            panic!(
+                "Needed parked cars {:?}, but expected {:?}",
+                needed, expected
+            );
+        }
+    });
+
+    t.run_slow("park_and_ride_from_border", |h| {
+        let flags = SimFlags::for_test("park_and_ride_from_border");
+        let (map, mut sim, mut rng) = flags.load(&mut Timer::throwaway()).unwrap();
+        let route = map.get_bus_routes("49")[0];
+        sim.seed_bus_route(route, &map, &mut Timer::throwaway());
+        let (stop1, stop2) = (route.stops[1], route.stops[2]);
+        let lot = *map
+            .get_l(map.get_bs(stop1).sidewalk_pos.lane())
+            .building_paths
+            .last()
+            .unwrap();
+        let goal_bldg = map
+            .get_l(map.get_bs(stop2).sidewalk_pos.lane())
+            .building_paths[0];
+        let vehicle = Scenario::rand_car(&mut rng);
+        let end = DrivingGoal::ParkNear(lot).goal_pos(PathConstraints::Car, &map);
+        let start_pos = map
+            .all_incoming_borders()
+            .into_iter()
+            .find_map(|i| {
+                let start = *i.get_outgoing_lanes(&map, PathConstraints::Car).first()?;
+                let start_pos = TripSpec::spawn_car_at(Position::new(start, Distance::ZERO), &map)?;
+                map.pathfind(PathRequest {
+                    start: start_pos,
+                    end,
+                    constraints: PathConstraints::Car,
+                })?;
+                Some(start_pos)
+            })
+            .unwrap();
+        let (ped, car) = sim.schedule_trip(
+            Time::START_OF_DAY,
+            TripSpec::ParkAndRideFromBorder {
+                start_pos,
+                park_near: lot,
+                goal: SidewalkSpot::building(goal_bldg, &map),
+                rides: vec![(stop1, stop2, route.id)],
+                vehicle_spec: vehicle,
+                ped_speed: Scenario::rand_ped_speed(&mut rng),
+            },
+            &map,
+        );
+        let (ped, car) = (ped.unwrap(), car.unwrap());
+        sim.spawn_all_trips(&map, &mut Timer::throwaway(), false);
+        let events = record_events(&mut sim);
+        h.setup_done(&mut sim, &map);
+        sim.just_run_until_done(&map, Some(Duration::minutes(60)));
+
+        // The car parks, then the driver rides the bus and walks to the goal.
+        let steps: Vec<&str> = events
+            .borrow()
+            .iter()
+            .filter_map(|(_, ev)| match ev {
+                Event::CarReachedParkingSpot(c, _) if *c == car => Some("park"),
+                Event::PedReachedBusStop(p, stop, _) if *p == ped && *stop == stop1 => Some("wait"),
+                Event::PedEntersBus(p, _, r) if *p == ped && *r == route.id => Some("board"),
+                Event::PedLeavesBus(p, _, r) if *p == ped && *r == route.id => Some("leave"),
+                Event::PedReachedBuilding(p, b) if *p == ped && *b == goal_bldg => Some("arrive"),
+                _ => None,
+            })
+            .collect();
+        if steps != vec!["park", "wait", "board", "leave", "arrive"] {
+            panic!("Park-and-ride from a border went {:?}", steps);
+        }
+    });
+}
+
+// A trip between two buildings, as PSRC would describe it
+fn psrc_trip(
+    person: (usize, usize),
+    from: usize,
+    to: usize,
+    depart_at: Time,
+    trip_time: Duration,
+    mode: Mode,
+) -> Trip {
+    let purpose = match mode {
+        Mode::Transit => (Purpose::ParkAndRideTransfer, Purpose::Work),
+        _ => (Purpose::Home, Purpose::ParkAndRideTransfer),
+    };
+    Trip {
+        person,
+        from: TripEndpt::Building(BuildingID(from)),
+        to: TripEndpt::Building(BuildingID(to)),
+        depart_at,
+        purpose,
+        mode,
+        trip_time,
+        trip_dist: Distance::miles(1.0),
+        park_and_ride: None,
+    }
 }